@@ -1,9 +1,15 @@
-use std::{io::Read, sync::OnceLock};
+use std::{
+    cell::RefCell,
+    io::Read,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub use oodle_lz::{CompressionLevel, Compressor};
 
+#[allow(non_snake_case)]
 mod oodle_lz {
     #[derive(Debug, Clone, Copy)]
     #[repr(i32)]
@@ -88,6 +94,11 @@ mod oodle_lz {
         threadPhase: u32,
     ) -> isize;
 
+    /// `compressor` may be -1 (`OodleLZ_Compressor_Invalid`) and `rawLen` -1 for the memory
+    /// needed to decode blocks of any compressor and size
+    pub type DecoderMemorySizeNeeded =
+        unsafe extern "system" fn(compressor: i32, rawLen: isize) -> isize;
+
     pub type GetCompressedBufferSizeNeeded =
         unsafe extern "system" fn(compressor: Compressor, rawSize: usize) -> usize;
 
@@ -137,8 +148,15 @@ pub enum Error {
     HashMismatch { expected: String, found: String },
     #[error("Oodle compression failed")]
     CompressionFailed,
-    #[error("Oodle initialization failed previously")]
-    InitializationFailed,
+    #[error("Oodle decompression failed")]
+    DecompressionFailed,
+    #[error("Oodle initialization failed {attempts} times, retrying in {retry_in:?}: {error}")]
+    InitializationFailed {
+        attempts: u32,
+        /// The error of the latest attempt
+        error: String,
+        retry_in: Duration,
+    },
     #[error("IO error {0:?}")]
     Io(#[from] std::io::Error),
     #[error("ureq error {0:?}")]
@@ -184,22 +202,36 @@ fn fetch_oodle() -> Result<std::path::PathBuf> {
     Ok(oodle_path)
 }
 
+thread_local! {
+    /// Decoder memory of each thread, reused by every decompression on it so Oodle doesn't
+    /// allocate its own for each call
+    static DECODER_MEMORY: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
 pub struct Oodle {
     _library: libloading::Library,
     compress: oodle_lz::Compress,
     decompress: oodle_lz::Decompress,
     get_compressed_buffer_size_needed: oodle_lz::GetCompressedBufferSizeNeeded,
     set_printf: oodle_lz::SetPrintf,
+    /// Decoder memory needed for any block, 0 if the library doesn't tell, in which case Oodle
+    /// allocates it for each call
+    decoder_memory_size: usize,
 }
 impl Oodle {
     fn new(lib: libloading::Library) -> Result<Self> {
         unsafe {
+            // optional so substituted versions of the library without it still load
+            let decoder_memory_size = lib
+                .get::<oodle_lz::DecoderMemorySizeNeeded>(b"OodleLZDecoder_MemorySizeNeeded")
+                .map_or(0, |size_needed| size_needed(-1, -1).max(0) as usize);
             let res = Oodle {
                 compress: *lib.get(b"OodleLZ_Compress")?,
                 decompress: *lib.get(b"OodleLZ_Decompress")?,
                 get_compressed_buffer_size_needed: *lib
                     .get(b"OodleLZ_GetCompressedBufferSizeNeeded")?,
                 set_printf: *lib.get(b"OodleCore_Plugins_SetPrintf")?,
+                decoder_memory_size,
                 _library: lib,
             };
             (res.set_printf)(std::ptr::null()); // silence oodle logging
@@ -237,7 +269,86 @@ impl Oodle {
             Ok(buffer)
         }
     }
+    /// Compresses `input` in chunks of `chunk_size` bytes into independent streams which can
+    /// each be decompressed on their own, such as the compression blocks of a pak. Chunks are
+    /// compressed in parallel with the `rayon` feature.
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn compress_chunked(
+        &self,
+        input: &[u8],
+        chunk_size: usize,
+        compressor: Compressor,
+        compression_level: CompressionLevel,
+    ) -> Result<Vec<Vec<u8>>> {
+        let compress = |chunk| self.compress(chunk, compressor, compression_level);
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            input.par_chunks(chunk_size).map(compress).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        input.chunks(chunk_size).map(compress).collect()
+    }
+    /// Decompresses chunks compressed by [`Self::compress_chunked`] with the same `chunk_size`
+    /// into `output`, which must be exactly as long as the input was. Chunks are decompressed in
+    /// parallel with the `rayon` feature.
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn decompress_chunked<C: AsRef<[u8]> + Sync>(
+        &self,
+        chunks: &[C],
+        chunk_size: usize,
+        output: &mut [u8],
+    ) -> Result<()> {
+        if output.len().div_ceil(chunk_size) != chunks.len() {
+            return Err(Error::DecompressionFailed);
+        }
+        let decompress = |(chunk, output): (&C, &mut [u8])| match self
+            .decompress(chunk.as_ref(), output)
+            == output.len() as isize
+        {
+            true => Ok(()),
+            false => Err(Error::DecompressionFailed),
+        };
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            chunks
+                .par_iter()
+                .zip(output.par_chunks_mut(chunk_size))
+                .try_for_each(decompress)
+        }
+        #[cfg(not(feature = "rayon"))]
+        chunks
+            .iter()
+            .zip(output.chunks_mut(chunk_size))
+            .try_for_each(decompress)
+    }
+    /// Decompresses `input` into `output`, which must be exactly as long as the decompressed
+    /// data, and returns the number of bytes decompressed or 0 on failure. The decoder memory
+    /// is allocated once per thread, see [`Self::decoder_memory_size`].
     pub fn decompress(&self, input: &[u8], output: &mut [u8]) -> isize {
+        DECODER_MEMORY.with(|memory| {
+            let mut memory = memory.borrow_mut();
+            if memory.len() < self.decoder_memory_size {
+                memory.resize(self.decoder_memory_size, 0);
+            }
+            self.decompress_with_memory(input, output, &mut memory)
+        })
+    }
+    /// [`Self::decompress`] using `memory` as the decoder memory, which Oodle allocates itself
+    /// if it is smaller than [`Self::decoder_memory_size`]
+    pub fn decompress_with_memory(
+        &self,
+        input: &[u8],
+        output: &mut [u8],
+        memory: &mut [u8],
+    ) -> isize {
+        let (memory, memory_size) = match memory.len() {
+            len if len > 0 && len >= self.decoder_memory_size => (memory.as_mut_ptr(), len),
+            _ => (std::ptr::null_mut(), 0),
+        };
         unsafe {
             (self.decompress)(
                 input.as_ptr(),
@@ -251,12 +362,17 @@ impl Oodle {
                 0,
                 0,
                 0,
-                std::ptr::null_mut(),
-                0,
+                memory,
+                memory_size,
                 3,
             )
         }
     }
+    /// Bytes of decoder memory needed to decompress blocks of any compressor and size, 0 if the
+    /// library doesn't export `OodleLZDecoder_MemorySizeNeeded`
+    pub fn decoder_memory_size(&self) -> usize {
+        self.decoder_memory_size
+    }
     fn get_compressed_buffer_size_needed(
         &self,
         compressor: oodle_lz::Compressor,
@@ -266,7 +382,65 @@ impl Oodle {
     }
 }
 
-static OODLE: OnceLock<Option<Oodle>> = OnceLock::new();
+/// Value initialized on first use whose failed initializations aren't kept, so a later call
+/// can succeed once the cause, such as a network failure, is fixed. Initialization is retried
+/// no sooner than a backoff growing with each failed attempt, returning the error of the latest
+/// attempt in between.
+struct Retrying<T> {
+    value: OnceLock<T>,
+    /// Number of failed attempts and the time and error of the latest. Locked while
+    /// initializing so concurrent callers wait for a single attempt.
+    failures: Mutex<(u32, Option<(Instant, String)>)>,
+    backoff: fn(u32) -> Duration,
+}
+
+impl<T> Retrying<T> {
+    const fn new(backoff: fn(u32) -> Duration) -> Self {
+        Self {
+            value: OnceLock::new(),
+            failures: Mutex::new((0, None)),
+            backoff,
+        }
+    }
+
+    fn get_or_try_init(&self, init: impl FnOnce() -> Result<T>) -> Result<&T> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        // initialized by another thread while this one waited
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let (attempts, latest) = &mut *failures;
+        if let Some((at, error)) = latest {
+            let retry_in = (self.backoff)(*attempts).saturating_sub(at.elapsed());
+            if !retry_in.is_zero() {
+                return Err(Error::InitializationFailed {
+                    attempts: *attempts,
+                    error: error.clone(),
+                    retry_in,
+                });
+            }
+        }
+        match init() {
+            Ok(value) => Ok(self.value.get_or_init(|| value)),
+            Err(err) => {
+                *attempts += 1;
+                *latest = Some((Instant::now(), err.to_string()));
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Time to wait after `attempts` failed attempts to load Oodle: a second, doubling with each
+/// further failure up to a minute
+fn backoff(attempts: u32) -> Duration {
+    Duration::from_secs(1 << attempts.saturating_sub(1).min(6)).min(Duration::from_secs(60))
+}
+
+static OODLE: Retrying<Oodle> = Retrying::new(backoff);
 
 fn load_oodle() -> Result<Oodle> {
     let path = fetch_oodle()?;
@@ -276,29 +450,76 @@ fn load_oodle() -> Result<Oodle> {
     }
 }
 
+/// The Oodle library, downloaded next to the executable if it isn't there yet and loaded on
+/// first use. A failed load is retried by later calls, see [`Error::InitializationFailed`].
 pub fn oodle() -> Result<&'static Oodle> {
-    let mut result = None;
-    let oodle = OODLE.get_or_init(|| match load_oodle() {
-        Err(err) => {
-            result = Some(Err(err));
-            None
-        }
-        Ok(oodle) => Some(oodle),
-    });
-    match (result, oodle) {
-        // oodle initialized so return
-        (_, Some(oodle)) => Ok(oodle),
-        // error during initialization
-        (Some(result), _) => result?,
-        // no error because initialization was tried and failed before
-        _ => Err(Error::InitializationFailed),
-    }
+    OODLE.get_or_try_init(load_oodle)
+}
+
+/// Downloads and loads the Oodle library now rather than on its first use, so tools can report
+/// the download up front instead of stalling in the middle of their work
+pub fn preload() -> Result<()> {
+    oodle().map(|_| ())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_retrying() {
+        let retrying = Retrying::<u32>::new(|_| Duration::ZERO);
+        let failed = || Err(Error::Io(std::io::ErrorKind::NotFound.into()));
+        assert!(matches!(
+            retrying.get_or_try_init(failed),
+            Err(Error::Io(_))
+        ));
+        assert!(matches!(
+            retrying.get_or_try_init(failed),
+            Err(Error::Io(_))
+        ));
+        assert_eq!(*retrying.get_or_try_init(|| Ok(1)).unwrap(), 1);
+        // only the successful initialization is kept
+        assert_eq!(*retrying.get_or_try_init(|| Ok(2)).unwrap(), 1);
+        assert_eq!(*retrying.get_or_try_init(failed).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_retrying_backoff() {
+        let retrying = Retrying::<u32>::new(|_| Duration::from_secs(60));
+        let failed = || Err(Error::Io(std::io::ErrorKind::NotFound.into()));
+        assert!(matches!(
+            retrying.get_or_try_init(failed),
+            Err(Error::Io(_))
+        ));
+        // not retried until the backoff elapsed, reporting the latest error
+        let err = retrying.get_or_try_init(|| panic!("retried")).unwrap_err();
+        match err {
+            Error::InitializationFailed {
+                attempts,
+                error,
+                retry_in,
+            } => {
+                assert_eq!(attempts, 1);
+                assert_eq!(
+                    error,
+                    Error::Io(std::io::ErrorKind::NotFound.into()).to_string()
+                );
+                assert!(retry_in > Duration::from_secs(50), "{retry_in:?}");
+            }
+            err => panic!("{err}"),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(2), Duration::from_secs(2));
+        assert_eq!(backoff(4), Duration::from_secs(8));
+        assert_eq!(backoff(7), Duration::from_secs(60));
+        assert_eq!(backoff(u32::MAX), Duration::from_secs(60));
+    }
+
     #[test]
     fn test_oodle() {
         let oodle = oodle().unwrap();
@@ -319,5 +540,53 @@ mod test {
         oodle.decompress(&buffer, &mut uncomp);
 
         assert_eq!(data[..], uncomp[..]);
+
+        // with decoder memory of the caller, and without if it is too small
+        assert!(oodle.decoder_memory_size() > 0);
+        for size in [oodle.decoder_memory_size(), 1] {
+            let mut memory = vec![0; size];
+            let mut uncomp = vec![0; data.len()];
+            let len = oodle.decompress_with_memory(&buffer, &mut uncomp, &mut memory);
+            assert_eq!(len, data.len() as isize);
+            assert_eq!(data[..], uncomp[..]);
+        }
+    }
+
+    #[test]
+    fn test_oodle_chunked() {
+        let oodle = oodle().unwrap();
+
+        let data = (0..1_000_000u32)
+            .map(|i| ((i % 251) ^ (i >> 10)) as u8)
+            .collect::<Vec<_>>();
+        let chunk_size = 262144;
+
+        let chunks = oodle
+            .compress_chunked(
+                &data,
+                chunk_size,
+                Compressor::Mermaid,
+                CompressionLevel::Fast,
+            )
+            .unwrap();
+        assert_eq!(chunks.len(), 4);
+
+        // every chunk is a stream of its own
+        for (chunk, expected) in chunks.iter().zip(data.chunks(chunk_size)) {
+            let mut uncomp = vec![0; expected.len()];
+            oodle.decompress(chunk, &mut uncomp);
+            assert_eq!(uncomp, expected);
+        }
+
+        let mut uncomp = vec![0; data.len()];
+        oodle
+            .decompress_chunked(&chunks, chunk_size, &mut uncomp)
+            .unwrap();
+        assert_eq!(data, uncomp);
+
+        let mut short = vec![0; data.len() - chunk_size];
+        assert!(oodle
+            .decompress_chunked(&chunks, chunk_size, &mut short)
+            .is_err());
     }
 }
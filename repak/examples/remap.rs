@@ -0,0 +1,37 @@
+//! Serves the entries of a pak under a different root without rewriting it, as a mod loader
+//! would for a pak authored for another game directory.
+//!
+//! ```sh
+//! cargo run --example remap -- <pak> <from> <to> [path]
+//! ```
+//!
+//! Lists the remapped paths, or writes the entry at the remapped `path` to stdout.
+
+use std::fs::File;
+use std::io::{BufReader, Write};
+
+fn main() -> Result<(), repak::Error> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let [pak_path, from, to, rest @ ..] = args.as_slice() else {
+        eprintln!("usage: remap <pak> <from> <to> [path]");
+        std::process::exit(2);
+    };
+
+    let mut reader = BufReader::new(File::open(pak_path)?);
+    let pak = repak::PakBuilder::new().reader(&mut reader)?;
+    let view = pak.with_path_remap(vec![(from.clone(), to.clone())])?;
+
+    match rest {
+        [] => {
+            for path in view.files() {
+                println!("{path}");
+            }
+        }
+        [path, ..] => {
+            let mut stdout = std::io::stdout().lock();
+            view.read_file(path, &mut reader, &mut stdout)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use std::io::Write;
 
 use crate::{
-    entry::{Block, Entry},
-    Compression, Error, Hash, Version, VersionMajor,
+    entry::{Block, Entry, UNCOMPRESSED_BLOCK_SIZE},
+    Compression, CompressionSlot, EntryTransform, Error, Hash, Version, VersionMajor,
 };
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -21,13 +21,18 @@ pub(crate) struct PartialBlock {
 }
 pub(crate) enum PartialEntryData<D> {
     Slice(D),
+    /// Uncompressed data changed by an [`EntryTransform`]
+    Transformed(Vec<u8>),
     Blocks(Vec<PartialBlock>),
 }
 
-#[cfg(feature = "compression")]
-fn get_compression_slot(
+/// Slot of `compression`, which is added to the first empty slot if no slot names it yet.
+/// Slots holding a name repak doesn't know, as listed by `compression_names`, aren't empty
+/// as entries may use them.
+pub(crate) fn get_compression_slot(
     version: Version,
     compression_slots: &mut Vec<Option<Compression>>,
+    compression_names: &[[u8; 32]],
     compression: Compression,
 ) -> Result<u32> {
     let slot = compression_slots
@@ -44,11 +49,17 @@ fn get_compression_slot(
             )));
         }
 
+        // slots naming methods repak doesn't know are kept for the entries using them
+        let unknown = |i: usize| {
+            compression_names.get(i).is_some_and(|name| {
+                matches!(CompressionSlot::parse(name), CompressionSlot::Unknown(_))
+            })
+        };
         // find empty slot
         if let Some((i, empty_slot)) = compression_slots
             .iter_mut()
             .enumerate()
-            .find(|(_, s)| s.is_none())
+            .find(|(i, s)| s.is_none() && !unknown(*i))
         {
             // empty found, set it to used compression type
             *empty_slot = Some(compression);
@@ -62,22 +73,43 @@ fn get_compression_slot(
 }
 
 impl<D: AsRef<[u8]>> PartialEntry<D> {
+    /// Compresses `data` the same way it would be when written to a pak, which allows
+    /// estimating the size of an entry without writing it. Unlike when writing, `compression`
+    /// is used even if the data doesn't shrink.
+    pub fn new(compression: Option<Compression>, data: D) -> Result<Self> {
+        #[cfg(not(feature = "compression"))]
+        if compression.is_some() {
+            return Err(Error::Compression);
+        }
+        build_partial_entry(compression.as_slice(), f64::INFINITY, data)
+    }
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+    /// Size of the data as stored, excluding the entry record
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+    pub fn uncompressed_size(&self) -> u64 {
+        self.uncompressed_size
+    }
     pub(crate) fn build_entry(
         &self,
         version: Version,
         #[allow(unused)] compression_slots: &mut Vec<Option<Compression>>,
+        #[allow(unused)] compression_names: &[[u8; 32]],
         file_offset: u64,
     ) -> Result<Entry> {
         #[cfg(feature = "compression")]
         let compression_slot = self
             .compression
-            .map(|c| get_compression_slot(version, compression_slots, c))
+            .map(|c| get_compression_slot(version, compression_slots, compression_names, c))
             .transpose()?;
         #[cfg(not(feature = "compression"))]
         let compression_slot = None;
 
         let blocks = match &self.data {
-            PartialEntryData::Slice(_) => None,
+            PartialEntryData::Slice(_) | PartialEntryData::Transformed(_) => None,
             PartialEntryData::Blocks(blocks) => {
                 let entry_size =
                     Entry::get_serialized_size(version, compression_slot, blocks.len() as u32);
@@ -113,11 +145,48 @@ impl<D: AsRef<[u8]>> PartialEntry<D> {
             compression_block_size: self.compression_block_size,
         })
     }
+    /// Applies [`EntryTransform::encode`] to the data as it is stored and hashes it again
+    pub(crate) fn transform(&mut self, transform: &dyn EntryTransform, path: &str) {
+        use sha1::{Digest, Sha1};
+        let mut hasher = Sha1::new();
+        match &mut self.data {
+            PartialEntryData::Slice(data) => {
+                let mut data = data.as_ref().to_vec();
+                for (i, chunk) in data
+                    .chunks_mut(UNCOMPRESSED_BLOCK_SIZE as usize)
+                    .enumerate()
+                {
+                    transform.encode(path, 0, i, chunk);
+                }
+                hasher.update(&data);
+                self.data = PartialEntryData::Transformed(data);
+            }
+            PartialEntryData::Transformed(data) => {
+                for (i, chunk) in data
+                    .chunks_mut(UNCOMPRESSED_BLOCK_SIZE as usize)
+                    .enumerate()
+                {
+                    transform.encode(path, 0, i, chunk);
+                }
+                hasher.update(&data);
+            }
+            PartialEntryData::Blocks(blocks) => {
+                for (i, block) in blocks.iter_mut().enumerate() {
+                    transform.encode(path, 0, i, &mut block.data);
+                    hasher.update(&block.data);
+                }
+            }
+        }
+        self.hash = Hash(hasher.finalize().into());
+    }
     pub(crate) fn write_data<S: Write>(&self, stream: &mut S) -> Result<()> {
         match &self.data {
             PartialEntryData::Slice(data) => {
                 stream.write_all(data.as_ref())?;
             }
+            PartialEntryData::Transformed(data) => {
+                stream.write_all(data)?;
+            }
             PartialEntryData::Blocks(blocks) => {
                 for block in blocks {
                     stream.write_all(&block.data)?;
@@ -128,8 +197,22 @@ impl<D: AsRef<[u8]>> PartialEntry<D> {
     }
 }
 
+/// Ratio of the stored to the uncompressed size above which data is stored uncompressed,
+/// unless set with [`crate::PakBuilder::compression_threshold`]
+pub(crate) const DEFAULT_COMPRESSION_THRESHOLD: f64 = 0.95;
+
+/// Builds an entry from `data`, picking the compression method as follows:
+///
+/// - every method in `allowed_compression` is tried and the one with the smallest output is
+///   picked, the earliest one on ties
+/// - the data is stored uncompressed if even that output is larger than `threshold` times the
+///   uncompressed size, as compressing it would only slow down reading for little gain
+///
+/// Compressing with every allowed method multiplies the time spent compressing, so allowing a
+/// single method is fastest.
 pub(crate) fn build_partial_entry<D>(
     allowed_compression: &[Compression],
+    #[allow(unused)] threshold: f64,
     data: D,
 ) -> Result<PartialEntry<D>>
 where
@@ -139,52 +222,130 @@ where
     use sha1::{Digest, Sha1};
     let mut hasher = Sha1::new();
 
-    // TODO possibly select best compression based on some criteria instead of picking first
-    let compression = allowed_compression.first().cloned();
     let uncompressed_size = data.as_ref().len() as u64;
-    let compression_block_size;
 
-    let (data, compressed_size) = match compression {
-        #[cfg(not(feature = "compression"))]
-        Some(_) => {
-            unreachable!("should not be able to reach this point without compression feature")
-        }
-        #[cfg(feature = "compression")]
-        Some(compression) => {
-            // https://github.com/EpicGames/UnrealEngine/commit/3aad0ff7976be1073005dca2c1282af548b45d89
-            // Block size must fit into flags field or it may cause unreadable paks for earlier Unreal Engine versions
-            compression_block_size = 0x3e << 11; // max possible block size
-            let mut compressed_size = 0;
-            let mut blocks = vec![];
-            for chunk in data.as_ref().chunks(compression_block_size as usize) {
-                let data = compress(compression, chunk)?;
-                compressed_size += data.len() as u64;
-                hasher.update(&data);
-                blocks.push(PartialBlock {
-                    uncompressed_size: chunk.len(),
-                    data,
-                })
+    #[cfg(not(feature = "compression"))]
+    if !allowed_compression.is_empty() {
+        unreachable!("should not be able to reach this point without compression feature")
+    }
+    #[cfg(feature = "compression")]
+    {
+        let mut best: Option<(Compression, Vec<PartialBlock>, u64)> = None;
+        for &compression in allowed_compression {
+            let (blocks, compressed_size) = compress_blocks(compression, data.as_ref())?;
+            if !matches!(&best, Some((_, _, size)) if *size <= compressed_size) {
+                best = Some((compression, blocks, compressed_size));
             }
-
-            (PartialEntryData::Blocks(blocks), compressed_size)
         }
-        None => {
-            compression_block_size = 0;
-            hasher.update(data.as_ref());
-            (PartialEntryData::Slice(data), uncompressed_size)
+        if let Some((compression, blocks, compressed_size)) = best {
+            if compressed_size as f64 <= threshold * uncompressed_size as f64 {
+                for block in &blocks {
+                    hasher.update(&block.data);
+                }
+                return Ok(PartialEntry {
+                    compression: Some(compression),
+                    compressed_size,
+                    uncompressed_size,
+                    compression_block_size: compression_block_size(uncompressed_size)?,
+                    data: PartialEntryData::Blocks(blocks),
+                    hash: Hash(hasher.finalize().into()),
+                });
+            }
         }
-    };
+    }
 
+    hasher.update(data.as_ref());
     Ok(PartialEntry {
-        compression,
-        compressed_size,
+        compression: None,
+        compressed_size: uncompressed_size,
         uncompressed_size,
-        compression_block_size,
-        data,
+        compression_block_size: 0,
+        data: PartialEntryData::Slice(data),
         hash: Hash(hasher.finalize().into()),
     })
 }
 
+// https://github.com/EpicGames/UnrealEngine/commit/3aad0ff7976be1073005dca2c1282af548b45d89
+// Block size must fit into flags field or it may cause unreadable paks for earlier Unreal Engine versions
+#[cfg(feature = "compression")]
+const COMPRESSION_BLOCK_SIZE: u32 = 0x3e << 11; // max possible block size
+
+/// Most blocks an entry can have, as the encoded index of V10+ paks stores the block count in
+/// 16 bits
+#[cfg(feature = "compression")]
+const MAX_COMPRESSION_BLOCKS: u64 = 0xffff;
+
+/// Block size used to compress `size` bytes: [`COMPRESSION_BLOCK_SIZE`], unless that would take
+/// more than [`MAX_COMPRESSION_BLOCKS`] blocks (entries over about 8 GiB), in which case the
+/// smallest multiple of 2 KiB which fits is used
+#[cfg(feature = "compression")]
+fn compression_block_size(size: u64) -> Result<u32> {
+    if size <= MAX_COMPRESSION_BLOCKS * COMPRESSION_BLOCK_SIZE as u64 {
+        return Ok(COMPRESSION_BLOCK_SIZE);
+    }
+    let block_size = size
+        .div_ceil(MAX_COMPRESSION_BLOCKS)
+        .next_multiple_of(1 << 11);
+    crate::ext::checked_field(block_size, "compression block size")
+}
+
+/// Compresses `data` in blocks of [`compression_block_size`], returning them and their total
+/// size
+#[cfg(feature = "compression")]
+fn compress_blocks(compression: Compression, data: &[u8]) -> Result<(Vec<PartialBlock>, u64)> {
+    let block_size = compression_block_size(data.len() as u64)?;
+    // each block is an independent Oodle stream the engine decompresses on its own
+    #[cfg(feature = "oodle")]
+    if compression == Compression::Oodle {
+        let blocks = oodle_loader::oodle()?
+            .compress_chunked(
+                data,
+                block_size as usize,
+                oodle_loader::Compressor::Mermaid,
+                oodle_loader::CompressionLevel::Normal,
+            )?
+            .into_iter()
+            .zip(data.chunks(block_size as usize))
+            .map(|(data, chunk)| PartialBlock {
+                uncompressed_size: chunk.len(),
+                data,
+            })
+            .collect::<Vec<_>>();
+        let compressed_size = blocks.iter().map(|b| b.data.len() as u64).sum();
+        return Ok((blocks, compressed_size));
+    }
+    let mut compressed_size = 0;
+    let mut blocks = vec![];
+    // zero-filled blocks of placeholder files all compress the same, so only the first
+    // of each size is run through the compressor
+    let mut zero_block: Option<(usize, Vec<u8>)> = None;
+    for chunk in data.chunks(block_size as usize) {
+        let data = match (is_zero(chunk), &zero_block) {
+            (true, Some((size, data))) if *size == chunk.len() => data.clone(),
+            (true, _) => {
+                let data = compress(compression, chunk)?;
+                zero_block = Some((chunk.len(), data.clone()));
+                data
+            }
+            (false, _) => compress(compression, chunk)?,
+        };
+        compressed_size += data.len() as u64;
+        blocks.push(PartialBlock {
+            uncompressed_size: chunk.len(),
+            data,
+        })
+    }
+    Ok((blocks, compressed_size))
+}
+
+/// Whether `data` only contains zeros. Folds fixed-size runs rather than stopping at the first
+/// non-zero byte so the check vectorizes.
+#[cfg(feature = "compression")]
+fn is_zero(data: &[u8]) -> bool {
+    data.chunks(4096)
+        .all(|run| run.iter().fold(0, |acc, &b| acc | b) == 0)
+}
+
 #[cfg(feature = "compression")]
 fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>> {
     use std::io::Write;
@@ -220,3 +381,73 @@ fn compress(compression: Compression, data: &[u8]) -> Result<Vec<u8>> {
 
     Ok(compressed)
 }
+
+#[cfg(all(test, feature = "compression"))]
+mod test {
+    use super::{
+        build_partial_entry, compress, compression_block_size, is_zero, PartialEntryData,
+        COMPRESSION_BLOCK_SIZE, MAX_COMPRESSION_BLOCKS,
+    };
+    use crate::Compression;
+
+    #[test]
+    fn test_is_zero() {
+        assert!(is_zero(&[]));
+        assert!(is_zero(&[0; 10000]));
+        let mut data = vec![0; 10000];
+        data[9999] = 1;
+        assert!(!is_zero(&data));
+    }
+
+    #[test]
+    fn test_compression_block_size() {
+        let default = COMPRESSION_BLOCK_SIZE as u64;
+        for size in [0, 1, default, 5 << 30, MAX_COMPRESSION_BLOCKS * default] {
+            assert_eq!(
+                compression_block_size(size).unwrap(),
+                COMPRESSION_BLOCK_SIZE
+            );
+        }
+        for size in [MAX_COMPRESSION_BLOCKS * default + 1, 20 << 30, 1 << 47] {
+            let block_size = compression_block_size(size).unwrap() as u64;
+            assert!(block_size > default && block_size.is_multiple_of(1 << 11));
+            assert!(size.div_ceil(block_size) <= MAX_COMPRESSION_BLOCKS);
+        }
+        assert!(matches!(
+            compression_block_size(1 << 48),
+            Err(crate::Error::EntryFieldOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_zero_blocks_compress_like_any_other() {
+        let zeros = include_bytes!("../tests/pack/root/zeros.bin").to_vec();
+        let block_size = 0x3e << 11;
+        let mut mixed = vec![0; block_size * 3 + 100];
+        mixed[block_size + 7] = 1;
+        let large = vec![0; block_size * 2 + 1];
+
+        for compression in [
+            Compression::Zlib,
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::LZ4,
+        ] {
+            for data in [&zeros, &mixed, &large] {
+                let entry = build_partial_entry(&[compression], f64::INFINITY, data).unwrap();
+                let PartialEntryData::Blocks(blocks) = entry.data else {
+                    panic!("compressed entry without blocks");
+                };
+                let expected = data
+                    .chunks(block_size)
+                    .map(|chunk| compress(compression, chunk).unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    blocks.into_iter().map(|b| b.data).collect::<Vec<_>>(),
+                    expected,
+                    "{compression:?}"
+                );
+            }
+        }
+    }
+}
@@ -1,8 +1,13 @@
-use crate::{data::build_partial_entry, Error, Hash};
+use crate::{data::build_partial_entry, EntryTransform, Error, Hash};
 
-use super::{ext::BoolExt, ext::ReadExt, Compression, Version, VersionMajor};
+use super::{
+    ext::checked_field, ext::checked_usize, ext::BoolExt, ext::ReadExt, Compression, Version,
+    VersionMajor,
+};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub(crate) enum EntryLocation {
@@ -31,11 +36,204 @@ impl Block {
     }
 }
 
+/// How [`Entry::read_file`] reads an entry, set up by [`crate::PakReader`]
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ReadOptions<'a> {
+    /// Threads decompressing the blocks of the entry, 0 and 1 decompress on the calling thread
+    pub threads: usize,
+    /// Stops the read between blocks once set
+    pub cancel: Option<&'a AtomicBool>,
+    /// Check the stored hash and the number of bytes written
+    pub verify: bool,
+    /// Format of Zlib blocks detected for the pak so far
+    pub zlib_format: Option<&'a OnceLock<ZlibFormat>>,
+    /// Transform decoding each block, with the path of the entry
+    pub transform: Option<(&'a dyn EntryTransform, &'a str)>,
+    /// Bytes of encrypted entries to decrypt, all of them if `None`. See
+    /// [`crate::DecryptLimit`].
+    pub decrypt_limit: Option<usize>,
+}
+
+/// Stream format of the blocks of entries compressed with [`Compression::Zlib`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZlibFormat {
+    /// Deflate stream wrapped in a zlib header and checksum, as written by UnrealPak
+    Zlib,
+    /// Bare deflate stream, written by the UnrealPak of some older mobile ports
+    RawDeflate,
+}
+
+impl std::fmt::Display for ZlibFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ZlibFormat::Zlib => "zlib",
+            ZlibFormat::RawDeflate => "raw deflate",
+        })
+    }
+}
+
+/// Writer counting the bytes passed through to the output of [`Entry::read_file`]
+struct CountingWriter<'a, W> {
+    inner: &'a mut W,
+    written: u64,
+}
+
+impl<W: io::Write> io::Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts entry data in place. A partial final block was never encrypted so only whole
+/// blocks are decrypted.
+#[cfg(feature = "encryption")]
+fn decrypt(key: &super::Key, data: &mut [u8]) -> Result<(), super::Error> {
+    let super::Key::Some(key) = key else {
+        return Err(super::Error::Encrypted);
+    };
+    use aes::cipher::BlockDecrypt;
+    for block in data.chunks_exact_mut(16) {
+        key.decrypt_block(aes::Block::from_mut_slice(block))
+    }
+    Ok(())
+}
+
 fn align(offset: u64) -> u64 {
     // add alignment (aes block size: 16) then zero out alignment bits
     (offset + 15) & !15
 }
 
+/// Compression block size assumed when bounding the block count of an entry whose block size
+/// is unknown, 0 or larger than the entry, well below the 64 KiB UnrealPak uses by default
+const MIN_COMPRESSION_BLOCK_SIZE: u64 = 0x400;
+
+/// Checks that an entry of `uncompressed` bytes can have `count` compression blocks of
+/// `block_size`, allowing one block of slack either way. Garbage in the index could otherwise
+/// declare thousands of blocks for every entry, which are allocated before any data is read,
+/// while too few blocks can't hold the data. Only the upper bound is checked if the block size
+/// is unknown, 0 or larger than the entry.
+fn check_block_count(count: u64, uncompressed: u64, block_size: u32) -> Result<(), Error> {
+    let (block_size, known) = match block_size as u64 {
+        size if size == 0 || size > uncompressed => (MIN_COMPRESSION_BLOCK_SIZE, false),
+        size => (size, true),
+    };
+    let too_many = count > uncompressed.div_ceil(block_size) + 1;
+    let too_few = known && (count + 1).saturating_mul(block_size) < uncompressed;
+    match too_many || too_few {
+        false => Ok(()),
+        true => Err(Error::ImplausibleBlockCount {
+            path: None,
+            count,
+            uncompressed,
+        }),
+    }
+}
+
+/// Decompresses a single compression block which decompresses to `uncompressed` bytes.
+/// `zlib_format` remembers whether the Zlib blocks of the pak are raw deflate streams once a
+/// block was decompressed.
+#[cfg(feature = "compression")]
+fn decompress_block(
+    compression: Compression,
+    data: &[u8],
+    uncompressed: usize,
+    zlib_format: Option<&OnceLock<ZlibFormat>>,
+) -> Result<Vec<u8>, super::Error> {
+    let mut decompressed = Vec::with_capacity(uncompressed);
+    decompress_block_into(
+        compression,
+        data,
+        uncompressed,
+        zlib_format,
+        &mut decompressed,
+    )?;
+    Ok(decompressed)
+}
+
+/// [`decompress_block`] into `decompressed`, replacing its contents so its allocation can be
+/// reused from block to block
+#[cfg(feature = "compression")]
+fn decompress_block_into(
+    compression: Compression,
+    data: &[u8],
+    uncompressed: usize,
+    zlib_format: Option<&OnceLock<ZlibFormat>>,
+    decompressed: &mut Vec<u8>,
+) -> Result<(), super::Error> {
+    use io::Read;
+    decompressed.clear();
+    decompressed.reserve(uncompressed);
+    match compression {
+        Compression::Zlib => match zlib_format.and_then(OnceLock::get) {
+            Some(ZlibFormat::Zlib) => {
+                flate2::read::ZlibDecoder::new(data).read_to_end(decompressed)?;
+            }
+            Some(ZlibFormat::RawDeflate) => {
+                flate2::read::DeflateDecoder::new(data).read_to_end(decompressed)?;
+            }
+            None => {
+                let format = match flate2::read::ZlibDecoder::new(data).read_to_end(decompressed) {
+                    Ok(_) => ZlibFormat::Zlib,
+                    // paks of some platforms label raw deflate streams as Zlib. Garbage such as
+                    // data decrypted with the wrong key must not be mistaken for one, so the
+                    // stream has to decompress to exactly the expected size.
+                    Err(err) if !is_zlib_header(data) => {
+                        decompressed.clear();
+                        match flate2::read::DeflateDecoder::new(data).read_to_end(decompressed) {
+                            Ok(len) if len == uncompressed => ZlibFormat::RawDeflate,
+                            _ => return Err(err.into()),
+                        }
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                if let Some(detected) = zlib_format {
+                    let _ = detected.set(format);
+                }
+            }
+        },
+        Compression::Gzip => {
+            flate2::read::GzDecoder::new(data).read_to_end(decompressed)?;
+        }
+        Compression::Zstd => {
+            zstd::stream::read::Decoder::new(data)?.read_to_end(decompressed)?;
+        }
+        Compression::LZ4 => {
+            decompressed.resize(uncompressed, 0);
+            let len = lz4_flex::block::decompress_into(data, decompressed)
+                .map_err(|_| Error::DecompressionFailed(Compression::LZ4))?;
+            decompressed.truncate(len);
+        }
+        #[cfg(feature = "oodle")]
+        Compression::Oodle => {
+            decompressed.resize(uncompressed, 0);
+            if oodle_loader::oodle()?.decompress(data, decompressed) == 0 {
+                return Err(Error::DecompressionFailed(Compression::Oodle));
+            }
+        }
+        #[cfg(not(feature = "oodle"))]
+        Compression::Oodle => return Err(super::Error::Oodle),
+    }
+    Ok(())
+}
+
+/// Whether `data` starts with a valid zlib header: deflate with a window of at most 32 KiB
+/// and a check value which makes the first two bytes a multiple of 31
+#[cfg(feature = "compression")]
+fn is_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            cmf & 0x0f == 8 && cmf >> 4 <= 7 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+        }
+        _ => false,
+    }
+}
+
 fn compression_index_size(version: Version) -> CompressionIndexSize {
     match version {
         Version::V8A => CompressionIndexSize::U8,
@@ -48,7 +246,65 @@ enum CompressionIndexSize {
     U32,
 }
 
-#[derive(Debug)]
+/// Metadata of a single entry as stored in the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// Offset of the entry header in the pak
+    pub offset: u64,
+    /// Size of the stored data, excluding encryption padding
+    pub compressed: u64,
+    /// Size of the data after decompression
+    pub uncompressed: u64,
+    /// Compression method or `None` if stored uncompressed
+    pub compression: Option<Compression>,
+    pub encrypted: bool,
+    /// Offset of the encoded entry in the index of a V10+ pak which was read. Identifies the
+    /// entry within this pak, even among paths which only differ in case, and is kept by
+    /// rewrites of the index through [`crate::PakReader::into_pakwriter`] which don't replace
+    /// the entry. Indexes written from scratch, such as by
+    /// [`crate::PakReader::write_compacted`], encode entries in path order instead. Always
+    /// `None` for entries returned by [`crate::PakWriter`], as the index isn't written yet.
+    pub encoded_offset: Option<u32>,
+}
+
+/// Byte ranges of a pak file an entry occupies, see [`crate::PakReader::entry_extents`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryExtents {
+    /// Record preceding the data
+    pub header: std::ops::Range<u64>,
+    /// Compression blocks as stored, including the encryption padding each block of encrypted
+    /// entries has of its own. A single block with all of the data for entries stored without
+    /// compression blocks.
+    pub blocks: Vec<std::ops::Range<u64>>,
+    /// From the start of the record to the end of the data as stored, the same as
+    /// [`crate::PakReader::entry_range`]
+    pub span: std::ops::Range<u64>,
+}
+
+/// A compression block of an entry and the size it decompresses to, see
+/// [`crate::PakReader::probe_blocks`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockProbe {
+    /// Bytes of the pak the block is stored in, including encryption padding
+    pub range: std::ops::Range<u64>,
+    /// Size of the block as stored, excluding encryption padding
+    pub compressed: u64,
+    /// Size the block should decompress to: the compression block size of the entry for all
+    /// blocks but the last, which holds the rest
+    pub expected: u64,
+    /// Size the block decompresses to, or why it doesn't. Oodle and LZ4 blocks larger than
+    /// expected fail to decompress rather than reporting their size.
+    pub uncompressed: Result<u64, String>,
+}
+
+impl BlockProbe {
+    /// Whether the block decompresses to the expected size
+    pub fn is_ok(&self) -> bool {
+        self.uncompressed == Ok(self.expected)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Entry {
     pub offset: u64,
     pub compressed: u64,
@@ -68,6 +324,59 @@ impl Entry {
     pub fn is_deleted(&self) -> bool {
         0 != (self.flags >> 1) & 1
     }
+    pub fn info(&self, compression: &[Option<Compression>]) -> EntryInfo {
+        EntryInfo {
+            offset: self.offset,
+            compressed: self.compressed,
+            uncompressed: self.uncompressed,
+            compression: self
+                .compression_slot
+                .and_then(|c| compression.get(c as usize).copied().flatten()),
+            encrypted: self.is_encrypted(),
+            encoded_offset: None,
+        }
+    }
+    /// Compression method of the data, failing for slots which name no supported method rather
+    /// than treating the data as stored
+    fn compression_method(
+        &self,
+        compression: &[Option<Compression>],
+    ) -> Result<Option<Compression>, Error> {
+        self.compression_slot
+            .map(|c| {
+                compression
+                    .get(c as usize)
+                    .copied()
+                    .flatten()
+                    .ok_or(Error::UnknownCompressionSlot(c))
+            })
+            .transpose()
+    }
+    /// Size the compression blocks but the last decompress to, or all of the data for entries
+    /// with a single block
+    pub(crate) fn block_size(&self) -> u64 {
+        match &self.blocks {
+            Some(blocks) if blocks.len() != 1 => self
+                .derived_block_size()
+                .unwrap_or(self.compression_block_size as u64),
+            _ => self.uncompressed,
+        }
+    }
+    /// Block size derived from the block count for compressed entries of several blocks whose
+    /// stored block size is 0 or larger than the entry, as written by some old UnrealPak forks
+    pub(crate) fn derived_block_size(&self) -> Option<u64> {
+        let size = self.compression_block_size as u64;
+        match &self.blocks {
+            Some(blocks)
+                if self.compression_slot.is_some()
+                    && blocks.len() > 1
+                    && (size == 0 || size > self.uncompressed) =>
+            {
+                Some(self.uncompressed.div_ceil(blocks.len() as u64))
+            }
+            _ => None,
+        }
+    }
     pub fn get_serialized_size(
         version: super::Version,
         compression: Option<u32>,
@@ -98,16 +407,29 @@ impl Entry {
         size
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn write_file<W: io::Write + io::Seek>(
         writer: &mut W,
         version: Version,
         compression_slots: &mut Vec<Option<Compression>>,
+        compression_names: &[[u8; 32]],
         allowed_compression: &[Compression],
+        compression_threshold: f64,
         data: &[u8],
+        transform: Option<(&dyn EntryTransform, &str)>,
     ) -> Result<Self, Error> {
-        let partial_entry = build_partial_entry(allowed_compression, data)?;
+        let mut partial_entry =
+            build_partial_entry(allowed_compression, compression_threshold, data)?;
+        if let Some((transform, path)) = transform {
+            partial_entry.transform(transform, path);
+        }
         let stream_position = writer.stream_position()?;
-        let entry = partial_entry.build_entry(version, compression_slots, stream_position)?;
+        let entry = partial_entry.build_entry(
+            version,
+            compression_slots,
+            compression_names,
+            stream_position,
+        )?;
         entry.write(writer, version, crate::entry::EntryLocation::Data)?;
         partial_entry.write_data(writer)?;
         Ok(entry)
@@ -130,14 +452,22 @@ impl Entry {
         };
         let timestamp = (ver == VersionMajor::Initial).then_try(|| reader.read_u64::<LE>())?;
         let hash = Some(Hash(reader.read_guid()?));
+        // the block size follows the blocks, so they are checked again once it's known
         let blocks = (ver >= VersionMajor::CompressionEncryption && compression.is_some())
-            .then_try(|| reader.read_array(Block::read))?;
+            .then_try(|| {
+                let count = reader.read_u32::<LE>()?;
+                check_block_count(count as u64, uncompressed, 0)?;
+                reader.read_array_len(count as usize, Block::read)
+            })?;
         let flags = (ver >= VersionMajor::CompressionEncryption)
             .then_try(|| reader.read_u8())?
             .unwrap_or(0);
         let compression_block_size = (ver >= VersionMajor::CompressionEncryption)
             .then_try(|| reader.read_u32::<LE>())?
             .unwrap_or(0);
+        if let Some(blocks) = &blocks {
+            check_block_count(blocks.len() as u64, uncompressed, compression_block_size)?;
+        }
         Ok(Self {
             offset,
             compressed,
@@ -165,21 +495,25 @@ impl Entry {
         writer.write_u64::<LE>(self.uncompressed)?;
         let compression = self.compression_slot.map_or(0, |n| n + 1);
         match compression_index_size(version) {
-            CompressionIndexSize::U8 => writer.write_u8(compression.try_into().unwrap())?,
+            CompressionIndexSize::U8 => {
+                writer.write_u8(checked_field(compression.into(), "compression slot")?)?
+            }
             CompressionIndexSize::U32 => writer.write_u32::<LE>(compression)?,
         }
 
         if version.version_major() == VersionMajor::Initial {
             writer.write_u64::<LE>(self.timestamp.unwrap_or_default())?;
         }
-        if let Some(hash) = self.hash {
-            writer.write_all(&hash.0)?;
-        } else {
-            panic!("hash missing");
-        }
+        // only entries decoded from a V10+ encoded index lack a hash, and those are never
+        // written as full records
+        let hash = self.hash.ok_or(super::Error::MissingHash)?;
+        writer.write_all(&hash.0)?;
         if version.version_major() >= VersionMajor::CompressionEncryption {
             if let Some(blocks) = &self.blocks {
-                writer.write_u32::<LE>(blocks.len() as u32)?;
+                writer.write_u32::<LE>(checked_field(
+                    blocks.len() as u64,
+                    "compression block count",
+                )?)?;
                 for block in blocks {
                     block.write(writer)?;
                 }
@@ -226,6 +560,12 @@ impl Entry {
             _ => var_int(29)?,
         };
 
+        // uncompressed entries have no blocks whatever block size they store
+        check_block_count(
+            compression_block_count as u64,
+            uncompressed,
+            compression.map_or(0, |_| compression_block_size),
+        )?;
         let offset_base = Entry::get_serialized_size(version, compression, compression_block_count);
 
         let blocks = if compression_block_count == 1 && !encrypted {
@@ -274,19 +614,22 @@ impl Entry {
             compression_block_size = 0x3f;
         }
         let compression_blocks_count = if self.compression_slot.is_some() {
-            self.blocks.as_ref().unwrap().len() as u32
+            self.blocks.as_ref().unwrap().len() as u64
         } else {
             0
         };
+        // the flags store the block count in 16 bits
+        if compression_blocks_count > 0xffff {
+            return Err(super::Error::EntryFieldOverflow {
+                field: "compression block count",
+                value: compression_blocks_count,
+            });
+        }
+        let compression_blocks_count = compression_blocks_count as u32;
         let is_size_32_bit_safe = self.compressed <= u32::MAX as u64;
         let is_uncompressed_size_32_bit_safe = self.uncompressed <= u32::MAX as u64;
         let is_offset_32_bit_safe = self.offset <= u32::MAX as u64;
 
-        assert!(
-            compression_blocks_count < 0x10_000,
-            "compression blocks count fits in 16 bits"
-        );
-
         let flags = (compression_block_size)
             | (compression_blocks_count << 6)
             | ((self.is_encrypted() as u32) << 22)
@@ -325,7 +668,7 @@ impl Entry {
             if blocks.len() > 1 || self.is_encrypted() {
                 for b in blocks {
                     let block_size = b.end - b.start;
-                    writer.write_u32::<LE>(block_size.try_into().unwrap())?;
+                    writer.write_u32::<LE>(checked_field(block_size, "compression block size")?)?;
                 }
             }
         }
@@ -333,119 +676,628 @@ impl Entry {
         Ok(())
     }
 
+    /// Reads the record preceding the data, returning it along with the offset and stored
+    /// length of the data, including any encryption padding
+    pub(crate) fn read_header<R: io::Read + io::Seek>(
+        &self,
+        reader: &mut R,
+        version: Version,
+        data_end: u64,
+    ) -> Result<(Entry, u64, u64), super::Error> {
+        reader.seek(io::SeekFrom::Start(self.offset))?;
+        let header = Entry::read(reader, version)?;
+        let data_offset = reader.stream_position()?;
+        // encrypted data is padded to the AES block size, but some packers omit the padding of
+        // the last entry so the aligned length would run into whatever follows the data region
+        let len = match self.is_encrypted() {
+            true => align(self.compressed)
+                .min(data_end.saturating_sub(data_offset))
+                .max(self.compressed),
+            false => self.compressed,
+        };
+        Ok((header, data_offset, len))
+    }
+
+    /// Reads the record preceding the data and the data as stored, including any encryption
+    /// padding, returning them along with the offset of the data
+    fn read_stored<R: io::Read + io::Seek>(
+        &self,
+        reader: &mut R,
+        version: Version,
+        data_end: u64,
+    ) -> Result<(Entry, u64, Vec<u8>), super::Error> {
+        let (header, data_offset, len) = self.read_header(reader, version, data_end)?;
+        let data = reader.read_len(checked_usize(len, "entry data")?)?;
+        Ok((header, data_offset, data))
+    }
+
+    /// Byte range covering the record preceding the data and the data as stored
+    pub(crate) fn stored_range<R: io::Read + io::Seek>(
+        &self,
+        reader: &mut R,
+        version: Version,
+        data_end: u64,
+    ) -> Result<std::ops::Range<u64>, super::Error> {
+        let (_, data_offset, len) = self.read_header(reader, version, data_end)?;
+        Ok(self.offset..data_offset + len)
+    }
+
+    /// Byte ranges in the pak of the compression blocks, excluding encryption padding, or of
+    /// all of the data for entries without blocks. The blocks are located relative to the
+    /// record from V5 and by absolute offset before.
+    fn block_ranges(&self, version: Version, data_offset: u64) -> Vec<std::ops::Range<u64>> {
+        match &self.blocks {
+            Some(blocks) => {
+                let base = match version.version_major() >= VersionMajor::RelativeChunkOffsets {
+                    true => self.offset,
+                    false => 0,
+                };
+                blocks
+                    .iter()
+                    .map(|block| base + block.start..base + block.end)
+                    .collect()
+            }
+            #[allow(clippy::single_range_in_vec_init)]
+            None => vec![data_offset..data_offset + self.compressed],
+        }
+    }
+
+    /// `block` extended by the encryption padding each block of encrypted entries has of its
+    /// own, not running past `stored_end`, the end of the data as stored
+    fn padded_block(&self, block: &std::ops::Range<u64>, stored_end: u64) -> std::ops::Range<u64> {
+        match self.is_encrypted() {
+            true => {
+                let end = (block.start + align(block.end - block.start)).min(stored_end);
+                block.start..end.max(block.end)
+            }
+            false => block.clone(),
+        }
+    }
+
+    /// Byte ranges of the record and the blocks of this entry in the pak, see
+    /// [`crate::PakReader::entry_extents`]
+    pub(crate) fn extents<R: io::Read + io::Seek>(
+        &self,
+        reader: &mut R,
+        version: Version,
+        data_end: u64,
+    ) -> Result<EntryExtents, super::Error> {
+        let (_, data_offset, len) = self.read_header(reader, version, data_end)?;
+        let stored_end = data_offset + len;
+        Ok(EntryExtents {
+            header: self.offset..data_offset,
+            blocks: self
+                .block_ranges(version, data_offset)
+                .iter()
+                .map(|block| self.padded_block(block, stored_end))
+                .collect(),
+            span: self.offset..stored_end,
+        })
+    }
+
+    /// Byte range of the data as stored, including any encryption padding
+    pub(crate) fn data_range<R: io::Read + io::Seek>(
+        &self,
+        reader: &mut R,
+        version: Version,
+        data_end: u64,
+    ) -> Result<std::ops::Range<u64>, super::Error> {
+        let (_, data_offset, len) = self.read_header(reader, version, data_end)?;
+        Ok(data_offset..data_offset + len)
+    }
+
+    /// Streams the data as stored, including any encryption padding, to `writer` and returns
+    /// its length
+    pub(crate) fn write_stored<R: io::Read + io::Seek, W: io::Write>(
+        &self,
+        reader: &mut R,
+        version: Version,
+        data_end: u64,
+        writer: &mut W,
+    ) -> Result<u64, super::Error> {
+        let (_, _, len) = self.read_header(reader, version, data_end)?;
+        let copied = io::copy(&mut io::Read::take(&mut *reader, len), writer)?;
+        if copied != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(len)
+    }
+
+    /// Copies the stored data of this entry from `reader` to the current position of `writer`
+    /// without recompressing it and returns the entry describing the copy.
+    ///
+    /// Encrypted data is kept as is if `decrypt_key` is `None`, otherwise it is decrypted and
+    /// the padding of each block removed. Only the first `decrypt_limit` bytes are decrypted if
+    /// set, see [`crate::DecryptLimit`].
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables, unused_mut))]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn copy<R: io::Read + io::Seek, W: io::Write + io::Seek>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+        version: Version,
+        data_end: u64,
+        compression_slot: Option<u32>,
+        decrypt_key: Option<&super::Key>,
+        decrypt_limit: Option<usize>,
+    ) -> Result<Entry, super::Error> {
+        let (header, data_offset, mut data) = self.read_stored(reader, version, data_end)?;
+        let offset = writer.stream_position()?;
+        let relative = version.version_major() >= VersionMajor::RelativeChunkOffsets;
+        // start of the data in the coordinates used by the blocks
+        let (old_base, new_base) = match relative {
+            true => (data_offset - self.offset, data_offset - self.offset),
+            false => (data_offset, offset + (data_offset - self.offset)),
+        };
+
+        let mut entry = Entry {
+            offset,
+            compressed: self.compressed,
+            uncompressed: self.uncompressed,
+            compression_slot,
+            timestamp: header.timestamp,
+            hash: header.hash,
+            blocks: self.blocks.as_ref().map(|blocks| {
+                blocks
+                    .iter()
+                    .map(|b| Block {
+                        start: b.start - old_base + new_base,
+                        end: b.end - old_base + new_base,
+                    })
+                    .collect()
+            }),
+            flags: self.flags,
+            compression_block_size: self.compression_block_size,
+        };
+
+        if let (true, Some(key)) = (self.is_encrypted(), decrypt_key) {
+            #[cfg(not(feature = "encryption"))]
+            return Err(super::Error::EncryptionFeatureDisabled);
+            #[cfg(feature = "encryption")]
+            {
+                let limit = decrypt_limit.unwrap_or(usize::MAX).min(data.len());
+                decrypt(key, &mut data[..limit])?;
+                data = match &self.blocks {
+                    Some(blocks) => {
+                        // each block is padded separately so pack them back to back
+                        let mut packed = Vec::with_capacity(data.len());
+                        let mut new_blocks = Vec::with_capacity(blocks.len());
+                        for b in blocks {
+                            let range = (b.start - old_base) as usize..(b.end - old_base) as usize;
+                            let start = new_base + packed.len() as u64;
+                            packed.extend_from_slice(data.get(range).ok_or_else(|| {
+                                Error::Other("compression block exceeds entry data".to_owned())
+                            })?);
+                            new_blocks.push(Block {
+                                start,
+                                end: new_base + packed.len() as u64,
+                            });
+                        }
+                        entry.blocks = Some(new_blocks);
+                        packed
+                    }
+                    None => {
+                        data.truncate(self.compressed as usize);
+                        data
+                    }
+                };
+                use sha1::{Digest, Sha1};
+                entry.compressed = data.len() as u64;
+                entry.hash = Some(Hash(Sha1::digest(&data).into()));
+                entry.flags &= !1;
+            }
+        }
+
+        entry.write(writer, version, EntryLocation::Data)?;
+        writer.write_all(&data)?;
+        Ok(entry)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn read_file<R: io::Read + io::Seek, W: io::Write>(
         &self,
         reader: &mut R,
         version: Version,
         compression: &[Option<Compression>],
         #[allow(unused)] key: &super::Key,
+        data_end: u64,
+        options: ReadOptions,
         buf: &mut W,
     ) -> Result<(), super::Error> {
-        reader.seek(io::SeekFrom::Start(self.offset))?;
-        Entry::read(reader, version)?;
-        #[cfg(any(feature = "compression", feature = "oodle"))]
-        let data_offset = reader.stream_position()?;
-        #[allow(unused_mut)]
-        let mut data = reader.read_len(match self.is_encrypted() {
-            true => align(self.compressed),
-            false => self.compressed,
-        } as usize)?;
+        let check_cancelled = || match options.cancel.is_some_and(|c| c.load(Ordering::Relaxed)) {
+            true => Err(super::Error::Cancelled),
+            false => Ok(()),
+        };
+        check_cancelled()?;
+        // the data offset is only needed to locate compression blocks
+        #[allow(unused_mut, unused_variables)]
+        let (header, data_offset, mut data) = self.read_stored(reader, version, data_end)?;
+        // UnrealPak hashes the data as stored, after encryption but without trailing padding
+        if let Some(expected) = options
+            .verify
+            .then_some(self.hash.or(header.hash))
+            .flatten()
+        {
+            use sha1::{Digest, Sha1};
+            let len = checked_usize(self.compressed, "entry data")?.min(data.len());
+            let got: [u8; 20] = Sha1::digest(&data[..len]).into();
+            if got != expected.0 {
+                return Err(Error::VerificationFailed {
+                    path: String::new(),
+                    expected: format!("sha1 {}", hex::encode(expected.0)),
+                    got: format!("sha1 {}", hex::encode(got)),
+                });
+            }
+        }
         if self.is_encrypted() {
             #[cfg(not(feature = "encryption"))]
-            return Err(super::Error::Encryption);
+            return Err(super::Error::EncryptionFeatureDisabled);
             #[cfg(feature = "encryption")]
             {
-                let super::Key::Some(key) = key else {
-                    return Err(super::Error::Encrypted);
-                };
-                use aes::cipher::BlockDecrypt;
-                for block in data.chunks_mut(16) {
-                    key.decrypt_block(aes::Block::from_mut_slice(block))
-                }
+                let limit = options.decrypt_limit.unwrap_or(usize::MAX).min(data.len());
+                decrypt(key, &mut data[..limit])?;
+                // bounded by the length of data which has already been read
                 data.truncate(self.compressed as usize);
             }
         }
 
+        // blocks within `data`
         #[cfg(feature = "compression")]
-        let ranges = {
-            let offset = |index: u64| -> usize {
-                (match version.version_major() >= VersionMajor::RelativeChunkOffsets {
-                    true => index - (data_offset - self.offset),
-                    false => index - data_offset,
-                }) as usize
-            };
-
-            match &self.blocks {
-                Some(blocks) => blocks
-                    .iter()
-                    .map(|block| offset(block.start)..offset(block.end))
-                    .collect::<Vec<_>>(),
-                #[allow(clippy::single_range_in_vec_init)]
-                None => vec![0..data.len()],
-            }
-        };
+        let ranges = self
+            .block_ranges(version, data_offset)
+            .into_iter()
+            .map(|block| (block.start - data_offset) as usize..(block.end - data_offset) as usize)
+            .collect::<Vec<_>>();
 
-        #[cfg(feature = "compression")]
-        macro_rules! decompress {
-            ($decompressor: ty) => {
-                for range in ranges {
-                    io::copy(&mut <$decompressor>::new(&data[range]), buf)?;
+        use io::Write;
+        let mut buf = CountingWriter {
+            inner: buf,
+            written: 0,
+        };
+        let buf = &mut buf;
+        match self.compression_method(compression)? {
+            None => {
+                for (i, chunk) in data
+                    .chunks_mut(UNCOMPRESSED_BLOCK_SIZE as usize)
+                    .enumerate()
+                {
+                    check_cancelled()?;
+                    if let Some((transform, path)) = options.transform {
+                        transform.decode(path, self.flags, i, chunk);
+                    }
+                    buf.write_all(chunk)?;
                 }
-            };
-        }
-
-        match self.compression_slot.and_then(|c| compression[c as usize]) {
-            None => buf.write_all(&data)?,
+            }
             #[cfg(not(feature = "compression"))]
             _ => return Err(super::Error::Compression),
             #[cfg(feature = "compression")]
-            Some(comp) => {
-                let chunk_size = if ranges.len() == 1 {
-                    self.uncompressed as usize
-                } else {
-                    self.compression_block_size as usize
-                };
+            Some(comp) => (|| {
+                let uncompressed = checked_usize(self.uncompressed, "uncompressed entry")?;
+                let chunk_size = checked_usize(self.block_size(), "compression block")?;
 
-                match comp {
-                    Compression::Zlib => decompress!(flate2::read::ZlibDecoder<&[u8]>),
-                    Compression::Gzip => decompress!(flate2::read::GzDecoder<&[u8]>),
-                    Compression::Zstd => {
-                        for range in ranges {
-                            io::copy(&mut zstd::stream::read::Decoder::new(&data[range])?, buf)?;
-                        }
+                if let Some((transform, path)) = options.transform {
+                    for (i, range) in ranges.iter().enumerate() {
+                        transform.decode(path, self.flags, i, &mut data[range.clone()]);
                     }
-                    Compression::LZ4 => {
-                        let mut decompressed = vec![0; self.uncompressed as usize];
-                        for (decomp_chunk, comp_range) in
-                            decompressed.chunks_mut(chunk_size).zip(ranges)
-                        {
-                            lz4_flex::block::decompress_into(&data[comp_range], decomp_chunk)
-                                .map_err(|_| Error::DecompressionFailed(Compression::LZ4))?;
+                }
+                let blocks = ranges
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, range)| {
+                        let remaining = uncompressed.saturating_sub(i * chunk_size);
+                        (&data[range], chunk_size.min(remaining))
+                    })
+                    .collect::<Vec<_>>();
+                match options.threads {
+                    0 | 1 => {
+                        // one buffer for all blocks rather than one per block
+                        let mut decompressed = Vec::with_capacity(chunk_size);
+                        for (block, size) in blocks {
+                            check_cancelled()?;
+                            decompress_block_into(
+                                comp,
+                                block,
+                                size,
+                                options.zlib_format,
+                                &mut decompressed,
+                            )?;
+                            buf.write_all(&decompressed)?;
                         }
-                        buf.write_all(&decompressed)?;
                     }
-                    #[cfg(feature = "oodle")]
-                    Compression::Oodle => {
-                        let mut decompressed = vec![0; self.uncompressed as usize];
-                        for (decomp_chunk, comp_range) in
-                            decompressed.chunks_mut(chunk_size).zip(ranges)
-                        {
-                            let out =
-                                oodle_loader::oodle()?.decompress(&data[comp_range], decomp_chunk);
-                            if out == 0 {
-                                return Err(Error::DecompressionFailed(Compression::Oodle));
-                            }
+                    _ => {
+                        // each thread decompresses a contiguous run of blocks
+                        let per_thread = blocks.len().div_ceil(options.threads);
+                        let decompressed = std::thread::scope(|scope| {
+                            blocks
+                                .chunks(per_thread)
+                                .map(|run| {
+                                    scope.spawn(move || {
+                                        run.iter()
+                                            .map(|(block, size)| {
+                                                check_cancelled()?;
+                                                decompress_block(
+                                                    comp,
+                                                    block,
+                                                    *size,
+                                                    options.zlib_format,
+                                                )
+                                            })
+                                            .collect::<Result<Vec<_>, _>>()
+                                    })
+                                })
+                                .collect::<Vec<_>>()
+                                .into_iter()
+                                .map(|handle| handle.join().expect("decompression panicked"))
+                                .collect::<Result<Vec<_>, _>>()
+                        })?;
+                        for block in decompressed.iter().flatten() {
+                            check_cancelled()?;
+                            buf.write_all(block)?;
                         }
-                        buf.write_all(&decompressed)?;
                     }
-                    #[cfg(not(feature = "oodle"))]
-                    Compression::Oodle => return Err(super::Error::Oodle),
                 }
-            }
+                Ok(())
+            })()
+            .map_err(|err| self.decompression_error(comp, err))?,
         }
         buf.flush()?;
+        if options.verify && buf.written != self.uncompressed {
+            return Err(Error::VerificationFailed {
+                path: String::new(),
+                expected: format!("{} bytes", self.uncompressed),
+                got: format!("{} bytes", buf.written),
+            });
+        }
         Ok(())
     }
+
+    /// Garbage after decryption is the likely cause of decompression failures of encrypted
+    /// entries
+    fn decompression_error(&self, compression: Compression, err: Error) -> Error {
+        match err {
+            Error::DecompressionFailed(_) if self.is_encrypted() => {
+                Error::EncryptedDataInvalid(compression)
+            }
+            Error::Io(e)
+                if self.is_encrypted()
+                    && matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput
+                    ) =>
+            {
+                Error::EncryptedDataInvalid(compression)
+            }
+            err => err,
+        }
+    }
+
+    /// Opens the data of this entry for reading, decrypting and decompressing it block by block
+    /// as it is read
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn open<'a, R: io::Read + io::Seek>(
+        &'a self,
+        reader: &'a mut R,
+        version: Version,
+        compression: &[Option<Compression>],
+        key: &'a super::Key,
+        data_end: u64,
+        zlib_format: &'a OnceLock<ZlibFormat>,
+        transform: Option<(&'a dyn EntryTransform, &'a str)>,
+        decrypt_limit: Option<usize>,
+    ) -> Result<EntryReader<'a, R>, super::Error> {
+        let (_, data_offset, len) = self.read_header(reader, version, data_end)?;
+        let compression = self.compression_method(compression)?;
+        let (blocks, block_size) = match (compression, &self.blocks) {
+            (None, _) => (
+                (0..self.compressed)
+                    .step_by(UNCOMPRESSED_BLOCK_SIZE as usize)
+                    .map(|start| {
+                        data_offset + start
+                            ..data_offset + (start + UNCOMPRESSED_BLOCK_SIZE).min(self.compressed)
+                    })
+                    .collect(),
+                UNCOMPRESSED_BLOCK_SIZE,
+            ),
+            (Some(_), _) => (self.block_ranges(version, data_offset), self.block_size()),
+        };
+        let size = match compression {
+            Some(_) => self.uncompressed,
+            None => self.compressed,
+        };
+        if size > 0 && block_size == 0 {
+            return Err(Error::Other("compression block size is zero".to_owned()));
+        }
+        Ok(EntryReader {
+            entry: self,
+            reader,
+            key,
+            compression,
+            zlib_format,
+            transform,
+            blocks,
+            stored_end: data_offset + len,
+            decrypt_end: decrypt_limit
+                .map_or(u64::MAX, |limit| data_offset.saturating_add(limit as u64)),
+            block_size,
+            size,
+            pos: 0,
+            current: None,
+            blocks_read: 0,
+        })
+    }
+}
+
+/// Size of the blocks uncompressed entries are read and transformed in, a multiple of the AES
+/// block size
+pub(crate) const UNCOMPRESSED_BLOCK_SIZE: u64 = 0x10000;
+
+/// Reader over the data of a single entry returned by [`crate::PakReader::open_entry`].
+///
+/// Only the block containing the current position is kept in memory. Seeking is supported for
+/// all entries and skips straight to the block containing the new position.
+pub struct EntryReader<'a, R> {
+    entry: &'a Entry,
+    reader: &'a mut R,
+    key: &'a super::Key,
+    compression: Option<Compression>,
+    #[allow(unused)]
+    zlib_format: &'a OnceLock<ZlibFormat>,
+    transform: Option<(&'a dyn EntryTransform, &'a str)>,
+    /// Stored blocks in the pak, excluding encryption padding
+    blocks: Vec<std::ops::Range<u64>>,
+    /// End of the stored data including encryption padding
+    stored_end: u64,
+    /// End of the encrypted data, see [`crate::DecryptLimit`]
+    decrypt_end: u64,
+    /// Size of each decoded block but the last
+    block_size: u64,
+    /// Size of the decoded entry
+    size: u64,
+    pos: u64,
+    /// Index and decoded contents of the block last read from
+    current: Option<(usize, Vec<u8>)>,
+    blocks_read: usize,
+}
+
+impl<R: io::Read + io::Seek> EntryReader<'_, R> {
+    /// Number of blocks the entry is stored in
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Number of blocks read from the pak so far, counting a block again if it had to be read
+    /// again after reading another one
+    pub fn blocks_read(&self) -> usize {
+        self.blocks_read
+    }
+
+    /// Size the block at `index` decodes to, the block size for all blocks but the last
+    fn expected_len(&self, index: usize) -> u64 {
+        self.block_size
+            .min(self.size.saturating_sub(index as u64 * self.block_size))
+    }
+
+    /// Reads, decrypts and decompresses the block at `index` into `out`, whose allocation is
+    /// reused for compressed blocks
+    fn decode(&mut self, index: usize, out: &mut Vec<u8>) -> Result<(), super::Error> {
+        self.decode_block(index, out)?;
+        match self.compression {
+            Some(comp) if out.len() as u64 != self.expected_len(index) => Err(self
+                .entry
+                .decompression_error(comp, Error::DecompressionFailed(comp))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decodes the block at `index` on its own, returning its range in the pak excluding
+    /// encryption padding, the size it should decode to and the size it does
+    pub(crate) fn probe_block(
+        &mut self,
+        index: usize,
+    ) -> (std::ops::Range<u64>, u64, Result<u64, super::Error>) {
+        let mut out = vec![];
+        let result = self
+            .decode_block(index, &mut out)
+            .map(|()| out.len() as u64);
+        (
+            self.blocks.get(index).cloned().unwrap_or_default(),
+            self.expected_len(index),
+            result,
+        )
+    }
+
+    /// [`Self::decode`] without checking the size the block decompresses to
+    #[allow(unused_mut)]
+    fn decode_block(&mut self, index: usize, out: &mut Vec<u8>) -> Result<(), super::Error> {
+        let block = self.blocks.get(index).cloned().ok_or_else(|| {
+            Error::Other("entry is larger than its compression blocks".to_owned())
+        })?;
+        let stored = self.entry.padded_block(&block, self.stored_end);
+        self.reader.seek(io::SeekFrom::Start(block.start))?;
+        let mut data = self
+            .reader
+            .read_len(checked_usize(stored.end - stored.start, "entry block")?)?;
+        self.blocks_read += 1;
+        if self.entry.is_encrypted() {
+            #[cfg(not(feature = "encryption"))]
+            return Err(super::Error::EncryptionFeatureDisabled);
+            #[cfg(feature = "encryption")]
+            {
+                let limit = self.decrypt_end.saturating_sub(block.start);
+                let limit = usize::try_from(limit).unwrap_or(usize::MAX).min(data.len());
+                decrypt(self.key, &mut data[..limit])?;
+                data.truncate((block.end - block.start) as usize);
+            }
+        }
+        if let Some((transform, path)) = self.transform {
+            transform.decode(path, self.entry.flags, index, &mut data);
+        }
+
+        match self.compression {
+            None => {
+                *out = data;
+                Ok(())
+            }
+            #[cfg(not(feature = "compression"))]
+            Some(_) => Err(super::Error::Compression),
+            #[cfg(feature = "compression")]
+            Some(comp) => decompress_block_into(
+                comp,
+                &data,
+                checked_usize(self.expected_len(index), "entry block")?,
+                Some(self.zlib_format),
+                out,
+            )
+            .map_err(|err| self.entry.decompression_error(comp, err)),
+        }
+    }
+}
+
+impl<R: io::Read + io::Seek> io::Read for EntryReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = (self.pos / self.block_size) as usize;
+        let data = match &self.current {
+            Some((current, data)) if *current == index => data,
+            _ => {
+                // the previous block is overwritten, keeping its allocation
+                let mut data = self
+                    .current
+                    .take()
+                    .map(|(_, data)| data)
+                    .unwrap_or_default();
+                self.decode(index, &mut data).map_err(|err| match err {
+                    Error::Io(err) => err,
+                    err => io::Error::new(io::ErrorKind::InvalidData, err),
+                })?;
+                &self.current.insert((index, data)).1
+            }
+        };
+        let start = (self.pos - index as u64 * self.block_size) as usize;
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: io::Read + io::Seek> io::Seek for EntryReader<'_, R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            io::SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
 }
 
 mod test {
@@ -465,4 +1317,219 @@ mod test {
             .unwrap();
         assert_eq!(&data, &out);
     }
+
+    fn large_entry() -> super::Entry {
+        super::Entry {
+            offset: 5 << 30,
+            compressed: 6 << 30,
+            uncompressed: 6 << 30,
+            compression_slot: None,
+            timestamp: None,
+            hash: Some(crate::Hash::default()),
+            blocks: None,
+            flags: 0,
+            compression_block_size: 0,
+        }
+    }
+
+    #[test]
+    fn test_encoded_entry_64_bit() {
+        let entry = large_entry();
+        let mut buf = vec![];
+        entry.write_encoded(&mut buf).unwrap();
+        let read = super::Entry::read_encoded(&mut std::io::Cursor::new(buf), super::Version::V11)
+            .unwrap();
+        assert_eq!(
+            (read.offset, read.compressed, read.uncompressed),
+            (entry.offset, entry.compressed, entry.uncompressed)
+        );
+    }
+
+    /// Compressed entry of `count` blocks of `block_size` bytes each, without any data
+    fn compressed_entry(count: u64, block_size: u64) -> super::Entry {
+        super::Entry {
+            compressed: count * block_size,
+            compression_slot: Some(0),
+            blocks: Some(
+                (0..count)
+                    .map(|i| super::Block {
+                        start: i * block_size,
+                        end: (i + 1) * block_size,
+                    })
+                    .collect(),
+            ),
+            uncompressed: count * 0x20_0000,
+            compression_block_size: 0x20_0000,
+            ..large_entry()
+        }
+    }
+
+    #[test]
+    fn test_encoded_entry_large_compressed() {
+        // over 4 GiB compressed, with a block size which doesn't fit the flags
+        let entry = compressed_entry(0xffff, 0x1_0000 + 1);
+        let mut buf = vec![];
+        entry.write_encoded(&mut buf).unwrap();
+        let read = super::Entry::read_encoded(&mut std::io::Cursor::new(buf), super::Version::V11)
+            .unwrap();
+        assert_eq!(
+            (read.offset, read.compressed, read.compression_block_size),
+            (entry.offset, entry.compressed, entry.compression_block_size)
+        );
+        let sizes = |entry: &super::Entry| {
+            entry
+                .blocks
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|b| b.end - b.start)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(&read), sizes(&entry));
+    }
+
+    #[test]
+    fn test_implausible_block_count() {
+        let implausible = |result: Result<super::Entry, crate::Error>| match result {
+            Err(crate::Error::ImplausibleBlockCount {
+                count,
+                uncompressed,
+                ..
+            }) => (count, uncompressed),
+            result => panic!("expected implausible block count, got {result:?}"),
+        };
+        let entry = super::Entry {
+            uncompressed: 0x1000,
+            ..compressed_entry(0xffff, 1)
+        };
+        let mut buf = vec![];
+        entry.write_encoded(&mut buf).unwrap();
+        assert_eq!(
+            implausible(super::Entry::read_encoded(
+                &mut std::io::Cursor::new(buf),
+                super::Version::V11
+            )),
+            (0xffff, 0x1000)
+        );
+
+        // rejected before the blocks are allocated
+        let mut buf = vec![];
+        entry
+            .write(&mut buf, super::Version::V11, super::EntryLocation::Index)
+            .unwrap();
+        let count = 8 + 8 + 8 + 4 + 20;
+        buf[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            implausible(super::Entry::read(
+                &mut std::io::Cursor::new(&buf),
+                super::Version::V11
+            )),
+            (u32::MAX as u64, 0x1000)
+        );
+
+        // one block more than the size needs is tolerated
+        let entry = super::Entry {
+            uncompressed: 2 * 0x20_0000,
+            ..compressed_entry(3, 1)
+        };
+        let mut buf = vec![];
+        entry.write_encoded(&mut buf).unwrap();
+        super::Entry::read_encoded(&mut std::io::Cursor::new(buf), super::Version::V11).unwrap();
+
+        // as is one block fewer, while a block list missing more of the data is truncated
+        let entry = |count| super::Entry {
+            uncompressed: 3 * 0x20_0000,
+            ..compressed_entry(count, 1)
+        };
+        let mut buf = vec![];
+        entry(2).write_encoded(&mut buf).unwrap();
+        super::Entry::read_encoded(&mut std::io::Cursor::new(buf), super::Version::V11).unwrap();
+        let mut buf = vec![];
+        entry(1).write_encoded(&mut buf).unwrap();
+        assert_eq!(
+            implausible(super::Entry::read_encoded(
+                &mut std::io::Cursor::new(buf),
+                super::Version::V11
+            )),
+            (1, 3 * 0x20_0000)
+        );
+        let mut buf = vec![];
+        entry(1)
+            .write(&mut buf, super::Version::V11, super::EntryLocation::Index)
+            .unwrap();
+        assert_eq!(
+            implausible(super::Entry::read(
+                &mut std::io::Cursor::new(&buf),
+                super::Version::V11
+            )),
+            (1, 3 * 0x20_0000)
+        );
+    }
+
+    #[test]
+    fn test_entry_field_overflow() {
+        let overflow = |result: Result<(), crate::Error>| match result {
+            Err(crate::Error::EntryFieldOverflow { field, value }) => (field, value),
+            result => panic!("expected overflow, got {result:?}"),
+        };
+        let entry = compressed_entry(0x1_0000, 1);
+        assert_eq!(
+            overflow(entry.write_encoded(&mut vec![])),
+            ("compression block count", 0x1_0000)
+        );
+        // full records store the block count in 32 bits
+        entry
+            .write(
+                &mut vec![],
+                super::Version::V11,
+                super::EntryLocation::Index,
+            )
+            .unwrap();
+
+        let entry = compressed_entry(2, 1 << 32);
+        assert_eq!(
+            overflow(entry.write_encoded(&mut vec![])),
+            ("compression block size", 1 << 32)
+        );
+
+        let entry = super::Entry {
+            compression_slot: Some(0x100),
+            ..large_entry()
+        };
+        assert_eq!(
+            overflow(entry.write(
+                &mut vec![],
+                super::Version::V8A,
+                super::EntryLocation::Index
+            )),
+            ("compression slot", 0x101)
+        );
+    }
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_read_file_too_large() {
+        let mut entry = large_entry();
+        entry.offset = 0;
+        let mut header = vec![];
+        entry
+            .write(&mut header, super::Version::V11, super::EntryLocation::Data)
+            .unwrap();
+        let result = entry.read_file(
+            &mut std::io::Cursor::new(header),
+            super::Version::V11,
+            &[],
+            &Default::default(),
+            u64::MAX,
+            Default::default(),
+            &mut vec![],
+        );
+        assert!(matches!(
+            result,
+            Err(crate::Error::TooLargeFor32Bit {
+                size: 0x1_8000_0000,
+                ..
+            })
+        ));
+    }
 }
@@ -10,13 +10,20 @@ pub enum Error {
     Aes,
 
     // feature errors
-    #[error("enable the compression feature to read compressed paks")]
+    #[error("repak was compiled without the `compression` feature, which is required to read or write compressed entries; enable it in Cargo.toml (it is a default feature)")]
     Compression,
 
-    #[error("enable the encryption feature to read encrypted paks")]
-    Encryption,
+    /// The pak or entry is encrypted and this build can't decrypt it, whether or not a key
+    /// would be available
+    #[error("pak is encrypted but repak was compiled without the `encryption` feature, so the pak can't be read even with a key; enable it in Cargo.toml (it is a default feature)")]
+    EncryptionFeatureDisabled,
 
-    #[error("enable the oodle feature to read Oodle compressed paks")]
+    /// The index is a V9 frozen index, a memory image of the engine's structures which is
+    /// neither read nor written
+    #[error("pak has a frozen index, which is not supported")]
+    FrozenIndex,
+
+    #[error("repak was compiled without the `oodle` feature, which is required to read or write Oodle compressed entries; enable it in Cargo.toml")]
     Oodle,
 
     // std errors
@@ -39,28 +46,65 @@ pub enum Error {
     #[error("got {0}, which is not a boolean")]
     Bool(u8),
 
-    #[error("found magic of {:#x} instead of {:#x}", .0, super::MAGIC)]
-    Magic(u32),
+    #[error("found magic of {found:#x} instead of {expected:#x} (a game specific magic can be set with --magic)")]
+    Magic { found: u32, expected: u32 },
 
     #[cfg(feature = "oodle")]
     #[error("Oodle loader error: {0}")]
     OodleFailed(#[from] oodle_loader::Error),
 
+    #[error("entry record is missing its hash")]
+    MissingHash,
+
+    #[error("\"{0}\" in the index is not valid UTF-8 or UTF-16 (it can be read lossily with --lossy-paths)")]
+    InvalidName(String),
+
     #[error("No entry found at {0}")]
     MissingEntry(String),
 
+    /// Like [`Error::MissingEntry`] for paths resembling an existing one, see
+    /// [`crate::PakReader::resolve_path`] for the forms of paths which are accepted
+    #[error("No entry found at {path}, did you mean {nearest}?")]
+    MissingEntryNearest { path: String, nearest: String },
+
+    /// The pak was opened with [`crate::PakBuilder::metadata_only`] so its entries weren't read
+    #[error("the entries of the pak were not read as it was opened for metadata only")]
+    IndexNotLoaded,
+
     #[error("Prefix \"{prefix}\" does not match path \"{path}\"")]
     PrefixMismatch { prefix: String, path: String },
 
     #[error("Attempted to write to \"{0}\" which is outside of output directory")]
     WriteOutsideOutput(String),
 
+    #[error("pak has entries after its index at {index_offset:#x} which appending in place of the index would overwrite, append at the end of the file instead")]
+    DataAfterIndex { index_offset: u64 },
+
+    #[error("path remap is ambiguous, both {:?} and {:?} map to {path:?}", .sources[0], .sources[1])]
+    AmbiguousRemap { path: String, sources: [String; 2] },
+
+    #[error("path remap rules map {prefix:?} to both {:?} and {:?}", .targets[0], .targets[1])]
+    ConflictingRemap {
+        prefix: String,
+        targets: [String; 2],
+    },
+
+    #[error("{path} can't share the data of {existing} as the entry transform encodes the data of each entry for its path")]
+    SharedDataWithTransform { path: String, existing: String },
+
     #[error("Output directory is not empty: \"{0}\"")]
     OutputNotEmpty(String),
 
+    #[error("Attempted to write through the symbolic link \"{0}\" in the output directory")]
+    SymlinkInOutput(String),
+
     #[error("Input is not a directory: \"{0}\"")]
     InputNotADirectory(String),
 
+    /// The input given as a pak can't be one, such as a directory or an empty file
+    #[error("{reason}: \"{path}\"")]
+    NotAPak { path: String, reason: String },
+
     #[error("{0} decompression failed")]
     DecompressionFailed(Compression),
 
@@ -73,16 +117,167 @@ pub enum Error {
     #[error("pak is encrypted but no key was provided")]
     Encrypted,
 
+    /// The decrypted index failed sanity checks or could not be parsed. `source` is the parse
+    /// error if there was one.
+    #[error("index could not be decrypted with the provided key or is corrupt{}", guid_hint(.guid))]
+    WrongKeyOrCorrupt {
+        guid: Option<u128>,
+        source: Option<Box<Error>>,
+    },
+
+    /// The SHA-1 of a part of the decrypted index doesn't match the hash stored for it, see
+    /// [`crate::PakBuilder::ignore_index_hashes`]
+    #[error("{section} hash mismatch, expected {expected} but got {actual}; the index is truncated or corrupt (it can be read anyway with --ignore-index-hashes)")]
+    IndexHashMismatch {
+        section: crate::IndexSection,
+        expected: String,
+        actual: String,
+    },
+
+    /// An entry declares more compression blocks than its uncompressed size allows, or too few
+    /// to hold it with its compression block size, which is never readable
+    #[error("{} has {count} compression blocks for {uncompressed} uncompressed bytes, which can't be valid", .path.as_deref().unwrap_or("entry"))]
+    ImplausibleBlockCount {
+        path: Option<String>,
+        count: u64,
+        uncompressed: u64,
+    },
+
+    /// The primary index ends with entries which are not encoded rather than the expected
+    /// count of zero, which repak doesn't read and a corrupt index produces too
+    #[error("the index lists {0} entries which are not encoded, which is not supported or the index is corrupt")]
+    UnencodedEntries(u32),
+
+    #[error("entry is compressed with slot {0} which names no supported compression method (see the compression slots printed by info)")]
+    UnknownCompressionSlot(u32),
+
+    #[error("{0} decompression of encrypted data failed, the data may be encrypted with a different key than the index (see --data-key)")]
+    EncryptedDataInvalid(Compression),
+
     #[error("error with OsString")]
     OsString(std::ffi::OsString),
 
+    #[error("entry {field} of {value} does not fit the entry record of the pak format")]
+    EntryFieldOverflow { field: &'static str, value: u64 },
+
+    #[error("{what} of {size} bytes exceeds the address space of this target")]
+    TooLargeFor32Bit { what: &'static str, size: u64 },
+
     #[error("{0}version unsupported or is encrypted (possibly missing --aes-key?)")]
     UnsupportedOrEncrypted(String),
 
+    /// The data read for an entry does not match its stored hash or uncompressed size
+    #[error("verification of {path:?} failed: expected {expected}, got {got}")]
+    VerificationFailed {
+        path: String,
+        expected: String,
+        got: String,
+    },
+
+    /// A pak written with [`crate::PakWriter::write_index_verified`] doesn't read back as
+    /// written
+    #[error("verification of the written pak failed{}: {reason}", .path.as_ref().map(|path| format!(" for {path:?}")).unwrap_or_default())]
+    PostWriteVerification {
+        path: Option<String>,
+        reason: String,
+    },
+
+    /// The index has a problem which [`crate::PakBuilder::strict`] refuses to work around
+    #[error("{0} (refused by strict parsing)")]
+    StrictWarning(crate::IndexWarning),
+
+    /// The mod metadata entry isn't JSON of the shape of [`crate::ModMetadata`]
+    #[error("mod metadata at {path} is invalid: {reason}")]
+    InvalidModMetadata { path: String, reason: String },
+
+    /// Reading was stopped because the cancellation flag passed by the caller was set
+    #[error("operation was cancelled")]
+    Cancelled,
+
     #[error("{0}")]
     Other(String),
 }
 
+/// Broad classification of an [`Error`] for callers that need to react to the cause of a
+/// failure without matching on every variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Invalid input from the caller such as a malformed key or a path that doesn't exist
+    InvalidInput,
+    /// The input is not a pak, is corrupt, or uses an unsupported version
+    Format,
+    /// The pak is encrypted and the key is missing or wrong
+    Key,
+    /// An underlying I/O operation failed
+    Io,
+    /// Refused to write output that would overwrite or escape the destination
+    Output,
+    /// The operation requires a feature which is not enabled or available
+    Unsupported,
+    Other,
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Aes
+            | Error::Strum(_)
+            | Error::MissingEntry(_)
+            | Error::MissingEntryNearest { .. }
+            | Error::IndexNotLoaded
+            | Error::PrefixMismatch { .. }
+            | Error::InputNotADirectory(_)
+            | Error::NotAPak { .. }
+            | Error::AmbiguousRemap { .. }
+            | Error::ConflictingRemap { .. }
+            | Error::SharedDataWithTransform { .. }
+            | Error::EntryFieldOverflow { .. } => ErrorKind::InvalidInput,
+            Error::Bool(_)
+            | Error::Magic { .. }
+            | Error::Version { .. }
+            | Error::UnsupportedOrEncrypted(_)
+            | Error::Utf8(_)
+            | Error::Utf16(_)
+            | Error::InvalidName(_)
+            | Error::DecompressionFailed(_)
+            | Error::MissingHash
+            | Error::VerificationFailed { .. }
+            | Error::PostWriteVerification { .. }
+            | Error::StrictWarning(_)
+            | Error::IndexHashMismatch { .. }
+            | Error::ImplausibleBlockCount { .. }
+            | Error::UnencodedEntries(_)
+            | Error::InvalidModMetadata { .. }
+            | Error::TooLargeFor32Bit { .. } => ErrorKind::Format,
+            Error::Encrypted | Error::WrongKeyOrCorrupt { .. } | Error::EncryptedDataInvalid(_) => {
+                ErrorKind::Key
+            }
+            Error::Io(_) | Error::IntoInner(_) | Error::OsString(_) => ErrorKind::Io,
+            Error::OutputNotEmpty(_)
+            | Error::WriteOutsideOutput(_)
+            | Error::SymlinkInOutput(_)
+            | Error::DataAfterIndex { .. } => ErrorKind::Output,
+            Error::Compression
+            | Error::EncryptionFeatureDisabled
+            | Error::FrozenIndex
+            | Error::Oodle
+            | Error::UnknownCompressionSlot(_) => ErrorKind::Unsupported,
+            #[cfg(feature = "oodle")]
+            Error::OodleFailed(_) => ErrorKind::Unsupported,
+            Error::Fmt(_) | Error::Cancelled | Error::Other(_) => ErrorKind::Other,
+        }
+    }
+}
+
+fn guid_hint(guid: &Option<u128>) -> String {
+    match guid {
+        Some(guid) => format!(
+            " (the index is encrypted with the key of GUID {guid:032X}, check that the key is for this build of the game)"
+        ),
+        None => String::new(),
+    }
+}
+
 impl std::fmt::Debug for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Display::fmt(self, f)
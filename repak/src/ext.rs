@@ -1,5 +1,19 @@
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 
+/// Converts a size read from a pak to `usize`, erroring instead of truncating on 32-bit targets
+pub fn checked_usize(size: u64, what: &'static str) -> Result<usize, super::Error> {
+    size.try_into()
+        .map_err(|_| super::Error::TooLargeFor32Bit { what, size })
+}
+
+/// Converts a value to the type of a field of an entry record, failing for values which don't
+/// fit
+pub fn checked_field<T: TryFrom<u64>>(value: u64, field: &'static str) -> Result<T, super::Error> {
+    value
+        .try_into()
+        .map_err(|_| super::Error::EntryFieldOverflow { field, value })
+}
+
 pub trait BoolExt<T, E, F: FnOnce() -> Result<T, E>> {
     fn then_try(&self, f: F) -> Result<Option<T>, E>;
 }
@@ -23,12 +37,76 @@ pub trait ReadExt {
         func: impl FnMut(&mut Self) -> Result<T, super::Error>,
     ) -> Result<Vec<T>, super::Error>;
     fn read_string(&mut self) -> Result<String, super::Error>;
+    fn read_raw_string(&mut self) -> Result<RawString, super::Error>;
     fn read_len(&mut self, len: usize) -> Result<Vec<u8>, super::Error>;
 }
 
 pub trait WriteExt {
     fn write_bool(&mut self, value: bool) -> Result<(), super::Error>;
     fn write_string(&mut self, value: &str) -> Result<(), super::Error>;
+    fn write_raw_string(&mut self, value: &RawString) -> Result<(), super::Error>;
+}
+
+/// A string as serialized, including its terminator, for strings which must be written back
+/// exactly as they were read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawString {
+    /// Narrow characters, expected to be UTF-8
+    Narrow(Vec<u8>),
+    /// UTF-16 code units
+    Wide(Vec<u16>),
+}
+
+impl RawString {
+    /// Encodes `value` with a terminator the way [`WriteExt::write_string`] writes it: as
+    /// narrow characters if it is ASCII and as UTF-16 otherwise, or always as UTF-16 if `wide`
+    /// is set
+    pub fn encode(value: &str, wide: bool) -> Self {
+        if !wide && value.is_ascii() {
+            RawString::Narrow(value.bytes().chain([0]).collect())
+        } else {
+            RawString::Wide(value.encode_utf16().chain([0]).collect())
+        }
+    }
+
+    fn narrow(chars: &[u8]) -> &[u8] {
+        &chars[..chars.iter().position(|&c| c == 0).unwrap_or(chars.len())]
+    }
+
+    fn wide(chars: &[u16]) -> &[u16] {
+        &chars[..chars.iter().position(|&c| c == 0).unwrap_or(chars.len())]
+    }
+
+    /// The characters as stored, including the terminator, with UTF-16 code units as little
+    /// endian bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RawString::Narrow(chars) => chars.clone(),
+            RawString::Wide(chars) => chars.iter().flat_map(|c| c.to_le_bytes()).collect(),
+        }
+    }
+
+    pub fn decode(&self) -> Result<String, super::Error> {
+        Ok(match self {
+            RawString::Narrow(chars) => String::from_utf8(Self::narrow(chars).to_vec())?,
+            RawString::Wide(chars) => String::from_utf16(Self::wide(chars))?,
+        })
+    }
+
+    pub fn decode_lossy(&self) -> String {
+        match self {
+            RawString::Narrow(chars) => String::from_utf8_lossy(Self::narrow(chars)).into_owned(),
+            RawString::Wide(chars) => String::from_utf16_lossy(Self::wide(chars)),
+        }
+    }
+
+    /// The string as decoded by the engine, which widens narrow characters as Latin-1
+    pub fn decode_engine(&self) -> String {
+        match self {
+            RawString::Narrow(chars) => Self::narrow(chars).iter().map(|&c| c as char).collect(),
+            RawString::Wide(chars) => String::from_utf16_lossy(Self::wide(chars)),
+        }
+    }
 }
 
 impl<R: std::io::Read> ReadExt for R {
@@ -67,16 +145,19 @@ impl<R: std::io::Read> ReadExt for R {
     }
 
     fn read_string(&mut self) -> Result<String, super::Error> {
+        self.read_raw_string()?.decode()
+    }
+
+    fn read_raw_string(&mut self) -> Result<RawString, super::Error> {
         let len = self.read_i32::<LE>()?;
         if len < 0 {
-            let chars = self.read_array_len((-len) as usize, |r| Ok(r.read_u16::<LE>()?))?;
-            let length = chars.iter().position(|&c| c == 0).unwrap_or(chars.len());
-            Ok(String::from_utf16(&chars[..length]).unwrap())
+            let chars =
+                self.read_array_len(len.unsigned_abs() as usize, |r| Ok(r.read_u16::<LE>()?))?;
+            Ok(RawString::Wide(chars))
         } else {
             let mut chars = vec![0; len as usize];
             self.read_exact(&mut chars)?;
-            let length = chars.iter().position(|&c| c == 0).unwrap_or(chars.len());
-            Ok(String::from_utf8_lossy(&chars[..length]).into_owned())
+            Ok(RawString::Narrow(chars))
         }
     }
 
@@ -96,18 +177,57 @@ impl<W: std::io::Write> WriteExt for W {
         Ok(())
     }
     fn write_string(&mut self, value: &str) -> Result<(), super::Error> {
-        if value.is_empty() || value.is_ascii() {
-            self.write_u32::<LE>(value.as_bytes().len() as u32 + 1)?;
-            self.write_all(value.as_bytes())?;
-            self.write_u8(0)?;
-        } else {
-            let chars: Vec<u16> = value.encode_utf16().collect();
-            self.write_i32::<LE>(-(chars.len() as i32 + 1))?;
-            for c in chars {
-                self.write_u16::<LE>(c)?;
+        self.write_raw_string(&RawString::encode(value, false))
+    }
+    fn write_raw_string(&mut self, value: &RawString) -> Result<(), super::Error> {
+        match value {
+            RawString::Narrow(chars) => {
+                self.write_i32::<LE>(chars.len() as i32)?;
+                self.write_all(chars)?;
+            }
+            RawString::Wide(chars) => {
+                self.write_i32::<LE>(-(chars.len() as i32))?;
+                for c in chars {
+                    self.write_u16::<LE>(*c)?;
+                }
             }
-            self.write_u16::<LE>(0)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{RawString, WriteExt};
+
+    #[test]
+    fn test_string_width() {
+        assert_eq!(RawString::encode("", false), RawString::Narrow(vec![0]));
+        assert_eq!(
+            RawString::encode("a/b", false),
+            RawString::Narrow(b"a/b\0".to_vec())
+        );
+        assert_eq!(
+            RawString::encode("caf\u{E9}", false),
+            RawString::Wide(vec!['c' as u16, 'a' as u16, 'f' as u16, 0xE9, 0])
+        );
+        assert_eq!(
+            RawString::encode("a/b", true),
+            RawString::Wide(vec!['a' as u16, '/' as u16, 'b' as u16, 0])
+        );
+        assert_eq!(RawString::encode("", true), RawString::Wide(vec![0]));
+
+        let written = |value: &str| {
+            let mut buf = vec![];
+            buf.write_string(value).unwrap();
+            buf
+        };
+        assert_eq!(written(""), [1, 0, 0, 0, 0]);
+        assert_eq!(written("ab"), [3, 0, 0, 0, b'a', b'b', 0]);
+        // negative lengths count UTF-16 code units
+        assert_eq!(
+            written("\u{E9}\u{1F600}"),
+            [0xFC, 0xFF, 0xFF, 0xFF, 0xE9, 0, 0x3D, 0xD8, 0x00, 0xDE, 0, 0]
+        );
+    }
+}
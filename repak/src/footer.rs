@@ -5,54 +5,182 @@ use crate::{
 
 use super::{ext::ReadExt, Compression, Version, VersionMajor};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
-use std::str::FromStr;
+use strum::VariantNames;
 
+/// Compression method named by one of the fixed size name slots of a footer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressionSlot {
+    Empty,
+    Known(Compression),
+    /// A name which doesn't match any supported method, decoded lossily
+    Unknown(String),
+}
+
+impl CompressionSlot {
+    /// Parses a name slot, which should be null padded ASCII but is sometimes padded with
+    /// spaces or contains garbage after the terminator
+    pub(crate) fn parse(raw: &[u8]) -> Self {
+        let name = raw.split(|&b| b == 0).next().unwrap_or_default();
+        let name = String::from_utf8_lossy(name);
+        let name = name.trim_end_matches(|c: char| c.is_whitespace() || c.is_control());
+        if name.is_empty() {
+            return Self::Empty;
+        }
+        match Compression::VARIANTS
+            .iter()
+            .find(|variant| variant.eq_ignore_ascii_case(name))
+        {
+            Some(variant) => Self::Known(variant.parse().expect("variant name parses")),
+            None => Self::Unknown(name.to_owned()),
+        }
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        match self {
+            Self::Known(compression) => Some(*compression),
+            _ => None,
+        }
+    }
+}
+
+/// Fixed size trailer of a pak locating its index
 #[derive(Debug)]
 pub struct Footer {
-    pub encryption_uuid: Option<u128>,
-    pub encrypted: bool,
-    pub magic: u32,
-    pub version: Version,
-    pub version_major: VersionMajor,
-    pub index_offset: u64,
-    pub index_size: u64,
-    pub hash: Hash,
-    pub frozen: bool,
-    pub compression: Vec<Option<Compression>>,
+    pub(crate) encryption_uuid: Option<u128>,
+    pub(crate) encrypted: bool,
+    pub(crate) magic: u32,
+    pub(crate) version: Version,
+    pub(crate) version_major: VersionMajor,
+    /// Version number stored in the footer if it is newer than any repak knows, in which case
+    /// the footer is read as [`Footer::version`]
+    pub(crate) unknown_version: Option<u32>,
+    pub(crate) index_offset: u64,
+    pub(crate) index_size: u64,
+    pub(crate) hash: Hash,
+    pub(crate) frozen: bool,
+    pub(crate) compression: Vec<Option<Compression>>,
+    /// Name slots as stored, empty before V8A
+    pub(crate) raw_compression: Vec<[u8; 32]>,
+    /// Unknown bytes preceding the name slots in footers of games which extend FPakInfo
+    pub(crate) extra: Vec<u8>,
 }
 
 impl Footer {
-    pub fn read<R: std::io::Read>(reader: &mut R, version: Version) -> Result<Self, super::Error> {
+    /// Range at the end of a pak of `file_len` bytes which contains the footer of any version.
+    /// Footers with [`Footer::extra_bytes`] extend that many bytes further.
+    pub fn range(file_len: u64) -> std::ops::Range<u64> {
+        let size = Version::iter().map(Version::size).max().unwrap_or_default() as u64;
+        file_len.saturating_sub(size)..file_len
+    }
+
+    /// Parses the footer of a `version` pak from the end of `bytes`, which may also contain
+    /// bytes preceding the footer such as all of [`Footer::range`]
+    pub fn parse(bytes: &[u8], version: Version) -> Result<Self, super::Error> {
+        Self::parse_with_magic(bytes, version, super::MAGIC)
+    }
+
+    /// [`Footer::parse`] for games using a non-standard magic
+    pub fn parse_with_magic(
+        bytes: &[u8],
+        version: Version,
+        magic: u32,
+    ) -> Result<Self, super::Error> {
+        let start = bytes
+            .len()
+            .checked_sub(version.size() as usize)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+        Self::read(&mut &bytes[start..], version, magic, 0)
+    }
+
+    /// [`Footer::parse_with_magic`] for games which insert `extra_bytes` unknown bytes between
+    /// the standard fields and the compression name slots
+    pub fn parse_with_extra_bytes(
+        bytes: &[u8],
+        version: Version,
+        magic: u32,
+        extra_bytes: usize,
+    ) -> Result<Self, super::Error> {
+        let start = bytes
+            .len()
+            .checked_sub(version.size() as usize + extra_bytes)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+        Self::read(&mut &bytes[start..], version, magic, extra_bytes)
+    }
+
+    /// Size in bytes including any extra bytes
+    pub fn size(&self) -> u64 {
+        self.version.size() as u64 + self.extra.len() as u64
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn encrypted_index(&self) -> bool {
+        self.encrypted
+    }
+
+    pub fn encryption_guid(&self) -> Option<u128> {
+        self.encryption_uuid
+    }
+
+    /// Compression methods named by the name slots, in slot order
+    pub fn compression_slots(&self) -> Vec<CompressionSlot> {
+        self.raw_compression
+            .iter()
+            .map(|raw| CompressionSlot::parse(raw))
+            .collect()
+    }
+
+    /// Name slots as stored, for diagnosing names which don't parse
+    pub fn raw_compression_slots(&self) -> &[[u8; 32]] {
+        &self.raw_compression
+    }
+
+    /// Unknown bytes between the standard fields and the compression name slots, empty unless
+    /// the footer was parsed with [`Footer::parse_with_extra_bytes`]
+    pub fn extra_bytes(&self) -> &[u8] {
+        &self.extra
+    }
+
+    /// Range of the primary index
+    pub fn index_range(&self) -> std::ops::Range<u64> {
+        self.index_offset..self.index_offset + self.index_size
+    }
+
+    pub(crate) fn read<R: std::io::Read>(
+        reader: &mut R,
+        version: Version,
+        expected_magic: u32,
+        extra_bytes: usize,
+    ) -> Result<Self, super::Error> {
         let encryption_uuid = (version.version_major() >= VersionMajor::EncryptionKeyGuid)
             .then_try(|| reader.read_u128::<LE>())?;
         let encrypted =
             version.version_major() >= VersionMajor::IndexEncryption && reader.read_bool()?;
         let magic = reader.read_u32::<LE>()?;
-        let version_major =
-            VersionMajor::from_repr(reader.read_u32::<LE>()?).unwrap_or(version.version_major());
+        let raw_version = reader.read_u32::<LE>()?;
         let index_offset = reader.read_u64::<LE>()?;
         let index_size = reader.read_u64::<LE>()?;
         let hash = Hash(reader.read_guid()?);
         let frozen = version.version_major() == VersionMajor::FrozenIndex && reader.read_bool()?;
+        let mut extra = vec![0; extra_bytes];
+        reader.read_exact(&mut extra)?;
+        let raw_compression = (0..match version {
+            ver if ver < Version::V8A => 0,
+            ver if ver < Version::V8B => 4,
+            _ => 5,
+        })
+            .map(|_| {
+                let mut raw = [0; 32];
+                reader.read_exact(&mut raw).map(|_| raw)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
         let compression = {
-            let mut compression = Vec::with_capacity(match version {
-                ver if ver < Version::V8A => 0,
-                ver if ver < Version::V8B => 4,
-                _ => 5,
-            });
-            for _ in 0..compression.capacity() {
-                compression.push(
-                    Compression::from_str(
-                        &reader
-                            .read_len(32)?
-                            .iter()
-                            // filter out whitespace and convert to char
-                            .filter_map(|&ch| (ch != 0).then_some(ch as char))
-                            .collect::<String>(),
-                    )
-                    .ok(),
-                )
-            }
+            let mut compression = raw_compression
+                .iter()
+                .map(|raw| CompressionSlot::parse(raw).compression())
+                .collect::<Vec<_>>();
             if version.version_major() < VersionMajor::FNameBasedCompression {
                 compression.push(Some(Compression::Zlib));
                 compression.push(Some(Compression::Gzip));
@@ -60,9 +188,13 @@ impl Footer {
             }
             compression
         };
-        if super::MAGIC != magic {
-            return Err(super::Error::Magic(magic));
+        if expected_magic != magic {
+            return Err(super::Error::Magic {
+                found: magic,
+                expected: expected_magic,
+            });
         }
+        let version_major = VersionMajor::from_repr(raw_version).unwrap_or(version.version_major());
         if version.version_major() != version_major {
             return Err(super::Error::Version {
                 used: version.version_major(),
@@ -75,17 +207,24 @@ impl Footer {
             magic,
             version,
             version_major,
+            unknown_version: VersionMajor::from_repr(raw_version)
+                .is_none()
+                .then_some(raw_version),
             index_offset,
             index_size,
             hash,
             frozen,
             compression,
+            raw_compression,
+            extra,
         })
     }
 
-    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> Result<(), super::Error> {
+    pub(crate) fn write<W: std::io::Write>(&self, writer: &mut W) -> Result<(), super::Error> {
         if self.version_major >= VersionMajor::EncryptionKeyGuid {
-            writer.write_u128::<LE>(0)?;
+            // the GUID names the key of an encrypted index, so it is only written along with one
+            let guid = self.encryption_uuid.filter(|_| self.encrypted);
+            writer.write_u128::<LE>(guid.unwrap_or_default())?;
         }
         if self.version_major >= VersionMajor::IndexEncryption {
             writer.write_bool(self.encrypted)?;
@@ -98,6 +237,7 @@ impl Footer {
         if self.version_major == VersionMajor::FrozenIndex {
             writer.write_bool(self.frozen)?;
         }
+        writer.write_all(&self.extra)?;
         let algo_size = match self.version {
             ver if ver < Version::V8A => 0,
             ver if ver < Version::V8B => 4,
@@ -105,8 +245,23 @@ impl Footer {
         };
         // TODO: handle if compression.len() > algo_size
         for i in 0..algo_size {
+            let compression = self.compression.get(i).cloned().flatten();
+            // names as read are kept as long as they still name the method of the slot,
+            // including names which aren't known
+            if let Some(raw) =
+                self.raw_compression
+                    .get(i)
+                    .filter(|raw| match CompressionSlot::parse(&raw[..]) {
+                        CompressionSlot::Known(known) => compression == Some(known),
+                        CompressionSlot::Unknown(_) => compression.is_none(),
+                        CompressionSlot::Empty => false,
+                    })
+            {
+                writer.write_all(raw)?;
+                continue;
+            }
             let mut name = [0; 32];
-            if let Some(algo) = self.compression.get(i).cloned().flatten() {
+            if let Some(algo) = compression {
                 for (i, b) in algo.to_string().as_bytes().iter().enumerate() {
                     name[i] = *b;
                 }
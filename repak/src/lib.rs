@@ -4,12 +4,38 @@ mod entry;
 mod error;
 mod ext;
 mod footer;
+#[cfg(feature = "mod-metadata")]
+mod metadata;
 mod pak;
+pub mod path_hash;
+mod remap;
+mod streaming;
+mod transform;
 
-pub use {data::PartialEntry, error::*, pak::*};
+pub use {
+    data::PartialEntry,
+    entry::{BlockProbe, EntryExtents, EntryInfo, EntryReader, ZlibFormat},
+    error::*,
+    footer::{CompressionSlot, Footer},
+    pak::*,
+    remap::RemappedPak,
+    streaming::{SpillBuffer, StreamingPakWriter, DEFAULT_STREAMING_MEMORY_BUDGET},
+    transform::{DecryptLimit, EntryTransform},
+};
+
+#[cfg(feature = "mod-metadata")]
+pub use metadata::{ModMetadata, MOD_METADATA_FORMAT, MOD_METADATA_PATH};
 
 pub const MAGIC: u32 = 0x5A6F12E1;
 
+/// Downloads and loads the Oodle library now rather than when the first Oodle compressed entry
+/// is read or written, so tools can report the download up front. A failed load is retried by
+/// later calls after a short backoff.
+#[cfg(feature = "oodle")]
+pub fn preload_oodle() -> Result<(), Error> {
+    Ok(oodle_loader::preload()?)
+}
+
 #[derive(
     Clone,
     Copy,
@@ -37,6 +63,7 @@ pub enum Version {
     V9,
     V10,
     V11,
+    V12,
 }
 
 #[repr(u32)]
@@ -57,6 +84,7 @@ pub enum VersionMajor {
     FrozenIndex,           // v9 frozen index byte included
     PathHashIndex,         // v10
     Fnv64BugFix,           // v11
+    Utf8PakDirectory,      // v12 full directory index names stored as UTF-8
 }
 
 // strum shouldn't need to be installed by users
@@ -107,6 +135,7 @@ impl Version {
             Version::V9 => VersionMajor::FrozenIndex,
             Version::V10 => VersionMajor::PathHashIndex,
             Version::V11 => VersionMajor::Fnv64BugFix,
+            Version::V12 => VersionMajor::Utf8PakDirectory,
         }
     }
 }
@@ -123,7 +152,7 @@ pub enum Compression {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub(crate) enum Key {
     #[cfg(feature = "encryption")]
     Some(aes::Aes256),
@@ -131,6 +160,25 @@ pub(crate) enum Key {
     None,
 }
 
+impl Key {
+    /// Whether both keys are the same, compared through their encryption of the same block
+    fn same_as(&self, other: &Key) -> bool {
+        match (self, other) {
+            #[cfg(feature = "encryption")]
+            (Key::Some(a), Key::Some(b)) => {
+                use aes::cipher::BlockEncrypt;
+                let (mut block_a, mut block_b) = (aes::Block::default(), aes::Block::default());
+                a.encrypt_block(&mut block_a);
+                b.encrypt_block(&mut block_b);
+                block_a == block_b
+            }
+            (Key::None, Key::None) => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+}
+
 #[cfg(feature = "encryption")]
 impl From<aes::Aes256> for Key {
     fn from(value: aes::Aes256) -> Self {
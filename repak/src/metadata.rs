@@ -0,0 +1,102 @@
+use crate::{Error, PakReader, PakWriter};
+use std::io::{Read, Seek, Write};
+
+/// Entry path, relative to the mount point, of the metadata written by
+/// [`PakWriter::write_mod_metadata`]
+pub const MOD_METADATA_PATH: &str = "repak_mod.json";
+
+/// Format version written to the `format` field of the metadata
+pub const MOD_METADATA_FORMAT: u32 = 1;
+
+/// Description of a mod stored as JSON in the pak itself, so tools which install or list mods
+/// don't each need their own sidecar file
+///
+/// Fields unknown to this version, such as those of a newer [`ModMetadata::format`], are
+/// ignored when reading. All fields are free-form and none are required.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ModMetadata {
+    /// Format version of the JSON, [`MOD_METADATA_FORMAT`] when written by this version
+    #[serde(default = "default_format")]
+    pub format: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Other mods this one needs, in whatever form the mod manager understands
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+fn default_format() -> u32 {
+    MOD_METADATA_FORMAT
+}
+
+impl Default for ModMetadata {
+    fn default() -> Self {
+        Self {
+            format: MOD_METADATA_FORMAT,
+            name: None,
+            version: None,
+            author: None,
+            description: None,
+            dependencies: vec![],
+        }
+    }
+}
+
+impl ModMetadata {
+    /// Parses the JSON of the entry at `path`
+    pub fn from_json(path: &str, json: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(json).map_err(|err| Error::InvalidModMetadata {
+            path: path.to_owned(),
+            reason: err.to_string(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("metadata serializes")
+    }
+}
+
+impl PakReader {
+    /// Metadata at [`MOD_METADATA_PATH`], `None` if the pak has no such entry
+    pub fn mod_metadata<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+    ) -> Result<Option<ModMetadata>, Error> {
+        self.mod_metadata_at(MOD_METADATA_PATH, reader)
+    }
+
+    /// Metadata at `path` for paks which keep it somewhere other than [`MOD_METADATA_PATH`]
+    pub fn mod_metadata_at<R: Read + Seek>(
+        &self,
+        path: &str,
+        reader: &mut R,
+    ) -> Result<Option<ModMetadata>, Error> {
+        if self.entry_info(path).is_none() {
+            return Ok(None);
+        }
+        ModMetadata::from_json(path, &self.get(path, reader)?).map(Some)
+    }
+}
+
+impl<W: Write + Seek> PakWriter<W> {
+    /// Writes `metadata` to [`MOD_METADATA_PATH`], see [`PakReader::mod_metadata`]
+    pub fn write_mod_metadata(&mut self, metadata: &ModMetadata) -> Result<(), Error> {
+        self.write_mod_metadata_at(MOD_METADATA_PATH, metadata)
+    }
+
+    /// Writes `metadata` to `path`, see [`PakReader::mod_metadata_at`]
+    pub fn write_mod_metadata_at(
+        &mut self,
+        path: &str,
+        metadata: &ModMetadata,
+    ) -> Result<(), Error> {
+        self.write_file(path, true, metadata.to_json())?;
+        Ok(())
+    }
+}
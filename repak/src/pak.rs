@@ -1,14 +1,21 @@
-use crate::data::build_partial_entry;
-use crate::entry::Entry;
-use crate::{Compression, Error, PartialEntry};
-
-use super::ext::{ReadExt, WriteExt};
+use crate::data::{build_partial_entry, DEFAULT_COMPRESSION_THRESHOLD};
+use crate::entry::{
+    BlockProbe, Entry, EntryExtents, EntryInfo, EntryReader, ReadOptions, ZlibFormat,
+};
+use crate::streaming::{SpillBuffer, StreamingPakWriter};
+use crate::{Compression, DecryptLimit, EntryTransform, Error, PartialEntry};
+
+use super::ext::{checked_usize, BoolExt, RawString, ReadExt, WriteExt};
+use super::footer::{CompressionSlot, Footer};
 use super::{Version, VersionMajor};
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, Read, Seek, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct Hash(pub(crate) [u8; 20]);
 impl std::fmt::Debug for Hash {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -16,10 +23,31 @@ impl std::fmt::Debug for Hash {
     }
 }
 
-#[derive(Debug)]
+/// Settings for reading and writing paks. Cloning is cheap apart from the keys, so one builder
+/// can be set up once and reused for any number of paks, see [`Self::build_reader`].
+#[derive(Debug, Clone)]
 pub struct PakBuilder {
+    /// Key used for entry data
     key: super::Key,
+    index_key: super::Key,
+    /// Keys to pick from by the encryption key GUID of the pak
+    guid_keys: Vec<(u128, super::Key)>,
     allowed_compression: Vec<Compression>,
+    compression_threshold: f64,
+    magic: u32,
+    footer_extra_bytes: usize,
+    lossy_paths: bool,
+    parallel_index_parse: bool,
+    decompression_threads: usize,
+    verify_reads: bool,
+    metadata_only: bool,
+    keep_encoded_index: bool,
+    strict: bool,
+    ignore_index_hashes: bool,
+    skip_implausible_entries: bool,
+    streaming_memory_budget: u64,
+    transform: Option<Arc<dyn EntryTransform>>,
+    decrypt_limit: Arc<DecryptLimit>,
 }
 
 impl Default for PakBuilder {
@@ -32,28 +60,337 @@ impl PakBuilder {
     pub fn new() -> Self {
         Self {
             key: Default::default(),
+            index_key: Default::default(),
+            guid_keys: Default::default(),
             allowed_compression: Default::default(),
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            magic: super::MAGIC,
+            footer_extra_bytes: 0,
+            lossy_paths: false,
+            parallel_index_parse: false,
+            decompression_threads: 1,
+            verify_reads: false,
+            metadata_only: false,
+            keep_encoded_index: false,
+            strict: false,
+            ignore_index_hashes: false,
+            skip_implausible_entries: false,
+            streaming_memory_budget: crate::streaming::DEFAULT_STREAMING_MEMORY_BUDGET,
+            transform: None,
+            decrypt_limit: Default::default(),
         }
     }
+    /// Key used for both the index and entry data
     #[cfg(feature = "encryption")]
     pub fn key(mut self, key: aes::Aes256) -> Self {
+        self.index_key = super::Key::Some(key.clone());
+        self.key = super::Key::Some(key);
+        self
+    }
+    /// Key used for the index, for paks whose entry data uses a different key
+    #[cfg(feature = "encryption")]
+    pub fn index_key(mut self, key: aes::Aes256) -> Self {
+        self.index_key = super::Key::Some(key);
+        self
+    }
+    /// Key used for entry data, for paks whose index uses a different key
+    #[cfg(feature = "encryption")]
+    pub fn data_key(mut self, key: aes::Aes256) -> Self {
         self.key = super::Key::Some(key);
         self
     }
+    /// Keys of a game by their GUID, such as those listed in a crypto.json. When reading, the
+    /// key matching the encryption key GUID in the footer is used for the index and entry data
+    /// unless they were set explicitly. Paks without a GUID use the key of GUID 0.
+    #[cfg(feature = "encryption")]
+    pub fn keys_by_guid(mut self, keys: impl IntoIterator<Item = (u128, aes::Aes256)>) -> Self {
+        self.guid_keys = keys
+            .into_iter()
+            .map(|(guid, key)| (guid, super::Key::Some(key)))
+            .collect();
+        self
+    }
+    /// Compression methods entries may be written with. Each is tried and the smallest output
+    /// kept, see [`Self::compression_threshold`] for when data is stored uncompressed instead.
     #[cfg(feature = "compression")]
     pub fn compression(mut self, compression: impl IntoIterator<Item = Compression>) -> Self {
         self.allowed_compression = compression.into_iter().collect();
         self
     }
+    /// Ratio of the compressed to the uncompressed size above which written data is stored
+    /// uncompressed, like UnrealPak does for data that barely shrinks. Defaults to 0.95, while
+    /// a value of 1.0 or more keeps all data that doesn't grow compressed.
+    #[cfg(feature = "compression")]
+    pub fn compression_threshold(mut self, threshold: f64) -> Self {
+        self.compression_threshold = threshold;
+        self
+    }
+    /// Override the magic expected in and written to the footer for games which changed it
+    pub fn magic(mut self, magic: u32) -> Self {
+        self.magic = magic;
+        self
+    }
+    /// Number of unknown bytes some games insert into the footer between the standard fields
+    /// and the compression name slots. Footers which don't parse as standard are retried with
+    /// that many extra bytes, which are kept and written back when the pak is rewritten.
+    pub fn footer_extra_bytes(mut self, extra_bytes: usize) -> Self {
+        self.footer_extra_bytes = extra_bytes;
+        self
+    }
+    /// Read names which are not valid UTF-8 or UTF-16 lossily instead of failing. Their
+    /// original encoding is kept and written back when the pak is rewritten.
+    pub fn lossy_paths(mut self, lossy: bool) -> Self {
+        self.lossy_paths = lossy;
+        self
+    }
+    /// Read indexes whose SHA-1 doesn't match the hash stored for them instead of failing
+    /// with [`super::Error::IndexHashMismatch`], to salvage what can be parsed of a truncated
+    /// or corrupt index
+    pub fn ignore_index_hashes(mut self, ignore: bool) -> Self {
+        self.ignore_index_hashes = ignore;
+        self
+    }
+    /// Skip V10+ entries whose compression block count can't be valid for their size, reported
+    /// as [`IndexWarning::ImplausibleBlockCount`], instead of failing with
+    /// [`super::Error::ImplausibleBlockCount`], to salvage the other entries of a corrupt index
+    pub fn skip_implausible_entries(mut self, skip: bool) -> Self {
+        self.skip_implausible_entries = skip;
+        self
+    }
+    /// Bytes of the pak staged in memory by [`Self::writer_streaming`] before the rest goes to
+    /// a temporary file, see [`super::DEFAULT_STREAMING_MEMORY_BUDGET`]
+    pub fn streaming_memory_budget(mut self, budget: u64) -> Self {
+        self.streaming_memory_budget = budget;
+        self
+    }
+    /// Decode the encoded entries of V10+ indexes on all available threads. Worthwhile for
+    /// indexes of hundreds of thousands of entries, while smaller indexes are decoded on the
+    /// calling thread regardless. The result is the same as when decoding serially.
+    pub fn parallel_index_parse(mut self, parallel: bool) -> Self {
+        self.parallel_index_parse = parallel;
+        self
+    }
+    /// Number of threads decompressing the blocks of a single entry in parallel when reading
+    /// entries split into several compression blocks. Defaults to 1, which decompresses on the
+    /// calling thread.
+    #[cfg(feature = "compression")]
+    pub fn decompression_threads(mut self, threads: usize) -> Self {
+        self.decompression_threads = threads.max(1);
+        self
+    }
+    /// Check the data of entries as they are read: the output must have the uncompressed size
+    /// of the entry and the stored data must match its hash. Mismatches fail the read with
+    /// [`super::Error::VerificationFailed`]. Off by default as hashing costs time on every read.
+    pub fn verify_reads(mut self, verify: bool) -> Self {
+        self.verify_reads = verify;
+        self
+    }
+    /// Read only the header of the primary index, skipping the entries and the secondary
+    /// indexes. The version, mount point, encryption details, path hash seed and
+    /// [`PakReader::entry_count`] are available, while reading entries fails with
+    /// [`super::Error::IndexNotLoaded`] and no paths are listed.
+    pub fn metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+    /// Keep the encoded entries and the secondary indexes of V10+ paks as stored (after
+    /// decryption) instead of discarding them once parsed, for tools which patch or re-emit
+    /// them. See [`PakReader::encoded_entry_bytes`] and [`PakReader::raw_index_sections`]. Off
+    /// by default as it keeps about as much memory again as the index takes on disk.
+    pub fn keep_encoded_index(mut self, keep: bool) -> Self {
+        self.keep_encoded_index = keep;
+        self
+    }
+    /// Fail with [`super::Error::StrictWarning`] instead of reading paks with any of the
+    /// problems listed by [`PakReader::warnings`], which are otherwise worked around. Off by
+    /// default.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+    /// Game specific obfuscation of entry data, undone when reading entries and applied when
+    /// writing them. See [`EntryTransform`] for where it applies relative to encryption and
+    /// compression.
+    pub fn entry_transform(mut self, transform: Box<dyn EntryTransform>) -> Self {
+        self.transform = Some(transform.into());
+        self
+    }
+    /// How much of the data of encrypted entries to decrypt, all of it by default. For games
+    /// which encrypt only a prefix of each entry, see [`DecryptLimit`].
+    pub fn decrypt_limit(mut self, limit: DecryptLimit) -> Self {
+        self.decrypt_limit = limit.into();
+        self
+    }
     pub fn reader<R: Read + Seek>(self, reader: &mut R) -> Result<PakReader, super::Error> {
-        PakReader::new_any_inner(reader, self.key)
+        let this = self.with_guid_key(reader)?;
+        PakReader::new_any_inner(
+            reader,
+            &this.index_key,
+            this.key,
+            this.magic,
+            this.footer_extra_bytes,
+            this.lossy_paths,
+            this.parallel_index_parse,
+            this.metadata_only,
+            !this.ignore_index_hashes,
+            this.skip_implausible_entries,
+        )
+        .map(|pak| {
+            pak.with_read_options(
+                this.decompression_threads,
+                this.verify_reads,
+                this.transform,
+                this.decrypt_limit,
+            )
+            .retain_encoded_index(this.keep_encoded_index)
+        })
+        .and_then(|pak| pak.check_strict(this.strict))
     }
     pub fn reader_with_version<R: Read + Seek>(
         self,
         reader: &mut R,
         version: super::Version,
     ) -> Result<PakReader, super::Error> {
-        PakReader::new_inner(reader, version, self.key)
+        let this = self.with_guid_key(reader)?;
+        let pak = Pak::read(
+            reader,
+            version,
+            &this.index_key,
+            this.magic,
+            this.footer_extra_bytes,
+            this.lossy_paths,
+            this.parallel_index_parse,
+            this.metadata_only,
+            !this.ignore_index_hashes,
+            this.skip_implausible_entries,
+        )?;
+        PakReader {
+            pak,
+            key: this.key,
+            decompression_threads: this.decompression_threads,
+            verify_reads: this.verify_reads,
+            zlib_format: Default::default(),
+            directories: Default::default(),
+            transform: this.transform,
+            decrypt_limit: this.decrypt_limit,
+            detection: None,
+        }
+        .retain_encoded_index(this.keep_encoded_index)
+        .check_strict(this.strict)
+    }
+    /// Fills in the keys which weren't set explicitly from [`Self::keys_by_guid`] using the
+    /// GUID in the footer of `reader`
+    fn with_guid_key<R: Read + Seek>(mut self, reader: &mut R) -> Result<Self, super::Error> {
+        if self.guid_keys.is_empty() {
+            return Ok(self);
+        }
+        let range = Footer::range(reader.seek(io::SeekFrom::End(0))?);
+        let start = range.start.saturating_sub(self.footer_extra_bytes as u64);
+        reader.seek(io::SeekFrom::Start(start))?;
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        // reading the pak reports the error if the footer doesn't parse
+        let Ok(footer) = self.parse_footer(&bytes) else {
+            return Ok(self);
+        };
+        let guid = footer.encryption_guid().unwrap_or_default();
+        if let Some((_, key)) = self.guid_keys.iter().find(|(g, _)| *g == guid) {
+            if matches!(self.index_key, super::Key::None) {
+                self.index_key = key.clone();
+            }
+            if matches!(self.key, super::Key::None) {
+                self.key = key.clone();
+            }
+        }
+        Ok(self)
+    }
+    /// Parses the footer from the end of `bytes`, probing the version like [`Self::reader`].
+    /// `bytes` may contain more than the footer, such as all of [`Footer::range`], and must
+    /// include the bytes added by [`Self::footer_extra_bytes`] in front of that range.
+    ///
+    /// Together with [`Self::secondary_index_ranges`] and [`Self::parse_index`] this allows
+    /// reading a pak from only the byte ranges it needs, e.g. over HTTP range requests.
+    pub fn parse_footer(&self, bytes: &[u8]) -> Result<Footer, super::Error> {
+        use std::fmt::Write;
+        let mut log = "\n".to_owned();
+
+        for ver in Version::iter() {
+            match parse_footer(bytes, ver, self.magic, self.footer_extra_bytes) {
+                Ok(footer) => return Ok(footer),
+                Err(err) => writeln!(log, "trying version {} failed: {}", ver, err)?,
+            }
+        }
+        Err(super::Error::UnsupportedOrEncrypted(log))
+    }
+    /// Ranges of the secondary indexes referenced by `index`, the bytes at
+    /// [`Footer::index_range`]. Both are `None` before V10.
+    pub fn secondary_index_ranges(
+        &self,
+        footer: &Footer,
+        index: &[u8],
+    ) -> Result<SecondaryIndexRanges, super::Error> {
+        let index = decrypt_index(footer, index.to_vec(), &self.index_key)?;
+        IndexHeader::read(&mut io::Cursor::new(&index), footer.version)
+            .map(|header| header.secondary)
+            .map_err(|err| wrong_key_or_corrupt(footer, err))
+    }
+    /// Creates a reader from the bytes at [`Footer::index_range`] and
+    /// [`Self::secondary_index_ranges`] without access to the rest of the pak
+    pub fn parse_index(
+        self,
+        footer: &Footer,
+        index: &[u8],
+        path_hash_index: Option<&[u8]>,
+        full_directory_index: Option<&[u8]>,
+    ) -> Result<PakReader, super::Error> {
+        let index = decrypt_index(footer, index.to_vec(), &self.index_key)?;
+        let verify_hashes = !self.ignore_index_hashes;
+        let pak = (|| {
+            if verify_hashes {
+                verify_index_hash(IndexSection::Primary, &footer.hash, &index)?;
+            }
+            Pak::parse(
+                footer,
+                &index,
+                path_hash_index,
+                full_directory_index,
+                &self.index_key,
+                self.lossy_paths,
+                self.parallel_index_parse,
+                verify_hashes,
+                self.skip_implausible_entries,
+            )
+        })()
+        .map_err(|err| wrong_key_or_corrupt(footer, err))?;
+        PakReader {
+            pak,
+            key: self.key,
+            decompression_threads: self.decompression_threads,
+            verify_reads: self.verify_reads,
+            zlib_format: Default::default(),
+            directories: Default::default(),
+            transform: self.transform,
+            decrypt_limit: self.decrypt_limit,
+            detection: None,
+        }
+        .retain_encoded_index(self.keep_encoded_index)
+        .check_strict(self.strict)
+    }
+    /// [`Self::reader`] without consuming the builder, for opening many paks with the same
+    /// settings
+    pub fn build_reader<R: Read + Seek>(&self, reader: &mut R) -> Result<PakReader, super::Error> {
+        self.clone().reader(reader)
+    }
+    /// [`Self::writer`] without consuming the builder
+    pub fn build_writer<W: Write + Seek>(
+        &self,
+        writer: W,
+        version: super::Version,
+        mount_point: String,
+        path_hash_seed: Option<u64>,
+    ) -> PakWriter<W> {
+        self.clone()
+            .writer(writer, version, mount_point, path_hash_seed)
     }
     pub fn writer<W: Write + Seek>(
         self,
@@ -62,13 +399,33 @@ impl PakBuilder {
         mount_point: String,
         path_hash_seed: Option<u64>,
     ) -> PakWriter<W> {
-        PakWriter::new_inner(
+        let mut writer = PakWriter::new_inner(
             writer,
             self.key,
             version,
             mount_point,
             path_hash_seed,
             self.allowed_compression,
+            self.magic,
+        );
+        writer.compression_threshold = self.compression_threshold;
+        writer.transform = self.transform;
+        writer
+    }
+    /// Writer for outputs which can't seek, such as pipes. The whole pak is staged before any
+    /// of it is written to `writer`, taking up to [`Self::streaming_memory_budget`] bytes of
+    /// memory and as much temporary file space as the rest of the pak needs.
+    pub fn writer_streaming<W: Write>(
+        self,
+        writer: W,
+        version: super::Version,
+        mount_point: String,
+        path_hash_seed: Option<u64>,
+    ) -> StreamingPakWriter<W> {
+        let staging = SpillBuffer::new(self.streaming_memory_budget);
+        StreamingPakWriter::new(
+            self.writer(staging, version, mount_point, path_hash_seed),
+            writer,
         )
     }
 }
@@ -77,6 +434,16 @@ impl PakBuilder {
 pub struct PakReader {
     pak: Pak,
     key: super::Key,
+    decompression_threads: usize,
+    verify_reads: bool,
+    /// Detected when the first Zlib block is decompressed, see [`PakReader::zlib_format`]
+    zlib_format: OnceLock<ZlibFormat>,
+    transform: Option<Arc<dyn EntryTransform>>,
+    decrypt_limit: Arc<DecryptLimit>,
+    detection: Option<VersionDetection>,
+    /// Directories by path without a trailing slash, built on the first call of
+    /// [`PakReader::list_dir`] or [`PakReader::dir_exists`]
+    directories: OnceLock<BTreeMap<String, Directory>>,
 }
 
 #[derive(Debug)]
@@ -85,25 +452,255 @@ pub struct PakWriter<W: Write + Seek> {
     writer: W,
     key: super::Key,
     allowed_compression: Vec<Compression>,
+    compression_threshold: f64,
+    padding: Padding,
+    /// Entries written by [`PakWriter::write_file_dedup`] by the SHA256 of their data and
+    /// whether compression was allowed
+    dedup: HashMap<([u8; 32], bool), Entry>,
+    /// Stored bytes not written again thanks to shared entries
+    deduplicated: u64,
+    transform: Option<Arc<dyn EntryTransform>>,
+}
+
+/// Alignment requirements applied when placing the index
+#[derive(Debug, Default, Clone)]
+struct Padding {
+    data_region: Option<u64>,
+    file: Option<u64>,
+}
+
+impl Padding {
+    /// Returns the first offset at or after `data_end` satisfying both alignments for an index
+    /// (including everything following it up to the end of the footer) of `index_len` bytes
+    fn index_offset(&self, data_end: u64, index_len: u64) -> Result<u64, Error> {
+        let overflow = || {
+            Error::Other(format!(
+                "an index of {index_len} bytes padded after offset {data_end:#x} ends past the largest possible offset"
+            ))
+        };
+        let step = self.data_region.unwrap_or(1).max(1);
+        let index_offset = data_end
+            .checked_next_multiple_of(step)
+            .ok_or_else(overflow)?;
+        let Some(file) = self.file.filter(|a| *a > 1) else {
+            return Ok(index_offset);
+        };
+        let end = index_offset.checked_add(index_len).ok_or_else(overflow)?;
+        // the index moves `step` bytes at a time, so it needs the fewest steps `k` with
+        // `k * step = missing (mod file)`, which only exist if the gcd divides `missing`
+        let missing = (file - end % file) % file;
+        let gcd = gcd(step, file);
+        if !missing.is_multiple_of(gcd) {
+            return Err(Error::Other(format!(
+                "cannot pad data region to a multiple of {step} and file to a multiple of {file}"
+            )));
+        }
+        let modulus = file / gcd;
+        let steps = (missing / gcd) as u128
+            * modular_inverse(step / gcd % modulus, modulus) as u128
+            % modulus as u128;
+        (steps as u64)
+            .checked_mul(step)
+            .and_then(|padding| index_offset.checked_add(padding))
+            .ok_or_else(overflow)
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Inverse of `a` modulo `modulus`, which must be coprime with `a`
+fn modular_inverse(a: u64, modulus: u64) -> u64 {
+    let (mut r, mut next_r) = (modulus as i128, a as i128);
+    let (mut t, mut next_t) = (0i128, 1i128);
+    while next_r != 0 {
+        let q = r / next_r;
+        (r, next_r) = (next_r, r - q * next_r);
+        (t, next_t) = (next_t, t - q * next_t);
+    }
+    t.rem_euclid(modulus as i128) as u64
 }
 
 #[derive(Debug)]
 pub(crate) struct Pak {
     version: Version,
+    magic: u32,
     mount_point: String,
+    /// Characters of the mount point as stored, see [`PakReader::mount_point_raw`]
+    mount_point_raw: Vec<u8>,
     index_offset: Option<u64>,
+    /// Offsets of the first and last entry records of a pak which was read, which may be after
+    /// the index for paks written by some third-party packers
+    entry_offsets: Option<std::ops::RangeInclusive<u64>>,
+    /// Byte ranges of the primary and secondary indexes of a pak which was read
+    index_ranges: Vec<std::ops::Range<u64>>,
     index: Index,
     encrypted_index: bool,
     encryption_guid: Option<u128>,
     compression: Vec<Option<Compression>>,
+    /// Compression name slots of a pak which was read as stored. Entries index the slots by
+    /// position, so slots keep their order and names repak doesn't know keep their slot.
+    compression_names: Vec<[u8; 32]>,
+    /// Unknown footer bytes of a pak which was read, written back unchanged
+    footer_extra: Vec<u8>,
+    /// Records of the path hash index of a V10+ pak which was read, as (path hash, offset of the
+    /// encoded entry)
+    path_hash_records: Option<Vec<(u64, u32)>>,
+    /// Offsets of the encoded entries of a V10+ pak which was read by path. Entries keep their
+    /// order by this offset when the index is written again.
+    encoded_offsets: BTreeMap<String, u32>,
+    /// Whether the V10+ path hash index is present
+    path_hash_index: bool,
+    /// Whether the V10+ full directory index is present
+    full_directory_index: bool,
+    /// Entry count from the index header of a pak read with [`PakBuilder::metadata_only`],
+    /// whose entries weren't read
+    unread_entries: Option<u32>,
+    /// Index sections of a V10+ pak which was read, dropped unless
+    /// [`PakBuilder::keep_encoded_index`] is set
+    encoded_index: Option<EncodedIndex>,
+    /// Problems of the index of a pak which was read which didn't prevent reading it
+    warnings: Vec<IndexWarning>,
+}
+
+/// Problem of the index of a pak which repak works around the way the engine does, see
+/// [`PakReader::warnings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexWarning {
+    /// A file listed more than once by the full directory index with different encoded entry
+    /// offsets, of which the first is used
+    ConflictingDuplicate {
+        path: String,
+        used: u32,
+        ignored: u32,
+    },
+    /// A file listed by the full directory index with the invalid encoded entry offset
+    /// `0x80000000`, which is skipped
+    InvalidEncodedOffset { path: String },
+    /// The mount point of a plaintext index is encrypted, which some games do to obfuscate it,
+    /// and was decrypted with the key
+    EncryptedMountPoint,
+    /// The mount point is not readable text and is replaced with [`UNREADABLE_MOUNT_POINT`],
+    /// see [`PakReader::mount_point_raw`] for the original
+    UnreadableMountPoint,
+    /// Compression name slot `slot` of the footer names a method repak doesn't support, so
+    /// entries compressed with it fail to read, see [`Footer::compression_slots`]
+    UnknownCompressionName { slot: usize, name: String },
+    /// A V10+ encoded entry declares a compression block count its size doesn't allow, see
+    /// [`super::Error::ImplausibleBlockCount`], and is skipped as set by
+    /// [`PakBuilder::skip_implausible_entries`]
+    ImplausibleBlockCount {
+        path: String,
+        count: u64,
+        uncompressed: u64,
+    },
+    /// A compressed entry of several blocks stores a compression block size of 0 or one larger
+    /// than the entry, so its blocks are read as the `derived` size implied by their count
+    DerivedBlockSize {
+        path: String,
+        stored: u32,
+        derived: u64,
+    },
+    /// The footer stores a version number newer than any repak knows, and the pak was read
+    /// with the layout of `read_as`
+    UnknownVersion { version: u32, read_as: Version },
+}
+
+impl std::fmt::Display for IndexWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexWarning::ConflictingDuplicate {
+                path,
+                used,
+                ignored,
+            } => write!(
+                f,
+                "{path} is listed more than once by the full directory index, using the entry at encoded offset {used:#x} and ignoring the one at {ignored:#x}"
+            ),
+            IndexWarning::InvalidEncodedOffset { path } => write!(
+                f,
+                "{path} has the invalid encoded entry offset 0x80000000 and is skipped"
+            ),
+            IndexWarning::EncryptedMountPoint => write!(
+                f,
+                "the mount point is encrypted although the index is not and was decrypted with the key"
+            ),
+            IndexWarning::UnreadableMountPoint => write!(
+                f,
+                "the mount point is not readable text, it may be encrypted and need a key, and is replaced with {UNREADABLE_MOUNT_POINT}"
+            ),
+            IndexWarning::UnknownCompressionName { slot, name } => write!(
+                f,
+                "compression slot {slot} names {name:?}, which is not supported, so entries compressed with it can't be read"
+            ),
+            IndexWarning::ImplausibleBlockCount {
+                path,
+                count,
+                uncompressed,
+            } => write!(
+                f,
+                "{path} has {count} compression blocks for {uncompressed} uncompressed bytes, which can't be valid, and is skipped"
+            ),
+            IndexWarning::DerivedBlockSize {
+                path,
+                stored,
+                derived,
+            } => write!(
+                f,
+                "{path} has the unusable compression block size {stored}, reading its blocks as {derived} bytes each from their count instead"
+            ),
+            IndexWarning::UnknownVersion { version, read_as } => write!(
+                f,
+                "the pak is version {version}, which is not supported, and was read as {read_as}"
+            ),
+        }
+    }
+}
+
+/// Mount point reported in place of one which is not readable text, see
+/// [`IndexWarning::UnreadableMountPoint`]
+pub const UNREADABLE_MOUNT_POINT: &str = "<unreadable mount point>";
+
+/// How the version of a pak was chosen among the versions it parses as, see
+/// [`PakReader::version_detection`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionDetection {
+    /// Plausibility checks passed by the chosen version out of [`Self::max_score`]: a readable
+    /// mount point, at least one entry, and entries within the bounds of the file. Entries
+    /// aren't checked with [`PakBuilder::metadata_only`] as they aren't read.
+    pub score: u32,
+    pub max_score: u32,
+    /// Best of the other versions the pak also parsed as, with its score
+    pub runner_up: Option<(Version, u32)>,
+}
+
+/// Number of checks of [`Pak::plausibility`]
+const PLAUSIBILITY_CHECKS: u32 = 3;
+
+/// Decrypted index sections of a V10+ pak as stored
+#[derive(Debug, Default)]
+struct EncodedIndex {
+    entries: Vec<u8>,
+    path_hash_index: Option<Vec<u8>>,
+    full_directory_index: Option<Vec<u8>>,
+    /// Range of the encoded entry of each path in `entries`
+    ranges: HashMap<String, std::ops::Range<usize>>,
 }
 
 impl Pak {
-    fn new(version: Version, mount_point: String, path_hash_seed: Option<u64>) -> Self {
+    fn new(version: Version, magic: u32, mount_point: String, path_hash_seed: Option<u64>) -> Self {
         Pak {
             version,
+            magic,
+            mount_point_raw: RawString::encode(&mount_point, false).to_bytes(),
             mount_point,
             index_offset: None,
+            entry_offsets: None,
+            index_ranges: vec![],
             index: Index::new(path_hash_seed),
             encrypted_index: false,
             encryption_guid: None,
@@ -116,6 +713,72 @@ impl Pak {
             } else {
                 vec![]
             }),
+            compression_names: vec![],
+            footer_extra: vec![],
+            path_hash_records: None,
+            encoded_offsets: Default::default(),
+            path_hash_index: true,
+            full_directory_index: true,
+            unread_entries: None,
+            encoded_index: None,
+            warnings: vec![],
+        }
+    }
+
+    /// Bytes taken by `entry` in the pak, from its record through its data as stored including
+    /// any encryption padding
+    fn entry_extent(&self, entry: &super::entry::Entry) -> std::ops::Range<u64> {
+        let record = Entry::get_serialized_size(
+            self.version,
+            entry.compression_slot,
+            entry
+                .blocks
+                .as_ref()
+                .map_or(0, |blocks| blocks.len() as u32),
+        );
+        let data = match entry.is_encrypted() {
+            true => entry.compressed.next_multiple_of(16),
+            false => entry.compressed,
+        };
+        entry.offset..entry.offset + record + data
+    }
+
+    /// [`EntryInfo`] of `entry` at `path`, with the encoded offset of V10+ paks
+    fn entry_info(&self, path: &str, entry: &super::entry::Entry) -> EntryInfo {
+        EntryInfo {
+            encoded_offset: self.encoded_offsets.get(path).copied(),
+            ..entry.info(&self.compression)
+        }
+    }
+
+    /// Cheap checks that the index is what this version reads rather than bytes which happen to
+    /// parse with its layout, such as a footer of another version lining up by chance. Returns
+    /// the number of [`PLAUSIBILITY_CHECKS`] passed.
+    fn plausibility(&self, file_len: u64) -> u32 {
+        let readable = self.mount_point != UNREADABLE_MOUNT_POINT
+            && !self.mount_point.chars().any(char::is_control);
+        let has_entries = match self.unread_entries {
+            Some(count) => count > 0,
+            None => !self.index.entries().is_empty(),
+        };
+        let in_bounds = self.index.entries().values().all(|entry| {
+            entry
+                .offset
+                .checked_add(entry.compressed)
+                .is_some_and(|end| end <= file_len)
+        });
+        [readable, has_entries, in_bounds]
+            .into_iter()
+            .filter(|passed| *passed)
+            .count() as u32
+    }
+
+    /// End of the data region containing `entry`. Entries are expected to precede the index so
+    /// reads past the index offset are only permitted for entries located after it.
+    fn data_end(&self, entry: &super::entry::Entry) -> u64 {
+        match self.index_offset {
+            Some(index_offset) if entry.offset < index_offset => index_offset,
+            _ => u64::MAX,
         }
     }
 }
@@ -124,6 +787,12 @@ impl Pak {
 pub(crate) struct Index {
     path_hash_seed: Option<u64>,
     entries: BTreeMap<String, super::entry::Entry>,
+    /// Original encoding of names which were read lossily or would be written differently,
+    /// keyed by the converted name as it appears in the index: the mount point, full paths
+    /// before V10 and the directory and file names of the full directory index from V10
+    raw_names: BTreeMap<String, RawString>,
+    /// Write names without a recorded encoding as UTF-16 even if they are ASCII
+    wide_strings: bool,
 }
 
 impl Index {
@@ -138,6 +807,13 @@ impl Index {
         &self.entries
     }
 
+    /// Entries in the order they are written to the index, see [`compare_paths`]
+    fn sorted_entries(&self) -> Vec<(&String, &super::entry::Entry)> {
+        let mut entries = Vec::from_iter(&self.entries);
+        entries.sort_by(|(a, _), (b, _)| compare_paths(a, b));
+        entries
+    }
+
     fn into_entries(self) -> BTreeMap<String, super::entry::Entry> {
         self.entries
     }
@@ -145,6 +821,153 @@ impl Index {
     fn add_entry(&mut self, path: String, entry: super::entry::Entry) {
         self.entries.insert(path, entry);
     }
+
+    /// Original encoding of `name` if it was read lossily
+    fn lossy_name(&self, name: &str) -> Option<&RawString> {
+        self.raw_names.get(name).filter(|raw| raw.decode().is_err())
+    }
+
+    /// Whether any part of `path` was read lossily
+    fn is_lossy(&self, path: &str) -> bool {
+        self.lossy_name(path).is_some()
+            || split_path_child(path).is_some_and(|(directory, filename)| {
+                self.lossy_name(directory).is_some() || self.lossy_name(filename).is_some()
+            })
+    }
+
+    /// `path` as decoded by the engine, which differs from the lossily converted path
+    fn engine_path<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let engine = |name: &str| self.lossy_name(name).map(RawString::decode_engine);
+        if let Some(path) = engine(path) {
+            return Cow::Owned(path);
+        }
+        match split_path_child(path) {
+            Some((directory, filename)) if self.is_lossy(path) => {
+                let directory = engine(directory).unwrap_or_else(|| directory.to_owned());
+                let filename = engine(filename).unwrap_or_else(|| filename.to_owned());
+                Cow::Owned(format!(
+                    "{}{filename}",
+                    directory.strip_prefix('/').unwrap_or(&directory)
+                ))
+            }
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    /// Writes `name`, using its original encoding if one was recorded when reading
+    fn write_name<W: Write>(&self, writer: &mut W, name: &str) -> Result<(), super::Error> {
+        match self.raw_names.get(name) {
+            Some(raw) => writer.write_raw_string(raw),
+            None => writer.write_raw_string(&RawString::encode(name, self.wide_strings)),
+        }
+    }
+
+    /// Writes a directory or file name of the full directory index, which from V12 stores
+    /// names without a recorded encoding as UTF-8 rather than UTF-16
+    fn write_directory_name<W: Write>(
+        &self,
+        writer: &mut W,
+        name: &str,
+        version: Version,
+    ) -> Result<(), super::Error> {
+        match self.raw_names.get(name) {
+            None if version.version_major() >= VersionMajor::Utf8PakDirectory => {
+                writer.write_raw_string(&RawString::Narrow(name.bytes().chain([0]).collect()))
+            }
+            _ => self.write_name(writer, name),
+        }
+    }
+}
+
+/// Reads a name from the index. Names which are not valid UTF-8 or UTF-16 are an error unless
+/// `lossy` is set, in which case they are converted lossily and their original encoding
+/// recorded in `raw_names`. The encoding of valid names is recorded too if writing them would
+/// produce different bytes, such as ASCII names serialized as UTF-16, so rewrites preserve it.
+fn read_name<R: Read>(
+    reader: &mut R,
+    raw_names: &mut BTreeMap<String, RawString>,
+    lossy: bool,
+) -> Result<String, super::Error> {
+    decode_name(reader.read_raw_string()?, raw_names, lossy)
+}
+
+/// Problems of the footer, reported along with those of the index
+fn footer_warnings(footer: &Footer) -> Vec<IndexWarning> {
+    let mut warnings = vec![];
+    if let Some(version) = footer.unknown_version {
+        warnings.push(IndexWarning::UnknownVersion {
+            version,
+            read_as: footer.version,
+        });
+    }
+    for (slot, compression) in footer.compression_slots().into_iter().enumerate() {
+        if let CompressionSlot::Unknown(name) = compression {
+            warnings.push(IndexWarning::UnknownCompressionName { slot, name });
+        }
+    }
+    warnings
+}
+
+/// Decodes the mount point. Some games obfuscate the mount point of an otherwise plaintext index
+/// by encrypting it, so if the mount point of a plaintext index is not readable text it is
+/// decrypted with `key`, or replaced with [`UNREADABLE_MOUNT_POINT`] if that doesn't help.
+/// Either way the original is recorded in `raw_names` so rewrites preserve it.
+fn decode_mount_point(
+    raw: RawString,
+    footer: &Footer,
+    #[allow(unused)] key: &super::Key,
+    raw_names: &mut BTreeMap<String, RawString>,
+    lossy: bool,
+    warnings: &mut Vec<IndexWarning>,
+) -> Result<String, super::Error> {
+    let readable = |name: &str| !name.chars().any(char::is_control);
+    if footer.encrypted || matches!(raw.decode(), Ok(name) if readable(&name)) {
+        return decode_name(raw, raw_names, lossy);
+    }
+    #[cfg(feature = "encryption")]
+    if let (super::Key::Some(_), RawString::Narrow(chars)) = (key, &raw) {
+        if !chars.is_empty() && chars.len() % 16 == 0 {
+            let mut decrypted = chars.clone();
+            decrypt(key, &mut decrypted)?;
+            if let Ok(name) = RawString::Narrow(decrypted).decode() {
+                if !name.is_empty() && readable(&name) {
+                    raw_names.insert(name.clone(), raw);
+                    warnings.push(IndexWarning::EncryptedMountPoint);
+                    return Ok(name);
+                }
+            }
+        }
+    }
+    raw_names.insert(UNREADABLE_MOUNT_POINT.to_owned(), raw);
+    warnings.push(IndexWarning::UnreadableMountPoint);
+    Ok(UNREADABLE_MOUNT_POINT.to_owned())
+}
+
+/// Decodes a name read from the index, see [`read_name`]
+fn decode_name(
+    raw: RawString,
+    raw_names: &mut BTreeMap<String, RawString>,
+    lossy: bool,
+) -> Result<String, super::Error> {
+    if let Ok(name) = raw.decode() {
+        if raw != RawString::encode(&name, false) {
+            raw_names.entry(name.clone()).or_insert(raw);
+        }
+        return Ok(name);
+    }
+    let name = raw.decode_lossy();
+    if !lossy {
+        return Err(super::Error::InvalidName(name));
+    }
+    match raw_names.get(&name) {
+        Some(existing) if *existing != raw => Err(super::Error::Other(format!(
+            "multiple names in the index are read lossily as \"{name}\""
+        ))),
+        _ => {
+            raw_names.insert(name.clone(), raw);
+            Ok(name)
+        }
+    }
 }
 
 #[cfg(feature = "encryption")]
@@ -160,89 +983,1158 @@ fn decrypt(key: &super::Key, bytes: &mut [u8]) -> Result<(), super::Error> {
     }
 }
 
-impl PakReader {
-    fn new_any_inner<R: Read + Seek>(
-        reader: &mut R,
-        key: super::Key,
-    ) -> Result<Self, super::Error> {
-        use std::fmt::Write;
-        let mut log = "\n".to_owned();
+/// Mount points are short relative paths, so anything longer was decrypted with the wrong key
+const MAX_MOUNT_POINT_LEN: u64 = 4096;
 
-        for ver in Version::iter() {
-            match Pak::read(&mut *reader, ver, &key) {
-                Ok(pak) => return Ok(Self { pak, key }),
-                Err(err) => writeln!(log, "trying version {} failed: {}", ver, err)?,
-            }
+/// Parses a standard footer, falling back to one with `extra_bytes` unknown bytes. The error of
+/// the standard parse is reported if neither works.
+fn parse_footer(
+    bytes: &[u8],
+    version: Version,
+    magic: u32,
+    extra_bytes: usize,
+) -> Result<Footer, super::Error> {
+    match Footer::parse_with_magic(bytes, version, magic) {
+        Err(err) if extra_bytes > 0 => {
+            Footer::parse_with_extra_bytes(bytes, version, magic, extra_bytes).map_err(|_| err)
         }
-        Err(super::Error::UnsupportedOrEncrypted(log))
+        footer => footer,
     }
+}
 
-    fn new_inner<R: Read + Seek>(
-        reader: &mut R,
-        version: super::Version,
-        key: super::Key,
-    ) -> Result<Self, super::Error> {
-        Pak::read(reader, version, &key).map(|pak| Self { pak, key })
+/// Cheap checks that the start of the decrypted index is plausible: the mount point must be
+/// short and fit in the index, and every entry takes at least a byte of the index
+#[cfg(feature = "encryption")]
+fn check_decrypted_index(footer: &Footer, index: &[u8]) -> Result<(), super::Error> {
+    let plausible = || -> Option<bool> {
+        let len = i32::from_le_bytes(index.get(..4)?.try_into().unwrap());
+        let size = match len < 0 {
+            true => len.unsigned_abs() as u64 * 2,
+            false => len as u64,
+        };
+        let count = index.get(4 + size as usize..)?.get(..4)?;
+        let count = u32::from_le_bytes(count.try_into().unwrap());
+        Some(size <= MAX_MOUNT_POINT_LEN && count as u64 <= index.len() as u64)
+    };
+    match plausible() {
+        Some(true) => Ok(()),
+        _ => Err(super::Error::WrongKeyOrCorrupt {
+            guid: footer.encryption_guid(),
+            source: None,
+        }),
     }
+}
 
-    pub fn version(&self) -> super::Version {
-        self.pak.version
+/// Number of encoded entries below which [`PakBuilder::parallel_index_parse`] decodes on the
+/// calling thread, as spawning threads costs more than decoding them
+const PARALLEL_PARSE_MIN_ENTRIES: usize = 16 * 1024;
+
+/// Decodes the entries at the offsets of `records` into `encoded_entries`, in the order of
+/// `records`, split between `threads` threads. Entries with an implausible block count fail
+/// unless `skip_implausible` is set, in which case they are returned as the warning telling why.
+fn decode_entries(
+    encoded_entries: &[u8],
+    records: &[(String, u32)],
+    version: super::Version,
+    threads: usize,
+    skip_implausible: bool,
+) -> Result<Vec<Result<super::entry::Entry, IndexWarning>>, super::Error> {
+    let decode = |records: &[(String, u32)]| {
+        let mut encoded_entries = io::Cursor::new(encoded_entries);
+        records
+            .iter()
+            .map(|(path, encoded_offset)| {
+                encoded_entries.set_position(*encoded_offset as u64);
+                match super::entry::Entry::read_encoded(&mut encoded_entries, version) {
+                    Ok(entry) => Ok(Ok(entry)),
+                    Err(super::Error::ImplausibleBlockCount {
+                        count,
+                        uncompressed,
+                        ..
+                    }) if skip_implausible => Ok(Err(IndexWarning::ImplausibleBlockCount {
+                        path: path.clone(),
+                        count,
+                        uncompressed,
+                    })),
+                    Err(super::Error::ImplausibleBlockCount {
+                        count,
+                        uncompressed,
+                        ..
+                    }) => Err(super::Error::ImplausibleBlockCount {
+                        path: Some(path.clone()),
+                        count,
+                        uncompressed,
+                    }),
+                    // keeps the kind, which tells a wrong key from other failures
+                    Err(super::Error::Io(err)) => Err(super::Error::Io(io::Error::new(
+                        err.kind(),
+                        format!("encoded entry of {path} at {encoded_offset:#x}: {err}"),
+                    ))),
+                    Err(err) => Err(err),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    if threads <= 1 || records.len() < 2 {
+        return decode(records);
     }
+    let chunk_size = records.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles = records
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || decode(chunk)))
+            .collect::<Vec<_>>();
+        let mut entries = Vec::with_capacity(records.len());
+        // joined in order so the first failing record is the one reported, as when serial
+        for handle in handles {
+            entries.extend(handle.join().unwrap()?);
+        }
+        Ok(entries)
+    })
+}
 
-    pub fn mount_point(&self) -> &str {
-        &self.pak.mount_point
+/// Garbage from decrypting with the wrong key makes parsing fail in arbitrary ways, so errors
+/// parsing an encrypted index are reported as a wrong key
+fn wrong_key_or_corrupt(footer: &Footer, err: super::Error) -> super::Error {
+    let garbage = match &err {
+        super::Error::InvalidName(_) => false,
+        super::Error::Io(err) => matches!(
+            err.kind(),
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData
+        ),
+        err => err.kind() == super::ErrorKind::Format,
+    };
+    match footer.encrypted && garbage {
+        true => super::Error::WrongKeyOrCorrupt {
+            guid: footer.encryption_guid(),
+            source: Some(Box::new(err)),
+        },
+        false => err,
     }
+}
 
-    pub fn encrypted_index(&self) -> bool {
-        self.pak.encrypted_index
+/// Decrypts the primary index if it is encrypted
+#[allow(unused_mut)]
+fn decrypt_index(
+    footer: &Footer,
+    mut index: Vec<u8>,
+    #[allow(unused)] key: &super::Key,
+) -> Result<Vec<u8>, super::Error> {
+    if footer.encrypted {
+        #[cfg(not(feature = "encryption"))]
+        return Err(super::Error::EncryptionFeatureDisabled);
+        #[cfg(feature = "encryption")]
+        {
+            decrypt(key, &mut index)?;
+            check_decrypted_index(footer, &index)?;
+        }
     }
+    Ok(index)
+}
 
-    pub fn encryption_guid(&self) -> Option<u128> {
-        self.pak.encryption_guid
-    }
+fn read_range<R: Read + Seek>(
+    reader: &mut R,
+    range: std::ops::Range<u64>,
+    what: &'static str,
+) -> Result<Vec<u8>, super::Error> {
+    reader.seek(io::SeekFrom::Start(range.start))?;
+    reader.read_len(checked_usize(range.end - range.start, what)?)
+}
 
-    pub fn path_hash_seed(&self) -> Option<u64> {
-        self.pak.index.path_hash_seed
-    }
+/// Ranges of the secondary indexes of a V10+ pak, which are located through the primary index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecondaryIndexRanges {
+    /// Not needed to list the entries, so it may be omitted when parsing the index
+    pub path_hash_index: Option<std::ops::Range<u64>>,
+    pub full_directory_index: Option<std::ops::Range<u64>>,
+}
 
-    pub fn get<R: Read + Seek>(&self, path: &str, reader: &mut R) -> Result<Vec<u8>, super::Error> {
-        let mut data = Vec::new();
-        self.read_file(path, reader, &mut data)?;
-        Ok(data)
-    }
+/// Part of the index whose SHA-1 is stored in the pak, the primary index in the footer and
+/// the secondary indexes in the primary index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexSection {
+    Primary,
+    PathHashIndex,
+    FullDirectoryIndex,
+}
 
-    pub fn read_file<R: Read + Seek, W: Write>(
-        &self,
-        path: &str,
-        reader: &mut R,
-        writer: &mut W,
-    ) -> Result<(), super::Error> {
-        match self.pak.index.entries().get(path) {
-            Some(entry) => entry.read_file(
-                reader,
-                self.pak.version,
-                &self.pak.compression,
-                &self.key,
-                writer,
-            ),
-            None => Err(super::Error::MissingEntry(path.to_owned())),
-        }
+impl std::fmt::Display for IndexSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IndexSection::Primary => "primary index",
+            IndexSection::PathHashIndex => "path hash index",
+            IndexSection::FullDirectoryIndex => "full directory index",
+        })
     }
+}
 
-    pub fn files(&self) -> Vec<String> {
-        self.pak.index.entries().keys().cloned().collect()
+/// Checks the decrypted bytes of an index section against the hash stored for it
+fn verify_index_hash(
+    section: IndexSection,
+    expected: &Hash,
+    data: &[u8],
+) -> Result<(), super::Error> {
+    let actual = hash(data);
+    match actual == *expected {
+        true => Ok(()),
+        false => Err(super::Error::IndexHashMismatch {
+            section,
+            expected: hex::encode(expected.0),
+            actual: hex::encode(actual.0),
+        }),
     }
+}
 
-    pub fn into_pakwriter<W: Write + Seek>(
-        self,
-        mut writer: W,
-    ) -> Result<PakWriter<W>, super::Error> {
-        writer.seek(io::SeekFrom::Start(self.pak.index_offset.unwrap()))?;
+/// Fields at the start of the decrypted primary index
+struct IndexHeader {
+    mount_point: RawString,
+    entry_count: u32,
+    path_hash_seed: Option<u64>,
+    secondary: SecondaryIndexRanges,
+    /// SHA-1 of the path hash index and of the full directory index
+    secondary_hashes: [Option<Hash>; 2],
+}
+
+impl IndexHeader {
+    fn read<R: Read>(reader: &mut R, version: Version) -> Result<Self, super::Error> {
+        let mount_point = reader.read_raw_string()?;
+        let entry_count = reader.read_u32::<LE>()?;
+        if version.version_major() < VersionMajor::PathHashIndex {
+            return Ok(IndexHeader {
+                mount_point,
+                entry_count,
+                path_hash_seed: None,
+                secondary: Default::default(),
+                secondary_hashes: Default::default(),
+            });
+        }
+
+        let path_hash_seed = reader.read_u64::<LE>()?;
+        // presence flag, offset, size and hash of a secondary index
+        let mut read_secondary = || -> Result<_, super::Error> {
+            (reader.read_u32::<LE>()? != 0).then_try(|| {
+                let offset = reader.read_u64::<LE>()?;
+                let size = reader.read_u64::<LE>()?;
+                let hash = Hash(reader.read_guid()?);
+                let end = offset.checked_add(size).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "secondary index size overflows")
+                })?;
+                Ok::<_, super::Error>((offset..end, hash))
+            })
+        };
+        let path_hash_index = read_secondary()?;
+        let full_directory_index = read_secondary()?;
+        Ok(IndexHeader {
+            mount_point,
+            entry_count,
+            path_hash_seed: Some(path_hash_seed),
+            secondary_hashes: [
+                path_hash_index.as_ref().map(|(_, hash)| *hash),
+                full_directory_index.as_ref().map(|(_, hash)| *hash),
+            ],
+            secondary: SecondaryIndexRanges {
+                path_hash_index: path_hash_index.map(|(range, _)| range),
+                full_directory_index: full_directory_index.map(|(range, _)| range),
+            },
+        })
+    }
+}
+
+impl PakReader {
+    #[allow(clippy::too_many_arguments)]
+    fn new_any_inner<R: Read + Seek>(
+        reader: &mut R,
+        index_key: &super::Key,
+        key: super::Key,
+        magic: u32,
+        footer_extra_bytes: usize,
+        lossy_paths: bool,
+        parallel: bool,
+        metadata_only: bool,
+        verify_hashes: bool,
+        skip_implausible: bool,
+    ) -> Result<Self, super::Error> {
+        use std::fmt::Write;
+        let mut log = "\n".to_owned();
+
+        let file_len = reader.seek(io::SeekFrom::End(0))?;
+        // versions the pak parsed as with their plausibility, newest first
+        let mut candidates = vec![];
+        // reported if no version parses, as a footer of the right layout over a corrupt index
+        let mut corrupt_index = None;
+        for ver in Version::iter() {
+            match Pak::read(
+                &mut *reader,
+                ver,
+                index_key,
+                magic,
+                footer_extra_bytes,
+                lossy_paths,
+                parallel,
+                metadata_only,
+                verify_hashes,
+                skip_implausible,
+            ) {
+                Ok(pak) => {
+                    let score = pak.plausibility(file_len);
+                    candidates.push((pak, score));
+                    // nothing older can do better, the usual case
+                    if score == PLAUSIBILITY_CHECKS {
+                        break;
+                    }
+                }
+                // magic matched so probing older layouts won't help
+                Err(
+                    err @ (super::Error::Encrypted
+                    | super::Error::EncryptionFeatureDisabled
+                    | super::Error::FrozenIndex
+                    | super::Error::WrongKeyOrCorrupt { .. }
+                    | super::Error::InvalidName(_)),
+                ) => match candidates.is_empty() {
+                    true => return Err(err),
+                    false => break,
+                },
+                Err(err) => {
+                    writeln!(log, "trying version {} failed: {}", ver, err)?;
+                    if let super::Error::IndexHashMismatch { .. }
+                    | super::Error::ImplausibleBlockCount { .. } = err
+                    {
+                        corrupt_index.get_or_insert(err);
+                    }
+                }
+            }
+        }
+        // the highest score wins, ties go to the newer version like when probing in order
+        let best = |candidates: &[(Pak, u32)]| {
+            candidates
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, (_, score))| (*score, std::cmp::Reverse(*i)))
+                .map(|(i, _)| i)
+        };
+        let Some(chosen) = best(&candidates) else {
+            return Err(corrupt_index.unwrap_or(super::Error::UnsupportedOrEncrypted(log)));
+        };
+        let (pak, score) = candidates.remove(chosen);
+        let runner_up = best(&candidates).map(|i| (candidates[i].0.version, candidates[i].1));
+        Ok(Self {
+            pak,
+            key,
+            decompression_threads: 1,
+            verify_reads: false,
+            zlib_format: Default::default(),
+            directories: Default::default(),
+            transform: None,
+            decrypt_limit: Default::default(),
+            detection: Some(VersionDetection {
+                score,
+                max_score: PLAUSIBILITY_CHECKS,
+                runner_up,
+            }),
+        })
+    }
+
+    /// Drops the encoded index kept from parsing unless `keep` is set, in which case the range
+    /// of the encoded entry of each path is located
+    fn retain_encoded_index(mut self, keep: bool) -> Self {
+        match (keep, self.pak.encoded_index.as_mut()) {
+            (false, _) => self.pak.encoded_index = None,
+            (true, Some(encoded)) => {
+                for (path, &offset) in &self.pak.encoded_offsets {
+                    let mut cursor = io::Cursor::new(&encoded.entries[offset as usize..]);
+                    // parsed successfully before, so only the length is of interest
+                    if Entry::read_encoded(&mut cursor, self.pak.version).is_ok() {
+                        let start = offset as usize;
+                        let end = start + cursor.position() as usize;
+                        encoded.ranges.insert(path.clone(), start..end);
+                    }
+                }
+            }
+            (true, None) => {}
+        }
+        self
+    }
+
+    /// Fails with the first warning if `strict`, see [`PakBuilder::strict`]
+    fn check_strict(self, strict: bool) -> Result<Self, super::Error> {
+        match self.pak.warnings.first() {
+            Some(warning) if strict => Err(super::Error::StrictWarning(warning.clone())),
+            _ => Ok(self),
+        }
+    }
+
+    fn with_read_options(
+        mut self,
+        threads: usize,
+        verify: bool,
+        transform: Option<Arc<dyn EntryTransform>>,
+        decrypt_limit: Arc<DecryptLimit>,
+    ) -> Self {
+        self.decompression_threads = threads;
+        self.verify_reads = verify;
+        self.transform = transform;
+        self.decrypt_limit = decrypt_limit;
+        self
+    }
+
+    pub fn version(&self) -> super::Version {
+        self.pak.version
+    }
+
+    pub fn mount_point(&self) -> &str {
+        &self.pak.mount_point
+    }
+
+    /// Characters of the mount point as stored in the index, including the terminator, with
+    /// UTF-16 mount points as little endian bytes. Differs from [`Self::mount_point`] for mount
+    /// points which were decrypted or replaced, see [`IndexWarning`].
+    pub fn mount_point_raw(&self) -> &[u8] {
+        &self.pak.mount_point_raw
+    }
+
+    /// Format of the blocks of Zlib entries, detected once the first of them is read. Some
+    /// platforms write raw deflate streams without the zlib header and checksum, which are
+    /// read transparently.
+    pub fn zlib_format(&self) -> Option<ZlibFormat> {
+        self.zlib_format.get().copied()
+    }
+
+    /// Problems found in the footer and index which didn't prevent reading the pak
+    pub fn warnings(&self) -> &[IndexWarning] {
+        &self.pak.warnings
+    }
+
+    /// How the version was chosen when it was detected by [`PakBuilder::reader`], `None` if it
+    /// was given or the pak was parsed from its parts
+    pub fn version_detection(&self) -> Option<&VersionDetection> {
+        self.detection.as_ref()
+    }
+
+    /// The encoded record of `path` in the primary index of a V10+ pak as stored, `None` unless
+    /// read with [`PakBuilder::keep_encoded_index`]. `path` may take any form accepted by
+    /// [`Self::resolve_path`].
+    pub fn encoded_entry_bytes(&self, path: &str) -> Option<&[u8]> {
+        let encoded = self.pak.encoded_index.as_ref()?;
+        let (path, _) = self.resolve_path(path)?;
+        encoded
+            .ranges
+            .get(path)
+            .map(|range| &encoded.entries[range.clone()])
+    }
+
+    /// The decrypted encoded entries and secondary indexes of a V10+ pak as stored, `None`
+    /// unless read with [`PakBuilder::keep_encoded_index`]
+    pub fn raw_index_sections(&self) -> Option<RawIndexSections<'_>> {
+        let encoded = self.pak.encoded_index.as_ref()?;
+        Some(RawIndexSections {
+            encoded_entries: &encoded.entries,
+            path_hash_index: encoded.path_hash_index.as_deref(),
+            full_directory_index: encoded.full_directory_index.as_deref(),
+        })
+    }
+
+    pub fn encrypted_index(&self) -> bool {
+        self.pak.encrypted_index
+    }
+
+    pub fn encryption_guid(&self) -> Option<u128> {
+        self.pak.encryption_guid
+    }
+
+    /// Unknown bytes in the footer of games which extend it, see
+    /// [`PakBuilder::footer_extra_bytes`]
+    pub fn footer_extra_bytes(&self) -> &[u8] {
+        &self.pak.footer_extra
+    }
+
+    pub fn path_hash_seed(&self) -> Option<u64> {
+        self.pak.index.path_hash_seed
+    }
+
+    /// Number of entries, which unlike [`Self::len`] is also known for paks read with
+    /// [`PakBuilder::metadata_only`]
+    pub fn entry_count(&self) -> usize {
+        match self.pak.unread_entries {
+            Some(count) => count as usize,
+            None => self.len(),
+        }
+    }
+
+    /// Entry at `path`, failing for paks whose entries weren't read
+    fn entry(&self, path: &str) -> Result<&Entry, super::Error> {
+        self.resolve_entry(path).map(|(_, entry)| entry)
+    }
+
+    /// Entry at `path` and its path as stored in the index, see [`Self::resolve_path`]
+    fn resolve_entry(&self, path: &str) -> Result<(&str, &Entry), super::Error> {
+        if self.pak.unread_entries.is_some() {
+            return Err(super::Error::IndexNotLoaded);
+        }
+        let entries = self.pak.index.entries();
+        match self.resolve_path(path) {
+            Some((resolved, _)) => Ok((resolved, &entries[resolved])),
+            None => Err(match nearest_path(entries.keys(), path) {
+                Some(nearest) => super::Error::MissingEntryNearest {
+                    path: path.to_owned(),
+                    nearest: nearest.to_owned(),
+                },
+                None => super::Error::MissingEntry(path.to_owned()),
+            }),
+        }
+    }
+
+    /// Path of the entry which `path` refers to and the form it was given in. Besides paths
+    /// relative to the mount point, as listed by [`Self::files`], this accepts full engine paths
+    /// such as `../../../Game/Content/A.uasset` for a pak mounted at `../../../Game/`, and paths
+    /// whose leading `../` segments differ from those of the mount point. Backslashes and
+    /// repeated separators are treated as single slashes.
+    pub fn resolve_path(&self, path: &str) -> Option<(&str, PathForm)> {
+        let entries = self.pak.index.entries();
+        let find = |path: &str, form| {
+            entries
+                .get_key_value(path)
+                .map(|(path, _)| (path.as_str(), form))
+        };
+        if let Some(found) = find(path, PathForm::Relative) {
+            return Some(found);
+        }
+        let path = normalize_separators(path);
+        let mount_point = MountPoint(&self.pak.mount_point);
+        let parents = MountPoint(&path).leading_parent_segments();
+        let stripped = &path[parents.len()..];
+        let mount_stripped = normalize_separators(
+            &self.pak.mount_point[mount_point.leading_parent_segments().len()..],
+        );
+        find(&path, PathForm::Relative)
+            .or_else(|| {
+                let relative = mount_point.relative_path(&path)?;
+                find(&relative, PathForm::MountPointPrefixed)
+            })
+            .or_else(|| match parents.is_empty() {
+                true => None,
+                false => find(stripped, PathForm::ParentsStripped).or_else(|| {
+                    let relative = MountPoint(&mount_stripped).relative_path(stripped)?;
+                    find(&relative, PathForm::ParentsStripped)
+                }),
+            })
+    }
+
+    pub fn get<R: Read + Seek>(&self, path: &str, reader: &mut R) -> Result<Vec<u8>, super::Error> {
+        let mut data = Vec::new();
+        self.read_file(path, reader, &mut data)?;
+        Ok(data)
+    }
+
+    pub fn read_file<R: Read + Seek, W: Write>(
+        &self,
+        path: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), super::Error> {
+        self.read_file_inner(path, reader, None, writer)
+    }
+
+    /// Like [`PakReader::read_file`], but checks `cancel` between blocks and stops with
+    /// [`Error::Cancelled`] once it is set, e.g. from another thread.
+    /// Data written before that is left in `writer`.
+    pub fn read_file_cancellable<R: Read + Seek, W: Write>(
+        &self,
+        path: &str,
+        reader: &mut R,
+        cancel: &AtomicBool,
+        writer: &mut W,
+    ) -> Result<(), super::Error> {
+        self.read_file_inner(path, reader, Some(cancel), writer)
+    }
+
+    fn read_file_inner<R: Read + Seek, W: Write>(
+        &self,
+        path: &str,
+        reader: &mut R,
+        cancel: Option<&AtomicBool>,
+        writer: &mut W,
+    ) -> Result<(), super::Error> {
+        let (path, entry) = self.resolve_entry(path)?;
+        entry
+            .read_file(
+                reader,
+                self.pak.version,
+                &self.pak.compression,
+                &self.key,
+                self.pak.data_end(entry),
+                ReadOptions {
+                    threads: self.decompression_threads,
+                    cancel,
+                    verify: self.verify_reads,
+                    zlib_format: Some(&self.zlib_format),
+                    transform: self.transform.as_deref().map(|t| (t, path)),
+                    decrypt_limit: self.decrypt_limit.limit(path),
+                },
+                writer,
+            )
+            .map_err(|err| match err {
+                super::Error::VerificationFailed { expected, got, .. } => {
+                    super::Error::VerificationFailed {
+                        path: path.to_owned(),
+                        expected,
+                        got,
+                    }
+                }
+                err => err,
+            })
+    }
+
+    /// Reads `path` from `data`, the whole pak in memory. Entries stored without compression and
+    /// encryption are borrowed from `data` rather than copied, others are read like
+    /// [`Self::get`]. Entries are always copied when [`PakBuilder::verify_reads`] is set so
+    /// they can be checked.
+    pub fn get_ref<'a>(&self, path: &str, data: &'a [u8]) -> Result<Cow<'a, [u8]>, super::Error> {
+        let entry = self.entry(path)?;
+        if entry.compression_slot.is_some()
+            || entry.is_encrypted()
+            || entry.compressed != entry.uncompressed
+            || self.verify_reads
+        {
+            return self.get(path, &mut io::Cursor::new(data)).map(Cow::Owned);
+        }
+        let range = entry.data_range(
+            &mut io::Cursor::new(data),
+            self.pak.version,
+            self.pak.data_end(entry),
+        )?;
+        usize::try_from(range.start)
+            .ok()
+            .zip(usize::try_from(range.end).ok())
+            .and_then(|(start, end)| data.get(start..end))
+            .map(Cow::Borrowed)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+
+    /// SHA1 of the data of `path` as stored, which [`PakBuilder::verify_reads`] checks reads
+    /// against. Taken from the index, or from the record in front of the data for V10+ paks
+    /// whose index doesn't keep it. `None` if neither has one.
+    pub fn stored_hash<R: Read + Seek>(
+        &self,
+        path: &str,
+        reader: &mut R,
+    ) -> Result<Option<[u8; 20]>, super::Error> {
+        let entry = self.entry(path)?;
+        let hash = match entry.hash {
+            Some(hash) => Some(hash),
+            None => {
+                let data_end = self.pak.data_end(entry);
+                entry
+                    .read_header(reader, self.pak.version, data_end)?
+                    .0
+                    .hash
+            }
+        };
+        Ok(hash.map(|hash| hash.0))
+    }
+
+    /// Writes the data of `path` as stored to `writer`, still compressed and encrypted and
+    /// including any encryption padding, without the record preceding it. Returns the number of
+    /// bytes written.
+    pub fn read_stored<R: Read + Seek, W: Write>(
+        &self,
+        path: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<u64, super::Error> {
+        let entry = self.entry(path)?;
+        entry.write_stored(reader, self.pak.version, self.pak.data_end(entry), writer)
+    }
+
+    /// Opens `path` for reading without loading all of its data into memory. Compressed
+    /// entries are decompressed one block at a time as they are read.
+    pub fn open_entry<'a, R: Read + Seek>(
+        &'a self,
+        path: &str,
+        reader: &'a mut R,
+    ) -> Result<EntryReader<'a, R>, super::Error> {
+        // the transform is passed the path as stored in the index, which outlives `path`
+        let (path, entry) = self.resolve_entry(path)?;
+        entry.open(
+            reader,
+            self.pak.version,
+            &self.pak.compression,
+            &self.key,
+            self.pak.data_end(entry),
+            &self.zlib_format,
+            self.transform.as_deref().map(|t| (t, path)),
+            self.decrypt_limit.limit(path),
+        )
+    }
+
+    /// Paths of all entries relative to the mount point, sorted by their UTF-8 bytes (which is
+    /// code point order and independent of the locale). This is not the order of the index in
+    /// the file, see [`compare_paths`]. Use [`Self::file_names`] to iterate them without cloning
+    /// every path. Empty for paks read with [`PakBuilder::metadata_only`].
+    pub fn files(&self) -> Vec<String> {
+        self.pak.index.entries().keys().cloned().collect()
+    }
+
+    /// Paths of all entries relative to the mount point, borrowed from the index in the same
+    /// order as [`Self::files`]
+    pub fn file_names(&self) -> impl ExactSizeIterator<Item = &str> + '_ {
+        self.pak.index.entries().keys().map(String::as_str)
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.pak.index.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pak.index.entries().is_empty()
+    }
+
+    /// View of this pak presenting its entries under other paths without rewriting it. Each
+    /// rule `(from, to)` replaces the prefix `from` of the path of an entry including the mount
+    /// point with `to`. Fails if two entries would end up at the same path.
+    pub fn with_path_remap(
+        &self,
+        rules: Vec<(String, String)>,
+    ) -> Result<super::RemappedPak<'_>, super::Error> {
+        super::RemappedPak::new(self, rules)
+    }
+
+    /// Whether `path` is not valid UTF-8 or UTF-16 in the index and was read lossily. `path`
+    /// may take any form accepted by [`Self::resolve_path`].
+    pub fn is_lossy_path(&self, path: &str) -> bool {
+        self.resolve_path(path)
+            .is_some_and(|(path, _)| self.pak.index.is_lossy(path))
+    }
+
+    /// Size, compression and location of the entry at `path` without reading its data, `None`
+    /// if there is none. `path` may take any form accepted by [`Self::resolve_path`].
+    pub fn entry_info(&self, path: &str) -> Option<EntryInfo> {
+        let (path, _) = self.resolve_path(path)?;
+        Some(self.pak.entry_info(path, &self.pak.index.entries()[path]))
+    }
+
+    /// Byte range of `path` in the pak covering the record preceding the data and the data as
+    /// stored, including any encryption padding
+    pub fn entry_range<R: Read + Seek>(
+        &self,
+        path: &str,
+        reader: &mut R,
+    ) -> Result<std::ops::Range<u64>, super::Error> {
+        let entry = self.entry(path)?;
+        entry.stored_range(reader, self.pak.version, self.pak.data_end(entry))
+    }
+
+    /// Subdirectories and files directly in the directory `path`, relative to the mount point
+    /// like the paths of [`Self::files`], or `None` if no entry is in it. The root is `""` or
+    /// `"/"`. The directories are indexed the first time this or [`Self::dir_exists`] is called,
+    /// after which listing a directory only visits its children.
+    pub fn list_dir(&self, path: &str) -> Option<DirListing<'_>> {
+        let path = normalize_separators(path);
+        let directories = self
+            .directories
+            .get_or_init(|| Directory::index(self.file_names()));
+        directories
+            .get(path.trim_matches('/'))
+            .map(|directory| DirListing {
+                reader: self,
+                directory,
+            })
+    }
+
+    /// Whether any entry is in the directory `path` or below it, see [`Self::list_dir`]
+    pub fn dir_exists(&self, path: &str) -> bool {
+        self.list_dir(path).is_some()
+    }
+
+    /// Byte ranges of the pak `path` occupies: its record, each of its blocks including
+    /// encryption padding, and the span of both. These are the ranges reading the entry reads,
+    /// so changes to other entries leave them untouched, which lets delta tools align chunks to
+    /// entries.
+    pub fn entry_extents<R: Read + Seek>(
+        &self,
+        path: &str,
+        reader: &mut R,
+    ) -> Result<EntryExtents, super::Error> {
+        let entry = self.entry(path)?;
+        entry.extents(reader, self.pak.version, self.pak.data_end(entry))
+    }
+
+    /// Decompresses each compression block of `path` on its own and reports the size it
+    /// decompresses to next to the size the index implies, which loaders rely on. Entries
+    /// stored without compression have a single block, which isn't read.
+    pub fn probe_blocks<R: Read + Seek>(
+        &self,
+        path: &str,
+        reader: &mut R,
+    ) -> Result<Vec<BlockProbe>, super::Error> {
+        let extents = self.entry_extents(path, reader)?;
+        let record = self.entry(path)?;
+        if record.compression_slot.is_none() {
+            return Ok(extents
+                .blocks
+                .into_iter()
+                .map(|range| BlockProbe {
+                    range,
+                    compressed: record.compressed,
+                    expected: record.uncompressed,
+                    uncompressed: Ok(record.uncompressed),
+                })
+                .collect());
+        }
+        let mut entry = self.open_entry(path, reader)?;
+        Ok(extents
+            .blocks
+            .into_iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let (block, expected, uncompressed) = entry.probe_block(index);
+                BlockProbe {
+                    range,
+                    compressed: block.end - block.start,
+                    expected,
+                    uncompressed: uncompressed.map_err(|err| err.to_string()),
+                }
+            })
+            .collect())
+    }
+
+    /// [`Self::entry_extents`] of every entry in the order of their data in the pak, read as the
+    /// iterator advances
+    pub fn all_extents<'a: 'r, 'r, R: Read + Seek>(
+        &'a self,
+        reader: &'r mut R,
+    ) -> impl Iterator<Item = Result<(&'a str, EntryExtents), super::Error>> + 'r {
+        let mut entries = Vec::from_iter(self.pak.index.entries());
+        entries.sort_by_key(|(path, entry)| (entry.offset, *path));
+        entries.into_iter().map(move |(path, entry)| {
+            let extents = entry.extents(reader, self.pak.version, self.pak.data_end(entry))?;
+            Ok((path.as_str(), extents))
+        })
+    }
+
+    /// Directory tree of all entries with sizes and file counts rolled up into each directory
+    pub fn directory_summary(&self) -> DirectorySummary {
+        let mut root = DirectorySummary::default();
+        for (path, entry) in self.pak.index.entries() {
+            root.insert(path, self.pak.entry_info(path, entry));
+        }
+        root
+    }
+
+    /// Copies the pak to `writer` with the index and every entry decrypted. Entries keep their
+    /// compression and are written in the order of their data, so apart from the removed
+    /// encryption and its padding the copy matches the original.
+    pub fn write_decrypted<R: Read + Seek, W: Write + Seek>(
+        &self,
+        reader: &mut R,
+        writer: W,
+    ) -> Result<W, super::Error> {
+        let encrypted = self.pak.index.entries().values().any(Entry::is_encrypted);
+        if encrypted && matches!(self.key, super::Key::None) {
+            return Err(super::Error::Encrypted);
+        }
+        self.rewrite(reader, writer, super::Key::None)
+    }
+
+    /// Copies the live entries to `writer` back to back as stored, without the dead space left
+    /// by replaced entries or padding. Entries stay encrypted, but the index is written
+    /// unencrypted as writing encrypted indexes is not supported.
+    pub fn write_compacted<R: Read + Seek, W: Write + Seek>(
+        &self,
+        reader: &mut R,
+        writer: W,
+    ) -> Result<W, super::Error> {
+        self.rewrite(reader, writer, self.key.clone())
+    }
+
+    /// Copies every entry in the order of their data to a new pak with the same layout, which
+    /// decrypts entries unless `key` is the key of this pak
+    fn rewrite<R: Read + Seek, W: Write + Seek>(
+        &self,
+        reader: &mut R,
+        writer: W,
+        key: super::Key,
+    ) -> Result<W, super::Error> {
+        if self.pak.unread_entries.is_some() {
+            return Err(super::Error::IndexNotLoaded);
+        }
+        let mut pak_writer = PakWriter::new_inner(
+            writer,
+            key,
+            self.pak.version,
+            self.pak.mount_point.clone(),
+            self.pak.index.path_hash_seed,
+            vec![],
+            self.pak.magic,
+        );
+        pak_writer.pak.compression = self.pak.compression.clone();
+        pak_writer.pak.compression_names = self.pak.compression_names.clone();
+        pak_writer.pak.path_hash_index = self.pak.path_hash_index;
+        pak_writer.pak.full_directory_index = self.pak.full_directory_index;
+
+        let mut entries = self.pak.index.entries().iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, entry)| entry.offset);
+        // entries sharing data are adjacent and keep sharing it
+        let mut previous: Option<(&str, u64)> = None;
+        for (path, entry) in entries {
+            match previous {
+                Some((shared, offset)) if offset == entry.offset => {
+                    pak_writer.write_duplicate(path, shared)?
+                }
+                _ => {
+                    pak_writer.copy_entry_from(self, reader, path)?;
+                    previous = Some((path, entry.offset));
+                }
+            }
+        }
+        pak_writer.write_index()
+    }
+
+    /// Compares the path hash index of a V10+ pak with the entries listed by its full directory
+    /// index, which is what repak reads. The engine looks entries up by the path hash index so
+    /// any disagreement makes some of them fail to load. `None` if there is no path hash index.
+    pub fn check_path_hash_index(&self) -> Option<PathHashIndexReport> {
+        let records = self.pak.path_hash_records.as_ref()?;
+        let mut report = PathHashIndexReport::default();
+        let mut by_hash = std::collections::HashMap::with_capacity(records.len());
+        for &(hash, offset) in records {
+            if by_hash.insert(hash, offset).is_some() {
+                report.extra.push((hash, offset));
+            }
+        }
+        let seed = self.pak.index.path_hash_seed.unwrap_or_default();
+        for (path, offset) in &self.pak.encoded_offsets {
+            let engine_path = self.pak.index.engine_path(path);
+            let hash = super::path_hash::hash_path(self.pak.version, &engine_path, seed);
+            match by_hash.remove(&hash) {
+                None => report.missing.push(path.clone()),
+                Some(record) if record != *offset => report.mismatched.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        report.extra.extend(by_hash);
+        report.extra.sort_by_key(|&(hash, offset)| (offset, hash));
+        Some(report)
+    }
+
+    /// Reports bytes of the pak, `file_len` bytes long, which are covered by neither an entry
+    /// nor the indexes and footer, such as the data of replaced entries
+    pub fn fragmentation_report(&self, file_len: u64) -> FragmentationReport {
+        let version = self.pak.version;
+        let mut used = self
+            .pak
+            .index
+            .entries()
+            .values()
+            .map(|entry| self.pak.entry_extent(entry))
+            .chain(self.pak.index_ranges.iter().cloned())
+            .chain(std::iter::once(
+                file_len.saturating_sub(version.size() as u64 + self.pak.footer_extra.len() as u64)
+                    ..file_len,
+            ))
+            .collect::<Vec<_>>();
+        used.sort_by_key(|range| range.start);
+
+        let mut report = FragmentationReport {
+            file_len,
+            ..Default::default()
+        };
+        let mut end = 0;
+        for range in used {
+            if range.start > end {
+                let gap = range.start - end;
+                report.dead_bytes += gap;
+                report.gaps += 1;
+                report.largest_gap = report.largest_gap.max(gap);
+            }
+            end = end.max(range.end);
+        }
+        report
+    }
+
+    /// Every entry in the order of its data in the pak, along with the bytes it takes and the
+    /// gap to whatever follows it, which shows the layout of the pak and where unused bytes are
+    pub fn entries_by_offset(&self) -> Vec<LayoutEntry> {
+        let mut entries = self
+            .pak
+            .index
+            .entries()
+            .iter()
+            .map(|(path, entry)| (path, entry, self.pak.entry_extent(entry)))
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a_path, _, a), (b_path, _, b)| {
+            (a.start, a.end, a_path).cmp(&(b.start, b.end, b_path))
+        });
+        let mut starts = entries
+            .iter()
+            .map(|(_, _, extent)| extent.start)
+            .chain(self.pak.index_ranges.iter().map(|range| range.start))
+            .collect::<Vec<_>>();
+        starts.sort_unstable();
+
+        entries
+            .into_iter()
+            .map(|(path, entry, extent)| {
+                // the first entry or index starting after this entry does, if any
+                let next = starts[starts.partition_point(|&start| start <= extent.start)..]
+                    .first()
+                    .copied();
+                LayoutEntry {
+                    path: path.clone(),
+                    info: self.pak.entry_info(path, entry),
+                    size: extent.end - extent.start,
+                    gap: next.map_or(0, |next| next.saturating_sub(extent.end)),
+                }
+            })
+            .collect()
+    }
+
+    /// Reads every entry in the order of [`Self::entries_by_offset`]. Failing to read an entry,
+    /// for example when its data was overwritten, doesn't stop the iteration, so what can be
+    /// read of a damaged pak is recovered.
+    pub fn read_entries_by_offset<'a, R: Read + Seek>(
+        &'a self,
+        reader: &'a mut R,
+    ) -> impl Iterator<Item = (LayoutEntry, Result<Vec<u8>, super::Error>)> + 'a {
+        self.entries_by_offset().into_iter().map(move |entry| {
+            let data = self.get(&entry.path, reader);
+            (entry, data)
+        })
+    }
+
+    /// Reads every entry in one forward pass over the pak, calling `callback` with the path and
+    /// contents of each. Entries are visited in the order of [`Self::entries_by_offset`] rather
+    /// than by path, which avoids seeking back and forth and on spinning disks is often faster
+    /// than reading on several threads. Unlike [`Self::read_entries_by_offset`], one buffer is
+    /// reused for every entry and the first error from reading or from `callback` stops the
+    /// pass.
+    pub fn read_all_sequential<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        mut callback: impl FnMut(&str, &[u8]) -> Result<(), super::Error>,
+    ) -> Result<(), super::Error> {
+        if self.pak.unread_entries.is_some() {
+            return Err(super::Error::IndexNotLoaded);
+        }
+        let mut data = vec![];
+        for entry in self.entries_by_offset() {
+            data.clear();
+            self.read_file(&entry.path, reader, &mut data)?;
+            callback(&entry.path, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Compares the contents of every entry with `baseline`, the SHA256 of the contents of the
+    /// entries of another pak by path, such as listed by `repak hash-list`. Each entry is read
+    /// and hashed once without keeping its contents, on as many threads as there are cores,
+    /// each reading from its own reader created by `open_reader`.
+    pub fn changed_entries<R: Read + Seek, F: Fn() -> io::Result<R> + Sync>(
+        &self,
+        baseline: &BTreeMap<String, [u8; 32]>,
+        open_reader: F,
+    ) -> Result<EntryChanges, super::Error> {
+        if self.pak.unread_entries.is_some() {
+            return Err(super::Error::IndexNotLoaded);
+        }
+        let paths = self.file_names().collect::<Vec<_>>();
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let hash_entries = || -> Result<Vec<usize>, super::Error> {
+            use sha2::{Digest, Sha256};
+            let mut reader = open_reader()?;
+            let mut changed = vec![];
+            while !failed.load(Ordering::Relaxed) {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&path) = paths.get(i) else {
+                    break;
+                };
+                let mut hasher = Sha256::new();
+                io::copy(&mut self.open_entry(path, &mut reader)?, &mut hasher)?;
+                if baseline.get(path) != Some(&hasher.finalize().into()) {
+                    changed.push(i);
+                }
+            }
+            Ok(changed)
+        };
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .clamp(1, paths.len().max(1));
+        let mut changed = std::thread::scope(|scope| {
+            (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        hash_entries().inspect_err(|_| failed.store(true, Ordering::Relaxed))
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().expect("hashing panicked"))
+                .collect::<Result<Vec<_>, _>>()
+        })?
+        .concat();
+        changed.sort_unstable();
+
+        let mut changes = EntryChanges::default();
+        for i in changed {
+            match baseline.contains_key(paths[i]) {
+                true => changes.modified.push(paths[i].to_owned()),
+                false => changes.added.push(paths[i].to_owned()),
+            }
+        }
+        changes.removed = baseline
+            .keys()
+            .filter(|path| !self.pak.index.entries().contains_key(path.as_str()))
+            .cloned()
+            .collect();
+        Ok(changes)
+    }
+
+    /// Creates a writer appending entries in place of the index, which is rewritten after them.
+    /// Fails for paks with entries after the index as they would be overwritten, see
+    /// [`Self::into_pakwriter_at_end`].
+    pub fn into_pakwriter<W: Write + Seek>(
+        self,
+        mut writer: W,
+    ) -> Result<PakWriter<W>, super::Error> {
+        let index_offset = self.pak.index_offset.unwrap();
+        if let Some(entry_offsets) = &self.pak.entry_offsets {
+            if *entry_offsets.end() >= index_offset {
+                return Err(super::Error::DataAfterIndex { index_offset });
+            }
+        }
+        writer.seek(io::SeekFrom::Start(index_offset))?;
+        self.into_pakwriter_inner(writer)
+    }
+
+    /// Creates a writer appending entries and the new index at the end of the file, leaving the
+    /// old index and footer unused in place. Works regardless of where the index is located.
+    pub fn into_pakwriter_at_end<W: Write + Seek>(
+        self,
+        mut writer: W,
+    ) -> Result<PakWriter<W>, super::Error> {
+        writer.seek(io::SeekFrom::End(0))?;
+        self.into_pakwriter_inner(writer)
+    }
+
+    fn into_pakwriter_inner<W: Write + Seek>(
+        self,
+        writer: W,
+    ) -> Result<PakWriter<W>, super::Error> {
+        // the index written by the writer would drop the entries
+        if self.pak.unread_entries.is_some() {
+            return Err(super::Error::IndexNotLoaded);
+        }
         Ok(PakWriter {
             allowed_compression: self.pak.compression.iter().filter_map(|c| *c).collect(),
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
             pak: self.pak,
             key: self.key,
             writer,
+            padding: Default::default(),
+            dedup: Default::default(),
+            deduplicated: 0,
+            transform: self.transform,
         })
     }
 }
@@ -255,12 +2147,18 @@ impl<W: Write + Seek> PakWriter<W> {
         mount_point: String,
         path_hash_seed: Option<u64>,
         allowed_compression: Vec<Compression>,
+        magic: u32,
     ) -> Self {
         PakWriter {
-            pak: Pak::new(version, mount_point, path_hash_seed),
+            pak: Pak::new(version, magic, mount_point, path_hash_seed),
             writer,
             key,
             allowed_compression,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            padding: Default::default(),
+            dedup: Default::default(),
+            deduplicated: 0,
+            transform: None,
         }
     }
 
@@ -268,63 +2166,460 @@ impl<W: Write + Seek> PakWriter<W> {
         self.writer
     }
 
+    /// Writes an entry for `path`, compressed with one of the allowed methods if
+    /// `allow_compress` is set and the data shrinks enough. Returns the metadata of the written
+    /// entry, which tells the method picked and the sizes.
     pub fn write_file(
         &mut self,
         path: &str,
         allow_compress: bool,
         data: impl AsRef<[u8]>,
-    ) -> Result<(), super::Error> {
-        self.pak.index.add_entry(
-            path.to_string(),
-            Entry::write_file(
-                &mut self.writer,
-                self.pak.version,
-                &mut self.pak.compression,
-                if allow_compress {
-                    &self.allowed_compression
-                } else {
-                    &[]
-                },
-                data.as_ref(),
-            )?,
+    ) -> Result<EntryInfo, super::Error> {
+        let entry = Entry::write_file(
+            &mut self.writer,
+            self.pak.version,
+            &mut self.pak.compression,
+            &self.pak.compression_names,
+            if allow_compress {
+                &self.allowed_compression
+            } else {
+                &[]
+            },
+            self.compression_threshold,
+            data.as_ref(),
+            self.transform.as_deref().map(|t| (t, path)),
+        )?;
+        let info = entry.info(&self.pak.compression);
+        self.pak.index.add_entry(path.to_string(), entry);
+
+        Ok(info)
+    }
+
+    /// [`Self::write_file`] storing data only once if it is identical to that of an earlier call
+    /// with the same `allow_compress`, as found by its SHA256. Later files become entries sharing
+    /// the data of the first, which the format permits as entries are independent. Returns
+    /// whether the data was shared.
+    ///
+    /// With an [`EntryTransform`] set nothing is shared, as the transform encodes the data of
+    /// each entry for its path and another path would decode it to garbage.
+    pub fn write_file_dedup(
+        &mut self,
+        path: &str,
+        allow_compress: bool,
+        data: impl AsRef<[u8]>,
+    ) -> Result<bool, super::Error> {
+        if self.transform.is_some() {
+            self.write_file(path, allow_compress, data)?;
+            return Ok(false);
+        }
+        use sha2::{Digest, Sha256};
+        let key = (Sha256::digest(data.as_ref()).into(), allow_compress);
+        if let Some(entry) = self.dedup.get(&key).cloned() {
+            self.add_shared_entry(path.to_owned(), entry);
+            return Ok(true);
+        }
+        self.write_file(path, allow_compress, data)?;
+        let entry = self.pak.index.entries[path].clone();
+        self.dedup.insert(key, entry);
+        Ok(false)
+    }
+
+    /// Adds an entry for `path` sharing the data of the already written entry `existing`
+    /// instead of storing it again
+    ///
+    /// Fails with [`super::Error::SharedDataWithTransform`] if an [`EntryTransform`] is set, as
+    /// the data of `existing` is encoded for its own path, see [`Self::write_file_dedup`].
+    pub fn write_duplicate(&mut self, path: &str, existing: &str) -> Result<(), super::Error> {
+        if self.transform.is_some() {
+            return Err(Error::SharedDataWithTransform {
+                path: path.to_owned(),
+                existing: existing.to_owned(),
+            });
+        }
+        let entry = self
+            .pak
+            .index
+            .entries
+            .get(existing)
+            .ok_or_else(|| Error::MissingEntry(existing.to_owned()))?
+            .clone();
+        self.add_shared_entry(path.to_owned(), entry);
+        Ok(())
+    }
+
+    fn add_shared_entry(&mut self, path: String, entry: Entry) {
+        let record = Entry::get_serialized_size(
+            self.pak.version,
+            entry.compression_slot,
+            entry
+                .blocks
+                .as_ref()
+                .map_or(0, |blocks| blocks.len() as u32),
         );
+        let data = match entry.is_encrypted() {
+            true => entry.compressed.next_multiple_of(16),
+            false => entry.compressed,
+        };
+        self.deduplicated += record + data;
+        self.pak.index.add_entry(path, entry);
+    }
+
+    /// Bytes of entry records and data not written thanks to entries sharing data with
+    /// [`Self::write_file_dedup`] and [`Self::write_duplicate`]
+    pub fn deduplicated_bytes(&self) -> u64 {
+        self.deduplicated
+    }
+
+    pub fn entry_builder(&self) -> EntryBuilder {
+        EntryBuilder {
+            allowed_compression: self.allowed_compression.clone(),
+            compression_threshold: self.compression_threshold,
+        }
+    }
+
+    pub fn write_entry<D: AsRef<[u8]>>(
+        &mut self,
+        path: String,
+        mut partial_entry: PartialEntry<D>,
+    ) -> Result<(), Error> {
+        if let Some(transform) = &self.transform {
+            partial_entry.transform(transform.as_ref(), &path);
+        }
+        let stream_position = self.writer.stream_position()?;
+
+        let entry = partial_entry.build_entry(
+            self.pak.version,
+            &mut self.pak.compression,
+            &self.pak.compression_names,
+            stream_position,
+        )?;
+
+        entry.write(
+            &mut self.writer,
+            self.pak.version,
+            crate::entry::EntryLocation::Data,
+        )?;
+
+        self.pak.index.add_entry(path, entry);
+        partial_entry.write_data(&mut self.writer)?;
 
         Ok(())
     }
+    /// Copy the entry at `path` from `source` as stored, without decompressing it.
+    ///
+    /// Encrypted entries stay encrypted if this writer was configured with the same key as
+    /// `source`, otherwise they are decrypted using the key of `source`. Both paks must be of the
+    /// same version.
+    pub fn copy_entry_from<R: Read + Seek>(
+        &mut self,
+        source: &PakReader,
+        reader: &mut R,
+        path: &str,
+    ) -> Result<(), Error> {
+        if source.pak.version != self.pak.version {
+            return Err(Error::Other(format!(
+                "cannot copy entries from a {} pak to a {} pak",
+                source.pak.version, self.pak.version
+            )));
+        }
+        let entry = source.entry(path)?;
+        let compression_slot = entry
+            .compression_slot
+            .map(|slot| {
+                let compression = source
+                    .pak
+                    .compression
+                    .get(slot as usize)
+                    .copied()
+                    .flatten()
+                    .ok_or_else(|| {
+                        Error::Other(format!("{path} uses unknown compression slot {slot}"))
+                    })?;
+                crate::data::get_compression_slot(
+                    self.pak.version,
+                    &mut self.pak.compression,
+                    &self.pak.compression_names,
+                    compression,
+                )
+            })
+            .transpose()?;
+        let copy = entry.copy(
+            reader,
+            &mut self.writer,
+            self.pak.version,
+            source.pak.data_end(entry),
+            compression_slot,
+            (!self.key.same_as(&source.key)).then_some(&source.key),
+            source.decrypt_limit.limit(path),
+        )?;
+        self.pak.index.add_entry(path.to_owned(), copy);
+        Ok(())
+    }
+
+    /// Insert zero padding after the data region so the index starts at a multiple of
+    /// `alignment`
+    pub fn pad_data_region_to(&mut self, alignment: u64) {
+        self.padding.data_region = Some(alignment);
+    }
+
+    /// Pad the pak so its total size is a multiple of `alignment`.
+    ///
+    /// The footer is located relative to the end of the file so no data may follow it. Padding
+    /// is instead inserted between the data region and the index, leaving slack space there.
+    /// Combined with [`PakWriter::pad_data_region_to`] both can only be satisfied if the size
+    /// of the index allows it, otherwise [`PakWriter::write_index`] fails.
+    pub fn pad_file_to(&mut self, alignment: u64) {
+        self.padding.file = Some(alignment);
+    }
+
+    /// Whether to write the path hash index used by the engine to look up entries by the hash
+    /// of their path. Only applies to V10+ and defaults to `true`.
+    pub fn write_path_hash_index(&mut self, enabled: bool) {
+        self.pak.path_hash_index = enabled;
+    }
+
+    /// Whether to write the full directory index containing the path of every entry. Only
+    /// applies to V10+ and defaults to `true`. Paks without it can't be listed by repak as only
+    /// hashes of the paths remain.
+    ///
+    /// At least one of the path hash index and full directory index must be written.
+    pub fn write_full_directory_index(&mut self, enabled: bool) {
+        self.pak.full_directory_index = enabled;
+    }
+
+    /// Whether to write the mount point and paths in the index as UTF-16 even if they are
+    /// ASCII, for games which expect it. Defaults to `false`, which only uses UTF-16 for names
+    /// which aren't ASCII. Names of a pak which was read keep their original encoding, and the
+    /// full directory index of V12 is always UTF-8.
+    pub fn force_utf16_strings(&mut self, enabled: bool) {
+        self.pak.index.wide_strings = enabled;
+    }
+
+    /// Size the indexes and footer written by [`Self::write_index`] would have for the entries
+    /// written so far, not counting any padding placed before them
+    pub fn predicted_index_size(&self) -> Result<u64, super::Error> {
+        // the size doesn't depend on where the index is placed
+        let (index, secondary_index) = self.pak.build_index(0)?;
+        Ok(self.pak.index_len(&index, secondary_index.as_ref()))
+    }
+
+    pub fn write_index(mut self) -> Result<W, super::Error> {
+        self.pak.write(&mut self.writer, &self.key, &self.padding)?;
+        Ok(self.writer)
+    }
+
+    /// Writes the index like [`PakWriter::write_index`], then reads the pak back from the
+    /// reader `reopen` makes of the writer. The index read back must list every entry as
+    /// written, the record preceding the data of each entry must parse and agree with it, and
+    /// the data of up to `sample` entries spread over the pak must match its hash. Fails with
+    /// [`super::Error::PostWriteVerification`] otherwise.
+    pub fn write_index_verified<R: Read + Seek>(
+        mut self,
+        sample: usize,
+        reopen: impl FnOnce(W) -> io::Result<R>,
+    ) -> Result<R, super::Error> {
+        self.pak.write(&mut self.writer, &self.key, &self.padding)?;
+        let mut reader = reopen(self.writer)?;
+        self.pak
+            .verify_written(&mut reader, &self.key, self.transform.as_deref(), sample)?;
+        Ok(reader)
+    }
+}
+
+/// Index sections of a V10+ pak returned by [`PakReader::raw_index_sections`], decrypted but
+/// otherwise as stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawIndexSections<'a> {
+    /// Encoded entry records from the primary index, without their size prefix
+    pub encoded_entries: &'a [u8],
+    /// The path hash index, if present, including any encryption padding
+    pub path_hash_index: Option<&'a [u8]>,
+    /// The full directory index, if present, including any encryption padding
+    pub full_directory_index: Option<&'a [u8]>,
+}
+
+/// Disagreements between the path hash index and the full directory index found by
+/// [`PakReader::check_path_hash_index`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PathHashIndexReport {
+    /// Entries without a record for the hash of their path
+    pub missing: Vec<String>,
+    /// Entries whose path hash record points at another encoded entry
+    pub mismatched: Vec<String>,
+    /// Records, as (path hash, offset of the encoded entry), whose hash matches no entry or
+    /// repeats the hash of an earlier record
+    pub extra: Vec<(u64, u32)>,
+}
+
+impl PathHashIndexReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Differences of the entries of a pak from a baseline found by
+/// [`PakReader::changed_entries`], each sorted by path
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EntryChanges {
+    /// Entries whose contents hash differently than in the baseline
+    pub modified: Vec<String>,
+    /// Entries missing from the baseline
+    pub added: Vec<String>,
+    /// Paths of the baseline without an entry in the pak
+    pub removed: Vec<String>,
+}
+
+impl EntryChanges {
+    /// Modified and added entries, which are all those whose contents have to be taken from the
+    /// pak, sorted by path
+    pub fn changed(&self) -> Vec<&str> {
+        let mut changed = self
+            .modified
+            .iter()
+            .chain(&self.added)
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        changed.sort_unstable();
+        changed
+    }
+}
+
+/// Entry of a pak as listed by [`PakReader::entries_by_offset`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutEntry {
+    pub path: String,
+    pub info: EntryInfo,
+    /// Bytes taken by the record preceding the data and the data as stored, including any
+    /// encryption padding
+    pub size: u64,
+    /// Bytes between the end of the entry and the next entry or index, zero if they overlap or
+    /// nothing follows
+    pub gap: u64,
+}
+
+/// Unused bytes of a pak as found by [`PakReader::fragmentation_report`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FragmentationReport {
+    pub file_len: u64,
+    /// Bytes between entries, the indexes and the footer which are not part of any of them
+    pub dead_bytes: u64,
+    /// Number of separate runs of dead bytes
+    pub gaps: usize,
+    pub largest_gap: u64,
+}
+
+impl FragmentationReport {
+    /// Dead bytes as a percentage of the file length
+    pub fn percentage(&self) -> f64 {
+        match self.file_len {
+            0 => 0.0,
+            len => self.dead_bytes as f64 * 100.0 / len as f64,
+        }
+    }
+}
 
-    pub fn entry_builder(&self) -> EntryBuilder {
-        EntryBuilder {
-            allowed_compression: self.allowed_compression.clone(),
+/// Cumulative statistics of a directory and everything below it
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DirectorySummary {
+    pub file_count: u64,
+    pub uncompressed: u64,
+    pub compressed: u64,
+    pub directories: BTreeMap<String, DirectorySummary>,
+    pub files: BTreeMap<String, EntryInfo>,
+}
+
+impl DirectorySummary {
+    fn insert(&mut self, path: &str, info: EntryInfo) {
+        self.file_count += 1;
+        self.uncompressed += info.uncompressed;
+        self.compressed += info.compressed;
+        match path.split_once('/') {
+            Some((directory, rest)) => self
+                .directories
+                .entry(directory.to_owned())
+                .or_default()
+                .insert(rest, info),
+            None => {
+                self.files.insert(path.to_owned(), info);
+            }
         }
     }
+}
 
-    pub fn write_entry<D: AsRef<[u8]>>(
-        &mut self,
-        path: String,
-        partial_entry: PartialEntry<D>,
-    ) -> Result<(), Error> {
-        let stream_position = self.writer.stream_position()?;
+/// Directory of a pak which was read, see [`PakReader::list_dir`]
+#[derive(Debug, Default)]
+struct Directory {
+    /// Names of the subdirectories, sorted
+    directories: Vec<String>,
+    /// Paths of the files in the index, sorted
+    files: Vec<String>,
+}
 
-        let entry = partial_entry.build_entry(
-            self.pak.version,
-            &mut self.pak.compression,
-            stream_position,
-        )?;
+impl Directory {
+    /// Directories containing `paths`, which are sorted, and their ancestors by path without
+    /// a trailing slash. The root is always present.
+    fn index<'a>(paths: impl Iterator<Item = &'a str>) -> BTreeMap<String, Directory> {
+        fn add(directories: &mut BTreeMap<String, Directory>, path: &str) {
+            if directories.contains_key(path) {
+                return;
+            }
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+            add(directories, parent);
+            directories
+                .get_mut(parent)
+                .expect("parent was added")
+                .directories
+                .push(name.to_owned());
+            directories.insert(path.to_owned(), Directory::default());
+        }
 
-        entry.write(
-            &mut self.writer,
-            self.pak.version,
-            crate::entry::EntryLocation::Data,
-        )?;
+        let mut directories = BTreeMap::from([(String::new(), Directory::default())]);
+        for path in paths {
+            let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+            add(&mut directories, parent);
+            directories
+                .get_mut(parent)
+                .expect("directory was added")
+                .files
+                .push(path.to_owned());
+        }
+        // subdirectories are added in the order of the first file below them, which differs
+        // from the order of their names for names followed by characters below '/'
+        for directory in directories.values_mut() {
+            directory.directories.sort();
+        }
+        directories
+    }
+}
 
-        self.pak.index.add_entry(path, entry);
-        partial_entry.write_data(&mut self.writer)?;
+/// Contents of a directory of a pak, see [`PakReader::list_dir`]
+#[derive(Debug, Clone, Copy)]
+pub struct DirListing<'a> {
+    reader: &'a PakReader,
+    directory: &'a Directory,
+}
 
-        Ok(())
+impl<'a> DirListing<'a> {
+    /// Names of the subdirectories, sorted
+    pub fn directories(&self) -> impl ExactSizeIterator<Item = &'a str> + 'a {
+        self.directory.directories.iter().map(String::as_str)
     }
-    pub fn write_index(mut self) -> Result<W, super::Error> {
-        self.pak.write(&mut self.writer, &self.key)?;
-        Ok(self.writer)
+
+    /// Names and information of the files, sorted
+    pub fn files(&self) -> impl ExactSizeIterator<Item = (&'a str, EntryInfo)> + 'a {
+        let reader = self.reader;
+        self.directory.files.iter().map(move |path| {
+            let name = path
+                .rsplit_once('/')
+                .map_or(path.as_str(), |(_, name)| name);
+            let info = reader.entry_info(path).expect("listed entries exist");
+            (name, info)
+        })
+    }
+
+    /// Paths of the files relative to the mount point, in the order of [`Self::files`]
+    pub fn file_paths(&self) -> impl ExactSizeIterator<Item = &'a str> + 'a {
+        self.directory.files.iter().map(String::as_str)
     }
 }
 
@@ -338,65 +2633,301 @@ impl AsRef<[u8]> for Data<'_> {
 #[derive(Clone)]
 pub struct EntryBuilder {
     allowed_compression: Vec<Compression>,
+    compression_threshold: f64,
 }
 impl EntryBuilder {
-    /// Builds an entry in memory (compressed if requested) which must be written out later
+    /// Builds an entry in memory which must be written out later, compressed if requested the
+    /// same way as [`PakWriter::write_file`]
     pub fn build_entry<D: AsRef<[u8]> + Send + Sync>(
         &self,
         compress: bool,
         data: D,
     ) -> Result<PartialEntry<D>, Error> {
-        let compression = compress
-            .then_some(self.allowed_compression.as_slice())
-            .unwrap_or_default();
-        build_partial_entry(compression, data)
+        let compression = if compress {
+            self.allowed_compression.as_slice()
+        } else {
+            &[]
+        };
+        build_partial_entry(compression, self.compression_threshold, data)
     }
 }
 
 impl Pak {
+    /// Checks that the pak written from `self` to `reader` reads back as written, see
+    /// [`PakWriter::write_index_verified`]
+    fn verify_written<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        key: &super::Key,
+        transform: Option<&dyn EntryTransform>,
+        sample: usize,
+    ) -> Result<(), super::Error> {
+        let failed = |path: Option<&str>, reason: String| super::Error::PostWriteVerification {
+            path: path.map(str::to_owned),
+            reason,
+        };
+        let written = Pak::read(
+            reader,
+            self.version,
+            key,
+            self.magic,
+            self.footer_extra.len(),
+            true,
+            false,
+            false,
+            true,
+            false,
+        )
+        .map_err(|err| failed(None, format!("the index can't be read back: {err}")))?;
+
+        let expected = self.index.entries();
+        let entries = written.index.entries();
+        if entries.len() != expected.len() {
+            return Err(failed(
+                None,
+                format!(
+                    "the index lists {} entries but {} were written",
+                    entries.len(),
+                    expected.len()
+                ),
+            ));
+        }
+        for (path, entry) in expected {
+            let Some(read) = entries.get(path) else {
+                return Err(failed(Some(path), "missing from the index".to_owned()));
+            };
+            let indexed = (read.offset, read.compressed, read.uncompressed);
+            if indexed != (entry.offset, entry.compressed, entry.uncompressed)
+                || read.compression_slot != entry.compression_slot
+            {
+                return Err(failed(
+                    Some(path),
+                    "the index record differs from the entry written".to_owned(),
+                ));
+            }
+            let (header, _, _) = entry
+                .read_header(reader, self.version, self.data_end(entry))
+                .map_err(|err| {
+                    failed(Some(path), format!("the entry record can't be read: {err}"))
+                })?;
+            let hash_differs =
+                header.hash.is_some() && entry.hash.is_some() && header.hash != entry.hash;
+            if (header.compressed, header.uncompressed) != (entry.compressed, entry.uncompressed)
+                || header.compression_slot != entry.compression_slot
+                || hash_differs
+            {
+                return Err(failed(
+                    Some(path),
+                    "the entry record differs from the entry written".to_owned(),
+                ));
+            }
+        }
+
+        let sample = sample.min(expected.len());
+        let mut by_offset = expected.iter().collect::<Vec<_>>();
+        by_offset.sort_by_key(|(_, entry)| entry.offset);
+        for i in 0..sample {
+            let (path, entry) = by_offset[i * by_offset.len() / sample];
+            entry
+                .read_file(
+                    reader,
+                    self.version,
+                    &self.compression,
+                    key,
+                    self.data_end(entry),
+                    ReadOptions {
+                        verify: true,
+                        transform: transform.map(|t| (t, path.as_str())),
+                        ..Default::default()
+                    },
+                    &mut io::sink(),
+                )
+                .map_err(|err| failed(Some(path), format!("the data can't be read back: {err}")))?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn read<R: Read + Seek>(
         reader: &mut R,
         version: super::Version,
-        #[allow(unused)] key: &super::Key,
+        key: &super::Key,
+        magic: u32,
+        footer_extra_bytes: usize,
+        lossy_paths: bool,
+        parallel: bool,
+        metadata_only: bool,
+        verify_hashes: bool,
+        skip_implausible: bool,
     ) -> Result<Self, super::Error> {
         // read footer to get index, encryption & compression info
-        reader.seek(io::SeekFrom::End(-version.size()))?;
-        let footer = super::footer::Footer::read(reader, version)?;
+        let footer_len = version.size() as usize + footer_extra_bytes;
+        let footer_offset = reader.seek(io::SeekFrom::End(-(footer_len as i64)))?;
+        let footer = reader.read_len(footer_len)?;
+        let footer = parse_footer(&footer, version, magic, footer_extra_bytes)?;
+        let footer_offset = footer_offset + (footer_len as u64 - footer.size());
+        if footer.frozen {
+            return Err(super::Error::FrozenIndex);
+        }
         // read index to get all the entry info
-        reader.seek(io::SeekFrom::Start(footer.index_offset))?;
-        #[allow(unused_mut)]
-        let mut index = reader.read_len(footer.index_size as usize)?;
+        let index = read_range(reader, footer.index_range(), "index")?;
+        let index = decrypt_index(&footer, index, key)?;
+        if verify_hashes {
+            verify_index_hash(IndexSection::Primary, &footer.hash, &index)
+                .map_err(|err| wrong_key_or_corrupt(&footer, err))?;
+        }
+        if metadata_only {
+            return Pak::parse_header(&footer, &index, key, lossy_paths)
+                .map_err(|err| wrong_key_or_corrupt(&footer, err));
+        }
 
-        // decrypt index if needed
-        if footer.encrypted {
-            #[cfg(not(feature = "encryption"))]
-            return Err(super::Error::Encryption);
-            #[cfg(feature = "encryption")]
-            decrypt(key, &mut index)?;
+        (|| {
+            let secondary = IndexHeader::read(&mut io::Cursor::new(&index), version)?.secondary;
+            let mut read_secondary = |range: std::ops::Range<u64>, what| {
+                // checked before allocating a buffer for a size which may be garbage
+                if range.end > footer_offset {
+                    return Err(super::Error::Io(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("{what} extends past the end of the pak"),
+                    )));
+                }
+                read_range(reader, range, what)
+            };
+            let path_hash_index = secondary
+                .path_hash_index
+                .map(|range| read_secondary(range, "path hash index"))
+                .transpose()?;
+            let full_directory_index = secondary
+                .full_directory_index
+                .map(|range| read_secondary(range, "full directory index"))
+                .transpose()?;
+
+            Pak::parse(
+                &footer,
+                &index,
+                path_hash_index.as_deref(),
+                full_directory_index.as_deref(),
+                key,
+                lossy_paths,
+                parallel,
+                verify_hashes,
+                skip_implausible,
+            )
+        })()
+        .map_err(|err| wrong_key_or_corrupt(&footer, err))
+    }
+
+    /// Parses only the header of the decrypted primary index, leaving the index empty
+    fn parse_header(
+        footer: &Footer,
+        index: &[u8],
+        key: &super::Key,
+        lossy_paths: bool,
+    ) -> Result<Self, super::Error> {
+        let header = IndexHeader::read(&mut io::Cursor::new(index), footer.version)?;
+        let mount_point_raw = header.mount_point.to_bytes();
+        let mut warnings = footer_warnings(footer);
+        let mount_point = decode_mount_point(
+            header.mount_point,
+            footer,
+            key,
+            &mut BTreeMap::new(),
+            lossy_paths,
+            &mut warnings,
+        )?;
+        Ok(Pak {
+            mount_point_raw,
+            warnings,
+            index_offset: Some(footer.index_offset),
+            index_ranges: std::iter::once(footer.index_range())
+                .chain(header.secondary.path_hash_index.clone())
+                .chain(header.secondary.full_directory_index.clone())
+                .collect(),
+            encrypted_index: footer.encrypted,
+            encryption_guid: footer.encryption_uuid,
+            compression: footer.compression.clone(),
+            compression_names: footer.raw_compression.clone(),
+            footer_extra: footer.extra.clone(),
+            path_hash_index: footer.version.version_major() < VersionMajor::PathHashIndex
+                || header.secondary.path_hash_index.is_some(),
+            full_directory_index: footer.version.version_major() < VersionMajor::PathHashIndex
+                || header.secondary.full_directory_index.is_some(),
+            unread_entries: Some(header.entry_count),
+            ..Pak::new(
+                footer.version,
+                footer.magic,
+                mount_point,
+                header.path_hash_seed,
+            )
+        })
+    }
+
+    /// Parses the decrypted primary index and the secondary indexes as stored
+    #[allow(clippy::too_many_arguments)]
+    fn parse(
+        footer: &Footer,
+        index: &[u8],
+        path_hash_index: Option<&[u8]>,
+        full_directory_index: Option<&[u8]>,
+        #[allow(unused)] key: &super::Key,
+        lossy_paths: bool,
+        parallel: bool,
+        verify_hashes: bool,
+        skip_implausible: bool,
+    ) -> Result<Self, super::Error> {
+        if footer.frozen {
+            return Err(super::Error::FrozenIndex);
         }
+        let version = footer.version;
+        let mut raw_names = BTreeMap::new();
 
         let mut index = io::Cursor::new(index);
-        let mount_point = index.read_string()?;
-        let len = index.read_u32::<LE>()? as usize;
+        let header = IndexHeader::read(&mut index, version)?;
+        let index_ranges = std::iter::once(footer.index_range())
+            .chain(header.secondary.path_hash_index.clone())
+            .chain(header.secondary.full_directory_index.clone())
+            .collect();
+        let mount_point_raw = header.mount_point.to_bytes();
+        let mut warnings = footer_warnings(footer);
+        let mount_point = decode_mount_point(
+            header.mount_point,
+            footer,
+            key,
+            &mut raw_names,
+            lossy_paths,
+            &mut warnings,
+        )?;
+
+        // decrypts a secondary index, which is encrypted along with the primary index
+        #[allow(unused_mut)]
+        let decrypt = |mut bytes: Vec<u8>| -> Result<Vec<u8>, super::Error> {
+            if footer.encrypted {
+                #[cfg(not(feature = "encryption"))]
+                return Err(super::Error::EncryptionFeatureDisabled);
+                #[cfg(feature = "encryption")]
+                decrypt(key, &mut bytes)?;
+            }
+            Ok(bytes)
+        };
+
+        let has_path_hash_index;
+        let has_full_directory_index;
+        let mut path_hash_records = None;
+        let mut encoded_offsets = BTreeMap::new();
+        let mut encoded_index = None;
 
         let index = if version.version_major() >= VersionMajor::PathHashIndex {
-            let path_hash_seed = index.read_u64::<LE>()?;
-
-            // Left in for potential desire to verify path index hashes.
-            let _path_hash_index = if index.read_u32::<LE>()? != 0 {
-                let path_hash_index_offset = index.read_u64::<LE>()?;
-                let path_hash_index_size = index.read_u64::<LE>()?;
-                let _path_hash_index_hash = index.read_len(20)?;
-
-                reader.seek(io::SeekFrom::Start(path_hash_index_offset))?;
-                let mut path_hash_index_buf = reader.read_len(path_hash_index_size as usize)?;
-                // TODO verify hash
-
-                if footer.encrypted {
-                    #[cfg(not(feature = "encryption"))]
-                    return Err(super::Error::Encryption);
-                    #[cfg(feature = "encryption")]
-                    decrypt(key, &mut path_hash_index_buf)?;
+            let secondary = &header.secondary;
+            has_path_hash_index = secondary.path_hash_index.is_some();
+            has_full_directory_index = secondary.full_directory_index.is_some();
+            let mut encoded = EncodedIndex::default();
+
+            // Kept to check it against the full directory index. It isn't needed to list the
+            // entries so it may be omitted.
+            if let (true, Some(path_hash_index)) = (has_path_hash_index, path_hash_index) {
+                let mut path_hash_index_buf = decrypt(path_hash_index.to_vec())?;
+                if let (true, Some(expected)) = (verify_hashes, &header.secondary_hashes[0]) {
+                    verify_index_hash(IndexSection::PathHashIndex, expected, &path_hash_index_buf)?;
                 }
 
                 let mut path_hash_index = vec![];
@@ -406,101 +2937,188 @@ impl Pak {
                     let encoded_entry_offset = phi_reader.read_u32::<LE>()?;
                     path_hash_index.push((hash, encoded_entry_offset));
                 }
+                path_hash_records = Some(path_hash_index);
+                encoded.path_hash_index = Some(path_hash_index_buf);
+            }
 
-                Some(path_hash_index)
-            } else {
-                None
-            };
+            let full_directory_index = match (has_full_directory_index, full_directory_index) {
+                (true, Some(full_directory_index)) => {
+                    let full_directory_index = decrypt(full_directory_index.to_vec())?;
+                    if let (true, Some(expected)) = (verify_hashes, &header.secondary_hashes[1]) {
+                        verify_index_hash(
+                            IndexSection::FullDirectoryIndex,
+                            expected,
+                            &full_directory_index,
+                        )?;
+                    }
 
-            // Left in for potential desire to verify full directory index hashes.
-            let full_directory_index = if index.read_u32::<LE>()? != 0 {
-                let full_directory_index_offset = index.read_u64::<LE>()?;
-                let full_directory_index_size = index.read_u64::<LE>()?;
-                let _full_directory_index_hash = index.read_len(20)?;
-
-                reader.seek(io::SeekFrom::Start(full_directory_index_offset))?;
-                #[allow(unused_mut)]
-                let mut full_directory_index =
-                    reader.read_len(full_directory_index_size as usize)?;
-                // TODO verify hash
-
-                if footer.encrypted {
-                    #[cfg(not(feature = "encryption"))]
-                    return Err(super::Error::Encryption);
-                    #[cfg(feature = "encryption")]
-                    decrypt(key, &mut full_directory_index)?;
-                }
-                let mut fdi = io::Cursor::new(full_directory_index);
-
-                let dir_count = fdi.read_u32::<LE>()? as usize;
-                let mut directories = BTreeMap::new();
-                for _ in 0..dir_count {
-                    let dir_name = fdi.read_string()?;
-                    let file_count = fdi.read_u32::<LE>()? as usize;
-                    let mut files = BTreeMap::new();
-                    for _ in 0..file_count {
-                        let file_name = fdi.read_string()?;
-                        files.insert(file_name, fdi.read_u32::<LE>()?);
+                    let mut fdi = io::Cursor::new(full_directory_index);
+
+                    let dir_count = fdi.read_u32::<LE>()? as usize;
+                    let mut directories = BTreeMap::<String, BTreeMap<String, u32>>::new();
+                    for _ in 0..dir_count {
+                        let dir_name = read_name(&mut fdi, &mut raw_names, lossy_paths)?;
+                        let file_count = fdi.read_u32::<LE>()? as usize;
+                        // some third-party packers list a directory more than once, which the
+                        // engine merges keeping the first entry of files listed repeatedly
+                        let files = directories.entry(dir_name.clone()).or_default();
+                        for _ in 0..file_count {
+                            let file_name = read_name(&mut fdi, &mut raw_names, lossy_paths)?;
+                            let encoded_offset = fdi.read_u32::<LE>()?;
+                            match files.entry(file_name) {
+                                std::collections::btree_map::Entry::Vacant(vacant) => {
+                                    vacant.insert(encoded_offset);
+                                }
+                                std::collections::btree_map::Entry::Occupied(first)
+                                    if *first.get() != encoded_offset =>
+                                {
+                                    warnings.push(IndexWarning::ConflictingDuplicate {
+                                        path: format!(
+                                            "{}{}",
+                                            dir_name.strip_prefix('/').unwrap_or(&dir_name),
+                                            first.key()
+                                        ),
+                                        used: *first.get(),
+                                        ignored: encoded_offset,
+                                    });
+                                }
+                                std::collections::btree_map::Entry::Occupied(_) => {}
+                            }
+                        }
                     }
-                    directories.insert(dir_name, files);
+                    encoded.full_directory_index = Some(fdi.into_inner());
+                    Some(directories)
                 }
-                Some(directories)
-            } else {
-                None
+                (true, None) => {
+                    return Err(super::Error::Other(
+                        "the full directory index is required to list the entries".to_owned(),
+                    ))
+                }
+                (false, _) => None,
             };
             let size = index.read_u32::<LE>()? as usize;
             let encoded_entries = index.read_len(size)?;
 
-            let mut entries_by_path = BTreeMap::new();
+            let mut records = vec![];
             if let Some(fdi) = &full_directory_index {
-                let mut encoded_entries = io::Cursor::new(&encoded_entries);
                 for (dir_name, dir) in fdi {
                     for (file_name, encoded_offset) in dir {
-                        if *encoded_offset == 0x80000000 {
-                            println!("{file_name:?} has invalid offset: 0x{encoded_offset:08x}");
-                            continue;
-                        }
-                        encoded_entries.seek(io::SeekFrom::Start(*encoded_offset as u64))?;
-                        let entry =
-                            super::entry::Entry::read_encoded(&mut encoded_entries, version)?;
                         let path = format!(
                             "{}{}",
                             dir_name.strip_prefix('/').unwrap_or(dir_name),
                             file_name
                         );
-                        entries_by_path.insert(path, entry);
+                        if *encoded_offset == 0x80000000 {
+                            warnings.push(IndexWarning::InvalidEncodedOffset { path });
+                            continue;
+                        }
+                        records.push((path, *encoded_offset));
                     }
                 }
             }
+            let threads = match parallel && records.len() >= PARALLEL_PARSE_MIN_ENTRIES {
+                true => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+                false => 1,
+            };
+            let entries = decode_entries(
+                &encoded_entries,
+                &records,
+                version,
+                threads,
+                skip_implausible,
+            )?;
+            let entries_by_path = records
+                .into_iter()
+                .zip(entries)
+                .filter_map(|((path, encoded_offset), entry)| match entry {
+                    Ok(entry) => {
+                        encoded_offsets.insert(path.clone(), encoded_offset);
+                        Some((path, entry))
+                    }
+                    Err(warning) => {
+                        warnings.push(warning);
+                        None
+                    }
+                })
+                .collect();
 
-            assert_eq!(index.read_u32::<LE>()?, 0, "remaining index bytes are 0"); // TODO possibly remaining unencoded entries?
+            // TODO read the entries which are not encoded instead of failing
+            match index.read_u32::<LE>()? {
+                0 => {}
+                count => return Err(super::Error::UnencodedEntries(count)),
+            }
+            encoded.entries = encoded_entries;
+            encoded_index = Some(encoded);
 
             Index {
-                path_hash_seed: Some(path_hash_seed),
+                path_hash_seed: header.path_hash_seed,
                 entries: entries_by_path,
+                raw_names,
+                wide_strings: false,
             }
         } else {
+            has_path_hash_index = true;
+            has_full_directory_index = true;
+
             let mut entries = BTreeMap::new();
-            for _ in 0..len {
-                entries.insert(
-                    index.read_string()?,
-                    super::entry::Entry::read(&mut index, version)?,
-                );
+            for _ in 0..header.entry_count {
+                let path = read_name(&mut index, &mut raw_names, lossy_paths)?;
+                let entry =
+                    super::entry::Entry::read(&mut index, version).map_err(|err| match err {
+                        super::Error::ImplausibleBlockCount {
+                            path: None,
+                            count,
+                            uncompressed,
+                        } => super::Error::ImplausibleBlockCount {
+                            path: Some(path.clone()),
+                            count,
+                            uncompressed,
+                        },
+                        err => err,
+                    })?;
+                entries.insert(path, entry);
             }
             Index {
                 path_hash_seed: None,
                 entries,
+                raw_names,
+                wide_strings: false,
             }
         };
 
+        for (path, entry) in index.entries() {
+            if let Some(derived) = entry.derived_block_size() {
+                warnings.push(IndexWarning::DerivedBlockSize {
+                    path: path.clone(),
+                    stored: entry.compression_block_size,
+                    derived,
+                });
+            }
+        }
+
+        let offsets = index.entries().values().map(|entry| entry.offset);
+        let entry_offsets = offsets.clone().min().zip(offsets.max());
         Ok(Pak {
             version,
+            magic: footer.magic,
             mount_point,
+            mount_point_raw,
             index_offset: Some(footer.index_offset),
+            entry_offsets: entry_offsets.map(|(first, last)| first..=last),
+            index_ranges,
             index,
             encrypted_index: footer.encrypted,
             encryption_guid: footer.encryption_uuid,
-            compression: footer.compression,
+            compression: footer.compression.clone(),
+            compression_names: footer.raw_compression.clone(),
+            footer_extra: footer.extra.clone(),
+            path_hash_records,
+            encoded_offsets,
+            path_hash_index: has_path_hash_index,
+            full_directory_index: has_full_directory_index,
+            unread_entries: None,
+            encoded_index,
+            warnings,
         })
     }
 
@@ -508,18 +3126,77 @@ impl Pak {
         &self,
         writer: &mut W,
         _key: &super::Key,
+        padding: &Padding,
     ) -> Result<(), super::Error> {
-        let index_offset = writer.stream_position()?;
+        let data_end = writer.stream_position()?;
+
+        // the size of the index does not depend on where it is placed so it can be serialized
+        // once to determine the padding and again if the offset changed
+        let (mut index_buf, mut secondary_index) = self.build_index(data_end)?;
+        let index_len = self.index_len(&index_buf, secondary_index.as_ref());
+        let index_offset = padding.index_offset(data_end, index_len)?;
+        if index_offset != data_end {
+            io::copy(&mut io::repeat(0).take(index_offset - data_end), writer)?;
+            (index_buf, secondary_index) = self.build_index(index_offset)?;
+        }
+
+        let index_hash = hash(&index_buf);
+
+        writer.write_all(&index_buf)?;
+
+        if let Some((phi_buf, fdi_buf)) = secondary_index {
+            writer.write_all(&phi_buf[..])?;
+            writer.write_all(&fdi_buf[..])?;
+        }
+
+        let footer = Footer {
+            encryption_uuid: None,
+            encrypted: false,
+            magic: self.magic,
+            version: self.version,
+            version_major: self.version.version_major(),
+            unknown_version: None,
+            index_offset,
+            index_size: index_buf.len() as u64,
+            hash: index_hash,
+            frozen: false,
+            compression: self.compression.clone(), // TODO: avoid this clone
+            raw_compression: self.compression_names.clone(),
+            extra: self.footer_extra.clone(),
+        };
+
+        footer.write(writer)?;
 
+        Ok(())
+    }
+
+    /// Size of the serialized indexes from [`Self::build_index`] together with the footer
+    /// following them
+    fn index_len(&self, index: &[u8], secondary_index: Option<&(Vec<u8>, Vec<u8>)>) -> u64 {
+        index.len() as u64
+            + secondary_index.map_or(0, |(phi, fdi)| (phi.len() + fdi.len()) as u64)
+            + self.version.size() as u64
+            + self.footer_extra.len() as u64
+    }
+
+    /// Serializes the primary index and, for V10+, the path hash and full directory indexes
+    /// which follow it
+    #[allow(clippy::type_complexity)]
+    fn build_index(
+        &self,
+        index_offset: u64,
+    ) -> Result<(Vec<u8>, Option<(Vec<u8>, Vec<u8>)>), super::Error> {
         let mut index_buf = vec![];
         let mut index_writer = io::Cursor::new(&mut index_buf);
-        index_writer.write_string(&self.mount_point)?;
+        self.index
+            .write_name(&mut index_writer, &self.mount_point)?;
+        let mount_point_size = index_writer.position();
 
         let secondary_index = if self.version < super::Version::V10 {
             let record_count = self.index.entries.len() as u32;
             index_writer.write_u32::<LE>(record_count)?;
-            for (path, entry) in &self.index.entries {
-                index_writer.write_string(path)?;
+            for (path, entry) in self.index.sorted_entries() {
+                self.index.write_name(&mut index_writer, path)?;
                 entry.write(
                     &mut index_writer,
                     self.version,
@@ -536,13 +3213,28 @@ impl Pak {
             let (encoded_entries, offsets) = {
                 let mut offsets = Vec::with_capacity(self.index.entries.len());
                 let mut encoded_entries = io::Cursor::new(vec![]);
-                for entry in self.index.entries.values() {
-                    offsets.push(encoded_entries.get_ref().len() as u32);
+                // entries of a pak which was read keep their order so that entries which didn't
+                // change keep their encoded offset, followed by new entries
+                let mut entries = self.index.sorted_entries();
+                entries.sort_by_key(|(path, _)| {
+                    self.encoded_offsets
+                        .get(path.as_str())
+                        .map_or((1, 0), |&offset| (0, offset))
+                });
+                for (path, entry) in entries {
+                    offsets.push((path.as_str(), encoded_entries.get_ref().len() as u32));
                     entry.write_encoded(&mut encoded_entries)?;
                 }
                 (encoded_entries.into_inner(), offsets)
             };
 
+            if !self.path_hash_index && !self.full_directory_index {
+                return Err(Error::Other(
+                    "at least one of the path hash index and full directory index must be written"
+                        .to_owned(),
+                ));
+            }
+
             // The index is organized sequentially as:
             // - Index Header, which contains:
             //     - Mount Point (u32 len + string w/ terminating byte)
@@ -566,14 +3258,17 @@ impl Pak {
             //     - Size (u32)
             let bytes_before_phi = {
                 let mut size = 0;
-                size += 4; // mount point len
-                size += self.mount_point.len() as u64 + 1; // mount point string w/ NUL byte
+                size += mount_point_size; // mount point len + string w/ terminator
                 size += 8; // path hash seed
                 size += 4; // record count
-                size += 4; // has path hash index (since we're generating, always true)
-                size += 8 + 8 + 20; // path hash index offset, size and hash
-                size += 4; // has full directory index (since we're generating, always true)
-                size += 8 + 8 + 20; // full directory index offset, size and hash
+                size += 4; // has path hash index
+                if self.path_hash_index {
+                    size += 8 + 8 + 20; // path hash index offset, size and hash
+                }
+                size += 4; // has full directory index
+                if self.full_directory_index {
+                    size += 8 + 8 + 20; // full directory index offset, size and hash
+                }
                 size += 4; // encoded entry size
                 size += encoded_entries.len() as u64;
                 size += 4; // unused file count
@@ -583,63 +3278,54 @@ impl Pak {
             let path_hash_index_offset = index_offset + bytes_before_phi;
 
             let mut phi_buf = vec![];
-            let mut phi_writer = io::Cursor::new(&mut phi_buf);
-            generate_path_hash_index(
-                &mut phi_writer,
-                path_hash_seed,
-                &self.index.entries,
-                &offsets,
-            )?;
+            if self.path_hash_index {
+                let mut phi_writer = io::Cursor::new(&mut phi_buf);
+                generate_path_hash_index(
+                    &mut phi_writer,
+                    self.version,
+                    path_hash_seed,
+                    &self.index,
+                    &offsets,
+                )?;
+            }
 
             let full_directory_index_offset = path_hash_index_offset + phi_buf.len() as u64;
 
             let mut fdi_buf = vec![];
-            let mut fdi_writer = io::Cursor::new(&mut fdi_buf);
-            generate_full_directory_index(&mut fdi_writer, &self.index.entries, &offsets)?;
+            if self.full_directory_index {
+                let mut fdi_writer = io::Cursor::new(&mut fdi_buf);
+                generate_full_directory_index(
+                    &mut fdi_writer,
+                    self.version,
+                    &self.index,
+                    &offsets,
+                )?;
+            }
 
-            index_writer.write_u32::<LE>(1)?; // we have path hash index
-            index_writer.write_u64::<LE>(path_hash_index_offset)?;
-            index_writer.write_u64::<LE>(phi_buf.len() as u64)?; // path hash index size
-            index_writer.write_all(&hash(&phi_buf).0)?;
+            index_writer.write_u32::<LE>(self.path_hash_index as u32)?;
+            if self.path_hash_index {
+                index_writer.write_u64::<LE>(path_hash_index_offset)?;
+                index_writer.write_u64::<LE>(phi_buf.len() as u64)?; // path hash index size
+                index_writer.write_all(&hash(&phi_buf).0)?;
+            }
 
-            index_writer.write_u32::<LE>(1)?; // we have full directory index
-            index_writer.write_u64::<LE>(full_directory_index_offset)?;
-            index_writer.write_u64::<LE>(fdi_buf.len() as u64)?; // path hash index size
-            index_writer.write_all(&hash(&fdi_buf).0)?;
+            index_writer.write_u32::<LE>(self.full_directory_index as u32)?;
+            if self.full_directory_index {
+                index_writer.write_u64::<LE>(full_directory_index_offset)?;
+                index_writer.write_u64::<LE>(fdi_buf.len() as u64)?; // full directory index size
+                index_writer.write_all(&hash(&fdi_buf).0)?;
+            }
 
             index_writer.write_u32::<LE>(encoded_entries.len() as u32)?;
             index_writer.write_all(&encoded_entries)?;
 
             index_writer.write_u32::<LE>(0)?;
+            debug_assert_eq!(index_writer.position(), bytes_before_phi);
 
             Some((phi_buf, fdi_buf))
         };
 
-        let index_hash = hash(&index_buf);
-
-        writer.write_all(&index_buf)?;
-
-        if let Some((phi_buf, fdi_buf)) = secondary_index {
-            writer.write_all(&phi_buf[..])?;
-            writer.write_all(&fdi_buf[..])?;
-        }
-
-        let footer = super::footer::Footer {
-            encryption_uuid: None,
-            encrypted: false,
-            magic: super::MAGIC,
-            version: self.version,
-            version_major: self.version.version_major(),
-            index_offset,
-            index_size: index_buf.len() as u64,
-            hash: index_hash,
-            frozen: false,
-            compression: self.compression.clone(), // TODO: avoid this clone
-        };
-
-        footer.write(writer)?;
-
-        Ok(())
+        Ok((index_buf, secondary_index))
     }
 }
 
@@ -652,13 +3338,15 @@ fn hash(data: &[u8]) -> Hash {
 
 fn generate_path_hash_index<W: Write>(
     writer: &mut W,
+    version: Version,
     path_hash_seed: u64,
-    entries: &BTreeMap<String, super::entry::Entry>,
-    offsets: &Vec<u32>,
+    index: &Index,
+    offsets: &[(&str, u32)],
 ) -> Result<(), super::Error> {
-    writer.write_u32::<LE>(entries.len() as u32)?;
-    for (path, offset) in entries.keys().zip(offsets) {
-        let path_hash = fnv64_path(path, path_hash_seed);
+    writer.write_u32::<LE>(offsets.len() as u32)?;
+    for (path, offset) in offsets {
+        let path = index.engine_path(path);
+        let path_hash = super::path_hash::hash_path(version, &path, path_hash_seed);
         writer.write_u64::<LE>(path_hash)?;
         writer.write_u32::<LE>(*offset)?;
     }
@@ -668,24 +3356,126 @@ fn generate_path_hash_index<W: Write>(
     Ok(())
 }
 
-fn fnv64<I>(data: I, offset: u64) -> u64
-where
-    I: IntoIterator<Item = u8>,
-{
-    const OFFSET: u64 = 0xcbf29ce484222325;
-    const PRIME: u64 = 0x00000100000001b3;
-    let mut hash = OFFSET.wrapping_add(offset);
-    for b in data.into_iter() {
-        hash ^= b as u64;
-        hash = hash.wrapping_mul(PRIME);
+/// Orders paths the way UnrealPak sorts the files of a pak and so the entries of the index it
+/// writes: by `FString` comparison, which compares UTF-16 code units after lowercasing ASCII
+/// letters only and is therefore independent of the locale. Paths equal apart from the case of
+/// ASCII letters are ordered by their code units so the order is total.
+///
+/// [`PakWriter`] writes the primary index, path hash index and full directory index in this
+/// order, and applies it to directory and file names separately in the latter.
+pub fn compare_paths(a: &str, b: &str) -> std::cmp::Ordering {
+    fn folded(s: &str) -> impl Iterator<Item = u16> + '_ {
+        s.encode_utf16().map(|c| match c {
+            0x41..=0x5a => c + 0x20,
+            c => c,
+        })
+    }
+    folded(a)
+        .cmp(folded(b))
+        .then_with(|| a.encode_utf16().cmp(b.encode_utf16()))
+}
+
+/// Mount point of a pak as returned by [`PakReader::mount_point`], typically relative to the
+/// directory of the pak such as `../../../Game/Content/`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MountPoint<'a>(pub &'a str);
+
+impl<'a> MountPoint<'a> {
+    /// `path` relative to the mount point if it starts with it, such as `Maps/A.umap` for
+    /// `../../../Game/Content/Maps/A.umap` and the mount point `../../../Game/Content/`.
+    /// Backslashes and repeated separators of both are treated as single slashes.
+    pub fn relative_path(&self, path: &str) -> Option<String> {
+        let mount_point = normalize_separators(self.0);
+        let mount_point = mount_point.trim_end_matches('/');
+        if mount_point.is_empty() {
+            return None;
+        }
+        let path = normalize_separators(path);
+        path.strip_prefix(mount_point)?
+            .strip_prefix('/')
+            .filter(|relative| !relative.is_empty())
+            .map(str::to_owned)
+    }
+
+    /// The `../` segments the mount point starts with, including a final `..` without a
+    /// separator. Empty for mount points which don't start with `..`, such as `/` or `Game/`.
+    pub fn leading_parent_segments(&self) -> &'a str {
+        let mut len = 0;
+        loop {
+            let rest = &self.0[len..];
+            if rest.starts_with("../") {
+                len += 3;
+            } else if rest == ".." {
+                len += 2;
+            } else {
+                return &self.0[..len];
+            }
+        }
+    }
+}
+
+/// Form in which a path given to [`PakReader::get`] and the other methods taking paths matched
+/// an entry, see [`PakReader::resolve_path`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathForm {
+    /// Relative to the mount point, like the paths of [`PakReader::files`]
+    Relative,
+    /// Starting with the mount point, such as `../../../Game/Content/A.uasset` for a pak
+    /// mounted at `../../../Game/`
+    MountPointPrefixed,
+    /// Starting with a different number of `../` segments, which were ignored
+    ParentsStripped,
+}
+
+/// `path` with backslashes replaced by slashes and repeated slashes collapsed
+fn normalize_separators(path: &str) -> Cow<'_, str> {
+    if !path.contains('\\') && !path.contains("//") {
+        return Cow::Borrowed(path);
+    }
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars().map(|c| if c == '\\' { '/' } else { c }) {
+        if !(c == '/' && normalized.ends_with('/')) {
+            normalized.push(c);
+        }
     }
-    hash
+    Cow::Owned(normalized)
 }
 
-fn fnv64_path(path: &str, offset: u64) -> u64 {
-    let lower = path.to_lowercase();
-    let data = lower.encode_utf16().flat_map(u16::to_le_bytes);
-    fnv64(data, offset)
+/// Existing path most likely meant by `path` which isn't in the pak: the path with the same
+/// file name sharing the most trailing directories with it, otherwise the path sharing the
+/// most leading directories. `None` if nothing shares at least a file name or a directory.
+fn nearest_path<'a>(paths: impl Iterator<Item = &'a String>, path: &str) -> Option<&'a str> {
+    let path = normalize_separators(path);
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    let (file_name, directories) = segments.split_last()?;
+    let mut best: Option<((bool, usize), &str)> = None;
+    for candidate in paths {
+        let candidate_segments = candidate.split('/').collect::<Vec<_>>();
+        let same_file_name = candidate_segments
+            .last()
+            .is_some_and(|name| name.eq_ignore_ascii_case(file_name));
+        let shared = match same_file_name {
+            true => segments
+                .iter()
+                .rev()
+                .zip(candidate_segments.iter().rev())
+                .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                .count(),
+            false => directories
+                .iter()
+                .zip(&candidate_segments[..candidate_segments.len() - 1])
+                .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+                .count(),
+        };
+        let score = (same_file_name, shared);
+        if shared > 0 && best.is_none_or(|(best, _)| score > best) {
+            best = Some((score, candidate));
+        }
+    }
+    best.map(|(_, path)| path)
 }
 
 fn split_path_child(path: &str) -> Option<(&str, &str)> {
@@ -703,12 +3493,13 @@ fn split_path_child(path: &str) -> Option<(&str, &str)> {
 
 fn generate_full_directory_index<W: Write>(
     writer: &mut W,
-    entries: &BTreeMap<String, super::entry::Entry>,
-    offsets: &Vec<u32>,
+    version: Version,
+    index: &Index,
+    offsets: &[(&str, u32)],
 ) -> Result<(), super::Error> {
-    let mut fdi: BTreeMap<&str, BTreeMap<&str, u32>> = Default::default();
-    for (path, offset) in entries.keys().zip(offsets) {
-        let mut p = path.as_str();
+    let mut fdi: BTreeMap<&str, Vec<(&str, u32)>> = Default::default();
+    for &(path, offset) in offsets {
+        let mut p = path;
         while let Some((parent, _)) = split_path_child(p) {
             p = parent;
             fdi.entry(p).or_default();
@@ -716,16 +3507,19 @@ fn generate_full_directory_index<W: Write>(
 
         let (directory, filename) = split_path_child(path).expect("none root path");
 
-        fdi.entry(directory).or_default().insert(filename, *offset);
+        fdi.entry(directory).or_default().push((filename, offset));
     }
+    let mut fdi = Vec::from_iter(fdi);
+    fdi.sort_by(|(a, _), (b, _)| compare_paths(a, b));
 
     writer.write_u32::<LE>(fdi.len() as u32)?;
-    for (directory, files) in &fdi {
-        writer.write_string(directory)?;
+    for (directory, mut files) in fdi {
+        files.sort_by(|(a, _), (b, _)| compare_paths(a, b));
+        index.write_directory_name(writer, directory, version)?;
         writer.write_u32::<LE>(files.len() as u32)?;
         for (filename, offset) in files {
-            writer.write_string(filename)?;
-            writer.write_u32::<LE>(*offset)?;
+            index.write_directory_name(writer, filename, version)?;
+            writer.write_u32::<LE>(offset)?;
         }
     }
 
@@ -760,4 +3554,265 @@ mod test {
         assert_eq!(split_path_child("/"), None);
         assert_eq!(split_path_child(""), None);
     }
+
+    #[test]
+    fn test_compare_paths() {
+        use std::cmp::Ordering::*;
+        assert_eq!(compare_paths("a", "a"), Equal);
+        assert_eq!(compare_paths("a", "B"), Less);
+        assert_eq!(compare_paths("B", "a"), Greater);
+        // equal apart from case, upper case code units come first
+        assert_eq!(compare_paths("B", "b"), Less);
+        assert_eq!(compare_paths("Dir/A.txt", "dir/a.txt"), Less);
+        // '_' and '[' lie between the upper and lower case letters
+        assert_eq!(compare_paths("_", "A"), Less);
+        assert_eq!(compare_paths("[", "z"), Less);
+        // prefixes first
+        assert_eq!(compare_paths("dir", "dir/a"), Less);
+        assert_eq!(compare_paths("", "a"), Less);
+        // non-ASCII letters are not case folded
+        assert_eq!(compare_paths("é", "É"), Greater);
+        assert_eq!(compare_paths("É", "f"), Greater);
+        // UTF-16 code units rather than code points, so surrogates sort before U+E000..
+        assert_eq!(compare_paths("\u{10000}", "\u{e000}"), Less);
+        assert_eq!("\u{10000}".cmp("\u{e000}"), Greater);
+    }
+
+    #[test]
+    fn test_leading_parent_segments() {
+        for (mount_point, parents) in [
+            ("../../../", "../../../"),
+            ("../../../Game/Content/", "../../../"),
+            ("../../", "../../"),
+            ("../..", "../.."),
+            ("../..Game/", "../"),
+            ("/", ""),
+            ("Game/", ""),
+            ("", ""),
+        ] {
+            assert_eq!(
+                MountPoint(mount_point).leading_parent_segments(),
+                parents,
+                "{mount_point}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_relative_path() {
+        for (mount_point, path, relative) in [
+            (
+                "../../../Game/",
+                "../../../Game/Content/A.uasset",
+                Some("Content/A.uasset"),
+            ),
+            ("../../../Game", "../../../Game/A.uasset", Some("A.uasset")),
+            (
+                "../../../Game/",
+                "..\\..\\..\\Game\\A.uasset",
+                Some("A.uasset"),
+            ),
+            (
+                "../../..//Game/",
+                "../../../Game//A.uasset",
+                Some("A.uasset"),
+            ),
+            ("../../../Game/", "../../../GameMod/A.uasset", None),
+            ("../../../Game/", "../../../Game/", None),
+            ("../../../Game/", "Game/A.uasset", None),
+            ("/", "/A.uasset", None),
+        ] {
+            assert_eq!(
+                MountPoint(mount_point).relative_path(path).as_deref(),
+                relative,
+                "{mount_point} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_nearest_path() {
+        let paths = [
+            "Game/Content/A.uasset",
+            "Game/Content/Maps/A.uasset",
+            "Game/Content/B.uasset",
+            "Engine/C.uasset",
+        ]
+        .map(str::to_owned);
+        let nearest = |path| nearest_path(paths.iter(), path);
+        // the same file name in the most similar directory
+        assert_eq!(
+            nearest("Other/Maps/a.uasset"),
+            Some("Game/Content/Maps/A.uasset")
+        );
+        assert_eq!(nearest("Content/A.uasset"), Some("Game/Content/A.uasset"));
+        // otherwise the most similar directory
+        assert_eq!(
+            nearest("Game/Content/D.uasset"),
+            Some("Game/Content/A.uasset")
+        );
+        assert_eq!(nearest("Engine\\D.uasset"), Some("Engine/C.uasset"));
+        assert_eq!(nearest("D.uasset"), None);
+        assert_eq!(nearest("Other/D.uasset"), None);
+        assert_eq!(nearest(""), None);
+    }
+
+    #[test]
+    fn test_directory_index() {
+        let paths = ["a/b c/x", "a/b/y", "a/z", "w", "d/e/f/g"];
+        let directories = Directory::index(paths.into_iter());
+        let listing = |path: &str| {
+            let directory = &directories[path];
+            (directory.directories.clone(), directory.files.clone())
+        };
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            directories.keys().collect::<Vec<_>>(),
+            ["", "a", "a/b", "a/b c", "d", "d/e", "d/e/f"]
+        );
+        assert_eq!(listing(""), (strings(&["a", "d"]), strings(&["w"])));
+        assert_eq!(listing("a"), (strings(&["b", "b c"]), strings(&["a/z"])));
+        assert_eq!(listing("d/e"), (strings(&["f"]), vec![]));
+        assert_eq!(listing("d/e/f"), (vec![], strings(&["d/e/f/g"])));
+    }
+
+    #[test]
+    fn test_padding_index_offset() {
+        // the first offset found by stepping through the data region alignment
+        let stepped = |padding: &Padding, data_end: u64, index_len: u64| {
+            let step = padding.data_region.unwrap_or(1).max(1);
+            let file = padding.file.unwrap_or(1).max(1);
+            (0..file)
+                .map(|k| data_end.next_multiple_of(step) + k * step)
+                .find(|offset| (offset + index_len).is_multiple_of(file))
+        };
+        for data_region in [None, Some(1), Some(4), Some(6), Some(0x800)] {
+            for file in [None, Some(1), Some(8), Some(9), Some(0x1000)] {
+                let padding = Padding { data_region, file };
+                for (data_end, index_len) in [(0, 0), (1, 7), (0x1234, 0x56), (0xfff, 0x1001)] {
+                    assert_eq!(
+                        padding.index_offset(data_end, index_len).ok(),
+                        stepped(&padding, data_end, index_len),
+                        "{padding:?} {data_end:#x} {index_len:#x}"
+                    );
+                }
+            }
+        }
+
+        let padding = Padding {
+            data_region: Some(0x1000),
+            file: Some(0x10000),
+        };
+        assert!(padding.index_offset(u64::MAX - 0x10, 0).is_err());
+        assert!(padding.index_offset(0x1000, u64::MAX - 0x10).is_err());
+    }
+
+    #[test]
+    fn test_decode_entries() {
+        let bytes = include_bytes!("../tests/packs/pack_v11.pak");
+        let reader = PakBuilder::new()
+            .keep_encoded_index(true)
+            .reader(&mut io::Cursor::new(bytes))
+            .unwrap();
+        let pak = &reader.pak;
+        let encoded = &pak.encoded_index.as_ref().unwrap().entries;
+        let records = pak
+            .encoded_offsets
+            .iter()
+            .map(|(path, offset)| (path.clone(), *offset))
+            .collect::<Vec<_>>();
+        let expected = format!("{:?}", pak.index.entries().values().collect::<Vec<_>>());
+        for threads in 1..=5 {
+            let entries = decode_entries(encoded, &records, pak.version, threads, false)
+                .unwrap()
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(format!("{entries:?}"), expected, "{threads} threads");
+        }
+
+        let mut records = records;
+        records[2].1 = encoded.len() as u32;
+        let err = decode_entries(encoded, &records, pak.version, 3, false).unwrap_err();
+        let message = format!("encoded entry of {} at {:#x}", records[2].0, encoded.len());
+        assert!(err.to_string().contains(&message), "{err}");
+        assert_eq!(err.kind(), crate::ErrorKind::Io);
+    }
+
+    #[test]
+    fn test_engine_path() {
+        let mut index = Index::new(None);
+        index.raw_names.insert(
+            "caf\u{FFFD}/".to_owned(),
+            RawString::Narrow(b"caf\xE9/\0".to_vec()),
+        );
+        index.raw_names.insert(
+            "\u{FFFD}.txt".to_owned(),
+            RawString::Wide(vec![
+                0xD800, '.' as u16, 't' as u16, 'x' as u16, 't' as u16, 0,
+            ]),
+        );
+
+        assert!(!index.is_lossy("dir/test.txt"));
+        assert_eq!(index.engine_path("dir/test.txt"), "dir/test.txt");
+        // narrow names are widened as Latin-1
+        assert!(index.is_lossy("caf\u{FFFD}/test.txt"));
+        assert_eq!(
+            index.engine_path("caf\u{FFFD}/test.txt"),
+            "caf\u{E9}/test.txt"
+        );
+        assert!(index.is_lossy("\u{FFFD}.txt"));
+        assert_eq!(index.engine_path("\u{FFFD}.txt"), "\u{FFFD}.txt");
+        // names only recorded to keep their width aren't lossy
+        index
+            .raw_names
+            .insert("wide.txt".to_owned(), RawString::encode("wide.txt", true));
+        assert!(!index.is_lossy("dir/wide.txt"));
+        assert_eq!(index.engine_path("dir/wide.txt"), "dir/wide.txt");
+    }
+
+    #[test]
+    fn test_encoded_entry_bytes() {
+        for bytes in [
+            &include_bytes!("../tests/packs/pack_v11.pak")[..],
+            include_bytes!("../tests/packs/pack_v11_compress.pak"),
+        ] {
+            let pak = PakBuilder::new()
+                .keep_encoded_index(true)
+                .reader(&mut io::Cursor::new(bytes))
+                .unwrap();
+            let sections = pak.raw_index_sections().unwrap();
+            let mut total = 0;
+            for (path, entry) in pak.pak.index.entries() {
+                let mut expected = vec![];
+                entry.write_encoded(&mut expected).unwrap();
+                assert_eq!(pak.encoded_entry_bytes(path), Some(&expected[..]), "{path}");
+                total += expected.len();
+            }
+            assert_eq!(sections.encoded_entries.len(), total);
+
+            let phi = sections.path_hash_index.unwrap();
+            assert_eq!(phi[..4], (pak.len() as u32).to_le_bytes());
+            let fdi = sections.full_directory_index.unwrap();
+            let dirs = u32::from_le_bytes(fdi[..4].try_into().unwrap());
+            assert_eq!(dirs, 2);
+
+            // only kept on request
+            let pak = PakBuilder::new()
+                .reader(&mut io::Cursor::new(bytes))
+                .unwrap();
+            assert_eq!(pak.encoded_entry_bytes("test.txt"), None);
+            assert_eq!(pak.raw_index_sections(), None);
+        }
+
+        // older versions have no encoded entries
+        let pak = PakBuilder::new()
+            .keep_encoded_index(true)
+            .reader(&mut io::Cursor::new(include_bytes!(
+                "../tests/packs/pack_v8b.pak"
+            )))
+            .unwrap();
+        assert_eq!(pak.encoded_entry_bytes("test.txt"), None);
+        assert_eq!(pak.raw_index_sections(), None);
+    }
 }
@@ -0,0 +1,99 @@
+//! Path hashing used by the V10+ path hash index.
+//!
+//! Paths are hashed relative to the mount point, lowercased and encoded as UTF-16LE, with
+//! 64-bit FNV-1a whose offset basis is shifted by the per-pak seed
+//! (`FFnv::MemFnv64(Data, Length, Seed)` in `FPakFile::HashPath`).
+//!
+//! V10 paks passed the length of the path in characters where a length in bytes was expected,
+//! so only the first half of the encoded path contributed to the hash. V11
+//! (`PakFile_Version_Fnv64BugFix`) hashes the whole path. Games look entries up using the
+//! variant matching the version of the pak.
+
+use crate::Version;
+
+const OFFSET: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x00000100000001b3;
+
+/// 64-bit FNV-1a with the offset basis shifted by `seed`
+pub fn fnv64<I>(data: I, seed: u64) -> u64
+where
+    I: IntoIterator<Item = u8>,
+{
+    let mut hash = OFFSET.wrapping_add(seed);
+    for b in data.into_iter() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
+
+fn encode(path: &str) -> Vec<u8> {
+    path.to_lowercase()
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Hash of `path` as found in the path hash index of V10 paks, which only covers the first
+/// half of the UTF-16 encoded path
+pub fn fnv64_v10(path: &str, seed: u64) -> u64 {
+    let data = encode(path);
+    fnv64(data[..data.len() / 2].iter().copied(), seed)
+}
+
+/// Hash of `path` as found in the path hash index of V11+ paks
+pub fn fnv64_v11(path: &str, seed: u64) -> u64 {
+    fnv64(encode(path), seed)
+}
+
+/// Hash of `path` using the variant matching `version`
+pub(crate) fn hash_path(version: Version, path: &str, seed: u64) -> u64 {
+    match version < Version::V11 {
+        true => fnv64_v10(path, seed),
+        false => fnv64_v11(path, seed),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SEED: u64 = 0x205C5A7D;
+
+    #[test]
+    fn test_fnv64_v11() {
+        // taken from the path hash index of tests/packs/pack_v11.pak
+        assert_eq!(fnv64_v11("test.txt", SEED), 0x505f79362ba172ea);
+        assert_eq!(fnv64_v11("test.png", SEED), 0x20704bb513057fc3);
+        assert_eq!(fnv64_v11("zeros.bin", SEED), 0x3261d69865a675d0);
+        assert_eq!(fnv64_v11("directory/nested.txt", SEED), 0xf778c4cfa5689e1f);
+        assert_eq!(
+            fnv64_v11("Directory/Nested.TXT", SEED),
+            fnv64_v11("directory/nested.txt", SEED)
+        );
+    }
+
+    #[test]
+    fn test_fnv64_v10() {
+        assert_eq!(fnv64_v10("test.txt", SEED), 0xdd495e190229d6bc);
+        assert_eq!(fnv64_v10("zeros.bin", SEED), 0xf8a163d9f99788c9);
+        assert_eq!(fnv64_v10("directory/nested.txt", SEED), 0xc2afd20b8ffd3a5c);
+        // only "test" is hashed
+        assert_eq!(fnv64_v10("test.png", SEED), fnv64_v10("test.txt", SEED));
+        assert_eq!(fnv64_v10("test.png", SEED), fnv64_v11("test", SEED));
+    }
+
+    #[test]
+    fn test_hash_path_version() {
+        assert_eq!(
+            hash_path(Version::V10, "test.txt", SEED),
+            fnv64_v10("test.txt", SEED)
+        );
+        for version in [Version::V11, Version::V12] {
+            assert_eq!(
+                hash_path(version, "test.txt", SEED),
+                fnv64_v11("test.txt", SEED)
+            );
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{Error, PakReader};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, Write};
+
+/// Read-only view of a [`PakReader`] presenting its entries under different virtual paths,
+/// created by [`PakReader::with_path_remap`]
+///
+/// Paths of the view are full paths including the mount point. Each rule replaces a prefix of
+/// the mounted path of an entry, and the longest matching prefix wins. Entries not matched by
+/// any rule keep their mounted path.
+#[derive(Debug)]
+pub struct RemappedPak<'a> {
+    pak: &'a PakReader,
+    rules: Vec<(String, String)>,
+    /// Entry path relative to the mount point for each virtual path
+    entries: BTreeMap<String, String>,
+}
+
+impl<'a> RemappedPak<'a> {
+    pub(crate) fn new(pak: &'a PakReader, rules: Vec<(String, String)>) -> Result<Self, Error> {
+        for (i, (from, to)) in rules.iter().enumerate() {
+            if let Some((_, other)) = rules[..i]
+                .iter()
+                .find(|(other_from, other_to)| other_from == from && other_to != to)
+            {
+                return Err(Error::ConflictingRemap {
+                    prefix: from.clone(),
+                    targets: [other.clone(), to.clone()],
+                });
+            }
+        }
+        let mut view = Self {
+            pak,
+            rules,
+            entries: BTreeMap::new(),
+        };
+        for file in pak.files() {
+            let mounted = format!("{}{file}", pak.mount_point());
+            let remapped = view.remap(&mounted);
+            if let Some(existing) = view.entries.get(&remapped) {
+                return Err(Error::AmbiguousRemap {
+                    path: remapped,
+                    sources: [format!("{}{existing}", pak.mount_point()), mounted],
+                });
+            }
+            view.entries.insert(remapped, file);
+        }
+        Ok(view)
+    }
+
+    /// Virtual path of an entry given its mounted path
+    pub fn remap(&self, mounted: &str) -> String {
+        match self
+            .rules
+            .iter()
+            .filter(|(from, _)| mounted.starts_with(from.as_str()))
+            .max_by_key(|(from, _)| from.len())
+        {
+            Some((from, to)) => format!("{to}{}", &mounted[from.len()..]),
+            None => mounted.to_owned(),
+        }
+    }
+
+    /// Path of the entry relative to the mount point of the underlying pak for a virtual path
+    pub fn resolve(&self, path: &str) -> Option<&str> {
+        self.entries.get(path).map(String::as_str)
+    }
+
+    pub fn pak(&self) -> &'a PakReader {
+        self.pak
+    }
+
+    /// Virtual paths of all entries, sorted
+    pub fn files(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    pub fn get<R: Read + Seek>(&self, path: &str, reader: &mut R) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        self.read_file(path, reader, &mut data)?;
+        Ok(data)
+    }
+
+    pub fn read_file<R: Read + Seek, W: Write>(
+        &self,
+        path: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        match self.resolve(path) {
+            Some(file) => self.pak.read_file(file, reader, writer),
+            None => Err(Error::MissingEntry(path.to_owned())),
+        }
+    }
+}
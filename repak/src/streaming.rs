@@ -0,0 +1,205 @@
+//! Writing paks to outputs which can't seek, such as pipes or stdout
+//!
+//! The pak is laid out by a regular [`PakWriter`] into a [`SpillBuffer`] which can seek, then
+//! copied to the output front to back once the index is written. This costs as much staging
+//! space as the whole pak, in memory up to the budget and in a temporary file past it.
+
+use crate::{Error, PakWriter};
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Bytes a [`SpillBuffer`] keeps in memory before moving to a temporary file, 256 MiB
+pub const DEFAULT_STREAMING_MEMORY_BUDGET: u64 = 256 << 20;
+
+/// Staging storage of a [`StreamingPakWriter`], in memory until it would grow past its budget
+/// and in a temporary file removed on drop from then on
+#[derive(Debug)]
+pub struct SpillBuffer {
+    budget: u64,
+    storage: Storage,
+}
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Cursor<Vec<u8>>),
+    File(SpillFile),
+}
+
+#[derive(Debug)]
+struct SpillFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl SpillFile {
+    fn create() -> io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "repak-stream-{}-{}.tmp",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self { file, path })
+    }
+}
+
+impl SpillBuffer {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            storage: Storage::Memory(Cursor::new(vec![])),
+        }
+    }
+
+    /// Whether the staged bytes outgrew the budget and moved to a temporary file
+    pub fn spilled(&self) -> bool {
+        matches!(self.storage, Storage::File(_))
+    }
+
+    /// Copies everything staged to `output` from the start
+    pub fn copy_to<W: Write>(&mut self, output: &mut W) -> io::Result<u64> {
+        self.seek(SeekFrom::Start(0))?;
+        io::copy(self, output)
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        if let Storage::Memory(memory) = &self.storage {
+            let mut spill = SpillFile::create()?;
+            spill.file.write_all(memory.get_ref())?;
+            spill.file.seek(SeekFrom::Start(memory.position()))?;
+            self.storage = Storage::File(spill);
+        }
+        Ok(())
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Storage::Memory(memory) = &self.storage {
+            if memory.position() + buf.len() as u64 > self.budget {
+                self.spill()?;
+            }
+        }
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.write(buf),
+            Storage::File(spill) => spill.file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::File(spill) => spill.file.flush(),
+        }
+    }
+}
+
+impl Read for SpillBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.read(buf),
+            Storage::File(spill) => spill.file.read(buf),
+        }
+    }
+}
+
+impl Seek for SpillBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.storage {
+            Storage::Memory(memory) => memory.seek(pos),
+            Storage::File(spill) => spill.file.seek(pos),
+        }
+    }
+}
+
+/// Writer of a pak to an output which can't seek, made by
+/// [`crate::PakBuilder::writer_streaming`]
+///
+/// Entries are written through the [`PakWriter`] it dereferences to, which stages the pak in a
+/// [`SpillBuffer`]. Nothing reaches the output until [`StreamingPakWriter::write_index`], which
+/// emits the pak in one pass, byte for byte what [`PakWriter`] writes to a seekable output
+/// with the same settings.
+pub struct StreamingPakWriter<W: Write> {
+    pak: PakWriter<SpillBuffer>,
+    output: W,
+}
+
+impl<W: Write> StreamingPakWriter<W> {
+    pub(crate) fn new(pak: PakWriter<SpillBuffer>, output: W) -> Self {
+        Self { pak, output }
+    }
+
+    /// Writes the index and copies the staged pak to the output, which is returned without
+    /// being flushed
+    pub fn write_index(mut self) -> Result<W, Error> {
+        self.pak.write_index()?.copy_to(&mut self.output)?;
+        Ok(self.output)
+    }
+
+    /// Like [`PakWriter::write_index_verified`], with the staged pak read back before any of
+    /// it reaches the output
+    pub fn write_index_verified(mut self, sample: usize) -> Result<W, Error> {
+        self.pak
+            .write_index_verified(sample, Ok)?
+            .copy_to(&mut self.output)?;
+        Ok(self.output)
+    }
+}
+
+impl<W: Write> std::ops::Deref for StreamingPakWriter<W> {
+    type Target = PakWriter<SpillBuffer>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pak
+    }
+}
+
+impl<W: Write> std::ops::DerefMut for StreamingPakWriter<W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.pak
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spill_buffer() {
+        let mut buf = SpillBuffer::new(8);
+        buf.write_all(b"header").unwrap();
+        assert!(!buf.spilled());
+        buf.write_all(b" and data").unwrap();
+        assert!(buf.spilled());
+        // patched after the move, like entry records are
+        buf.seek(SeekFrom::Start(0)).unwrap();
+        buf.write_all(b"HEADER").unwrap();
+        buf.seek(SeekFrom::End(0)).unwrap();
+        buf.write_all(b"!").unwrap();
+
+        let mut out = vec![];
+        assert_eq!(buf.copy_to(&mut out).unwrap(), 16);
+        assert_eq!(out, b"HEADER and data!");
+
+        let path = match &buf.storage {
+            Storage::File(spill) => spill.path.clone(),
+            Storage::Memory(_) => unreachable!(),
+        };
+        assert!(path.exists());
+        drop(buf);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,80 @@
+//! Hooks for games storing entry data differently than UnrealPak: obfuscation on top of the pak
+//! format and encryption of only part of each entry
+
+/// Reversible, per-game obfuscation of entry data, such as XOR with a key derived from the path
+/// or rotating the first bytes of each entry. Registered with
+/// [`crate::PakBuilder::entry_transform`].
+///
+/// The data of an entry is transformed block by block: each compression block of compressed
+/// entries and each 64 KiB of uncompressed entries, passed along with its index. The length of
+/// a block must stay the same, as the blocks are located by the index.
+///
+/// - When reading, [`Self::decode`] is called on each block after it is decrypted and before it
+///   is decompressed.
+/// - When writing, [`Self::encode`] is called on each block after it is compressed and before
+///   the data is hashed and written. Written entries are never encrypted.
+///
+/// Entries copied as stored, such as by [`crate::PakWriter::copy_entry_from`] or
+/// [`crate::PakReader::write_compacted`], keep the data as transformed. The hash of an entry
+/// covers the data as stored, so [`crate::PakBuilder::verify_reads`] checks it before decoding.
+/// Entries written with a transform never share data, see
+/// [`crate::PakWriter::write_file_dedup`].
+///
+/// Transforms are called for every block read or written, possibly from several threads.
+pub trait EntryTransform: Send + Sync {
+    /// Undoes [`Self::encode`] on `data`, block `block` of the entry at `path` whose record has
+    /// `flags` (bit 0 set for encrypted entries)
+    fn decode(&self, path: &str, flags: u8, block: usize, data: &mut [u8]);
+
+    /// Obfuscates `data`, block `block` of the entry at `path` whose record has `flags`
+    fn encode(&self, path: &str, flags: u8, block: usize, data: &mut [u8]);
+}
+
+impl std::fmt::Debug for dyn EntryTransform + '_ {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EntryTransform")
+    }
+}
+
+/// How much of the data of encrypted entries is decrypted. Registered with
+/// [`crate::PakBuilder::decrypt_limit`].
+///
+/// UnrealPak encrypts all of the data of an entry, but some games encrypt only a prefix of
+/// each entry to save time when loading and store the rest as is. Decrypting that rest too
+/// turns it into garbage. The limit counts bytes of the entry as stored, so after compression,
+/// and is rounded down to whole AES blocks.
+///
+/// A wrong limit can't be told apart by the hash of the entry, which covers the data as
+/// stored. Compressed entries usually fail to decompress with
+/// [`crate::Error::EncryptedDataInvalid`], while uncompressed ones are returned corrupted.
+#[derive(Default)]
+pub enum DecryptLimit {
+    /// Decrypt all of the data, like UnrealPak
+    #[default]
+    Full,
+    /// Decrypt only the first bytes of each entry
+    FirstBytes(usize),
+    /// Decrypt the number of bytes returned for the path of each entry
+    PerPath(Box<dyn Fn(&str) -> usize + Send + Sync>),
+}
+
+impl DecryptLimit {
+    /// Number of bytes to decrypt of the entry at `path`, `None` for all of them
+    pub(crate) fn limit(&self, path: &str) -> Option<usize> {
+        match self {
+            DecryptLimit::Full => None,
+            DecryptLimit::FirstBytes(limit) => Some(*limit),
+            DecryptLimit::PerPath(limit) => Some(limit(path)),
+        }
+    }
+}
+
+impl std::fmt::Debug for DecryptLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecryptLimit::Full => f.write_str("Full"),
+            DecryptLimit::FirstBytes(limit) => f.debug_tuple("FirstBytes").field(limit).finish(),
+            DecryptLimit::PerPath(_) => f.write_str("PerPath"),
+        }
+    }
+}
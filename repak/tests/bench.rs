@@ -0,0 +1,256 @@
+//! Benchmarks of the hot paths of reading and writing paks, ignored by default. Run with
+//! `cargo test --release --test bench -- --ignored --nocapture --test-threads 1` and compare
+//! builds with and without the `zlib-ng` feature.
+//!
+//! Setting `REPAK_BENCH_BASELINE` to a file checks for regressions: benchmarks missing from
+//! the file are recorded in it and the others fail when they are more than 25% slower than
+//! recorded. Delete the file or its lines to record new baselines, which are only comparable
+//! on the same machine.
+#![cfg(feature = "compression")]
+
+mod support;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BASELINE_VAR: &str = "REPAK_BENCH_BASELINE";
+
+/// Time per iteration relative to the baseline above which a benchmark fails
+const MAX_SLOWDOWN: f64 = 1.25;
+
+/// Entries of the synthetic pak used to benchmark the index
+const ENTRY_COUNT: usize = 100_000;
+
+/// Serializes updates of the baseline file by benchmarks running in parallel
+static BASELINE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Counts the allocations of all threads, for benchmarks which report allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Number of allocations made while running `f`
+#[cfg_attr(not(feature = "oodle"), allow(dead_code))]
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Runs `f` until at least a second has passed and prints the throughput
+fn bench(name: &str, bytes: u64, mut f: impl FnMut()) {
+    bench_with_setup(name, bytes, || (), |()| f());
+}
+
+/// Like [`bench`], but only times `f` and not the `setup` creating its input
+fn bench_with_setup<T>(name: &str, bytes: u64, mut setup: impl FnMut() -> T, mut f: impl FnMut(T)) {
+    let mut elapsed = Duration::ZERO;
+    let mut iterations = 0;
+    while elapsed < Duration::from_secs(1) {
+        let input = setup();
+        let start = Instant::now();
+        f(input);
+        elapsed += start.elapsed();
+        iterations += 1;
+    }
+    let per_iteration = elapsed / iterations;
+    match bytes {
+        0 => println!("{name}: {per_iteration:.2?} ({iterations} iterations in {elapsed:.2?})"),
+        bytes => println!(
+            "{name}: {:.1} MiB/s ({iterations} iterations in {elapsed:.2?})",
+            (bytes * iterations as u64) as f64 / elapsed.as_secs_f64() / (1 << 20) as f64
+        ),
+    }
+    check_baseline(name, per_iteration);
+}
+
+/// Compares the time per iteration with the baseline file, recording it if it is missing
+fn check_baseline(name: &str, per_iteration: Duration) {
+    let Some(path) = std::env::var_os(BASELINE_VAR) else {
+        return;
+    };
+    let _lock = BASELINE_LOCK.lock().unwrap();
+    let baselines = std::fs::read_to_string(&path).unwrap_or_default();
+    let recorded = baselines.lines().find_map(|line| {
+        let (recorded_name, seconds) = line.rsplit_once(' ')?;
+        (recorded_name == name).then(|| seconds.parse::<f64>().ok())?
+    });
+    let seconds = per_iteration.as_secs_f64();
+    match recorded {
+        Some(recorded) => assert!(
+            seconds <= recorded * MAX_SLOWDOWN,
+            "{name}: {seconds:.6}s per iteration is more than {:.0}% slower than the baseline of {recorded:.6}s",
+            (MAX_SLOWDOWN - 1.0) * 100.0
+        ),
+        None => {
+            use std::io::Write;
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .unwrap();
+            writeln!(file, "{name} {seconds:.9}").unwrap();
+        }
+    }
+}
+
+/// Reads every entry, returning the total uncompressed size
+fn extract(pak: &repak::PakReader, bytes: &[u8]) -> u64 {
+    let mut reader = Cursor::new(bytes);
+    pak.files()
+        .iter()
+        .map(|file| pak.get(file, &mut reader).unwrap().len() as u64)
+        .sum()
+}
+
+#[test]
+#[ignore]
+fn bench_extract_fixtures() {
+    for (name, bytes) in [
+        ("v5", &include_bytes!("packs/pack_v5_compress.pak")[..]),
+        ("v8b", include_bytes!("packs/pack_v8b_compress.pak")),
+        ("v11", include_bytes!("packs/pack_v11_compress.pak")),
+    ] {
+        let pak = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(bytes))
+            .unwrap();
+        let size = extract(&pak, bytes);
+        bench(&format!("fixture {name}"), size, || {
+            extract(&pak, bytes);
+        });
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_extract_large() {
+    // spanning many compression blocks
+    let data = support::pattern(64 << 20, 13);
+    for compression in [
+        None,
+        Some(repak::Compression::Zlib),
+        Some(repak::Compression::Zstd),
+    ] {
+        let bytes = support::large_entry_pak(compression, &data);
+        let name = compression.map_or("uncompressed".to_owned(), |c| c.to_string());
+
+        for threads in [1, 4] {
+            let pak = repak::PakBuilder::new()
+                .decompression_threads(threads)
+                .reader(&mut Cursor::new(&bytes))
+                .unwrap();
+            bench(
+                &format!("extract {name} with {threads} threads"),
+                data.len() as u64,
+                || {
+                    assert_eq!(extract(&pak, &bytes), data.len() as u64);
+                },
+            );
+        }
+    }
+}
+
+#[cfg(feature = "oodle")]
+#[test]
+#[ignore]
+fn bench_extract_oodle() {
+    // many blocks, each decompressed with the decoder memory of the thread
+    let data = support::pattern(64 << 20, 13);
+    let bytes = support::large_entry_pak(Some(repak::Compression::Oodle), &data);
+    for threads in [1, 4] {
+        let pak = repak::PakBuilder::new()
+            .decompression_threads(threads)
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap();
+        let name = format!("extract Oodle with {threads} threads");
+        let allocations = count_allocations(|| {
+            assert_eq!(extract(&pak, &bytes), data.len() as u64);
+        });
+        println!("{name}: {allocations} allocations");
+        bench(&name, data.len() as u64, || {
+            assert_eq!(extract(&pak, &bytes), data.len() as u64);
+        });
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_write_file() {
+    let data = support::pattern(64 << 20, 13);
+    for compression in [
+        None,
+        Some(repak::Compression::Zlib),
+        Some(repak::Compression::Gzip),
+        Some(repak::Compression::Zstd),
+        Some(repak::Compression::LZ4),
+    ] {
+        let name = compression.map_or("uncompressed".to_owned(), |c| c.to_string());
+        bench_with_setup(
+            &format!("write_file {name}"),
+            data.len() as u64,
+            || {
+                repak::PakBuilder::new().compression(compression).writer(
+                    Cursor::new(Vec::with_capacity(data.len())),
+                    repak::Version::V11,
+                    "../../../".to_owned(),
+                    None,
+                )
+            },
+            |mut writer| {
+                writer
+                    .write_file("large.bin", compression.is_some(), &data)
+                    .unwrap();
+            },
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_index() {
+    for version in [repak::Version::V8B, repak::Version::V11] {
+        let bytes = support::synthetic_pak(version, ENTRY_COUNT);
+        bench(&format!("parse {ENTRY_COUNT} entries {version}"), 0, || {
+            let pak = repak::PakBuilder::new()
+                .reader(&mut Cursor::new(&bytes))
+                .unwrap();
+            assert_eq!(pak.len(), ENTRY_COUNT);
+        });
+        bench(
+            &format!("parse {ENTRY_COUNT} entries {version} parallel"),
+            0,
+            || {
+                let pak = repak::PakBuilder::new()
+                    .parallel_index_parse(true)
+                    .reader(&mut Cursor::new(&bytes))
+                    .unwrap();
+                assert_eq!(pak.len(), ENTRY_COUNT);
+            },
+        );
+        bench_with_setup(
+            &format!("write_index {ENTRY_COUNT} entries {version}"),
+            0,
+            || support::synthetic_writer(version, ENTRY_COUNT),
+            |writer| {
+                writer.write_index().unwrap();
+            },
+        );
+    }
+}
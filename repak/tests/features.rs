@@ -0,0 +1,57 @@
+//! Errors of builds without optional features, run with
+//! `cargo test -p repak --no-default-features`
+
+#[cfg(not(feature = "encryption"))]
+#[test]
+fn test_encryption_feature_disabled() {
+    use std::io::Cursor;
+
+    for bytes in [
+        &include_bytes!("packs/pack_v11_encryptindex.pak")[..],
+        include_bytes!("packs/pack_v7_encryptindex.pak"),
+    ] {
+        let err = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(bytes))
+            .unwrap_err();
+        assert!(
+            matches!(err, repak::Error::EncryptionFeatureDisabled),
+            "{err}"
+        );
+        assert_eq!(err.kind(), repak::ErrorKind::Unsupported);
+        assert!(err.to_string().contains("`encryption` feature"), "{err}");
+    }
+
+    // only the entry data is encrypted
+    let bytes = include_bytes!("packs/pack_v11_encrypt.pak");
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let err = pak.get("test.txt", &mut reader).unwrap_err();
+    assert!(
+        matches!(err, repak::Error::EncryptionFeatureDisabled),
+        "{err}"
+    );
+}
+
+#[cfg(not(feature = "compression"))]
+#[test]
+fn test_compression_feature_disabled() {
+    use std::io::Cursor;
+
+    let bytes = include_bytes!("packs/pack_v11_compress.pak");
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let err = pak.get("test.png", &mut reader).unwrap_err();
+    assert!(matches!(err, repak::Error::Compression), "{err}");
+    assert_eq!(err.kind(), repak::ErrorKind::Unsupported);
+    assert!(err.to_string().contains("`compression` feature"), "{err}");
+    // stored entries can still be read
+    assert_eq!(
+        pak.get("test.txt", &mut reader).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+
+    assert!(matches!(
+        repak::PartialEntry::new(Some(repak::Compression::Zlib), [0; 16]),
+        Err(repak::Error::Compression)
+    ));
+}
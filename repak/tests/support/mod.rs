@@ -0,0 +1,72 @@
+//! Synthetic paks generated at test time, shared by the integration tests and benchmarks so
+//! large fixtures don't have to be committed
+#![allow(dead_code)]
+
+use std::io::Cursor;
+
+/// Compressible but not trivially so. Larger `shift`s make longer runs of equal bytes.
+pub fn pattern(len: u32, shift: u32) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i.wrapping_mul(i) >> shift) as u8)
+        .collect()
+}
+
+/// Incompressible bytes from a xorshift generator, the same for every call
+pub fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 56) as u8
+        })
+        .collect()
+}
+
+/// Path of the `i`th entry of [`synthetic_writer`], in directories of 100 entries
+pub fn entry_path(i: usize) -> String {
+    format!("Game/Content/Dir{:04}/Asset{i:06}.uasset", i / 100)
+}
+
+/// Writer with `count` small uncompressed entries at [`entry_path`], whose index is yet to be
+/// written
+pub fn synthetic_writer(
+    version: repak::Version,
+    count: usize,
+) -> repak::PakWriter<Cursor<Vec<u8>>> {
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        version,
+        "../../../".to_owned(),
+        Some(0x205C5A7D),
+    );
+    for i in 0..count {
+        writer
+            .write_file(&entry_path(i), false, i.to_le_bytes())
+            .unwrap();
+    }
+    writer
+}
+
+/// Pak with `count` small uncompressed entries at [`entry_path`]
+pub fn synthetic_pak(version: repak::Version, count: usize) -> Vec<u8> {
+    synthetic_writer(version, count)
+        .write_index()
+        .unwrap()
+        .into_inner()
+}
+
+/// V11 pak with `data` at `large.bin`, compressed unless `compression` is `None`
+pub fn large_entry_pak(compression: Option<repak::Compression>, data: &[u8]) -> Vec<u8> {
+    let mut writer = repak::PakBuilder::new().compression(compression).writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    writer
+        .write_file("large.bin", compression.is_some(), data)
+        .unwrap();
+    writer.write_index().unwrap().into_inner()
+}
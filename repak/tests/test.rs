@@ -1,7 +1,9 @@
 #![cfg(feature = "default")]
+mod support;
+
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use paste::paste;
-use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 
 /// A reader that tracks how many times bytes in the inner reader been read. Useful to check read coverage.
 #[derive(Debug)]
@@ -191,6 +193,54 @@ fn test_write(_version: repak::Version, _file_name: &str, bytes: &[u8]) {
     assert!(pak_writer.write_index().unwrap().into_inner() == reader.into_inner());
 }
 
+fn test_decrypt(version: repak::Version, _file_name: &str, bytes: &[u8]) {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new()
+        .key(key)
+        .reader(&mut reader)
+        .unwrap();
+    let decrypted = pak
+        .write_decrypted(&mut reader, Cursor::new(vec![]))
+        .unwrap()
+        .into_inner();
+
+    // readable without a key
+    let mut decrypted_reader = Cursor::new(&decrypted);
+    let copy = repak::PakBuilder::new()
+        .reader(&mut decrypted_reader)
+        .unwrap();
+    assert_eq!(copy.version(), version);
+    assert_eq!(copy.mount_point(), pak.mount_point());
+    assert_eq!(copy.path_hash_seed(), pak.path_hash_seed());
+    assert!(!copy.encrypted_index());
+    assert_eq!(copy.files(), pak.files());
+    for file in pak.files() {
+        let (original, info) = (
+            pak.entry_info(&file).unwrap(),
+            copy.entry_info(&file).unwrap(),
+        );
+        assert!(!info.encrypted);
+        assert_eq!(info.compression, original.compression);
+        assert_eq!(info.uncompressed, original.uncompressed);
+        assert_eq!(
+            copy.get(&file, &mut decrypted_reader).unwrap(),
+            pak.get(&file, &mut reader).unwrap()
+        );
+    }
+
+    // already decrypted paks are copied unchanged
+    let again = copy
+        .write_decrypted(&mut decrypted_reader, Cursor::new(vec![]))
+        .unwrap()
+        .into_inner();
+    assert_eq!(again, decrypted);
+}
+
 fn test_rewrite_index(_version: repak::Version, _file_name: &str, bytes: &[u8]) {
     use aes::cipher::KeyInit;
     use base64::{engine::general_purpose, Engine as _};
@@ -214,88 +264,4171 @@ fn test_rewrite_index(_version: repak::Version, _file_name: &str, bytes: &[u8])
     assert!(bytes == rewrite);
 }
 
-macro_rules! matrix_test {
-    ( $name:literal, ($($version:literal $exp_version:expr),* $(,)?), $compress:tt, $encrypt:tt, $encryptindex:tt, $body:tt ) => {
-        $( matrix_test_compress!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
-    };
+fn test_rewrite_index_utf16(_version: repak::Version, _file_name: &str, bytes: &[u8]) {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    // the same pak with every string in the index serialized as UTF-16
+    let mut buf = Cursor::new(bytes.to_vec());
+    let pak_reader = repak::PakBuilder::new()
+        .key(key.clone())
+        .reader(&mut buf)
+        .unwrap();
+    let mut pak_writer = pak_reader.into_pakwriter(buf).unwrap();
+    pak_writer.force_utf16_strings(true);
+    let utf16 = pak_writer.write_index().unwrap().into_inner();
+    let wide_mount_point = "../mount/point/root/"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+    assert!(utf16
+        .windows(wide_mount_point.len())
+        .any(|window| window == wide_mount_point));
+
+    let mut buf = Cursor::new(utf16.clone());
+    let pak_reader = repak::PakBuilder::new().key(key).reader(&mut buf).unwrap();
+    assert_eq!(pak_reader.mount_point(), "../mount/point/root/");
+    assert_eq!(
+        pak_reader.get("directory/nested.txt", &mut buf).unwrap(),
+        include_bytes!("pack/root/directory/nested.txt")
+    );
+
+    // rewriting without forcing UTF-16 keeps the original width of every string
+    let rewrite = pak_reader
+        .into_pakwriter(buf)
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    assert!(utf16 == rewrite);
 }
 
-macro_rules! matrix_test_compress {
-    ( $name:literal, $version:literal, $exp_version:expr, ($($compress:literal),* $(,)?), $encrypt:tt, $encryptindex:tt, $body:tt ) => {
-        $( matrix_test_encrypt!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
-    };
+#[test]
+fn test_index_order() {
+    let names = [
+        "Zeta.txt",
+        "beta.txt",
+        "émoji.txt",
+        "alpha.txt",
+        "_under.txt",
+        "Beta.txt",
+    ];
+    // FString comparison lowercases ASCII only, so '_' sorts before letters and non-ASCII last
+    let expected = [
+        "_under.txt",
+        "alpha.txt",
+        "Beta.txt",
+        "beta.txt",
+        "Zeta.txt",
+        "émoji.txt",
+    ];
+    let mut sorted = names;
+    sorted.sort_by(|a, b| repak::compare_paths(a, b));
+    assert_eq!(sorted, expected);
+
+    for version in [repak::Version::V8B, repak::Version::V11] {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            version,
+            "../mount/point/root/".to_owned(),
+            None,
+        );
+        for name in names {
+            pak_writer.write_file(name, false, name.as_bytes()).unwrap();
+        }
+        let written = pak_writer.write_index().unwrap().into_inner();
+
+        let position = |name: &str| {
+            let encoded = if name.is_ascii() {
+                let mut encoded = (name.len() as u32 + 1).to_le_bytes().to_vec();
+                encoded.extend(name.bytes().chain([0]));
+                encoded
+            } else {
+                name.encode_utf16().flat_map(u16::to_le_bytes).collect()
+            };
+            written
+                .windows(encoded.len())
+                .position(|window| window == encoded)
+                .unwrap()
+        };
+        let positions = expected.map(position);
+        assert!(positions.is_sorted(), "{version}: {positions:?}");
+
+        let mut buf = Cursor::new(written);
+        let pak_reader = repak::PakBuilder::new().reader(&mut buf).unwrap();
+        let mut bytewise = names.map(str::to_owned);
+        bytewise.sort();
+        assert_eq!(pak_reader.files(), bytewise);
+        for name in names {
+            assert_eq!(pak_reader.get(name, &mut buf).unwrap(), name.as_bytes());
+        }
+    }
 }
 
-macro_rules! matrix_test_encrypt {
-    ( $name:literal, $version:literal, $exp_version:expr, $compress:literal, ($($encrypt:literal),* $(,)?), $encryptindex:tt, $body:tt ) => {
-        $( matrix_test_encryptindex!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
+#[test]
+fn test_footer_extra_bytes() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    let extra = *b"\x01\x02\x03\x04\xfe\xff\0\x08";
+    // rewrites leave the index unencrypted, so they are shorter for paks with an encrypted index
+    for bytes in [
+        &include_bytes!("packs/pack_v8b_compress_encrypt.pak")[..],
+        &include_bytes!("packs/pack_v9.pak")[..],
+        &include_bytes!("packs/pack_v11_compress.pak")[..],
+    ] {
+        // unknown bytes between the standard fields and the compression name slots
+        let mut bytes = bytes.to_vec();
+        let slots_start = bytes.len() - 5 * 32;
+        bytes.splice(slots_start..slots_start, extra);
+
+        assert!(repak::PakBuilder::new()
+            .key(key.clone())
+            .reader(&mut Cursor::new(&bytes))
+            .is_err());
+
+        let builder = repak::PakBuilder::new()
+            .key(key.clone())
+            .footer_extra_bytes(extra.len());
+        let range = repak::Footer::range(bytes.len() as u64);
+        let footer = builder
+            .parse_footer(&bytes[range.start as usize - extra.len()..])
+            .unwrap();
+        assert_eq!(footer.extra_bytes(), extra);
+        assert_eq!(footer.size(), footer.version().size() as u64 + 8);
+
+        let mut buf = Cursor::new(bytes.clone());
+        let pak_reader = builder.reader(&mut buf).unwrap();
+        assert_eq!(pak_reader.footer_extra_bytes(), extra);
+        assert_eq!(
+            pak_reader.get("directory/nested.txt", &mut buf).unwrap(),
+            include_bytes!("pack/root/directory/nested.txt")
+        );
+
+        // standard paks still read with the option set
+        let plain = &bytes[..slots_start]
+            .iter()
+            .chain(&bytes[slots_start + extra.len()..])
+            .copied()
+            .collect::<Vec<_>>();
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .footer_extra_bytes(extra.len())
+            .reader(&mut Cursor::new(plain))
+            .unwrap();
+        assert!(pak.footer_extra_bytes().is_empty());
+
+        // rewriting keeps the extra bytes in place
+        let rewrite = pak_reader
+            .into_pakwriter(buf)
+            .unwrap()
+            .write_index()
+            .unwrap()
+            .into_inner();
+        let slots_start = rewrite.len() - 5 * 32;
+        assert_eq!(rewrite[slots_start - extra.len()..slots_start], extra);
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .footer_extra_bytes(extra.len())
+            .reader(&mut Cursor::new(&rewrite))
+            .unwrap();
+        assert_eq!(pak.footer_extra_bytes(), extra);
+        assert_eq!(pak.files().len(), 4);
+    }
+}
+
+#[test]
+fn test_predicted_index_size() {
+    for version in repak::Version::iter() {
+        for (path_hash_index, compress) in [(true, false), (false, true)] {
+            let mut pak_writer = repak::PakBuilder::new()
+                .compression([repak::Compression::Zlib])
+                .writer(
+                    Cursor::new(vec![]),
+                    version,
+                    "../mount/point/root/".to_owned(),
+                    Some(0x205C5A7D),
+                );
+            if version >= repak::Version::V10 {
+                pak_writer.write_path_hash_index(path_hash_index);
+            }
+            pak_writer.pad_file_to(4096);
+            for (path, data) in [
+                (
+                    "directory/nested.txt",
+                    &include_bytes!("pack/root/directory/nested.txt")[..],
+                ),
+                ("test.png", include_bytes!("pack/root/test.png")),
+                ("zeros.bin", include_bytes!("pack/root/zeros.bin")),
+                ("wide/é.txt", b"wide"),
+            ] {
+                pak_writer.write_file(path, compress, data).unwrap();
+            }
+            let predicted = pak_writer.predicted_index_size().unwrap();
+
+            let written = pak_writer.write_index().unwrap().into_inner();
+            let footer = repak::PakBuilder::new().parse_footer(&written).unwrap();
+            assert_eq!(
+                predicted,
+                written.len() as u64 - footer.index_range().start,
+                "{version}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_check_path_hash_index() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    for bytes in [
+        &include_bytes!("packs/pack_v11.pak")[..],
+        include_bytes!("packs/pack_v11_compress_encrypt_encryptindex.pak"),
+    ] {
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .reader(&mut Cursor::new(bytes))
+            .unwrap();
+        assert!(pak.check_path_hash_index().unwrap().is_consistent());
+    }
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(include_bytes!("packs/pack_v8b.pak")))
+        .unwrap();
+    assert_eq!(pak.check_path_hash_index(), None);
+
+    // point the first record at the entry of the second and change the hash of the third
+    let mut bytes = include_bytes!("packs/pack_v11.pak").to_vec();
+    let builder = repak::PakBuilder::new();
+    let footer = builder.parse_footer(&bytes).unwrap();
+    let index = &bytes[footer.index_range().start as usize..footer.index_range().end as usize];
+    let phi = builder
+        .secondary_index_ranges(&footer, index)
+        .unwrap()
+        .path_hash_index
+        .unwrap();
+    let record = |i: usize| phi.start as usize + 4 + i * 12;
+    let second_offset = bytes[record(1) + 8..record(1) + 12].to_vec();
+    bytes[record(0) + 8..record(0) + 12].copy_from_slice(&second_offset);
+    bytes[record(2)] ^= 0xff;
+    let hash = u64::from_le_bytes(bytes[record(2)..record(2) + 8].try_into().unwrap());
+    let offset = u32::from_le_bytes(bytes[record(2) + 8..record(2) + 12].try_into().unwrap());
+
+    let mut buf = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new()
+        .ignore_index_hashes(true)
+        .reader(&mut buf)
+        .unwrap();
+    let report = pak.check_path_hash_index().unwrap();
+    assert!(!report.is_consistent());
+    assert_eq!(report.mismatched.len(), 1);
+    assert_eq!(report.missing.len(), 1);
+    assert_ne!(report.mismatched, report.missing);
+    assert_eq!(report.extra, [(hash, offset)]);
+    // the full directory index is intact so every entry still reads
+    for file in pak.files() {
+        pak.get(&file, &mut buf).unwrap();
+    }
+
+    // rewriting the index regenerates the path hash index from the entries
+    let repaired = pak
+        .into_pakwriter(buf)
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    assert!(repaired == include_bytes!("packs/pack_v11.pak"));
+}
+
+#[test]
+fn test_write_file_dedup() {
+    let png = include_bytes!("pack/root/test.png");
+    let txt = include_bytes!("pack/root/test.txt");
+    let files: [(&str, bool, &[u8]); 5] = [
+        ("a/test.png", true, png),
+        ("b/test.png", true, png),
+        ("test.txt", false, txt),
+        ("copy.txt", false, txt),
+        // same data but compressed, so stored separately
+        ("compressed.txt", true, txt),
+    ];
+    for version in [repak::Version::V4, repak::Version::V8B, repak::Version::V11] {
+        let write = |dedup: bool| {
+            let mut pak_writer = repak::PakBuilder::new()
+                .compression([repak::Compression::Zlib])
+                .writer(Cursor::new(vec![]), version, "../../../".to_owned(), None);
+            let mut shared = vec![];
+            for (path, compress, data) in files {
+                if dedup {
+                    shared.push(pak_writer.write_file_dedup(path, compress, data).unwrap());
+                } else {
+                    pak_writer.write_file(path, compress, data).unwrap();
+                }
+            }
+            let deduplicated = pak_writer.deduplicated_bytes();
+            (
+                pak_writer.write_index().unwrap().into_inner(),
+                shared,
+                deduplicated,
+            )
+        };
+        let (plain, _, _) = write(false);
+        let (deduped, shared, deduplicated) = write(true);
+        assert_eq!(shared, [false, true, false, true, false]);
+        assert!(deduplicated > png.len() as u64 / 2);
+        assert_eq!(deduped.len() as u64 + deduplicated, plain.len() as u64);
+
+        let mut reader = Cursor::new(&deduped);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        for (path, _, data) in files {
+            assert_eq!(pak.get(path, &mut reader).unwrap(), data, "{path}");
+        }
+        assert_eq!(
+            pak.entry_range("a/test.png", &mut reader).unwrap(),
+            pak.entry_range("b/test.png", &mut reader).unwrap()
+        );
+        assert_eq!(pak.fragmentation_report(deduped.len() as u64).dead_bytes, 0);
+
+        // rewrites keep the data shared
+        let compacted = pak
+            .write_compacted(&mut reader, Cursor::new(vec![]))
+            .unwrap()
+            .into_inner();
+        assert!(compacted == deduped, "{version}");
+    }
+}
+
+#[test]
+fn test_write_index_verified() {
+    let png = include_bytes!("pack/root/test.png");
+    let txt = include_bytes!("pack/root/test.txt");
+    for version in [repak::Version::V4, repak::Version::V8B, repak::Version::V11] {
+        let writer = || {
+            let mut pak_writer = repak::PakBuilder::new()
+                .compression([repak::Compression::Zlib])
+                .writer(Cursor::new(vec![]), version, "../../../".to_owned(), None);
+            pak_writer.write_file("test.png", false, png).unwrap();
+            pak_writer.write_file("test.txt", true, txt).unwrap();
+            pak_writer
+        };
+        let verified = |sample: usize, corrupt: &dyn Fn(&mut Vec<u8>)| {
+            writer().write_index_verified(sample, |mut cursor| {
+                corrupt(cursor.get_mut());
+                Ok(cursor)
+            })
+        };
+        let flip = |pos: u64| move |bytes: &mut Vec<u8>| bytes[pos as usize] ^= 0xff;
+
+        let bytes = writer().write_index().unwrap().into_inner();
+        let written = verified(usize::MAX, &|_| {}).unwrap().into_inner();
+        assert_eq!(written, bytes);
+
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let png_range = pak.entry_range("test.png", &mut reader).unwrap();
+
+        // last byte of the data is only noticed when the entry is part of the sample
+        let err = verified(2, &flip(png_range.end - 1)).unwrap_err();
+        assert!(
+            matches!(&err, repak::Error::PostWriteVerification { path: Some(path), .. } if path == "test.png"),
+            "{err}"
+        );
+        assert_eq!(err.kind(), repak::ErrorKind::Format);
+        verified(0, &flip(png_range.end - 1)).unwrap();
+
+        // compressed size in the record preceding the data is always checked
+        let err = verified(0, &flip(png_range.start + 8)).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the entry record differs from the entry written"),
+            "{err}"
+        );
+
+        // a truncated footer
+        let err = verified(0, &|bytes| bytes.truncate(bytes.len() - 1)).unwrap_err();
+        assert!(
+            matches!(err, repak::Error::PostWriteVerification { path: None, .. }),
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn test_append_to_existing_pak() {
+    for bytes in [
+        &include_bytes!("packs/pack_v11.pak")[..],
+        &include_bytes!("packs/pack_v11_compress.pak")[..],
+        &include_bytes!("packs/pack_v9.pak")[..],
+    ] {
+        let mut buf = Cursor::new(bytes.to_vec());
+        let pak_reader = repak::PakBuilder::new().reader(&mut buf).unwrap();
+        let original = pak_reader
+            .files()
+            .into_iter()
+            .map(|path| {
+                let data = pak_reader.get(&path, &mut buf).unwrap();
+                (path, data)
+            })
+            .collect::<Vec<_>>();
+
+        let mut pak_writer = pak_reader.into_pakwriter(buf).unwrap();
+        pak_writer
+            .write_file("added/new.txt", false, b"appended after the original data")
+            .unwrap();
+        let appended = pak_writer.write_index().unwrap().into_inner();
+
+        let mut reader = Cursor::new(appended);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(pak.files().len(), original.len() + 1);
+        for (path, data) in &original {
+            assert_eq!(&pak.get(path, &mut reader).unwrap(), data);
+        }
+        assert_eq!(
+            pak.get("added/new.txt", &mut reader).unwrap(),
+            b"appended after the original data"
+        );
+
+        // the appended pak must survive another lossless rewrite
+        let rewrite = pak
+            .into_pakwriter(Cursor::new(reader.get_ref().clone()))
+            .unwrap()
+            .write_index()
+            .unwrap()
+            .into_inner();
+        assert_eq!(&rewrite, reader.get_ref());
+    }
+}
+
+#[test]
+fn test_encoded_offsets() {
+    let offsets = |bytes: &[u8]| {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        pak.files()
+            .into_iter()
+            .map(|path| {
+                let offset = pak.entry_info(&path).unwrap().encoded_offset.unwrap();
+                (path, offset)
+            })
+            .collect::<std::collections::BTreeMap<_, _>>()
+    };
+    let rewrite = |bytes: Vec<u8>, added: Option<&str>| {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let mut pak_writer = pak.into_pakwriter(reader).unwrap();
+        if let Some(path) = added {
+            pak_writer.write_file(path, false, b"added").unwrap();
+        }
+        pak_writer.write_index().unwrap().into_inner()
     };
+
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    for path in ["b/test.txt", "c.txt"] {
+        pak_writer.write_file(path, false, path.as_bytes()).unwrap();
+    }
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+    let original = offsets(&bytes);
+
+    // an entry added by a rewrite is encoded after the existing ones even though its path sorts
+    // first, so their offsets are kept
+    let appended = rewrite(bytes, Some("a.txt"));
+    let mut expected = original.clone();
+    expected.insert("a.txt".to_owned(), original.values().max().unwrap() + 12);
+    assert_eq!(offsets(&appended), expected);
+
+    // a rewrite which doesn't change any entry keeps every offset
+    assert_eq!(offsets(&rewrite(appended.clone(), None)), expected);
+
+    // compaction encodes the entries in path order
+    let mut reader = Cursor::new(&appended);
+    let compacted = repak::PakBuilder::new()
+        .reader(&mut reader)
+        .unwrap()
+        .write_compacted(&mut reader, Cursor::new(vec![]))
+        .unwrap()
+        .into_inner();
+    let compacted = offsets(&compacted);
+    assert_eq!(compacted["a.txt"], 0);
+    assert_ne!(compacted, expected);
+
+    // paks before V10 have no encoded entries
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v9.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(pak.entry_info("test.txt").unwrap().encoded_offset, None);
 }
 
-macro_rules! matrix_test_encryptindex {
-    ( $name:literal, $version:literal, $exp_version:expr, $compress:literal, $encrypt:literal, ($($encryptindex:literal),* $(,)?), $body:tt ) => {
-        $( matrix_test_body!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
+#[test]
+fn test_index_before_data() {
+    use byteorder::LE;
+    let files = [
+        ("a.txt", &b"first entry"[..]),
+        ("b/c.txt", include_bytes!("pack/root/test.txt")),
+    ];
+
+    // leave room for the index at the start of the file, then move it there
+    let mut writer = Cursor::new(vec![]);
+    writer.seek(SeekFrom::Start(0x1000)).unwrap();
+    let mut pak_writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(writer, repak::Version::V8B, "../../../".to_owned(), None);
+    for (path, data) in files {
+        pak_writer.write_file(path, true, data).unwrap();
+    }
+    let written = pak_writer.write_index().unwrap().into_inner();
+    let footer_offset = written.len() - repak::Version::V8B.size() as usize;
+    let footer = &written[footer_offset..];
+    // encryption guid, encrypted flag, magic and version precede the index offset and size
+    let mut reader = Cursor::new(&footer[16 + 1 + 4 + 4..]);
+    let index_offset = reader.read_u64::<LE>().unwrap() as usize;
+    let index_size = reader.read_u64::<LE>().unwrap() as usize;
+    assert!(index_size <= 0x1000);
+    let mut bytes = written[..index_offset].to_vec();
+    bytes[..index_size].copy_from_slice(&written[index_offset..index_offset + index_size]);
+    let mut footer = footer.to_vec();
+    footer[16 + 1 + 4 + 4..][..8].copy_from_slice(&0u64.to_le_bytes());
+    bytes.extend(footer);
+
+    let check = |bytes: &[u8], files: &[(&str, &[u8])]| {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(pak.files().len(), files.len());
+        for (path, data) in files {
+            assert_eq!(&pak.get(path, &mut reader).unwrap(), data, "{path}");
+        }
+        pak
     };
+    let pak = check(&bytes, &files);
+    let err = pak.into_pakwriter(Cursor::new(bytes.clone())).unwrap_err();
+    assert!(
+        matches!(err, repak::Error::DataAfterIndex { index_offset: 0 }),
+        "{err}"
+    );
+    assert_eq!(err.kind(), repak::ErrorKind::Output);
+
+    let pak = check(&bytes, &files);
+    let mut pak_writer = pak
+        .into_pakwriter_at_end(Cursor::new(bytes.clone()))
+        .unwrap();
+    pak_writer
+        .write_file("d.txt", false, b"appended after the footer")
+        .unwrap();
+    let appended = pak_writer.write_index().unwrap().into_inner();
+    assert_eq!(&appended[..bytes.len()], &bytes[..]);
+    let pak = check(
+        &appended,
+        &[
+            files[0],
+            files[1],
+            ("d.txt", &b"appended after the footer"[..]),
+        ],
+    );
+
+    // the new index follows all data so appending in place works again
+    pak.into_pakwriter(Cursor::new(appended)).unwrap();
 }
 
-macro_rules! matrix_test_body {
-    ( $name:literal, $version:literal, $exp_version:expr, $compress:literal, $encrypt:literal, $encryptindex:literal, $body:expr ) => {
-        paste! {
-            #[test]
-            fn [< test_ $name _version_ $version $compress $encrypt $encryptindex >]() {
-                $body(
-                    $exp_version,
-                    concat!("pack_", $version, $compress, $encrypt, $encryptindex, ".pak"),
-                    include_bytes!(concat!("packs/pack_", $version, $compress, $encrypt, $encryptindex, ".pak")));
-            }
+#[test]
+fn test_changed_entries() {
+    let write = |files: &[(&str, &[u8])]| {
+        let mut writer = repak::PakBuilder::new()
+            .compression([repak::Compression::Zlib])
+            .writer(
+                Cursor::new(vec![]),
+                repak::Version::V11,
+                "../../../".to_owned(),
+                None,
+            );
+        for (path, data) in files {
+            writer.write_file(path, true, data).unwrap();
         }
+        writer.write_index().unwrap().into_inner()
     };
+    let large = support::pattern(300_000, 13);
+    let old = write(&[
+        ("same.bin", &large),
+        ("modified.txt", b"old contents"),
+        ("removed.txt", b"removed"),
+    ]);
+    let new = write(&[
+        ("added.txt", b"added"),
+        ("modified.txt", b"new contents"),
+        ("same.bin", &large),
+    ]);
+
+    let mut reader = Cursor::new(&old);
+    let old_pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let baseline = old_pak
+        .files()
+        .into_iter()
+        .map(|path| {
+            use sha2::Digest;
+            let hash = sha2::Sha256::digest(old_pak.get(&path, &mut reader).unwrap()).into();
+            (path, hash)
+        })
+        .collect();
+
+    let new_pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&new))
+        .unwrap();
+    let changes = new_pak
+        .changed_entries(&baseline, || Ok(Cursor::new(&new)))
+        .unwrap();
+    assert_eq!(
+        changes,
+        repak::EntryChanges {
+            modified: vec!["modified.txt".to_owned()],
+            added: vec!["added.txt".to_owned()],
+            removed: vec!["removed.txt".to_owned()],
+        }
+    );
+    assert_eq!(changes.changed(), ["added.txt", "modified.txt"]);
+
+    // against itself nothing changed
+    let changes = old_pak
+        .changed_entries(&baseline, || Ok(Cursor::new(&old)))
+        .unwrap();
+    assert_eq!(changes, repak::EntryChanges::default());
+
+    let err = new_pak
+        .changed_entries(&baseline, || -> io::Result<Cursor<&[u8]>> {
+            Err(io::ErrorKind::NotFound.into())
+        })
+        .unwrap_err();
+    assert!(matches!(err, repak::Error::Io(_)), "{err}");
 }
 
-matrix_test!(
-    "read",
-    (
-        "v5" repak::Version::V5,
-        "v7" repak::Version::V7,
-        "v8a" repak::Version::V8A,
-        "v8b" repak::Version::V8B,
-        "v9" repak::Version::V9,
-        "v11" repak::Version::V11,
-    ),
-    ("", "_compress"),
-    ("", "_encrypt"),
-    ("", "_encryptindex"),
-    test_read
-);
+#[test]
+fn test_fragmentation_report() {
+    // paks written by UnrealPak have no unused bytes
+    for bytes in [
+        include_bytes!("packs/pack_v5.pak").as_slice(),
+        include_bytes!("packs/pack_v8b_compress.pak"),
+        include_bytes!("packs/pack_v11_compress.pak"),
+    ] {
+        let pak = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(bytes))
+            .unwrap();
+        let report = pak.fragmentation_report(bytes.len() as u64);
+        assert_eq!(report.file_len, bytes.len() as u64);
+        assert_eq!(report.dead_bytes, 0, "{report:?}");
+        assert_eq!(report.gaps, 0, "{report:?}");
+        assert_eq!(report.largest_gap, 0, "{report:?}");
+    }
 
-matrix_test!(
-    "write",
-    (
-        "v5" repak::Version::V5,
-        "v7" repak::Version::V7,
-        "v8a" repak::Version::V8A,
-        "v8b" repak::Version::V8B,
-        "v9" repak::Version::V9,
-        "v11" repak::Version::V11,
-    ),
-    ("", /*"_compress"*/),
-    ("", /*"_encrypt"*/),
-    ("", /*"_encryptindex"*/),
-    test_write
-);
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../mount/point/root/".to_owned(),
+        None,
+    );
+    writer.write_file("a.bin", false, [1; 100]).unwrap();
+    writer.write_file("b.bin", false, [2; 300]).unwrap();
+    writer.write_file("c.bin", false, [3; 200]).unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    let a = pak.entry_range("a.bin", &mut Cursor::new(&bytes)).unwrap();
+    let c = pak.entry_range("c.bin", &mut Cursor::new(&bytes)).unwrap();
 
-matrix_test!(
-    "rewrite_index",
-    (
-        "v5" repak::Version::V5,
-        "v7" repak::Version::V7,
-        "v8a" repak::Version::V8A,
-        "v8b" repak::Version::V8B,
-        "v9" repak::Version::V9,
-        "v11" repak::Version::V11,
-    ),
-    ("", "_compress"),
-    ("", "_encrypt"),
-    ("", /*"_encryptindex"*/),
-    test_rewrite_index
-);
+    // replacing the first and last entries leaves their old records and data behind
+    let mut writer = pak.into_pakwriter(Cursor::new(bytes)).unwrap();
+    writer.write_file("a.bin", false, [4; 10]).unwrap();
+    writer.write_file("c.bin", false, [5; 10]).unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    let report = pak.fragmentation_report(bytes.len() as u64);
+    assert_eq!(report.gaps, 2, "{report:?}");
+    assert_eq!(report.dead_bytes, a.end - a.start + c.end - c.start);
+    assert_eq!(report.largest_gap, c.end - c.start);
+}
+
+#[test]
+fn test_fragmentation_and_compaction() {
+    let mut writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../mount/point/root/".to_owned(),
+            Some(0x205C5A7D),
+        );
+    writer
+        .write_file("a.txt", false, include_bytes!("pack/root/test.txt"))
+        .unwrap();
+    writer
+        .write_file("b.png", true, include_bytes!("pack/root/test.png"))
+        .unwrap();
+    writer.write_file("c.bin", true, [0; 2048]).unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let report = pak.fragmentation_report(bytes.len() as u64);
+    assert_eq!(report.dead_bytes, 0);
+    assert_eq!(report.percentage(), 0.0);
+
+    // replacing an entry leaves its old data behind
+    let mut writer = pak.into_pakwriter(Cursor::new(bytes.clone())).unwrap();
+    writer
+        .write_file("b.png", false, b"replaced".as_slice())
+        .unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let report = pak.fragmentation_report(bytes.len() as u64);
+    assert_eq!(report.gaps, 1);
+    assert_eq!(report.largest_gap, report.dead_bytes);
+    assert!(report.dead_bytes > 7000, "{report:?}");
+    assert!(report.percentage() > 30.0, "{report:?}");
+
+    let compacted = pak
+        .write_compacted(&mut reader, Cursor::new(vec![]))
+        .unwrap()
+        .into_inner();
+    assert!(compacted.len() as u64 + report.dead_bytes <= bytes.len() as u64 + 64);
+    let mut compacted_reader = Cursor::new(&compacted);
+    let copy = repak::PakBuilder::new()
+        .reader(&mut compacted_reader)
+        .unwrap();
+    assert_eq!(
+        copy.fragmentation_report(compacted.len() as u64).dead_bytes,
+        0
+    );
+    assert_eq!(copy.mount_point(), pak.mount_point());
+    assert_eq!(copy.path_hash_seed(), pak.path_hash_seed());
+    assert_eq!(copy.files(), pak.files());
+    for file in pak.files() {
+        assert_eq!(
+            copy.get(&file, &mut compacted_reader).unwrap(),
+            pak.get(&file, &mut reader).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_keys_by_guid() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key =
+        aes::Aes256::new_from_slice(&general_purpose::STANDARD.decode(AES_KEY).unwrap()).unwrap();
+    let wrong = aes::Aes256::new_from_slice(&[0x42; 32]).unwrap();
+
+    let bytes = include_bytes!("packs/pack_v11_compress_encrypt_encryptindex.pak");
+    let pak = repak::PakBuilder::new()
+        .keys_by_guid([(1, wrong.clone()), (0, key.clone())])
+        .reader(&mut Cursor::new(bytes))
+        .unwrap();
+    assert_eq!(
+        pak.get("test.txt", &mut Cursor::new(bytes)).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+
+    let err = repak::PakBuilder::new()
+        .keys_by_guid([(1, key.clone())])
+        .reader(&mut Cursor::new(bytes))
+        .unwrap_err();
+    assert!(matches!(err, repak::Error::Encrypted), "{err}");
+
+    // an explicit key is not replaced
+    let err = repak::PakBuilder::new()
+        .key(wrong.clone())
+        .keys_by_guid([(0, key.clone())])
+        .reader(&mut Cursor::new(bytes))
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::WrongKeyOrCorrupt { .. }),
+        "{err}"
+    );
+
+    // the GUID is the first field of the footer
+    let mut bytes = bytes.to_vec();
+    let footer = bytes.len() - repak::Version::V11.size() as usize;
+    bytes[footer..footer + 16].copy_from_slice(&5u128.to_le_bytes());
+    let pak = repak::PakBuilder::new()
+        .keys_by_guid([(0, wrong), (5, key)])
+        .reader_with_version(&mut Cursor::new(&bytes), repak::Version::V11)
+        .unwrap();
+    assert_eq!(pak.encryption_guid(), Some(5));
+    assert_eq!(pak.files().len(), 4);
+}
+
+#[test]
+fn test_key_errors() {
+    use aes::cipher::KeyInit;
+    let bytes = include_bytes!("packs/pack_v11_encryptindex.pak");
+
+    let err = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(bytes))
+        .unwrap_err();
+    assert!(matches!(err, repak::Error::Encrypted), "{err}");
+    assert_eq!(err.kind(), repak::ErrorKind::Key);
+
+    let err = repak::PakBuilder::new()
+        .key(aes::Aes256::new_from_slice(&[0x42; 32]).unwrap())
+        .reader(&mut Cursor::new(bytes))
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::WrongKeyOrCorrupt { .. }),
+        "{err}"
+    );
+    assert_eq!(err.kind(), repak::ErrorKind::Key);
+
+    let err = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(b"not a pak"))
+        .unwrap_err();
+    assert_eq!(err.kind(), repak::ErrorKind::Format);
+}
+
+#[test]
+fn test_wrong_key_or_corrupt() {
+    use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    use byteorder::LE;
+    use std::error::Error as _;
+    let key = || {
+        let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+        aes::Aes256::new_from_slice(&key).unwrap()
+    };
+    let bytes = include_bytes!("packs/pack_v11_encryptindex.pak");
+
+    // garbage from the wrong key fails the sanity checks before parsing
+    let err = repak::PakBuilder::new()
+        .key(aes::Aes256::new_from_slice(&[0x42; 32]).unwrap())
+        .reader(&mut Cursor::new(bytes))
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            repak::Error::WrongKeyOrCorrupt {
+                guid: Some(0),
+                source: None
+            }
+        ),
+        "{err}"
+    );
+    assert!(err
+        .to_string()
+        .contains("GUID 00000000000000000000000000000000"));
+    assert_eq!(err.kind(), repak::ErrorKind::Key);
+
+    // an index which passes the sanity checks but fails to parse keeps the parse error
+    let mut bytes = bytes.to_vec();
+    let footer = bytes.len() - repak::Version::V11.size() as usize + 16 + 1 + 4 + 4;
+    let mut reader = Cursor::new(&bytes[footer..]);
+    let index_offset = reader.read_u64::<LE>().unwrap() as usize;
+    let index_size = reader.read_u64::<LE>().unwrap() as usize;
+    let index = &mut bytes[index_offset..index_offset + index_size];
+    for block in index.chunks_mut(16) {
+        key().decrypt_block(aes::Block::from_mut_slice(block));
+    }
+    let mount_point_len = Cursor::new(&index).read_u32::<LE>().unwrap() as usize;
+    // offset of the path hash index, after the mount point, record count, path hash seed and
+    // presence flag
+    let phi_offset = 4 + mount_point_len + 4 + 8 + 4;
+    index[phi_offset..phi_offset + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    for block in index.chunks_mut(16) {
+        key().encrypt_block(aes::Block::from_mut_slice(block));
+    }
+
+    let err = repak::PakBuilder::new()
+        .key(key())
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            repak::Error::WrongKeyOrCorrupt {
+                source: Some(source),
+                ..
+            } if matches!(**source, repak::Error::IndexHashMismatch {
+                section: repak::IndexSection::Primary,
+                ..
+            })
+        ),
+        "{err}"
+    );
+    let err = repak::PakBuilder::new()
+        .key(key())
+        .ignore_index_hashes(true)
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            repak::Error::WrongKeyOrCorrupt {
+                source: Some(source),
+                ..
+            } if matches!(**source, repak::Error::Io(_))
+        ),
+        "{err}"
+    );
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn test_separate_index_key() {
+    use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    use byteorder::LE;
+    let data_key = || {
+        let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+        aes::Aes256::new_from_slice(&key).unwrap()
+    };
+    let index_key = || aes::Aes256::new_from_slice(&[0x42; 32]).unwrap();
+
+    // re-encrypt the index, path hash index and full directory index with a second key
+    let mut bytes = include_bytes!("packs/pack_v11_compress_encrypt_encryptindex.pak").to_vec();
+    let reencrypt = |bytes: &mut [u8]| {
+        for block in bytes.chunks_mut(16) {
+            let block = aes::Block::from_mut_slice(block);
+            data_key().decrypt_block(block);
+            index_key().encrypt_block(block);
+        }
+    };
+    let footer = bytes.len() - repak::Version::V11.size() as usize + 16 + 1 + 4 + 4;
+    let mut reader = Cursor::new(&bytes[footer..]);
+    let index_offset = reader.read_u64::<LE>().unwrap() as usize;
+    let index_size = reader.read_u64::<LE>().unwrap() as usize;
+    let index = &mut bytes[index_offset..index_offset + index_size];
+    reencrypt(index);
+    let mut index = index.to_vec();
+    for block in index.chunks_mut(16) {
+        index_key().decrypt_block(aes::Block::from_mut_slice(block));
+    }
+    let mut reader = Cursor::new(&index);
+    let mount_point_len = reader.read_u32::<LE>().unwrap();
+    // mount point, record count and path hash seed
+    reader
+        .seek(SeekFrom::Current(mount_point_len as i64 + 4 + 8))
+        .unwrap();
+    let mut secondary = vec![];
+    for _ in 0..2 {
+        assert_eq!(reader.read_u32::<LE>().unwrap(), 1);
+        let offset = reader.read_u64::<LE>().unwrap() as usize;
+        let size = reader.read_u64::<LE>().unwrap() as usize;
+        reader.seek(SeekFrom::Current(20)).unwrap();
+        secondary.push(offset..offset + size);
+    }
+    for range in secondary {
+        reencrypt(&mut bytes[range]);
+    }
+
+    let err = repak::PakBuilder::new()
+        .key(data_key())
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::WrongKeyOrCorrupt { .. }),
+        "{err}"
+    );
+
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new()
+        .index_key(index_key())
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(pak.files().len(), 4);
+    let err = pak.get("test.txt", &mut reader).unwrap_err();
+    assert!(matches!(err, repak::Error::Encrypted), "{err}");
+
+    let pak = repak::PakBuilder::new()
+        .key(index_key())
+        .reader(&mut reader)
+        .unwrap();
+    let err = pak.get("test.png", &mut reader).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            repak::Error::EncryptedDataInvalid(repak::Compression::Zlib)
+        ),
+        "{err}"
+    );
+    assert!(err.to_string().contains("different key"));
+
+    let pak = repak::PakBuilder::new()
+        .index_key(index_key())
+        .data_key(data_key())
+        .reader(&mut reader)
+        .unwrap();
+    let expected = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(include_bytes!("packs/pack_v11.pak")))
+        .unwrap();
+    for path in pak.files() {
+        assert_eq!(
+            pak.get(&path, &mut reader).unwrap(),
+            expected
+                .get(
+                    &path,
+                    &mut Cursor::new(include_bytes!("packs/pack_v11.pak"))
+                )
+                .unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_read_encrypted_unaligned_tail() {
+    use aes::cipher::{BlockEncrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD
+        .decode(AES_KEY)
+        .as_ref()
+        .map_err(|_| repak::Error::Aes)
+        .and_then(|bytes| aes::Aes256::new_from_slice(bytes).map_err(|_| repak::Error::Aes))
+        .unwrap();
+
+    let data = b"37 bytes of data without AES padding.";
+    let mount_point = "../mount/point/root/";
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V8B,
+        mount_point.to_owned(),
+        None,
+    );
+    pak_writer.write_file("test.txt", false, data).unwrap();
+    let mut bytes = pak_writer.write_index().unwrap().into_inner();
+
+    // entry header: offset, compressed, uncompressed, compression, hash, flags, block size
+    let data_offset = 8 + 8 + 8 + 4 + 20 + 1 + 4;
+    let index_offset = data_offset + data.len();
+    // encrypt whole blocks only, leaving the unpadded tail as is directly followed by the index
+    for block in bytes[data_offset..index_offset].chunks_exact_mut(16) {
+        key.encrypt_block(aes::Block::from_mut_slice(block));
+    }
+    // index: mount point, entry count, path, then the record with flags at the same position
+    let record = index_offset + 4 + mount_point.len() + 1 + 4 + 4 + "test.txt".len() + 1;
+    bytes[record + 8 + 8 + 8 + 4 + 20] = 1;
+
+    // the index no longer matches its hash in the footer
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new()
+        .ignore_index_hashes(true)
+        .key(key)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(pak.get("test.txt", &mut reader).unwrap(), data);
+}
+
+#[test]
+fn test_decrypt_limit() {
+    use aes::cipher::{BlockEncrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD
+        .decode(AES_KEY)
+        .as_ref()
+        .map_err(|_| repak::Error::Aes)
+        .and_then(|bytes| aes::Aes256::new_from_slice(bytes).map_err(|_| repak::Error::Aes))
+        .unwrap();
+
+    let data = (0..2000)
+        .map(|i| format!("line {i}\n"))
+        .collect::<String>()
+        .into_bytes();
+    // a pak with `test.txt` whose stored data is encrypted in only its first 64 bytes
+    let encrypt_prefix = |compression: &[repak::Compression]| {
+        let mut pak_writer = repak::PakBuilder::new()
+            .compression(compression.iter().copied())
+            .writer(Cursor::new(vec![]), repak::Version::V8B, "../".into(), None);
+        pak_writer
+            .write_file("test.txt", !compression.is_empty(), &data)
+            .unwrap();
+        let mut bytes = pak_writer.write_index().unwrap().into_inner();
+        let footer = repak::PakBuilder::new().parse_footer(&bytes).unwrap();
+        let index = footer.index_range();
+        let pak = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap();
+        let info = pak.entry_info("test.txt").unwrap();
+        assert!(info.compressed > 64);
+        let data_offset = (index.start - info.compressed) as usize;
+        for block in bytes[data_offset..data_offset + 64].chunks_exact_mut(16) {
+            key.encrypt_block(aes::Block::from_mut_slice(block));
+        }
+        // the only record ends the index with its flags and block size
+        bytes[index.end as usize - 5] = 1;
+        bytes
+    };
+    let read = |bytes: &[u8], limit: repak::DecryptLimit| {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new()
+            .ignore_index_hashes(true)
+            .key(key.clone())
+            .decrypt_limit(limit)
+            .reader(&mut reader)
+            .unwrap();
+        let mut streamed = vec![];
+        let streamed = pak
+            .open_entry("test.txt", &mut reader)
+            .and_then(|mut entry| Ok(entry.read_to_end(&mut streamed).map(|_| streamed)?));
+        let got = pak.get("test.txt", &mut reader);
+        match (got, streamed) {
+            (Ok(got), Ok(streamed)) => {
+                assert_eq!(got, streamed);
+                Ok(got)
+            }
+            (Err(err), Err(_)) => Err(err),
+            (got, streamed) => panic!("{:?} {:?}", got.err(), streamed.err()),
+        }
+    };
+    let per_path = || {
+        repak::DecryptLimit::PerPath(Box::new(|path| match path {
+            "test.txt" => 64,
+            _ => usize::MAX,
+        }))
+    };
+
+    let stored = encrypt_prefix(&[]);
+    assert_eq!(
+        read(&stored, repak::DecryptLimit::FirstBytes(64)).unwrap(),
+        data
+    );
+    assert_eq!(read(&stored, per_path()).unwrap(), data);
+    // decrypting the plain tail of uncompressed entries goes unnoticed
+    let got = read(&stored, repak::DecryptLimit::Full).unwrap();
+    assert_eq!(got[..64], data[..64]);
+    assert_ne!(got, data);
+
+    let compressed = encrypt_prefix(&[repak::Compression::Zlib]);
+    assert_eq!(
+        read(&compressed, repak::DecryptLimit::FirstBytes(64)).unwrap(),
+        data
+    );
+    assert_eq!(read(&compressed, per_path()).unwrap(), data);
+    for limit in [
+        repak::DecryptLimit::Full,
+        repak::DecryptLimit::FirstBytes(32),
+    ] {
+        let err = read(&compressed, limit).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                repak::Error::EncryptedDataInvalid(repak::Compression::Zlib)
+            ),
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn test_version_detection_corpus() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ambiguous");
+    for file in std::fs::read_dir(dir).unwrap() {
+        let path = file.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "pak") {
+            continue;
+        }
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let expected = name.split('_').next().unwrap().to_uppercase();
+        let mut reader = Cursor::new(std::fs::read(&path).unwrap());
+        // the hash of the fake index can't be stored in the V8B footer as it overlaps the V8A
+        // footer, so only paks read without checking the hashes are ambiguous
+        let pak = repak::PakBuilder::new()
+            .ignore_index_hashes(true)
+            .reader(&mut reader)
+            .unwrap();
+        assert_eq!(pak.version().to_string(), expected, "{name}");
+        assert_eq!(pak.files().len(), 4, "{name}");
+        let detection = pak.version_detection().unwrap();
+        assert_eq!(detection.score, detection.max_score, "{name}");
+        let (runner_up, score) = detection.runner_up.unwrap();
+        assert_eq!(runner_up, repak::Version::V8B, "{name}");
+        assert!(score < detection.score, "{name}");
+
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(pak.version().to_string(), expected, "{name}");
+        assert_eq!(pak.version_detection().unwrap().runner_up, None, "{name}");
+    }
+
+    // without the entries only the header tells the versions apart
+    let mut reader = Cursor::new(include_bytes!("ambiguous/v8a_also_v8b.pak"));
+    let pak = repak::PakBuilder::new()
+        .metadata_only(true)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(pak.version(), repak::Version::V8A);
+
+    // paks which parse as a single version aren't ambiguous
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v8a.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(
+        pak.version_detection(),
+        Some(&repak::VersionDetection {
+            score: 3,
+            max_score: 3,
+            runner_up: None
+        })
+    );
+    let pak = repak::PakBuilder::new()
+        .reader_with_version(&mut reader, repak::Version::V8A)
+        .unwrap();
+    assert_eq!(pak.version_detection(), None);
+}
+
+#[test]
+fn test_directory_summary() {
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11_compress.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+
+    let summary = pak.directory_summary();
+    assert_eq!(summary.file_count, 4);
+    assert_eq!(summary.uncompressed, 10257 + 446 + 2048 + 596);
+    assert_eq!(
+        summary.files.keys().collect::<Vec<_>>(),
+        ["test.png", "test.txt", "zeros.bin"]
+    );
+
+    let directory = &summary.directories["directory"];
+    assert_eq!(directory.file_count, 1);
+    assert_eq!(directory.uncompressed, 596);
+    assert_eq!(directory.compressed, 596);
+    assert_eq!(
+        directory.files["nested.txt"],
+        pak.entry_info("directory/nested.txt").unwrap()
+    );
+    assert!(directory.directories.is_empty());
+}
+
+#[test]
+fn test_write_padding() {
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak_reader = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let files = pak_reader
+        .files()
+        .into_iter()
+        .map(|path| {
+            let data = pak_reader.get(&path, &mut reader).unwrap();
+            (path, data)
+        })
+        .collect::<Vec<_>>();
+
+    type Writer = repak::PakWriter<Cursor<Vec<u8>>>;
+    let write = |pad: &dyn Fn(&mut Writer)| {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            pak_reader.mount_point().to_owned(),
+            Some(0x205C5A7D),
+        );
+        pad(&mut pak_writer);
+        for (path, data) in &files {
+            pak_writer.write_file(path, false, data).unwrap();
+        }
+        let bytes = pak_writer.write_index().unwrap().into_inner();
+
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        for (path, data) in &files {
+            assert_eq!(&pak.get(path, &mut reader).unwrap(), data);
+        }
+
+        // footer: encryption guid, encrypted, magic, version, then the index offset
+        let footer = bytes.len() - repak::Version::V11.size() as usize + 16 + 1 + 4 + 4;
+        let index_offset = u64::from_le_bytes(bytes[footer..footer + 8].try_into().unwrap());
+        (bytes.len() as u64, index_offset)
+    };
+
+    let (len, index_offset) = write(&|_| {});
+    assert_eq!(
+        write(&|w| w.pad_data_region_to(1)),
+        (len, index_offset),
+        "no padding is inserted for already aligned data"
+    );
+
+    let (_, index_offset) = write(&|w| w.pad_data_region_to(0x1000));
+    assert_eq!(index_offset, 0x4000);
+
+    let (len, _) = write(&|w| w.pad_file_to(0x10000));
+    assert_eq!(len, 0x10000);
+}
+
+#[test]
+fn test_v12_footer() {
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak_reader = repak::PakBuilder::new().reader(&mut reader).unwrap();
+
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V12,
+        pak_reader.mount_point().to_owned(),
+        Some(0x205C5A7D),
+    );
+    for path in pak_reader.files() {
+        let data = pak_reader.get(&path, &mut reader).unwrap();
+        pak_writer.write_file(&path, false, data).unwrap();
+    }
+    let mut bytes = pak_writer.write_index().unwrap().into_inner();
+
+    let mut v12 = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut v12).unwrap();
+    assert_eq!(pak.version(), repak::Version::V12);
+    assert_eq!(pak.files().len(), pak_reader.files().len());
+    assert_eq!(
+        pak.get("test.txt", &mut v12).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+
+    // footer: encryption guid, encrypted, magic, then the version
+    let version = bytes.len() - repak::Version::V12.size() as usize + 16 + 1 + 4;
+    bytes[version..version + 4].copy_from_slice(&13u32.to_le_bytes());
+    let mut v13 = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut v13).unwrap();
+    assert_eq!(pak.version(), repak::Version::V12);
+    assert_eq!(
+        pak.warnings(),
+        [repak::IndexWarning::UnknownVersion {
+            version: 13,
+            read_as: repak::Version::V12,
+        }]
+    );
+    assert_eq!(
+        pak.get("test.txt", &mut v13).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+    let err = repak::PakBuilder::new()
+        .strict(true)
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            repak::Error::StrictWarning(repak::IndexWarning::UnknownVersion { version: 13, .. })
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_v12_utf8_directory_names() {
+    let write = |version| {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            version,
+            "../../../".to_owned(),
+            Some(0),
+        );
+        pak_writer
+            .write_file("Maps/Café.umap", false, b"map")
+            .unwrap();
+        pak_writer.write_index().unwrap().into_inner()
+    };
+    let full_directory_index = |bytes: &[u8]| {
+        let pak = repak::PakBuilder::new()
+            .keep_encoded_index(true)
+            .reader(&mut Cursor::new(bytes))
+            .unwrap();
+        let sections = pak.raw_index_sections().unwrap();
+        sections.full_directory_index.unwrap().to_vec()
+    };
+    // the last file name followed by its encoded entry offset ends the full directory index
+    let file = |len: i32, chars: &[u8]| [&len.to_le_bytes()[..], chars, &[0; 4]].concat();
+    let utf16 = "Café.umap\0"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+
+    // V11 names which aren't ASCII are UTF-16 with a negative length, V12 ones are UTF-8
+    let v11 = full_directory_index(&write(repak::Version::V11));
+    assert!(v11.ends_with(&file(-10, &utf16)), "{v11:02x?}");
+    let v12 = full_directory_index(&write(repak::Version::V12));
+    assert!(
+        v12.ends_with(&file(11, "Café.umap\0".as_bytes())),
+        "{v12:02x?}"
+    );
+
+    let bytes = write(repak::Version::V12);
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(pak.version(), repak::Version::V12);
+    assert_eq!(pak.files(), ["Maps/Café.umap"]);
+    assert_eq!(pak.get("Maps/Café.umap", &mut reader).unwrap(), b"map");
+}
+
+#[test]
+fn test_secondary_indexes() {
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak_reader = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let files = pak_reader
+        .files()
+        .into_iter()
+        .map(|path| {
+            let data = pak_reader.get(&path, &mut reader).unwrap();
+            (path, data)
+        })
+        .collect::<Vec<_>>();
+
+    for version in [repak::Version::V10, repak::Version::V11] {
+        let write = |path_hash_index, full_directory_index| {
+            let mut pak_writer = repak::PakBuilder::new().writer(
+                Cursor::new(vec![]),
+                version,
+                pak_reader.mount_point().to_owned(),
+                Some(0x205C5A7D),
+            );
+            pak_writer.write_path_hash_index(path_hash_index);
+            pak_writer.write_full_directory_index(full_directory_index);
+            for (path, data) in &files {
+                pak_writer.write_file(path, false, data).unwrap();
+            }
+            pak_writer.write_index().map(Cursor::into_inner)
+        };
+
+        let both = write(true, true).unwrap();
+        let phi_only = write(true, false).unwrap();
+        let fdi_only = write(false, true).unwrap();
+        assert!(write(false, false).is_err());
+        assert!(phi_only.len() < both.len());
+        assert!(fdi_only.len() < both.len());
+
+        for bytes in [&both, &fdi_only] {
+            let mut reader = Cursor::new(bytes);
+            let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+            assert_eq!(pak.version(), version);
+            assert_eq!(pak.files().len(), files.len());
+            for (path, data) in &files {
+                assert_eq!(&pak.get(path, &mut reader).unwrap(), data);
+            }
+
+            // the layout of the index is preserved when it is rewritten
+            let rewrite = pak
+                .into_pakwriter(Cursor::new(bytes.clone()))
+                .unwrap()
+                .write_index()
+                .unwrap()
+                .into_inner();
+            assert_eq!(&rewrite, bytes);
+        }
+
+        // entry paths are only stored in the full directory index
+        let pak = repak::PakBuilder::new()
+            .reader(&mut Cursor::new(&phi_only))
+            .unwrap();
+        assert_eq!(pak.version(), version);
+        assert!(pak.files().is_empty());
+    }
+}
+
+/// V10 was only written by engine builds between 4.25 and 4.26, so there are no fixtures of it in
+/// the read and write matrix. It shares the layout of the footer and of all index sections with
+/// V11 (4.26-5.2), so a V10 pak written by repak must match the V11 pak of the same files in
+/// everything but the version field of the footer and the hash function of the path hash index
+#[test]
+fn test_write_v10_layout() {
+    use byteorder::LE;
+
+    // the V10 hash covers only the first half of each path, so the first halves differ to
+    // avoid collisions such as test.txt and test.png
+    let paths = [
+        "directory/nested.txt",
+        "Game/Content/Maps/A.umap",
+        "test.png",
+        "zeros.bin",
+    ];
+    let seed = 0x205C5A7D;
+    let write = |version| {
+        let mut writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            version,
+            "../mount/point/root/".to_owned(),
+            Some(seed),
+        );
+        for path in paths {
+            writer.write_file(path, false, path).unwrap();
+        }
+        writer.write_index().unwrap().into_inner()
+    };
+    let (v10, v11) = (write(repak::Version::V10), write(repak::Version::V11));
+    assert_eq!(v10.len(), v11.len());
+
+    let footer = v10.len() - repak::Version::V10.size() as usize;
+    // footer: encryption guid, encrypted, magic, then the version
+    let version_field = footer + 16 + 1 + 4;
+    assert_eq!(
+        (&v10[version_field..]).read_u32::<LE>().unwrap(),
+        repak::VersionMajor::PathHashIndex as u32
+    );
+    assert_eq!(
+        (&v11[version_field..]).read_u32::<LE>().unwrap(),
+        repak::VersionMajor::Fnv64BugFix as u32
+    );
+
+    let read = |bytes: &[u8]| {
+        repak::PakBuilder::new()
+            .keep_encoded_index(true)
+            .reader(&mut Cursor::new(bytes.to_vec()))
+            .unwrap()
+    };
+    let (pak_v10, pak_v11) = (read(&v10), read(&v11));
+    assert_eq!(pak_v10.version(), repak::Version::V10);
+    assert_eq!(pak_v10.check_path_hash_index(), Some(Default::default()));
+    let (raw_v10, raw_v11) = (
+        pak_v10.raw_index_sections().unwrap(),
+        pak_v11.raw_index_sections().unwrap(),
+    );
+    assert_eq!(raw_v10.encoded_entries, raw_v11.encoded_entries);
+    assert_eq!(raw_v10.full_directory_index, raw_v11.full_directory_index);
+
+    // path hash records as (path, offset of the encoded entry), by the hash of each version
+    let records = |phi: &[u8], hash: fn(&str, u64) -> u64| {
+        let mut phi = Cursor::new(phi);
+        let mut records = (0..phi.read_u32::<LE>().unwrap())
+            .map(|_| {
+                let record_hash = phi.read_u64::<LE>().unwrap();
+                let offset = phi.read_u32::<LE>().unwrap();
+                let path = paths
+                    .into_iter()
+                    .find(|path| hash(path, seed) == record_hash)
+                    .unwrap_or_else(|| panic!("no path hashes to {record_hash:016x}"));
+                (path, offset)
+            })
+            .collect::<Vec<_>>();
+        records.sort();
+        records
+    };
+    assert_eq!(
+        records(
+            raw_v10.path_hash_index.unwrap(),
+            repak::path_hash::fnv64_v10
+        ),
+        records(
+            raw_v11.path_hash_index.unwrap(),
+            repak::path_hash::fnv64_v11
+        )
+    );
+}
+
+#[test]
+fn test_path_hash_index_version() {
+    use byteorder::LE;
+
+    /// Reads the hashes stored in the path hash index of an unencrypted V10+ pak
+    fn path_hashes(bytes: &[u8], version: repak::Version) -> Vec<u64> {
+        let mut reader = Cursor::new(bytes);
+        // footer: encryption guid, encrypted, magic, version, then the index offset
+        reader
+            .seek(SeekFrom::End(-version.size() + 16 + 1 + 4 + 4))
+            .unwrap();
+        let index_offset = reader.read_u64::<LE>().unwrap();
+        reader.seek(SeekFrom::Start(index_offset)).unwrap();
+        let mount_point_len = reader.read_u32::<LE>().unwrap();
+        // mount point, record count, path hash seed, has path hash index
+        reader
+            .seek(SeekFrom::Current(mount_point_len as i64 + 4 + 8 + 4))
+            .unwrap();
+        let phi_offset = reader.read_u64::<LE>().unwrap();
+        reader.seek(SeekFrom::Start(phi_offset)).unwrap();
+        (0..reader.read_u32::<LE>().unwrap())
+            .map(|_| {
+                let hash = reader.read_u64::<LE>().unwrap();
+                reader.read_u32::<LE>().unwrap();
+                hash
+            })
+            .collect()
+    }
+
+    let seed = 0x205C5A7D;
+    let paths = ["directory/nested.txt", "test.png", "test.txt", "zeros.bin"];
+    for (version, hash) in [
+        (
+            repak::Version::V10,
+            repak::path_hash::fnv64_v10 as fn(&str, u64) -> u64,
+        ),
+        (repak::Version::V11, repak::path_hash::fnv64_v11),
+    ] {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            version,
+            "../mount/point/root/".to_owned(),
+            Some(seed),
+        );
+        for path in paths {
+            pak_writer.write_file(path, false, path).unwrap();
+        }
+        let bytes = pak_writer.write_index().unwrap().into_inner();
+        assert_eq!(
+            path_hashes(&bytes, version),
+            paths.map(|path| hash(path, seed)),
+            "{version}"
+        );
+    }
+
+    let fixture = include_bytes!("packs/pack_v11.pak");
+    assert_eq!(
+        path_hashes(fixture, repak::Version::V11),
+        paths.map(|path| repak::path_hash::fnv64_v11(path, seed))
+    );
+}
+
+#[test]
+fn test_copy_entry_from() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = || {
+        let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+        aes::Aes256::new_from_slice(&key).unwrap()
+    };
+    // offset, compressed, uncompressed, compression slot, hash, block count, one block,
+    // flags and block size of a V11 entry record with a single compression block
+    const RECORD: u64 = 8 + 8 + 8 + 4 + 20 + 4 + 16 + 1 + 4;
+
+    for (source, copy_key, encrypted) in [
+        (
+            &include_bytes!("packs/pack_v11_compress.pak")[..],
+            false,
+            false,
+        ),
+        (
+            include_bytes!("packs/pack_v11_compress_encrypt.pak"),
+            true,
+            true,
+        ),
+        // without the key in the destination encrypted entries are decrypted
+        (
+            include_bytes!("packs/pack_v11_compress_encrypt.pak"),
+            false,
+            false,
+        ),
+    ] {
+        let mut reader = Cursor::new(source);
+        let pak_reader = repak::PakBuilder::new()
+            .key(key())
+            .reader(&mut reader)
+            .unwrap();
+
+        let mut builder = repak::PakBuilder::new();
+        if copy_key {
+            builder = builder.key(key());
+        }
+        let mut pak_writer = builder.writer(
+            Cursor::new(vec![]),
+            pak_reader.version(),
+            pak_reader.mount_point().to_owned(),
+            pak_reader.path_hash_seed(),
+        );
+        pak_writer
+            .copy_entry_from(&pak_reader, &mut reader, "test.png")
+            .unwrap();
+        pak_writer
+            .copy_entry_from(&pak_reader, &mut reader, "test.txt")
+            .unwrap();
+        assert!(matches!(
+            pak_writer.copy_entry_from(&pak_reader, &mut reader, "missing.txt"),
+            Err(repak::Error::MissingEntry(_))
+        ));
+        let bytes = pak_writer.write_index().unwrap().into_inner();
+
+        let mut copy_reader = Cursor::new(&bytes);
+        let copy = repak::PakBuilder::new()
+            .key(key())
+            .reader(&mut copy_reader)
+            .unwrap();
+        assert_eq!(copy.files(), ["test.png", "test.txt"]);
+        for path in copy.files() {
+            assert_eq!(
+                copy.get(&path, &mut copy_reader).unwrap(),
+                pak_reader.get(&path, &mut reader).unwrap()
+            );
+        }
+
+        let src = pak_reader.entry_info("test.png").unwrap();
+        let dst = copy.entry_info("test.png").unwrap();
+        assert_eq!(dst.compression, Some(repak::Compression::Zlib));
+        assert_eq!(dst.encrypted, encrypted);
+        if src.encrypted == dst.encrypted {
+            assert_eq!(src.compressed, dst.compressed);
+            let raw = |bytes: &[u8], offset: u64| {
+                bytes[(offset + RECORD) as usize..(offset + RECORD + src.compressed) as usize]
+                    .to_vec()
+            };
+            assert_eq!(raw(source, src.offset), raw(&bytes, dst.offset));
+        }
+    }
+}
+
+#[test]
+fn test_copy_entry_from_absolute_offsets() {
+    let mut source = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V4,
+            "../mount/point/root/".to_owned(),
+            None,
+        );
+    source.write_file("padding.bin", false, [0u8; 100]).unwrap();
+    source
+        .write_file("test.txt", true, include_bytes!("pack/root/test.txt"))
+        .unwrap();
+    let source = source.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&source);
+    let pak_reader = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V4,
+        pak_reader.mount_point().to_owned(),
+        None,
+    );
+    pak_writer
+        .copy_entry_from(&pak_reader, &mut reader, "test.txt")
+        .unwrap();
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    // block offsets are relative to the start of the file before V5 so they must be moved
+    let mut reader = Cursor::new(&bytes);
+    let copy = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(copy.entry_info("test.txt").unwrap().offset, 0);
+    assert_eq!(
+        copy.get("test.txt", &mut reader).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+}
+
+#[test]
+fn test_read_stored() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    for (bytes, compressed, encrypted) in [
+        (&include_bytes!("packs/pack_v11.pak")[..], false, false),
+        (include_bytes!("packs/pack_v11_compress.pak"), true, false),
+        (include_bytes!("packs/pack_v8b_encrypt.pak"), false, true),
+        (
+            include_bytes!("packs/pack_v5_compress_encrypt.pak"),
+            true,
+            true,
+        ),
+    ] {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .reader(&mut reader)
+            .unwrap();
+        for file in pak.files() {
+            let info = pak.entry_info(&file).unwrap();
+            let mut stored = vec![];
+            let len = pak.read_stored(&file, &mut reader, &mut stored).unwrap();
+            assert_eq!(len, stored.len() as u64);
+            let range = pak.entry_range(&file, &mut reader).unwrap();
+            assert_eq!(
+                stored,
+                bytes[range.end as usize - stored.len()..range.end as usize]
+            );
+            match encrypted {
+                true => assert_eq!(len, info.compressed.next_multiple_of(16), "{file}"),
+                false => assert_eq!(len, info.compressed, "{file}"),
+            }
+            if !compressed && !encrypted {
+                assert_eq!(stored, pak.get(&file, &mut reader).unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_get_ref() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    use std::borrow::Cow;
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    for (bytes, borrowed) in [
+        (
+            &include_bytes!("packs/pack_v11.pak")[..],
+            &["directory/nested.txt", "test.png", "test.txt", "zeros.bin"][..],
+        ),
+        (
+            include_bytes!("packs/pack_v11_compress.pak"),
+            &["directory/nested.txt", "test.txt"],
+        ),
+        (include_bytes!("packs/pack_v5_encrypt.pak"), &[]),
+    ] {
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .reader(&mut Cursor::new(bytes))
+            .unwrap();
+        for file in pak.files() {
+            let data = pak.get_ref(&file, bytes).unwrap();
+            assert_eq!(data, pak.get(&file, &mut Cursor::new(bytes)).unwrap());
+            match data {
+                Cow::Borrowed(data) => {
+                    assert!(borrowed.contains(&file.as_str()), "{file}");
+                    assert!(bytes.as_ptr_range().contains(&data.as_ptr()));
+                    assert!(data.as_ptr_range().end <= bytes.as_ptr_range().end);
+                }
+                Cow::Owned(_) => assert!(!borrowed.contains(&file.as_str()), "{file}"),
+            }
+        }
+    }
+
+    let bytes = include_bytes!("packs/pack_v11.pak");
+    let pak = repak::PakBuilder::new()
+        .verify_reads(true)
+        .reader(&mut Cursor::new(bytes))
+        .unwrap();
+    assert!(matches!(
+        pak.get_ref("test.txt", bytes).unwrap(),
+        Cow::Owned(_)
+    ));
+
+    // the data of test.png ends past a truncated buffer
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(bytes))
+        .unwrap();
+    let end = pak
+        .entry_range("test.png", &mut Cursor::new(bytes))
+        .unwrap()
+        .end;
+    assert!(pak.get_ref("test.png", &bytes[..end as usize - 1]).is_err());
+    assert!(pak.get_ref("test.png", &bytes[..end as usize]).is_ok());
+    assert!(pak.get_ref("missing.txt", bytes).is_err());
+}
+
+#[test]
+fn test_metadata_only() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/packs");
+    let mut count = 0;
+    for file in std::fs::read_dir(dir).unwrap() {
+        let path = file.unwrap().path();
+        let bytes = std::fs::read(&path).unwrap();
+        let builder = || repak::PakBuilder::new().key(key.clone());
+        let full = builder().reader(&mut Cursor::new(&bytes)).unwrap();
+        let pak = builder()
+            .metadata_only(true)
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap();
+        let name = path.display();
+        assert_eq!(pak.version(), full.version(), "{name}");
+        assert_eq!(pak.mount_point(), full.mount_point(), "{name}");
+        assert_eq!(pak.encrypted_index(), full.encrypted_index(), "{name}");
+        assert_eq!(pak.encryption_guid(), full.encryption_guid(), "{name}");
+        assert_eq!(pak.path_hash_seed(), full.path_hash_seed(), "{name}");
+        assert_eq!(pak.entry_count(), full.len(), "{name}");
+        assert_eq!(full.entry_count(), full.len(), "{name}");
+
+        assert!(pak.files().is_empty(), "{name}");
+        assert!(matches!(
+            pak.get("test.txt", &mut Cursor::new(&bytes)),
+            Err(repak::Error::IndexNotLoaded)
+        ));
+        assert!(matches!(
+            pak.into_pakwriter(Cursor::new(bytes.clone())),
+            Err(repak::Error::IndexNotLoaded)
+        ));
+        count += 1;
+    }
+    assert_eq!(count, 48);
+
+    // the secondary indexes after the primary index aren't read
+    let bytes = include_bytes!("packs/pack_v11.pak");
+    let mut reader = ReadCounter::new_size(Cursor::new(bytes), bytes.len());
+    repak::PakBuilder::new()
+        .metadata_only(true)
+        .reader(&mut reader)
+        .unwrap();
+    let reads = reader.into_reads();
+    let builder = repak::PakBuilder::new();
+    let footer = builder.parse_footer(bytes).unwrap();
+    let index_range = footer.index_range();
+    let index = &bytes[index_range.start as usize..index_range.end as usize];
+    let secondary = builder.secondary_index_ranges(&footer, index).unwrap();
+    for range in [secondary.path_hash_index, secondary.full_directory_index] {
+        let range = range.unwrap();
+        assert!(reads[range.start as usize..range.end as usize]
+            .iter()
+            .all(|&r| r == 0));
+    }
+}
+
+#[test]
+fn test_open_entry() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    for bytes in [
+        &include_bytes!("packs/pack_v5_compress_encrypt.pak")[..],
+        include_bytes!("packs/pack_v8a_encrypt.pak"),
+        include_bytes!("packs/pack_v11_compress.pak"),
+        include_bytes!("packs/pack_v11_compress_encrypt_encryptindex.pak"),
+    ] {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .reader(&mut reader)
+            .unwrap();
+        for file in pak.files() {
+            let expected = pak.get(&file, &mut reader).unwrap();
+            let mut data = vec![];
+            let mut entry =
+                io::BufReader::with_capacity(7, pak.open_entry(&file, &mut reader).unwrap());
+            entry.read_to_end(&mut data).unwrap();
+            assert_eq!(data, expected, "{file}");
+        }
+    }
+
+    // entries spanning several compression blocks, with relative and absolute block offsets
+    let data = support::pattern(400_000, 7);
+    for version in [repak::Version::V4, repak::Version::V11] {
+        for compression in [None, Some(repak::Compression::Zlib)] {
+            let mut writer = repak::PakBuilder::new().compression(compression).writer(
+                Cursor::new(vec![]),
+                version,
+                "../../../".to_owned(),
+                None,
+            );
+            writer.write_file("padding.bin", false, [0u8; 100]).unwrap();
+            writer
+                .write_file("large.bin", compression.is_some(), &data)
+                .unwrap();
+            let bytes = writer.write_index().unwrap().into_inner();
+
+            let mut reader = Cursor::new(&bytes);
+            let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+            assert_eq!(
+                pak.entry_info("large.bin").unwrap().compression,
+                compression
+            );
+            assert_eq!(pak.get("large.bin", &mut reader).unwrap(), data);
+
+            let mut entry = pak.open_entry("large.bin", &mut reader).unwrap();
+            let mut read = vec![];
+            entry.read_to_end(&mut read).unwrap();
+            assert_eq!(read, data);
+
+            // seek backwards and across blocks
+            for pos in [300_000, 5, 126_970, 399_990, 400_000] {
+                entry.seek(SeekFrom::Start(pos)).unwrap();
+                let mut buf = [0; 16];
+                let n = entry.read(&mut buf).unwrap();
+                let expected = &data[pos as usize..(pos as usize + 16).min(data.len())];
+                assert_eq!(&buf[..n], &expected[..n]);
+                assert!(n > 0 || expected.is_empty());
+            }
+            assert_eq!(entry.seek(SeekFrom::End(-10)).unwrap(), 399_990);
+            assert_eq!(entry.seek(SeekFrom::Current(-10)).unwrap(), 399_980);
+            let mut rest = vec![];
+            entry.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, &data[399_980..]);
+            assert!(entry.seek(SeekFrom::Current(-400_001)).is_err());
+
+            // reading the end only touches the last block
+            let mut entry = pak.open_entry("large.bin", &mut reader).unwrap();
+            entry.seek(SeekFrom::Start(399_990)).unwrap();
+            entry.read_to_end(&mut vec![]).unwrap();
+            let block_size: u64 = match compression {
+                Some(_) => 0x3e << 11,
+                None => 0x10000,
+            };
+            assert_eq!(entry.block_count() as u64, 400_000u64.div_ceil(block_size));
+            assert_eq!(entry.blocks_read(), 1);
+        }
+    }
+
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert!(matches!(
+        pak.open_entry("missing.txt", &mut reader),
+        Err(repak::Error::MissingEntry(_))
+    ));
+}
+
+#[test]
+fn test_synthetic_pak() {
+    for version in [repak::Version::V5, repak::Version::V8B, repak::Version::V11] {
+        let bytes = support::synthetic_pak(version, 1000);
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(pak.len(), 1000);
+        for i in [0, 99, 100, 999] {
+            let data = pak.get(&support::entry_path(i), &mut reader).unwrap();
+            assert_eq!(data, i.to_le_bytes());
+        }
+    }
+}
+
+#[test]
+fn test_decompression_threads() {
+    let data = support::pattern(1_000_000, 9);
+    for compression in [repak::Compression::Zlib, repak::Compression::Zstd] {
+        let mut writer = repak::PakBuilder::new().compression([compression]).writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+        writer.write_file("large.bin", true, &data).unwrap();
+        writer.write_file("small.bin", true, [1; 100]).unwrap();
+        let bytes = writer.write_index().unwrap().into_inner();
+
+        // more threads than blocks, and blocks split unevenly between threads
+        for threads in [0, 1, 3, 16] {
+            let mut reader = Cursor::new(&bytes);
+            let pak = repak::PakBuilder::new()
+                .decompression_threads(threads)
+                .reader(&mut reader)
+                .unwrap();
+            assert_eq!(pak.get("large.bin", &mut reader).unwrap(), data);
+            assert_eq!(pak.get("small.bin", &mut reader).unwrap(), [1; 100]);
+        }
+    }
+}
+
+#[test]
+fn test_compression_threshold() {
+    use repak::Compression::{Zlib, Zstd, LZ4};
+
+    let noise = support::noise(100_000);
+    let zeros = vec![0; 100_000];
+    let text = include_bytes!("pack/root/test.txt");
+    let write = |allowed: &[repak::Compression], threshold: Option<f64>, data: &[u8]| {
+        let mut builder = repak::PakBuilder::new().compression(allowed.iter().copied());
+        if let Some(threshold) = threshold {
+            builder = builder.compression_threshold(threshold);
+        }
+        let mut writer = builder.writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+        let info = writer.write_file("file.bin", true, data).unwrap();
+
+        let mut bytes = Cursor::new(writer.write_index().unwrap().into_inner());
+        let pak = repak::PakBuilder::new().reader(&mut bytes).unwrap();
+        // the encoded offset is only known once the index is written
+        assert_eq!(
+            pak.entry_info("file.bin"),
+            Some(repak::EntryInfo {
+                encoded_offset: Some(0),
+                ..info
+            })
+        );
+        assert_eq!(pak.get("file.bin", &mut bytes).unwrap(), data);
+        info
+    };
+
+    // incompressible data stays uncompressed, while zeros shrink
+    let info = write(&[Zlib, Zstd], None, &noise);
+    assert_eq!(info.compression, None);
+    assert_eq!(info.compressed, noise.len() as u64);
+    let info = write(&[Zlib, Zstd], None, &zeros);
+    assert!(info.compression.is_some());
+    assert!(info.compressed < 1000);
+
+    // the smallest output of the allowed methods is picked, the earlier one on ties
+    let sizes = [Zlib, Zstd, LZ4].map(|c| write(&[c], None, text).compressed);
+    let smallest = write(&[Zlib, Zstd, LZ4], None, text);
+    assert_eq!(smallest.compressed, *sizes.iter().min().unwrap());
+    assert_eq!(write(&[Zlib, Zlib], None, text), write(&[Zlib], None, text));
+
+    // the threshold decides whether data that shrinks a little is worth compressing
+    let ratio = sizes[0] as f64 / text.len() as f64;
+    assert_eq!(write(&[Zlib], Some(ratio), text).compression, Some(Zlib));
+    assert_eq!(write(&[Zlib], Some(ratio - 0.01), text).compression, None);
+}
+
+#[test]
+fn test_raw_deflate_labelled_zlib() {
+    let data = support::pattern(10_000, 16);
+    let mut writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+    for path in ["a.bin", "b.bin"] {
+        writer.write_file(path, true, &data).unwrap();
+    }
+    let mut bytes = writer.write_index().unwrap().into_inner();
+
+    // strip the zlib header and checksum off each single block stream, padding it back to its
+    // size so the records stay valid
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    for path in ["a.bin", "b.bin"] {
+        let info = pak.entry_info(path).unwrap();
+        assert_eq!(info.compression, Some(repak::Compression::Zlib));
+        let end = pak.entry_range(path, &mut Cursor::new(&bytes)).unwrap().end as usize;
+        let stream = &mut bytes[end - info.compressed as usize..end];
+        let mut deflate = stream[2..stream.len() - 4].to_vec();
+        deflate.resize(stream.len(), 0);
+        stream.copy_from_slice(&deflate);
+    }
+
+    for threads in [1, 2] {
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new()
+            .decompression_threads(threads)
+            .reader(&mut reader)
+            .unwrap();
+        assert_eq!(pak.zlib_format(), None);
+        assert_eq!(pak.get("a.bin", &mut reader).unwrap(), data);
+        assert_eq!(pak.zlib_format(), Some(repak::ZlibFormat::RawDeflate));
+        // later entries use the detected format
+        let mut read = vec![];
+        pak.open_entry("b.bin", &mut reader)
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, data);
+    }
+
+    // standard paks keep using zlib
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11_compress.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    pak.get("test.png", &mut reader).unwrap();
+    assert_eq!(pak.zlib_format(), Some(repak::ZlibFormat::Zlib));
+}
+
+#[test]
+fn test_read_file_cancellable() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Sets the cancellation flag after the first write, like a host whose user pressed cancel
+    struct CancelAfterFirstWrite<'a> {
+        cancel: &'a AtomicBool,
+        written: Vec<usize>,
+    }
+    impl std::io::Write for CancelAfterFirstWrite<'_> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.push(buf.len());
+            self.cancel.store(true, Ordering::Relaxed);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let data = vec![7; 64 * 0x10000];
+    let mut writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+    writer.write_file("compressed.bin", true, &data).unwrap();
+    writer.write_file("stored.bin", false, &data).unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+
+    for threads in [1, 4] {
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new()
+            .decompression_threads(threads)
+            .reader(&mut reader)
+            .unwrap();
+        for path in ["compressed.bin", "stored.bin"] {
+            let cancel = AtomicBool::new(false);
+            let mut out = vec![];
+            pak.read_file_cancellable(path, &mut reader, &cancel, &mut out)
+                .unwrap();
+            assert_eq!(out, data);
+
+            let mut out = CancelAfterFirstWrite {
+                cancel: &cancel,
+                written: vec![],
+            };
+            cancel.store(false, Ordering::Relaxed);
+            let err = pak
+                .read_file_cancellable(path, &mut reader, &cancel, &mut out)
+                .unwrap_err();
+            assert!(matches!(err, repak::Error::Cancelled), "{err}");
+            assert_eq!(err.kind(), repak::ErrorKind::Other);
+            // stopped after the first block
+            assert_eq!(out.written.len(), 1, "{path} with {threads} threads");
+            assert!(out.written[0] < data.len() / 4);
+
+            // already cancelled before reading anything
+            let err = pak
+                .read_file_cancellable(path, &mut reader, &cancel, &mut vec![])
+                .unwrap_err();
+            assert!(matches!(err, repak::Error::Cancelled), "{err}");
+        }
+    }
+}
+
+#[test]
+fn test_verify_reads() {
+    let mut bytes = include_bytes!("packs/pack_v11_compress.pak").to_vec();
+    let pak = repak::PakBuilder::new()
+        .verify_reads(true)
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    assert_eq!(
+        pak.get("test.png", &mut Cursor::new(&bytes)).unwrap(),
+        include_bytes!("pack/root/test.png")
+    );
+
+    // flip a byte in the middle of the compressed data
+    let range = pak
+        .entry_range("test.png", &mut Cursor::new(&bytes))
+        .unwrap();
+    bytes[(range.end - (range.end - range.start) / 4) as usize] ^= 0xff;
+
+    let err = pak.get("test.png", &mut Cursor::new(&bytes)).unwrap_err();
+    match &err {
+        repak::Error::VerificationFailed {
+            path,
+            expected,
+            got,
+        } => {
+            assert_eq!(path, "test.png");
+            assert!(expected.starts_with("sha1 ") && got.starts_with("sha1 "));
+            assert_ne!(expected, got);
+        }
+        err => panic!("unexpected error {err}"),
+    }
+    assert_eq!(err.kind(), repak::ErrorKind::Format);
+    // other entries are unaffected
+    pak.get("test.txt", &mut Cursor::new(&bytes)).unwrap();
+
+    // without verification the corruption is only noticed if decompression fails
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    assert!(!matches!(
+        pak.get("test.png", &mut Cursor::new(&bytes)),
+        Err(repak::Error::VerificationFailed { .. })
+    ));
+}
+
+#[test]
+fn test_verify_reads_stored() {
+    let mut bytes = include_bytes!("packs/pack_v11.pak").to_vec();
+    let pak = repak::PakBuilder::new()
+        .verify_reads(true)
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+
+    // flip the last byte of the uncompressed data
+    let range = pak
+        .entry_range("test.txt", &mut Cursor::new(&bytes))
+        .unwrap();
+    bytes[range.end as usize - 1] ^= 0xff;
+
+    let mut out = vec![];
+    let err = pak
+        .read_file("test.txt", &mut Cursor::new(&bytes), &mut out)
+        .unwrap_err();
+    assert!(
+        matches!(&err, repak::Error::VerificationFailed { path, .. } if path == "test.txt"),
+        "{err}"
+    );
+
+    // without verification the corrupt data is returned as is
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    let mut out = vec![];
+    pak.read_file("test.txt", &mut Cursor::new(&bytes), &mut out)
+        .unwrap();
+    assert_ne!(out, include_bytes!("pack/root/test.txt"));
+}
+
+#[test]
+fn test_lossy_paths() {
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|w| w == needle)
+            .unwrap()
+    };
+
+    for version in [repak::Version::V8B, repak::Version::V11] {
+        let mut pak_writer = repak::PakBuilder::new().writer(
+            Cursor::new(vec![]),
+            version,
+            "../mount/point/root/".to_owned(),
+            Some(0x205C5A7D),
+        );
+        pak_writer
+            .write_file("caf_.txt", false, b"latin-1")
+            .unwrap();
+        pak_writer.write_file("test.txt", false, b"valid").unwrap();
+        let mut bytes = pak_writer.write_index().unwrap().into_inner();
+        // a Latin-1 'é' is not valid UTF-8
+        let i = find(&bytes, b"caf_.txt");
+        bytes[i + 3] = 0xE9;
+
+        // the patched index no longer matches its hash
+        let builder = repak::PakBuilder::new().ignore_index_hashes(true);
+        let mut reader = Cursor::new(bytes.clone());
+        assert!(matches!(
+            builder.build_reader(&mut reader),
+            Err(repak::Error::InvalidName(name)) if name == "caf\u{FFFD}.txt"
+        ));
+
+        let pak_reader = builder.lossy_paths(true).reader(&mut reader).unwrap();
+        assert_eq!(pak_reader.files(), ["caf\u{FFFD}.txt", "test.txt"]);
+        assert!(pak_reader.is_lossy_path("caf\u{FFFD}.txt"));
+        assert!(pak_reader.is_lossy_path("../mount/point/root/caf\u{FFFD}.txt"));
+        assert!(!pak_reader.is_lossy_path("test.txt"));
+        assert_eq!(
+            pak_reader.get("caf\u{FFFD}.txt", &mut reader).unwrap(),
+            b"latin-1"
+        );
+
+        let mut pak_writer = pak_reader.into_pakwriter(reader).unwrap();
+        pak_writer.write_file("new.txt", false, b"new").unwrap();
+        let rewrite = pak_writer.write_index().unwrap().into_inner();
+
+        // the original bytes are written back rather than the converted name, which would be
+        // valid UTF-16
+        let mut reader = Cursor::new(rewrite);
+        assert!(matches!(
+            repak::PakBuilder::new().reader(&mut reader),
+            Err(repak::Error::InvalidName(_))
+        ));
+        let pak_reader = repak::PakBuilder::new()
+            .lossy_paths(true)
+            .reader(&mut reader)
+            .unwrap();
+        assert_eq!(
+            pak_reader.files(),
+            ["caf\u{FFFD}.txt", "new.txt", "test.txt"]
+        );
+        assert_eq!(
+            pak_reader.get("caf\u{FFFD}.txt", &mut reader).unwrap(),
+            b"latin-1"
+        );
+    }
+}
+
+#[test]
+fn test_parse_ranges() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let builder = || {
+        let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+        repak::PakBuilder::new().key(aes::Aes256::new_from_slice(&key).unwrap())
+    };
+    let slice = |bytes: &[u8], range: std::ops::Range<u64>| -> Vec<u8> {
+        bytes[range.start as usize..range.end as usize].to_vec()
+    };
+
+    for bytes in [
+        &include_bytes!("packs/pack_v5.pak")[..],
+        include_bytes!("packs/pack_v5_compress_encrypt_encryptindex.pak"),
+        include_bytes!("packs/pack_v5_encryptindex.pak"),
+        include_bytes!("packs/pack_v7.pak"),
+        include_bytes!("packs/pack_v7_compress_encrypt_encryptindex.pak"),
+        include_bytes!("packs/pack_v7_encryptindex.pak"),
+        include_bytes!("packs/pack_v8a.pak"),
+        include_bytes!("packs/pack_v8a_compress_encrypt_encryptindex.pak"),
+        include_bytes!("packs/pack_v8a_encryptindex.pak"),
+        include_bytes!("packs/pack_v8b.pak"),
+        include_bytes!("packs/pack_v8b_compress_encrypt_encryptindex.pak"),
+        include_bytes!("packs/pack_v8b_encryptindex.pak"),
+        include_bytes!("packs/pack_v9.pak"),
+        include_bytes!("packs/pack_v9_compress_encrypt_encryptindex.pak"),
+        include_bytes!("packs/pack_v9_encryptindex.pak"),
+        include_bytes!("packs/pack_v11.pak"),
+        include_bytes!("packs/pack_v11_compress_encrypt_encryptindex.pak"),
+        include_bytes!("packs/pack_v11_encryptindex.pak"),
+    ] {
+        let streamed = builder().reader(&mut Cursor::new(bytes)).unwrap();
+
+        // fetch only the ranges reported as needed
+        let footer = builder()
+            .parse_footer(&slice(bytes, repak::Footer::range(bytes.len() as u64)))
+            .unwrap();
+        assert_eq!(footer.version(), streamed.version());
+        let index = slice(bytes, footer.index_range());
+        let secondary = builder().secondary_index_ranges(&footer, &index).unwrap();
+        assert_eq!(
+            secondary.full_directory_index.is_some(),
+            footer.version() >= repak::Version::V10
+        );
+        let path_hash_index = secondary.path_hash_index.map(|r| slice(bytes, r));
+        let full_directory_index = secondary.full_directory_index.map(|r| slice(bytes, r));
+        let parsed = builder()
+            .parse_index(
+                &footer,
+                &index,
+                path_hash_index.as_deref(),
+                full_directory_index.as_deref(),
+            )
+            .unwrap();
+
+        assert_eq!(parsed.version(), streamed.version());
+        assert_eq!(parsed.mount_point(), streamed.mount_point());
+        assert_eq!(parsed.encrypted_index(), streamed.encrypted_index());
+        assert_eq!(parsed.encryption_guid(), streamed.encryption_guid());
+        assert_eq!(parsed.path_hash_seed(), streamed.path_hash_seed());
+        assert_eq!(parsed.files(), streamed.files());
+        for file in parsed.files() {
+            assert_eq!(parsed.entry_info(&file), streamed.entry_info(&file));
+            assert_eq!(
+                parsed.get(&file, &mut Cursor::new(bytes)).unwrap(),
+                streamed.get(&file, &mut Cursor::new(bytes)).unwrap()
+            );
+        }
+
+        // the path hash index isn't needed to list the entries
+        let parsed = builder()
+            .parse_index(&footer, &index, None, full_directory_index.as_deref())
+            .unwrap();
+        assert_eq!(parsed.files(), streamed.files());
+    }
+
+    // the footer alone is enough to tell when there is no pak
+    assert!(matches!(
+        repak::PakBuilder::new().parse_footer(&[0; 300]),
+        Err(repak::Error::UnsupportedOrEncrypted(_))
+    ));
+    assert!(repak::Footer::parse(&[0; 10], repak::Version::V11).is_err());
+}
+
+#[test]
+fn test_unused_compression_has_no_slot() {
+    for version in [
+        repak::Version::V8A,
+        repak::Version::V8B,
+        repak::Version::V9,
+        repak::Version::V11,
+    ] {
+        let write = |compression: &[repak::Compression]| {
+            let mut writer = repak::PakBuilder::new()
+                .compression(compression.iter().copied())
+                .writer(Cursor::new(vec![]), version, "../../../".to_owned(), None);
+            for (i, path) in ["a.bin", "dir/b.bin"].into_iter().enumerate() {
+                let info = writer
+                    .write_file(path, true, support::noise(10_000 + i))
+                    .unwrap();
+                assert_eq!(info.compression, None);
+            }
+            writer.write_index().unwrap().into_inner()
+        };
+
+        // slots are only taken by methods an entry is compressed with
+        let bytes = write(&[repak::Compression::Zstd, repak::Compression::Zlib]);
+        assert_eq!(bytes, write(&[]), "{version}");
+        let footer_range = repak::Footer::range(bytes.len() as u64);
+        let footer = repak::PakBuilder::new()
+            .parse_footer(&bytes[footer_range.start as usize..])
+            .unwrap();
+        assert!(
+            footer
+                .raw_compression_slots()
+                .iter()
+                .all(|slot| *slot == [0; 32]),
+            "{version}"
+        );
+    }
+}
+
+#[test]
+fn test_encrypted_mount_point() {
+    use aes::cipher::{BlockEncrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    // 15 characters and the terminator fill a single AES block
+    let mount_point = "../../../Games/";
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        mount_point.to_owned(),
+        None,
+    );
+    writer.write_file("dir/a.txt", false, "a").unwrap();
+    writer.write_file("b.txt", false, "b").unwrap();
+    let mut bytes = writer.write_index().unwrap().into_inner();
+
+    // encrypt the characters of the mount point in the otherwise plaintext index
+    let footer_range = repak::Footer::range(bytes.len() as u64);
+    let footer = repak::PakBuilder::new()
+        .parse_footer(&bytes[footer_range.start as usize..])
+        .unwrap();
+    let chars = footer.index_range().start as usize + 4..;
+    let chars = chars.start..chars.start + 16;
+    assert_eq!(&bytes[chars.clone()], format!("{mount_point}\0").as_bytes());
+    key.encrypt_block(aes::Block::from_mut_slice(&mut bytes[chars.clone()]));
+    let encrypted = bytes[chars].to_vec();
+
+    let check = |builder: repak::PakBuilder| {
+        let mut reader = Cursor::new(&bytes);
+        // the index no longer matches its hash in the footer
+        let pak = builder
+            .ignore_index_hashes(true)
+            .reader(&mut reader)
+            .unwrap();
+        assert_eq!(pak.mount_point_raw(), encrypted);
+        assert_eq!(pak.files(), ["b.txt", "dir/a.txt"]);
+        assert_eq!(pak.get("dir/a.txt", &mut reader).unwrap(), b"a");
+        pak
+    };
+
+    let pak = check(repak::PakBuilder::new().key(key));
+    assert_eq!(pak.mount_point(), mount_point);
+    assert_eq!(pak.warnings(), [repak::IndexWarning::EncryptedMountPoint]);
+
+    let pak = check(repak::PakBuilder::new());
+    assert_eq!(pak.mount_point(), repak::UNREADABLE_MOUNT_POINT);
+    assert_eq!(pak.warnings(), [repak::IndexWarning::UnreadableMountPoint]);
+
+    // rewrites keep the mount point as stored
+    let rewrite = pak
+        .into_pakwriter(Cursor::new(bytes.clone()))
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&rewrite))
+        .unwrap();
+    assert_eq!(pak.mount_point_raw(), encrypted);
+}
+
+#[cfg(feature = "oodle")]
+#[test]
+fn test_oodle_blocks() {
+    // spanning several compression blocks, each an Oodle stream of its own
+    let data = support::pattern(400_000, 16);
+    let mut writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Oodle])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+    let info = writer.write_file("large.bin", true, &data).unwrap();
+    assert_eq!(info.compression, Some(repak::Compression::Oodle));
+    let bytes = writer.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&bytes);
+    for threads in [1, 4] {
+        let pak = repak::PakBuilder::new()
+            .decompression_threads(threads)
+            .reader(&mut reader)
+            .unwrap();
+        assert_eq!(pak.get("large.bin", &mut reader).unwrap(), data);
+    }
+}
+
+#[test]
+fn test_engine_style_paths() {
+    use repak::PathForm::*;
+
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(pak.mount_point(), "../mount/point/root/");
+    for (path, resolved, form) in [
+        ("test.txt", "test.txt", Relative),
+        ("directory//nested.txt", "directory/nested.txt", Relative),
+        (
+            "../mount/point/root/test.txt",
+            "test.txt",
+            MountPointPrefixed,
+        ),
+        (
+            "..\\mount\\point\\root\\directory\\nested.txt",
+            "directory/nested.txt",
+            MountPointPrefixed,
+        ),
+        (
+            "../../../mount/point/root/test.txt",
+            "test.txt",
+            ParentsStripped,
+        ),
+        (
+            "../../directory/nested.txt",
+            "directory/nested.txt",
+            ParentsStripped,
+        ),
+    ] {
+        assert_eq!(pak.resolve_path(path), Some((resolved, form)), "{path}");
+        assert_eq!(
+            pak.get(path, &mut reader).unwrap(),
+            pak.get(resolved, &mut reader).unwrap()
+        );
+        assert_eq!(pak.entry_info(path), pak.entry_info(resolved), "{path}");
+        assert!(pak.entry_info(path).is_some(), "{path}");
+    }
+    assert_eq!(pak.resolve_path("../mount/point/test.txt"), None);
+    assert_eq!(pak.entry_info("../mount/point/test.txt"), None);
+
+    let pak = repak::PakBuilder::new()
+        .keep_encoded_index(true)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(
+        pak.encoded_entry_bytes("../mount/point/root/test.txt"),
+        pak.encoded_entry_bytes("test.txt")
+    );
+    assert!(pak.encoded_entry_bytes("test.txt").is_some());
+    assert!(!pak.is_lossy_path("../mount/point/root/test.txt"));
+
+    // missing files are still missing, hinting at the path likely meant
+    for (path, nearest) in [
+        ("../mount/point/root/nested.txt", "directory/nested.txt"),
+        ("directory/missing.txt", "directory/nested.txt"),
+    ] {
+        match pak.get(path, &mut reader) {
+            Err(err @ repak::Error::MissingEntryNearest { .. }) => assert_eq!(
+                err.to_string(),
+                format!("No entry found at {path}, did you mean {nearest}?")
+            ),
+            result => panic!("{path}: {result:?}"),
+        }
+    }
+    assert!(matches!(
+        pak.get("../mount/point/root/missing.txt", &mut reader),
+        Err(repak::Error::MissingEntry(_))
+    ));
+}
+
+#[test]
+fn test_list_dir() {
+    for bytes in [
+        &include_bytes!("packs/pack_v11.pak")[..],
+        include_bytes!("packs/pack_v5.pak"),
+    ] {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        for root in ["", "/"] {
+            let listing = pak.list_dir(root).unwrap();
+            assert_eq!(listing.directories().collect::<Vec<_>>(), ["directory"]);
+            let files = listing.files().collect::<Vec<_>>();
+            assert_eq!(
+                files.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+                ["test.png", "test.txt", "zeros.bin"]
+            );
+            for (name, info) in files {
+                assert_eq!(Some(info), pak.entry_info(name));
+            }
+        }
+        for directory in ["directory", "/directory/", "directory\\"] {
+            let listing = pak.list_dir(directory).unwrap();
+            assert_eq!(listing.directories().len(), 0);
+            assert_eq!(
+                listing.file_paths().collect::<Vec<_>>(),
+                ["directory/nested.txt"]
+            );
+            let [(name, info)] = listing.files().collect::<Vec<_>>()[..] else {
+                panic!("{directory}");
+            };
+            assert_eq!(name, "nested.txt");
+            assert_eq!(info.uncompressed, 596);
+            assert!(pak.dir_exists(directory));
+        }
+        for missing in ["missing", "direct", "test.txt", "directory/nested.txt"] {
+            assert!(pak.list_dir(missing).is_none(), "{missing}");
+            assert!(!pak.dir_exists(missing));
+        }
+    }
+}
+
+#[test]
+fn test_entry_extents() {
+    use aes::cipher::{BlockDecrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    for (name, bytes) in [
+        (
+            "v5",
+            &include_bytes!("packs/pack_v5_compress_encrypt.pak")[..],
+        ),
+        ("v7", include_bytes!("packs/pack_v7_encrypt.pak")),
+        ("v8b", include_bytes!("packs/pack_v8b_compress.pak")),
+        ("v11", include_bytes!("packs/pack_v11_compress_encrypt.pak")),
+        ("v11 stored", include_bytes!("packs/pack_v11.pak")),
+    ] {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new()
+            .key(key.clone())
+            .reader(&mut reader)
+            .unwrap();
+        let all = pak
+            .all_extents(&mut reader)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(all.len(), pak.len(), "{name}");
+        for pair in all.windows(2) {
+            assert!(pair[0].1.span.end <= pair[1].1.span.start, "{name}");
+        }
+        for (path, extents) in all {
+            assert_eq!(pak.entry_extents(path, &mut reader).unwrap(), extents);
+            assert_eq!(pak.entry_range(path, &mut reader).unwrap(), extents.span);
+            assert_eq!(extents.header.start, extents.span.start);
+            assert_eq!(extents.blocks[0].start, extents.header.end, "{name} {path}");
+            assert_eq!(extents.blocks.last().unwrap().end, extents.span.end);
+
+            // the data is exactly the blocks, which decode on their own
+            let info = pak.entry_info(path).unwrap();
+            let mut data = vec![];
+            for block in &extents.blocks {
+                let mut raw = bytes[block.start as usize..block.end as usize].to_vec();
+                if info.encrypted {
+                    for chunk in raw.chunks_exact_mut(16) {
+                        key.decrypt_block(aes::Block::from_mut_slice(chunk));
+                    }
+                }
+                match info.compression {
+                    // the decoder stops at the end of the stream, before any padding
+                    Some(repak::Compression::Zlib) => {
+                        flate2::read::ZlibDecoder::new(&raw[..])
+                            .read_to_end(&mut data)
+                            .unwrap();
+                    }
+                    None => data.extend_from_slice(&raw),
+                    compression => panic!("{compression:?}"),
+                }
+            }
+            data.truncate(info.uncompressed as usize);
+            assert_eq!(data, pak.get(path, &mut reader).unwrap(), "{name} {path}");
+        }
+    }
+}
+
+#[test]
+fn test_encryption_guid_needs_encrypted_index() {
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    writer.write_file("a.txt", false, b"a").unwrap();
+    let mut bytes = writer.write_index().unwrap().into_inner();
+    // the GUID is the first field of the footer and not written without an encrypted index
+    let guid = bytes.len() - repak::Version::V11.size() as usize..;
+    let guid = guid.start..guid.start + 16;
+    assert_eq!(bytes[guid.clone()], [0; 16]);
+
+    // a GUID without an encrypted index is dropped when the index is rewritten
+    bytes[guid].copy_from_slice(&0x1234u128.to_le_bytes());
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(pak.encryption_guid(), Some(0x1234));
+    let rewritten = pak
+        .into_pakwriter(reader)
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&rewritten))
+        .unwrap();
+    assert_eq!(pak.encryption_guid(), Some(0));
+}
+
+#[test]
+fn test_parallel_index_parse() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    let entries = |pak: &repak::PakReader| {
+        pak.files()
+            .into_iter()
+            .map(|path| {
+                let info = pak.entry_info(&path).unwrap();
+                (path, info)
+            })
+            .collect::<Vec<_>>()
+    };
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/packs");
+    let paks = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|file| {
+            let path = file.unwrap().path();
+            (path.display().to_string(), std::fs::read(&path).unwrap())
+        })
+        // large enough to be decoded on several threads
+        .chain([(
+            "synthetic".to_owned(),
+            support::synthetic_pak(repak::Version::V11, 20_000),
+        )]);
+    for (name, bytes) in paks {
+        let builder = || repak::PakBuilder::new().key(key.clone());
+        let serial = builder().reader(&mut Cursor::new(&bytes)).unwrap();
+        let parallel = builder()
+            .parallel_index_parse(true)
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap();
+        assert_eq!(parallel.version(), serial.version(), "{name}");
+        assert_eq!(entries(&parallel), entries(&serial), "{name}");
+        assert_eq!(parallel.warnings(), serial.warnings(), "{name}");
+    }
+}
+
+#[test]
+fn test_strict() {
+    let original = include_bytes!("packs/pack_v11_compress.pak");
+    let mut bytes = original.to_vec();
+    let slots_start = bytes.len() - 5 * 32;
+    bytes[slots_start..slots_start + 5].copy_from_slice(b"Zlib2");
+
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    assert_eq!(pak.warnings().len(), 1);
+    let warning = pak.warnings()[0].clone();
+
+    for metadata_only in [false, true] {
+        let err = repak::PakBuilder::new()
+            .strict(true)
+            .metadata_only(metadata_only)
+            .reader(&mut Cursor::new(&bytes))
+            .unwrap_err();
+        assert!(
+            matches!(&err, repak::Error::StrictWarning(w) if *w == warning),
+            "{err}"
+        );
+        assert_eq!(err.kind(), repak::ErrorKind::Format);
+    }
+    let err = repak::PakBuilder::new()
+        .strict(true)
+        .reader_with_version(&mut Cursor::new(&bytes), repak::Version::V11)
+        .unwrap_err();
+    assert!(matches!(err, repak::Error::StrictWarning(_)), "{err}");
+
+    // paks without problems read the same
+    let pak = repak::PakBuilder::new()
+        .strict(true)
+        .reader(&mut Cursor::new(original))
+        .unwrap();
+    assert!(pak.warnings().is_empty());
+}
+
+#[test]
+fn test_mod_metadata() {
+    let metadata = repak::ModMetadata {
+        name: Some("Better Lighting".to_owned()),
+        version: Some("1.2.0".to_owned()),
+        author: Some("someone".to_owned()),
+        description: None,
+        dependencies: vec!["Core Mod >= 2".to_owned()],
+        ..Default::default()
+    };
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    writer.write_file("a.txt", false, b"a").unwrap();
+    writer.write_mod_metadata(&metadata).unwrap();
+    writer
+        .write_mod_metadata_at("elsewhere/mod.json", &metadata)
+        .unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(
+        pak.mod_metadata(&mut reader).unwrap().as_ref(),
+        Some(&metadata)
+    );
+    assert_eq!(
+        pak.mod_metadata_at("elsewhere/mod.json", &mut reader)
+            .unwrap(),
+        Some(metadata)
+    );
+    let json = String::from_utf8(pak.get(repak::MOD_METADATA_PATH, &mut reader).unwrap()).unwrap();
+    assert!(json.contains("\"format\": 1"), "{json}");
+    assert!(!json.contains("description"), "{json}");
+
+    // paks without metadata
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert_eq!(pak.mod_metadata(&mut reader).unwrap(), None);
+
+    // newer formats and unknown fields read as far as they are understood
+    let newer = br#"{"format": 7, "name": "x", "icon": {"path": "icon.png"}}"#;
+    let parsed = repak::ModMetadata::from_json(repak::MOD_METADATA_PATH, newer).unwrap();
+    assert_eq!(parsed.format, 7);
+    assert_eq!(parsed.name.as_deref(), Some("x"));
+    assert_eq!(parsed.dependencies, Vec::<String>::new());
+
+    let err =
+        repak::ModMetadata::from_json(repak::MOD_METADATA_PATH, b"{\"name\": 1}").unwrap_err();
+    assert!(
+        matches!(&err, repak::Error::InvalidModMetadata { path, .. } if path == repak::MOD_METADATA_PATH),
+        "{err}"
+    );
+    assert_eq!(err.kind(), repak::ErrorKind::Format);
+}
+
+#[test]
+fn test_probe_blocks() {
+    let data = support::pattern(400_000, 7);
+    let mut writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V8B,
+            "../../../".to_owned(),
+            None,
+        );
+    writer.write_file("large.bin", true, &data).unwrap();
+    writer.write_file("stored.txt", false, b"stored").unwrap();
+    let mut bytes = writer.write_index().unwrap().into_inner();
+
+    let block_size = 0x3e << 11;
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let blocks = pak.probe_blocks("large.bin", &mut reader).unwrap();
+    let extents = pak.entry_extents("large.bin", &mut reader).unwrap();
+    assert_eq!(blocks.len(), 4);
+    for (i, block) in blocks.iter().enumerate() {
+        let expected = match i {
+            3 => 400_000 - 3 * block_size,
+            _ => block_size,
+        };
+        assert_eq!(block.expected, expected);
+        assert_eq!(block.uncompressed, Ok(expected));
+        assert!(block.is_ok());
+        assert_eq!(block.range, extents.blocks[i]);
+        assert_eq!(block.compressed, block.range.end - block.range.start);
+    }
+    assert_eq!(
+        blocks.iter().map(|b| b.compressed).sum::<u64>(),
+        pak.entry_info("large.bin").unwrap().compressed
+    );
+    assert_eq!(
+        pak.probe_blocks("stored.txt", &mut reader).unwrap(),
+        [repak::BlockProbe {
+            range: extents.span.end + 53..extents.span.end + 59,
+            compressed: 6,
+            expected: 6,
+            uncompressed: Ok(6),
+        }]
+    );
+
+    // a compression block size the blocks weren't written with, as by a buggy writer, in both
+    // the index and the record before the data
+    let stored = (block_size as u32).to_le_bytes();
+    let positions = (0..bytes.len() - 4)
+        .filter(|&i| bytes[i..i + 4] == stored)
+        .collect::<Vec<_>>();
+    assert_eq!(positions.len(), 2);
+    for i in positions {
+        bytes[i..i + 4].copy_from_slice(&0x20000u32.to_le_bytes());
+    }
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new()
+        .ignore_index_hashes(true)
+        .reader(&mut reader)
+        .unwrap();
+    // reading the whole entry concatenates the blocks, seeking within it relies on their size
+    assert_eq!(pak.get("large.bin", &mut reader).unwrap(), data);
+    let mut entry = pak.open_entry("large.bin", &mut reader).unwrap();
+    assert!(entry.read_to_end(&mut vec![]).is_err());
+    let blocks = pak.probe_blocks("large.bin", &mut reader).unwrap();
+    assert_eq!(
+        blocks
+            .iter()
+            .map(|b| (b.expected, b.uncompressed.clone()))
+            .collect::<Vec<_>>(),
+        [
+            (0x20000, Ok(block_size)),
+            (0x20000, Ok(block_size)),
+            (0x20000, Ok(block_size)),
+            (400_000 - 3 * 0x20000, Ok(400_000 - 3 * block_size)),
+        ]
+    );
+    assert!(blocks.iter().all(|b| !b.is_ok()));
+}
+
+#[test]
+fn test_builder_reuse() {
+    use aes::cipher::KeyInit;
+    use base64::{engine::general_purpose, Engine as _};
+    let key = general_purpose::STANDARD.decode(AES_KEY).unwrap();
+    let key = aes::Aes256::new_from_slice(&key).unwrap();
+
+    let builder = repak::PakBuilder::new().key(key);
+    let cloned = builder.clone();
+    for (builder, bytes) in [
+        (
+            &builder,
+            &include_bytes!("packs/pack_v11_compress_encrypt_encryptindex.pak")[..],
+        ),
+        (
+            &builder,
+            &include_bytes!("packs/pack_v8b_encrypt_encryptindex.pak")[..],
+        ),
+        (
+            &cloned,
+            &include_bytes!("packs/pack_v5_compress_encrypt.pak")[..],
+        ),
+    ] {
+        let mut reader = Cursor::new(bytes);
+        let pak = builder.build_reader(&mut reader).unwrap();
+        assert_eq!(
+            pak.get("test.txt", &mut reader).unwrap(),
+            include_bytes!("pack/root/test.txt")
+        );
+    }
+
+    // the builder is untouched by building, and the consuming methods still work
+    let mut writer = builder.build_writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    writer.write_file("a.txt", false, b"a").unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+    let mut reader = Cursor::new(&bytes);
+    let pak = builder.reader(&mut reader).unwrap();
+    assert_eq!(pak.get("a.txt", &mut reader).unwrap(), b"a");
+
+    // options set on a clone don't affect the original
+    let metadata_only = cloned.clone().metadata_only(true);
+    assert!(metadata_only
+        .build_reader(&mut Cursor::new(&bytes))
+        .unwrap()
+        .files()
+        .is_empty());
+    assert_eq!(
+        cloned
+            .build_reader(&mut Cursor::new(&bytes))
+            .unwrap()
+            .files(),
+        ["a.txt"]
+    );
+}
+
+#[test]
+fn test_index_hashes() {
+    let original = include_bytes!("packs/pack_v11.pak");
+    let builder = repak::PakBuilder::new();
+    let footer = builder.parse_footer(original).unwrap();
+    let index = footer.index_range();
+    let secondary = builder
+        .secondary_index_ranges(&footer, &original[index.start as usize..index.end as usize])
+        .unwrap();
+    let fdi = secondary.full_directory_index.unwrap();
+    let phi = secondary.path_hash_index.unwrap();
+
+    let read = |at: u64, builder: repak::PakBuilder| {
+        let mut bytes = original.to_vec();
+        bytes[at as usize] ^= 1;
+        builder.reader(&mut Cursor::new(bytes))
+    };
+    // the first character of the first directory name, after the directory count and length
+    for (at, section) in [
+        (fdi.start + 8, repak::IndexSection::FullDirectoryIndex),
+        (phi.start + 4, repak::IndexSection::PathHashIndex),
+        (index.end - 1, repak::IndexSection::Primary),
+    ] {
+        let err = read(at, repak::PakBuilder::new()).unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                repak::Error::IndexHashMismatch { section: s, expected, actual }
+                    if *s == section && expected != actual && expected.len() == 40
+            ),
+            "{err}"
+        );
+        assert_eq!(err.kind(), repak::ErrorKind::Format);
+    }
+
+    // "/" became "." but the index still parses
+    let pak = read(
+        fdi.start + 8,
+        repak::PakBuilder::new().ignore_index_hashes(true),
+    )
+    .unwrap();
+    assert_eq!(
+        pak.files(),
+        [
+            ".test.png",
+            ".test.txt",
+            ".zeros.bin",
+            "directory/nested.txt"
+        ]
+    );
+    let pak = read(
+        phi.start + 4,
+        repak::PakBuilder::new().ignore_index_hashes(true),
+    )
+    .unwrap();
+    assert_eq!(pak.files().len(), 4);
+    assert!(!pak.check_path_hash_index().unwrap().is_consistent());
+
+    // the count of entries which are not encoded ending the primary index is no longer 0
+    let mut bytes = original.to_vec();
+    bytes[index.end as usize - 1] ^= 1;
+    let builder = repak::PakBuilder::new().ignore_index_hashes(true);
+    assert!(builder.build_reader(&mut Cursor::new(&bytes)).is_err());
+    let err = builder
+        .clone()
+        .reader_with_version(&mut Cursor::new(&bytes), repak::Version::V11)
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::UnencodedEntries(0x01000000)),
+        "{err}"
+    );
+    let index = &bytes[index.start as usize..index.end as usize];
+    let err = builder
+        .parse_index(
+            &footer,
+            index,
+            Some(&original[phi.start as usize..phi.end as usize]),
+            Some(&original[fdi.start as usize..fdi.end as usize]),
+        )
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::UnencodedEntries(0x01000000)),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_read_all_sequential() {
+    let bytes = support::large_entry_pak(
+        Some(repak::Compression::Zlib),
+        &support::pattern(0x30000, 3),
+    );
+    let mut pak_writer = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap()
+        .into_pakwriter(Cursor::new(bytes))
+        .unwrap();
+    // appended in reverse path order so the order of the data differs from path order
+    for i in (0..8).rev() {
+        pak_writer
+            .write_file(
+                &support::entry_path(i),
+                false,
+                support::pattern(100 + i as u32, 1),
+            )
+            .unwrap();
+    }
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let mut read = vec![];
+    pak.read_all_sequential(&mut reader, |path, data| {
+        read.push((path.to_owned(), data.to_vec()));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        read.iter()
+            .map(|(path, _)| path.as_str())
+            .collect::<Vec<_>>(),
+        pak.entries_by_offset()
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_ne!(
+        read.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+        pak.files().iter().collect::<Vec<_>>()
+    );
+    for (path, data) in &read {
+        assert_eq!(*data, pak.get(path, &mut reader).unwrap(), "{path}");
+    }
+    assert_eq!(read.len(), 9);
+
+    // the first error stops the pass
+    let mut calls = 0;
+    let err = pak.read_all_sequential(&mut reader, |_, _| {
+        calls += 1;
+        Err(repak::Error::Other("stop".to_owned()))
+    });
+    assert!(matches!(err, Err(repak::Error::Other(_))));
+    assert_eq!(calls, 1);
+
+    let pak = repak::PakBuilder::new()
+        .metadata_only(true)
+        .reader(&mut reader)
+        .unwrap();
+    assert!(matches!(
+        pak.read_all_sequential(&mut reader, |_, _| Ok(())),
+        Err(repak::Error::IndexNotLoaded)
+    ));
+}
+
+#[test]
+fn test_shuffled_compression_slots() {
+    let zlib = support::pattern(0x4000, 2);
+    let zstd = support::pattern(0x4000, 5);
+    let lz4 = support::pattern(0x4000, 7);
+    for (version, original, slots) in [
+        (
+            repak::Version::V8A,
+            &include_bytes!("packs/pack_v8a_compress.pak")[..],
+            4,
+        ),
+        (
+            repak::Version::V8B,
+            include_bytes!("packs/pack_v8b_compress.pak"),
+            5,
+        ),
+        (
+            repak::Version::V11,
+            include_bytes!("packs/pack_v11_compress.pak"),
+            5,
+        ),
+    ] {
+        // entries use slot 0, which names Zlib, followed by names in an order UnrealPak doesn't
+        // write them in
+        let slots_start = original.len() - slots * 32;
+        let with_names = |names: [&str; 5]| {
+            let mut bytes = original.to_vec();
+            for (slot, name) in bytes[slots_start..].chunks_mut(32).zip(names) {
+                slot.fill(0);
+                slot[..name.len()].copy_from_slice(name.as_bytes());
+            }
+            bytes
+        };
+        let bytes = with_names(["Zlib", "Zlib2", "Zstd", "", "Gzip"]);
+        let mut reader = Cursor::new(bytes.clone());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(
+            pak.get("test.png", &mut reader).unwrap(),
+            include_bytes!("pack/root/test.png"),
+            "{version}"
+        );
+
+        // new entries use the slot already naming their method or the first empty one, not the
+        // slot of the unknown name, and the other slots are written back as they were
+        let mut writer = pak.into_pakwriter(Cursor::new(bytes.clone())).unwrap();
+        for (path, compression, data) in [
+            ("zlib.bin", repak::Compression::Zlib, &zlib),
+            ("zstd.bin", repak::Compression::Zstd, &zstd),
+            ("lz4.bin", repak::Compression::LZ4, &lz4),
+        ] {
+            writer
+                .write_entry(
+                    path.to_owned(),
+                    repak::PartialEntry::new(Some(compression), data).unwrap(),
+                )
+                .unwrap();
+        }
+        let rewritten = writer.write_index().unwrap().into_inner();
+        assert_eq!(
+            rewritten[rewritten.len() - slots * 32..],
+            with_names(["Zlib", "Zlib2", "Zstd", "LZ4", "Gzip"])[slots_start..],
+            "{version}"
+        );
+        let mut reader = Cursor::new(&rewritten);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        for (path, data) in [("zlib.bin", &zlib), ("zstd.bin", &zstd), ("lz4.bin", &lz4)] {
+            assert_eq!(
+                pak.get(path, &mut reader).unwrap(),
+                *data,
+                "{version} {path}"
+            );
+        }
+        assert_eq!(
+            pak.get("test.png", &mut reader).unwrap(),
+            include_bytes!("pack/root/test.png")
+        );
+
+        // slot 0 naming another method changes how the entries using it are read
+        let mut bytes = bytes.clone();
+        bytes[slots_start..slots_start + 4].copy_from_slice(b"Zstd");
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(
+            pak.entry_info("test.png").unwrap().compression,
+            Some(repak::Compression::Zstd)
+        );
+        assert!(pak.get("test.png", &mut reader).is_err());
+    }
+}
+
+#[test]
+fn test_implausible_block_count() {
+    let data = support::pattern(0x10_0123, 3);
+    let mut bytes = support::large_entry_pak(Some(repak::Compression::Zlib), &data);
+    // the encoded entry in the index is the last place the size appears
+    let size = (data.len() as u32).to_le_bytes();
+    let at = bytes.windows(4).rposition(|w| w == size).unwrap();
+    bytes[at..at + 4].copy_from_slice(&1u32.to_le_bytes());
+
+    let builder = repak::PakBuilder::new().ignore_index_hashes(true);
+    let err = builder.build_reader(&mut Cursor::new(&bytes)).unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            repak::Error::ImplausibleBlockCount { path: Some(path), count: 9, uncompressed: 1 }
+                if path == "large.bin"
+        ),
+        "{err}"
+    );
+
+    let builder = builder.skip_implausible_entries(true);
+    let pak = builder.clone().reader(&mut Cursor::new(&bytes)).unwrap();
+    assert!(pak.files().is_empty());
+    assert!(
+        matches!(
+            pak.warnings(),
+            [repak::IndexWarning::ImplausibleBlockCount { path, count: 9, uncompressed: 1 }]
+                if path == "large.bin"
+        ),
+        "{:?}",
+        pak.warnings()
+    );
+    let err = builder
+        .strict(true)
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(matches!(err, repak::Error::StrictWarning(_)), "{err}");
+}
+
+#[test]
+fn test_streaming_writer() {
+    fn write_files<W: Write + Seek>(pak: &mut repak::PakWriter<W>) {
+        pak.write_file("a.txt", true, support::pattern(0x100, 0))
+            .unwrap();
+        pak.write_file("dir/b.bin", true, support::pattern(0x4_0123, 3))
+            .unwrap();
+        pak.write_file("dir/c.bin", true, support::noise(0x2000))
+            .unwrap();
+    }
+    let settings = [
+        (repak::Version::V11, Some(repak::Compression::Zlib)),
+        (repak::Version::V8B, Some(repak::Compression::Zlib)),
+        (repak::Version::V5, None),
+    ];
+    for (version, compression) in settings {
+        let builder = repak::PakBuilder::new().compression(compression);
+        let mut seekable = builder.build_writer(
+            Cursor::new(vec![]),
+            version,
+            "../../../".to_owned(),
+            Some(0x205C5A7D),
+        );
+        write_files(&mut seekable);
+        let expected = seekable.write_index().unwrap().into_inner();
+
+        // staged in memory, moved to a file part way through and from the first byte
+        for budget in [u64::MAX, 0x1000, 0] {
+            let streaming = || {
+                builder
+                    .clone()
+                    .streaming_memory_budget(budget)
+                    .writer_streaming(vec![], version, "../../../".to_owned(), Some(0x205C5A7D))
+            };
+            let mut pak = streaming();
+            write_files(&mut pak);
+            assert!(pak.write_index().unwrap() == expected, "{version} {budget}");
+
+            let mut pak = streaming();
+            write_files(&mut pak);
+            assert!(
+                pak.write_index_verified(usize::MAX).unwrap() == expected,
+                "{version} {budget}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_zero_compression_block_size() {
+    // three blocks of the size repak compresses with, so the derived size is the original
+    let data = support::pattern(3 * 0x1f000, 3);
+    for compression in [
+        repak::Compression::Zlib,
+        repak::Compression::Zstd,
+        repak::Compression::LZ4,
+    ] {
+        let mut bytes = support::large_entry_pak(Some(compression), &data);
+        // block size of the record preceding the data, after its three blocks and flags
+        let record = 48 + 4 + 3 * 16 + 1;
+        assert_eq!(bytes[record..record + 4], 0x1f000u32.to_le_bytes());
+        bytes[record..record + 4].fill(0);
+        // and of the encoded entry, from the flags before its offset and size
+        let size = (data.len() as u32).to_le_bytes();
+        let at = bytes.windows(4).rposition(|w| w == size).unwrap() - 8;
+        bytes[at] &= !0x3f;
+
+        for threads in [1, 4] {
+            let pak = repak::PakBuilder::new()
+                .ignore_index_hashes(true)
+                .verify_reads(true)
+                .decompression_threads(threads)
+                .reader(&mut Cursor::new(&bytes))
+                .unwrap();
+            assert_eq!(
+                pak.warnings(),
+                [repak::IndexWarning::DerivedBlockSize {
+                    path: "large.bin".to_owned(),
+                    stored: 0,
+                    derived: 0x1f000,
+                }],
+                "{compression}"
+            );
+            let mut reader = Cursor::new(&bytes);
+            assert!(pak.get("large.bin", &mut reader).unwrap() == data);
+            let mut read = vec![];
+            pak.open_entry("large.bin", &mut reader)
+                .unwrap()
+                .read_to_end(&mut read)
+                .unwrap();
+            assert!(read == data, "{compression}");
+        }
+    }
+}
+
+#[test]
+fn test_entries_by_offset() {
+    let mut writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+    writer.write_file("c.txt", false, [b'c'; 50]).unwrap();
+    writer.write_file("a.txt", false, [b'a'; 100]).unwrap();
+    writer
+        .write_file("b.txt", true, support::pattern(10_000, 16))
+        .unwrap();
+    let bytes = writer.write_index().unwrap().into_inner();
+
+    // replacing a.txt leaves its old data as a gap between c.txt and b.txt
+    let mut reader = Cursor::new(bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let old_a = pak.entry_range("a.txt", &mut reader).unwrap();
+    let mut writer = pak.into_pakwriter(reader).unwrap();
+    writer.write_file("a.txt", false, [b'A'; 10]).unwrap();
+    let mut bytes = writer.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let layout = pak.entries_by_offset();
+    assert_eq!(
+        layout.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(),
+        ["c.txt", "b.txt", "a.txt"]
+    );
+    for entry in &layout {
+        let range = pak.entry_range(&entry.path, &mut reader).unwrap();
+        assert_eq!(entry.info.offset, range.start);
+        assert_eq!(entry.size, range.end - range.start, "{}", entry.path);
+    }
+    assert_eq!(layout[0].info.offset + layout[0].size, old_a.start);
+    assert_eq!(
+        layout.iter().map(|e| e.gap).collect::<Vec<_>>(),
+        [old_a.end - old_a.start, 0, 0]
+    );
+    assert_eq!(
+        layout.iter().map(|e| e.gap).sum::<u64>(),
+        pak.fragmentation_report(bytes.len() as u64).dead_bytes
+    );
+
+    // damaged entries don't stop reading the others
+    let b = &layout[1];
+    let end = (b.info.offset + b.size) as usize;
+    bytes[end - b.info.compressed as usize..end].fill(0xff);
+    let read = pak
+        .read_entries_by_offset(&mut Cursor::new(&bytes))
+        .map(|(entry, data)| (entry.path, data.ok()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        read,
+        [
+            ("c.txt".to_owned(), Some(vec![b'c'; 50])),
+            ("b.txt".to_owned(), None),
+            ("a.txt".to_owned(), Some(vec![b'A'; 10])),
+        ]
+    );
+}
+
+#[test]
+fn test_duplicate_directories() {
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    for path in ["dir/a.txt", "dir/b.txt", "dir/c.txt", "other.txt"] {
+        writer.write_file(path, false, path).unwrap();
+    }
+    let bytes = writer.write_index().unwrap().into_inner();
+
+    let slice =
+        |range: std::ops::Range<u64>| bytes[range.start as usize..range.end as usize].to_vec();
+    // the duplicated directories don't match the hash of the full directory index as written
+    let builder = repak::PakBuilder::new().ignore_index_hashes(true);
+    let footer = builder
+        .parse_footer(&slice(repak::Footer::range(bytes.len() as u64)))
+        .unwrap();
+    let index = slice(footer.index_range());
+    let secondary = builder.secondary_index_ranges(&footer, &index).unwrap();
+    let fdi = slice(secondary.full_directory_index.unwrap());
+
+    // encoded entry offsets by path as listed by the full directory index
+    let read_string = |reader: &mut Cursor<&[u8]>| {
+        let len = reader.read_i32::<byteorder::LE>().unwrap() as usize;
+        let mut buf = vec![0; len];
+        reader.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf[..len - 1].to_vec()).unwrap()
+    };
+    let mut reader = Cursor::new(&fdi[..]);
+    let mut offsets = std::collections::HashMap::new();
+    for _ in 0..reader.read_u32::<byteorder::LE>().unwrap() {
+        let dir = read_string(&mut reader);
+        for _ in 0..reader.read_u32::<byteorder::LE>().unwrap() {
+            let file = read_string(&mut reader);
+            let offset = reader.read_u32::<byteorder::LE>().unwrap();
+            offsets.insert(format!("{}{file}", dir.trim_start_matches('/')), offset);
+        }
+    }
+
+    // "dir/" listed three times with overlapping files, the last listing "a.txt" with the entry
+    // of "c.txt"
+    let directories: &[(&str, &[(&str, &str)])] = &[
+        ("/", &[("other.txt", "other.txt")]),
+        ("dir/", &[("a.txt", "dir/a.txt"), ("b.txt", "dir/b.txt")]),
+        ("dir/", &[("b.txt", "dir/b.txt"), ("c.txt", "dir/c.txt")]),
+        ("dir/", &[("a.txt", "dir/c.txt")]),
+    ];
+    let write_string = |buf: &mut Vec<u8>, value: &str| {
+        buf.write_i32::<byteorder::LE>(value.len() as i32 + 1)
+            .unwrap();
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(0);
+    };
+    let mut duplicated = vec![];
+    duplicated
+        .write_u32::<byteorder::LE>(directories.len() as u32)
+        .unwrap();
+    for (dir, files) in directories {
+        write_string(&mut duplicated, dir);
+        duplicated
+            .write_u32::<byteorder::LE>(files.len() as u32)
+            .unwrap();
+        for (file, entry) in *files {
+            write_string(&mut duplicated, file);
+            duplicated
+                .write_u32::<byteorder::LE>(offsets[*entry])
+                .unwrap();
+        }
+    }
+
+    let pak = builder
+        .parse_index(&footer, &index, None, Some(&duplicated))
+        .unwrap();
+    assert_eq!(
+        pak.files(),
+        ["dir/a.txt", "dir/b.txt", "dir/c.txt", "other.txt"]
+    );
+    for file in pak.files() {
+        assert_eq!(
+            pak.get(&file, &mut Cursor::new(&bytes)).unwrap(),
+            file.as_bytes()
+        );
+    }
+    assert_eq!(
+        pak.warnings(),
+        [repak::IndexWarning::ConflictingDuplicate {
+            path: "dir/a.txt".to_owned(),
+            used: offsets["dir/a.txt"],
+            ignored: offsets["dir/c.txt"],
+        }]
+    );
+
+    // well-formed indexes have nothing to warn about
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap();
+    assert!(pak.warnings().is_empty());
+}
+
+#[test]
+fn test_compression_slot_names() {
+    let original = include_bytes!("packs/pack_v11_compress.pak");
+    let slots_start = original.len() - 5 * 32;
+    assert_eq!(&original[slots_start..slots_start + 5], b"Zlib\0");
+
+    let with_slot = |name: &[u8]| {
+        let mut bytes = original.to_vec();
+        let slot = &mut bytes[slots_start..slots_start + 32];
+        slot.fill(0);
+        slot[..name.len()].copy_from_slice(name);
+        bytes
+    };
+    let mut reader = Cursor::new(original);
+    let expected = repak::PakBuilder::new().reader(&mut reader).unwrap();
+
+    for name in [
+        &b"Zlib\0\x7f\x03\xff garbage"[..],
+        &[b"Zlib".as_slice(), &[b' '; 28]].concat(),
+        b"Zlib\t\r\n\x01",
+        b"zLIB",
+    ] {
+        let bytes = with_slot(name);
+        let footer = repak::Footer::parse(&bytes, repak::Version::V11).unwrap();
+        assert_eq!(
+            footer.compression_slots(),
+            [
+                repak::CompressionSlot::Known(repak::Compression::Zlib),
+                repak::CompressionSlot::Empty,
+                repak::CompressionSlot::Empty,
+                repak::CompressionSlot::Empty,
+                repak::CompressionSlot::Empty,
+            ],
+            "{name:?}"
+        );
+        assert_eq!(&footer.raw_compression_slots()[0][..name.len()], name);
+
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        for file in pak.files() {
+            assert_eq!(
+                pak.get(&file, &mut reader).unwrap(),
+                expected.get(&file, &mut Cursor::new(original)).unwrap()
+            );
+        }
+    }
+
+    // unknown names, including invalid UTF-8, are kept and fail reads instead of returning
+    // the compressed data as is
+    for (name, lossy) in [
+        (&b"Zlib2"[..], "Zlib2"),
+        (b"\xffZ\xfe", "\u{fffd}Z\u{fffd}"),
+    ] {
+        let bytes = with_slot(name);
+        let footer = repak::Footer::parse(&bytes, repak::Version::V11).unwrap();
+        assert_eq!(
+            footer.compression_slots()[0],
+            repak::CompressionSlot::Unknown(lossy.to_owned())
+        );
+
+        let mut reader = Cursor::new(&bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(
+            pak.warnings(),
+            [repak::IndexWarning::UnknownCompressionName {
+                slot: 0,
+                name: lossy.to_owned(),
+            }]
+        );
+        assert_eq!(pak.entry_info("test.png").unwrap().compression, None);
+        let err = pak.get("test.png", &mut reader).unwrap_err();
+        assert!(
+            matches!(err, repak::Error::UnknownCompressionSlot(0)),
+            "{err}"
+        );
+        assert_eq!(err.kind(), repak::ErrorKind::Unsupported);
+        // stored entries are unaffected
+        assert_eq!(
+            pak.get("test.txt", &mut reader).unwrap(),
+            include_bytes!("pack/root/test.txt")
+        );
+    }
+}
+
+#[test]
+fn test_frozen_index_unsupported() {
+    let mut bytes = include_bytes!("packs/pack_v9.pak").to_vec();
+    // GUID, encrypted flag, magic, version, index offset, index size and hash precede the
+    // frozen flag
+    let frozen = bytes.len() - repak::Version::V9.size() as usize + 16 + 1 + 4 + 4 + 8 + 8 + 20;
+    assert_eq!(bytes[frozen], 0);
+    bytes[frozen] = 1;
+
+    let err = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(matches!(err, repak::Error::FrozenIndex), "{err}");
+    assert_eq!(err.kind(), repak::ErrorKind::Unsupported);
+
+    let builder = repak::PakBuilder::new();
+    let footer = builder
+        .parse_footer(&bytes[repak::Footer::range(bytes.len() as u64).start as usize..])
+        .unwrap();
+    let index = &bytes[footer.index_range().start as usize..footer.index_range().end as usize];
+    assert!(matches!(
+        builder.parse_index(&footer, index, None, None),
+        Err(repak::Error::FrozenIndex)
+    ));
+}
+
+#[test]
+fn test_file_names() {
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+
+    // borrowed from the index rather than cloned
+    let names: Vec<&str> = pak.file_names().collect();
+    assert_eq!(names, pak.files());
+    assert!(names.is_sorted());
+    assert_eq!(pak.file_names().len(), 4);
+    assert_eq!(pak.len(), 4);
+    assert!(!pak.is_empty());
+
+    let bytes = repak::PakBuilder::new()
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        )
+        .write_index()
+        .unwrap()
+        .into_inner();
+    let pak = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(bytes))
+        .unwrap();
+    assert!(pak.is_empty());
+    assert_eq!(pak.file_names().next(), None);
+}
+
+#[test]
+fn test_path_remap() {
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let rule = |from: &str, to: &str| (from.to_owned(), to.to_owned());
+
+    let view = pak
+        .with_path_remap(vec![
+            rule("../mount/point/root/", "../../../Game/"),
+            // the longest matching prefix wins
+            rule("../mount/point/root/directory/", "../../../Other/"),
+        ])
+        .unwrap();
+    assert_eq!(
+        view.files(),
+        [
+            "../../../Game/test.png",
+            "../../../Game/test.txt",
+            "../../../Game/zeros.bin",
+            "../../../Other/nested.txt",
+        ]
+    );
+    assert_eq!(
+        view.resolve("../../../Other/nested.txt"),
+        Some("directory/nested.txt")
+    );
+    assert_eq!(
+        view.remap("../mount/point/root/test.txt"),
+        "../../../Game/test.txt"
+    );
+    assert_eq!(
+        view.get("../../../Game/test.txt", &mut reader).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+    assert_eq!(
+        view.get("../../../Other/nested.txt", &mut reader).unwrap(),
+        include_bytes!("pack/root/directory/nested.txt")
+    );
+    // the original paths are hidden by the view but the pak is untouched
+    assert!(matches!(
+        view.get("../mount/point/root/test.txt", &mut reader),
+        Err(repak::Error::MissingEntry(_))
+    ));
+    assert_eq!(view.pak().files(), pak.files());
+
+    // unmatched entries keep their mounted path
+    let view = pak
+        .with_path_remap(vec![rule(
+            "../mount/point/root/directory/",
+            "../../../Other/",
+        )])
+        .unwrap();
+    assert_eq!(
+        view.resolve("../mount/point/root/test.txt"),
+        Some("test.txt")
+    );
+    assert_eq!(view.resolve("test.txt"), None);
+
+    // an entry moved onto the path of another
+    let err = pak
+        .with_path_remap(vec![rule(
+            "../mount/point/root/directory/nested",
+            "../mount/point/root/test",
+        )])
+        .unwrap_err();
+    assert!(
+        matches!(&err, repak::Error::AmbiguousRemap { path, .. } if path == "../mount/point/root/test.txt"),
+        "{err}"
+    );
+    assert_eq!(err.kind(), repak::ErrorKind::InvalidInput);
+
+    // the same prefix mapped to different targets
+    let err = pak
+        .with_path_remap(vec![rule("../mount/", "a/"), rule("../mount/", "b/")])
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::ConflictingRemap { .. }),
+        "{err}"
+    );
+    assert!(pak
+        .with_path_remap(vec![rule("../mount/", "a/"), rule("../mount/", "a/")])
+        .is_ok());
+
+    // different sources merged into the same target directory
+    let mut writer = repak::PakBuilder::new().writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../../../".to_owned(),
+        None,
+    );
+    for path in ["A/same.txt", "A/a.txt", "B/same.txt", "B/b.txt"] {
+        writer.write_file(path, false, path).unwrap();
+    }
+    let bytes = writer.write_index().unwrap().into_inner();
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let err = pak
+        .with_path_remap(vec![
+            rule("../../../A/", "../../../Merged/"),
+            rule("../../../B/", "../../../Merged/"),
+        ])
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"path remap is ambiguous, both "../../../A/same.txt" and "../../../B/same.txt" map to "../../../Merged/same.txt""#
+    );
+}
+
+#[test]
+fn test_entry_range() {
+    for (bytes, data_record) in [
+        // offset, compressed, uncompressed, compression slot, hash, flags and block size
+        (
+            &include_bytes!("packs/pack_v11.pak")[..],
+            8 + 8 + 8 + 4 + 20 + 1 + 4,
+        ),
+        // same record layout, but read from the legacy index rather than the encoded one
+        (
+            include_bytes!("packs/pack_v5.pak"),
+            8 + 8 + 8 + 4 + 20 + 1 + 4,
+        ),
+    ] {
+        let mut reader = Cursor::new(bytes);
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let mut ranges = vec![];
+        for file in pak.files() {
+            let range = pak.entry_range(&file, &mut reader).unwrap();
+            let info = pak.entry_info(&file).unwrap();
+            assert_eq!(range.start, info.offset);
+            assert_eq!(range.end - range.start, data_record + info.compressed);
+            // uncompressed data is stored as is at the end of the range
+            let data = &bytes[(range.end - info.uncompressed) as usize..range.end as usize];
+            assert_eq!(data, pak.get(&file, &mut reader).unwrap());
+            ranges.push(range);
+        }
+        ranges.sort_by_key(|r| r.start);
+        assert!(ranges.windows(2).all(|w| w[0].end <= w[1].start));
+    }
+
+    let mut reader = Cursor::new(include_bytes!("packs/pack_v11.pak"));
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert!(matches!(
+        pak.entry_range("missing.txt", &mut reader),
+        Err(repak::Error::MissingEntry(_))
+    ));
+}
+
+#[test]
+fn test_custom_magic() {
+    const MAGIC: u32 = 0xFEEDFACE;
+
+    let mut pak_writer = repak::PakBuilder::new().magic(MAGIC).writer(
+        Cursor::new(vec![]),
+        repak::Version::V11,
+        "../mount/point/root/".to_owned(),
+        Some(0x205C5A7D),
+    );
+    pak_writer
+        .write_file("test.txt", false, include_bytes!("pack/root/test.txt"))
+        .unwrap();
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    let err = repak::PakBuilder::new()
+        .reader(&mut Cursor::new(&bytes))
+        .unwrap_err();
+    assert!(err.to_string().contains("--magic"), "{err}");
+
+    let mut reader = Cursor::new(&bytes);
+    let pak_reader = repak::PakBuilder::new()
+        .magic(MAGIC)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(
+        pak_reader.get("test.txt", &mut reader).unwrap(),
+        include_bytes!("pack/root/test.txt")
+    );
+
+    // rewriting the index keeps the custom magic
+    let rewrite = pak_reader
+        .into_pakwriter(Cursor::new(bytes.clone()))
+        .unwrap()
+        .write_index()
+        .unwrap()
+        .into_inner();
+    assert_eq!(rewrite, bytes);
+}
+
+macro_rules! matrix_test {
+    ( $name:literal, ($($version:literal $exp_version:expr),* $(,)?), $compress:tt, $encrypt:tt, $encryptindex:tt, $body:tt ) => {
+        $( matrix_test_compress!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
+    };
+}
+
+macro_rules! matrix_test_compress {
+    ( $name:literal, $version:literal, $exp_version:expr, ($($compress:literal),* $(,)?), $encrypt:tt, $encryptindex:tt, $body:tt ) => {
+        $( matrix_test_encrypt!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
+    };
+}
+
+macro_rules! matrix_test_encrypt {
+    ( $name:literal, $version:literal, $exp_version:expr, $compress:literal, ($($encrypt:literal),* $(,)?), $encryptindex:tt, $body:tt ) => {
+        $( matrix_test_encryptindex!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
+    };
+}
+
+macro_rules! matrix_test_encryptindex {
+    ( $name:literal, $version:literal, $exp_version:expr, $compress:literal, $encrypt:literal, ($($encryptindex:literal),* $(,)?), $body:tt ) => {
+        $( matrix_test_body!($name, $version, $exp_version, $compress, $encrypt, $encryptindex, $body); )*
+    };
+}
+
+macro_rules! matrix_test_body {
+    ( $name:literal, $version:literal, $exp_version:expr, $compress:literal, $encrypt:literal, $encryptindex:literal, $body:expr ) => {
+        paste! {
+            #[test]
+            fn [< test_ $name _version_ $version $compress $encrypt $encryptindex >]() {
+                $body(
+                    $exp_version,
+                    concat!("pack_", $version, $compress, $encrypt, $encryptindex, ".pak"),
+                    include_bytes!(concat!("packs/pack_", $version, $compress, $encrypt, $encryptindex, ".pak")));
+            }
+        }
+    };
+}
+
+matrix_test!(
+    "read",
+    (
+        "v5" repak::Version::V5,
+        "v7" repak::Version::V7,
+        "v8a" repak::Version::V8A,
+        "v8b" repak::Version::V8B,
+        "v9" repak::Version::V9,
+        "v11" repak::Version::V11,
+    ),
+    ("", "_compress"),
+    ("", "_encrypt"),
+    ("", "_encryptindex"),
+    test_read
+);
+
+matrix_test!(
+    "write",
+    (
+        "v5" repak::Version::V5,
+        "v7" repak::Version::V7,
+        "v8a" repak::Version::V8A,
+        "v8b" repak::Version::V8B,
+        "v9" repak::Version::V9,
+        "v11" repak::Version::V11,
+    ),
+    ("", /*"_compress"*/),
+    ("", /*"_encrypt"*/),
+    ("", /*"_encryptindex"*/),
+    test_write
+);
+
+matrix_test!(
+    "rewrite_index",
+    (
+        "v5" repak::Version::V5,
+        "v7" repak::Version::V7,
+        "v8a" repak::Version::V8A,
+        "v8b" repak::Version::V8B,
+        "v9" repak::Version::V9,
+        "v11" repak::Version::V11,
+    ),
+    ("", "_compress"),
+    ("", "_encrypt"),
+    ("", /*"_encryptindex"*/),
+    test_rewrite_index
+);
+
+matrix_test!(
+    "rewrite_index_utf16",
+    (
+        "v5" repak::Version::V5,
+        "v7" repak::Version::V7,
+        "v8a" repak::Version::V8A,
+        "v8b" repak::Version::V8B,
+        "v9" repak::Version::V9,
+        "v11" repak::Version::V11,
+    ),
+    ("", "_compress"),
+    ("", "_encrypt"),
+    ("", /*"_encryptindex"*/),
+    test_rewrite_index_utf16
+);
+
+matrix_test!(
+    "decrypt",
+    (
+        "v5" repak::Version::V5,
+        "v7" repak::Version::V7,
+        "v8a" repak::Version::V8A,
+        "v8b" repak::Version::V8B,
+        "v9" repak::Version::V9,
+        "v11" repak::Version::V11,
+    ),
+    ("", "_compress"),
+    ("", "_encrypt"),
+    ("", "_encryptindex"),
+    test_decrypt
+);
+
+/// XOR with a key derived from the path and block index, like some games obfuscate entries
+struct XorTransform;
+
+impl XorTransform {
+    fn apply(path: &str, block: usize, data: &mut [u8]) {
+        let key = path
+            .bytes()
+            .fold(block as u8 ^ 0x5a, |key, b| key.rotate_left(1) ^ b)
+            | 1;
+        for byte in data {
+            *byte ^= key;
+        }
+    }
+}
+
+impl repak::EntryTransform for XorTransform {
+    fn decode(&self, path: &str, _flags: u8, block: usize, data: &mut [u8]) {
+        Self::apply(path, block, data)
+    }
+
+    fn encode(&self, path: &str, _flags: u8, block: usize, data: &mut [u8]) {
+        Self::apply(path, block, data)
+    }
+}
+
+#[test]
+fn test_entry_transform() {
+    let compressed = support::pattern(300_000, 13);
+    let stored = support::noise(200_000);
+    let mut pak_writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .entry_transform(Box::new(XorTransform))
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+    let info = pak_writer
+        .write_file("compressed.bin", true, &compressed)
+        .unwrap();
+    assert_eq!(info.compression, Some(repak::Compression::Zlib));
+    pak_writer.write_file("stored.bin", false, &stored).unwrap();
+    let entry_builder = pak_writer.entry_builder();
+    let partial = entry_builder.build_entry(true, &compressed).unwrap();
+    pak_writer
+        .write_entry("built.bin".to_owned(), partial)
+        .unwrap();
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    // the hash covers the data as stored, so verification passes before decoding
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new()
+        .entry_transform(Box::new(XorTransform))
+        .verify_reads(true)
+        .reader(&mut reader)
+        .unwrap();
+    for (path, data) in [
+        ("compressed.bin", &compressed),
+        ("stored.bin", &stored),
+        ("built.bin", &compressed),
+    ] {
+        assert_eq!(&pak.get(path, &mut reader).unwrap(), data, "{path}");
+        let mut streamed = vec![];
+        pak.open_entry(path, &mut reader)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert_eq!(&streamed, data, "{path}");
+    }
+
+    // without the transform compressed entries don't decompress and others read garbage
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    assert!(pak.get("compressed.bin", &mut reader).is_err());
+    assert!(pak.get("built.bin", &mut reader).is_err());
+    let garbage = pak.get("stored.bin", &mut reader).unwrap();
+    assert_eq!(garbage.len(), stored.len());
+    assert_ne!(garbage, stored);
+}
+
+#[test]
+fn test_entry_transform_dedup() {
+    // the transform is keyed by the path, so identical files are stored differently
+    let data = support::pattern(100_000, 7);
+    let mut pak_writer = repak::PakBuilder::new()
+        .compression([repak::Compression::Zlib])
+        .entry_transform(Box::new(XorTransform))
+        .writer(
+            Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".to_owned(),
+            None,
+        );
+    for path in ["a/data.bin", "b/data.bin"] {
+        assert!(!pak_writer.write_file_dedup(path, true, &data).unwrap());
+    }
+    let err = pak_writer
+        .write_duplicate("c/data.bin", "a/data.bin")
+        .unwrap_err();
+    assert!(
+        matches!(err, repak::Error::SharedDataWithTransform { .. }),
+        "{err}"
+    );
+    assert_eq!(pak_writer.deduplicated_bytes(), 0);
+    let bytes = pak_writer.write_index().unwrap().into_inner();
+
+    let mut reader = Cursor::new(&bytes);
+    let pak = repak::PakBuilder::new()
+        .entry_transform(Box::new(XorTransform))
+        .verify_reads(true)
+        .reader(&mut reader)
+        .unwrap();
+    assert_eq!(pak.files(), ["a/data.bin", "b/data.bin"]);
+    for path in ["a/data.bin", "b/data.bin"] {
+        assert!(pak.get(path, &mut reader).unwrap() == data, "{path}");
+    }
+}
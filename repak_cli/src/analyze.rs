@@ -0,0 +1,267 @@
+//! Compression analysis of directories before packing them and of existing paks
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use path_slash::PathExt;
+use rayon::prelude::*;
+use serde::Serialize;
+
+/// Compression is only recommended if it saves at least this fraction of the size
+const MIN_SAVINGS: f64 = 0.1;
+
+/// Uncompressed and projected sizes of a group of files
+#[derive(Debug, Default, Serialize)]
+pub struct Projection {
+    pub files: u64,
+    pub size: u64,
+    /// Projected size of the stored data for each compression method
+    pub projected: BTreeMap<String, u64>,
+}
+
+impl Projection {
+    fn add(&mut self, file: &FileProjection) {
+        self.files += 1;
+        self.size += file.size;
+        for (method, size) in &file.projected {
+            *self.projected.entry(method.clone()).or_default() += size;
+        }
+    }
+
+    /// Method with the smallest projected size, unless no method saves enough to be worth it
+    pub fn best(&self) -> Option<&str> {
+        self.projected
+            .iter()
+            .min_by_key(|(_, size)| **size)
+            .filter(|(_, size)| (**size as f64) <= self.size as f64 * (1.0 - MIN_SAVINGS))
+            .map(|(method, _)| method.as_str())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileProjection {
+    pub path: String,
+    pub size: u64,
+    /// Number of bytes which were trial compressed
+    pub sampled: u64,
+    pub projected: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectoryAnalysis {
+    pub files: Vec<FileProjection>,
+    /// Projections by lowercase file extension, empty for files without one
+    pub extensions: BTreeMap<String, Projection>,
+    pub total: Projection,
+    /// Compression method to pack the directory with, if any
+    pub recommendation: Option<String>,
+}
+
+/// Trial compresses up to `sample_size` bytes of each file in `dir` with each of `methods`,
+/// projecting the compressed size of larger files from their sample
+pub fn analyze_directory(
+    dir: &Path,
+    methods: &[repak::Compression],
+    sample_size: u64,
+) -> Result<DirectoryAnalysis, repak::Error> {
+    let mut paths = vec![];
+    crate::collect_files(&mut paths, dir)?;
+    paths.sort();
+
+    let files = paths
+        .par_iter()
+        .map(|path| {
+            let size = path.metadata()?.len();
+            let mut sample = vec![];
+            File::open(path)?
+                .take(sample_size)
+                .read_to_end(&mut sample)?;
+            let sampled = sample.len() as u64;
+
+            let mut projected = BTreeMap::new();
+            for method in methods {
+                // the same compression as used by pack so projections match the packed size
+                let compressed =
+                    repak::PartialEntry::new(Some(*method), &sample)?.compressed_size();
+                let size = match sampled {
+                    0 => 0,
+                    _ if sampled == size => compressed,
+                    _ => (compressed as f64 * size as f64 / sampled as f64).round() as u64,
+                };
+                projected.insert(method.to_string(), size);
+            }
+            Ok(FileProjection {
+                path: path
+                    .strip_prefix(dir)
+                    .expect("file is in directory")
+                    .to_slash_lossy()
+                    .into_owned(),
+                size,
+                sampled,
+                projected,
+            })
+        })
+        .collect::<Result<Vec<_>, repak::Error>>()?;
+
+    let mut extensions = BTreeMap::<_, Projection>::new();
+    let mut total = Projection::default();
+    for file in &files {
+        let extension = Path::new(&file.path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        extensions.entry(extension).or_default().add(file);
+        total.add(file);
+    }
+
+    Ok(DirectoryAnalysis {
+        recommendation: total.best().map(str::to_owned),
+        files,
+        extensions,
+        total,
+    })
+}
+
+/// Actual sizes of a group of entries
+#[derive(Debug, Default, Serialize)]
+pub struct Sizes {
+    pub files: u64,
+    pub uncompressed: u64,
+    pub compressed: u64,
+}
+
+impl Sizes {
+    fn add(&mut self, info: &repak::EntryInfo) {
+        self.files += 1;
+        self.uncompressed += info.uncompressed;
+        self.compressed += info.compressed;
+    }
+
+    pub fn ratio(&self) -> f64 {
+        ratio(self.compressed, self.uncompressed)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntrySizes {
+    pub path: String,
+    pub uncompressed: u64,
+    pub compressed: u64,
+    pub compression: Option<String>,
+    pub ratio: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PakAnalysis {
+    pub entries: Vec<EntrySizes>,
+    /// Sizes by lowercase file extension, empty for entries without one
+    pub extensions: BTreeMap<String, Sizes>,
+    pub total: Sizes,
+}
+
+/// Reports the compression ratio of each entry from the index
+pub fn analyze_pak(pak: &repak::PakReader) -> PakAnalysis {
+    let mut entries = vec![];
+    let mut extensions = BTreeMap::<_, Sizes>::new();
+    let mut total = Sizes::default();
+    for path in pak.files() {
+        let info = pak.entry_info(&path).expect("listed entry exists");
+        let extension = Path::new(&path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        extensions.entry(extension).or_default().add(&info);
+        total.add(&info);
+        entries.push(EntrySizes {
+            path,
+            uncompressed: info.uncompressed,
+            compressed: info.compressed,
+            compression: info.compression.map(|c| c.to_string()),
+            ratio: ratio(info.compressed, info.uncompressed),
+        });
+    }
+    PakAnalysis {
+        entries,
+        extensions,
+        total,
+    }
+}
+
+/// Compressed size as a fraction of the uncompressed size
+pub fn ratio(compressed: u64, uncompressed: u64) -> f64 {
+    match uncompressed {
+        0 => 1.0,
+        _ => compressed as f64 / uncompressed as f64,
+    }
+}
+
+impl DirectoryAnalysis {
+    pub fn print_table(&self, methods: &[repak::Compression]) {
+        print!("{:<12} {:>6} {:>12}", "extension", "files", "size");
+        for method in methods {
+            print!(" {:>18}", method.to_string());
+        }
+        println!("  best");
+
+        let row = |name: &str, projection: &Projection| {
+            print!(
+                "{:<12} {:>6} {:>12}",
+                name, projection.files, projection.size
+            );
+            for method in methods {
+                let size = projection
+                    .projected
+                    .get(&method.to_string())
+                    .copied()
+                    .unwrap_or_default();
+                let percent = ratio(size, projection.size) * 100.0;
+                print!(" {:>18}", format!("{size} ({percent:.0}%)"));
+            }
+            println!("  {}", projection.best().unwrap_or("none"));
+        };
+        for (extension, projection) in &self.extensions {
+            match extension.is_empty() {
+                true => row("(none)", projection),
+                false => row(&format!(".{extension}"), projection),
+            }
+        }
+        row("total", &self.total);
+
+        match &self.recommendation {
+            Some(method) => println!("recommendation: compress with {method}"),
+            None => println!(
+                "recommendation: store uncompressed (no method saves {:.0}%)",
+                MIN_SAVINGS * 100.0
+            ),
+        }
+    }
+}
+
+impl PakAnalysis {
+    pub fn print_table(&self) {
+        println!(
+            "{:>6} {:>12} {:>12} {:<11} path",
+            "ratio", "uncompressed", "compressed", "compression"
+        );
+        for entry in &self.entries {
+            println!(
+                "{:>5.0}% {:>12} {:>12} {:<11} {}",
+                entry.ratio * 100.0,
+                entry.uncompressed,
+                entry.compressed,
+                entry.compression.as_deref().unwrap_or("none"),
+                entry.path
+            );
+        }
+        println!(
+            "{:>5.0}% {:>12} {:>12} {:<11} total of {} entries",
+            self.total.ratio() * 100.0,
+            self.total.uncompressed,
+            self.total.compressed,
+            "",
+            self.total.files
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Machine-readable record of what unpack extracted, written with `--audit-log` as one JSON
+//! object per line
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// A line of the audit log: a record of each pak, followed by the records of its entries as
+/// they are extracted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Pak(PakRecord),
+    Entry(EntryRecord),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PakRecord {
+    pub tool: &'static str,
+    pub tool_version: &'static str,
+    /// Path of the pak as given
+    pub pak: String,
+    /// SHA256 of the whole pak file
+    pub sha256: String,
+    pub size: u64,
+    pub version: String,
+    pub mount_point: String,
+    pub encrypted_index: bool,
+    /// Hex, as the GUID and seed don't fit the integers of all JSON readers
+    pub encryption_guid: Option<String>,
+    pub path_hash_seed: Option<String>,
+    pub entry_count: usize,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryRecord {
+    pub pak: String,
+    /// Path of the entry relative to the mount point
+    pub path: String,
+    /// File the entry was written to
+    pub output: String,
+    pub compressed: u64,
+    pub uncompressed: u64,
+    pub hash_algorithm: &'static str,
+    /// What [`Self::hash`] is of: "stored" for the hash of the stored data which reading
+    /// verified, "output" for the hash of the written file when not verifying
+    pub hash_of: &'static str,
+    pub hash: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Destination of the audit log records, called from the threads extracting entries
+pub trait AuditLog: Sync {
+    fn record(&self, record: &Record) -> io::Result<()>;
+}
+
+/// Writes each record as a line of JSON as soon as it is made, so an interrupted run still
+/// leaves the records of everything extracted so far
+pub struct JsonLines(Mutex<File>);
+
+impl JsonLines {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        File::create(path).map(|file| Self(Mutex::new(file)))
+    }
+}
+
+impl AuditLog for JsonLines {
+    fn record(&self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.0.lock().unwrap();
+        file.write_all(&line)?;
+        file.flush()
+    }
+}
+
+/// Records the pak at `input`, hashing the whole file
+pub fn record_pak(
+    log: &dyn AuditLog,
+    input: &str,
+    pak: &repak::PakReader,
+) -> Result<(), repak::Error> {
+    let (sha256, size) = sha256(&mut io::BufReader::new(crate::sharing::open_input(input)?))?;
+    log.record(&Record::Pak(PakRecord {
+        tool: "repak",
+        tool_version: env!("CARGO_PKG_VERSION"),
+        pak: input.to_owned(),
+        sha256,
+        size,
+        version: pak.version().to_string(),
+        mount_point: pak.mount_point().to_owned(),
+        encrypted_index: pak.encrypted_index(),
+        encryption_guid: pak.encryption_guid().map(|guid| format!("{guid:032X}")),
+        path_hash_seed: pak.path_hash_seed().map(|seed| format!("{seed:08X}")),
+        entry_count: pak.entry_count(),
+        timestamp: timestamp(),
+    }))?;
+    Ok(())
+}
+
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// SHA256 and size of everything `reader` yields
+pub fn sha256(reader: &mut impl Read) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(reader, &mut hasher)?;
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+/// Writer which hashes what passes through it with SHA256 if created with `hash` set
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<Sha256>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W, hash: bool) -> Self {
+        Self {
+            inner,
+            hasher: hash.then(Sha256::new),
+        }
+    }
+
+    /// The inner writer and the hex SHA256 of the data written, if hashing
+    pub fn finish(self) -> (W, Option<String>) {
+        let hash = self.hasher.map(|hasher| hex::encode(hasher.finalize()));
+        (self.inner, hash)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Keeps the records in memory
+    #[derive(Default)]
+    struct Captured(Mutex<Vec<Record>>);
+
+    impl AuditLog for Captured {
+        fn record(&self, record: &Record) -> io::Result<()> {
+            self.0.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    fn entry(path: &str) -> Record {
+        Record::Entry(EntryRecord {
+            pak: "a.pak".to_owned(),
+            path: path.to_owned(),
+            output: format!("a/{path}"),
+            compressed: 3,
+            uncompressed: 5,
+            hash_algorithm: "sha256",
+            hash_of: "output",
+            hash: "00".to_owned(),
+            timestamp: 1,
+        })
+    }
+
+    #[test]
+    fn test_record_json() {
+        let json = serde_json::to_value(entry("b.txt")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "entry",
+                "pak": "a.pak",
+                "path": "b.txt",
+                "output": "a/b.txt",
+                "compressed": 3,
+                "uncompressed": 5,
+                "hash_algorithm": "sha256",
+                "hash_of": "output",
+                "hash": "00",
+                "timestamp": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let log = JsonLines::create(&path).unwrap();
+        log.record(&entry("b.txt")).unwrap();
+        // on disk as soon as it is recorded
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+        log.record(&entry("c.txt")).unwrap();
+        let expected = [entry("b.txt"), entry("c.txt")]
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap() + "\n")
+            .collect::<String>();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_record_pak() {
+        let input = "../repak/tests/packs/pack_v11.pak";
+        let pak = repak::PakBuilder::new()
+            .reader(&mut io::BufReader::new(File::open(input).unwrap()))
+            .unwrap();
+        let captured = Captured::default();
+        record_pak(&captured, input, &pak).unwrap();
+        let records = captured.0.into_inner().unwrap();
+        let [Record::Pak(record)] = &records[..] else {
+            panic!("{records:?}");
+        };
+        let bytes = std::fs::read(input).unwrap();
+        assert_eq!(record.sha256, hex::encode(Sha256::digest(&bytes)));
+        assert_eq!(record.size, bytes.len() as u64);
+        assert_eq!(record.version, "V11");
+        assert_eq!(record.mount_point, "../mount/point/root/");
+        assert_eq!(record.path_hash_seed.as_deref(), Some("205C5A7D"));
+        assert_eq!(record.entry_count, 4);
+    }
+
+    #[test]
+    fn test_hashing_writer() {
+        let mut writer = HashingWriter::new(vec![], true);
+        writer.write_all(b"repak").unwrap();
+        let (data, hash) = writer.finish();
+        assert_eq!(data, b"repak");
+        assert_eq!(hash, Some(sha256(&mut &b"repak"[..]).unwrap().0));
+        assert_eq!(HashingWriter::new(vec![], false).finish().1, None);
+    }
+}
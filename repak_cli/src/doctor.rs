@@ -0,0 +1,190 @@
+//! Checks for common reasons a game won't load a modded pak, found by comparing it against one
+//! of the game's original paks
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Likely to prevent the game from loading the pak or its entries
+    Warning,
+    /// Worth knowing but often intended
+    Note,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    /// Suggested fix
+    pub help: Option<String>,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: String, help: Option<String>) -> Self {
+        Self {
+            severity,
+            message,
+            help,
+        }
+    }
+}
+
+/// Entry paths including the mount point, looked up ignoring case
+struct PathSet(BTreeMap<String, String>);
+
+impl PathSet {
+    fn new(pak: &repak::PakReader) -> Self {
+        Self(
+            pak.file_names()
+                .map(|file| {
+                    let path = format!("{}{file}", pak.mount_point());
+                    (path.to_lowercase(), path)
+                })
+                .collect(),
+        )
+    }
+
+    /// Path as spelled in the set if it contains `path` ignoring case
+    fn get(&self, path: &str) -> Option<&str> {
+        self.0.get(&path.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// Compression methods used by at least one entry
+fn compression_methods(pak: &repak::PakReader) -> BTreeSet<String> {
+    pak.file_names()
+        .filter_map(|file| {
+            pak.entry_info(file)
+                .expect("listed entry exists")
+                .compression
+        })
+        .map(|compression| compression.to_string())
+        .collect()
+}
+
+/// Compares `pak` located at `path` against `reference`, an original pak of the same game
+pub fn diagnose(path: &Path, pak: &repak::PakReader, reference: &repak::PakReader) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    if pak.version() != reference.version() {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "version {} does not match the reference version {}",
+                pak.version(),
+                reference.version()
+            ),
+            Some(format!("repack with `--version {}`", reference.version())),
+        ));
+    }
+
+    if pak.mount_point() != reference.mount_point() {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "mount point {:?} does not match the reference mount point {:?}",
+                pak.mount_point(),
+                reference.mount_point()
+            ),
+            Some(format!(
+                "repack with `--mount-point {}` unless the entry paths account for the difference",
+                reference.mount_point()
+            )),
+        ));
+    }
+
+    let reference_methods = compression_methods(reference);
+    for method in compression_methods(pak).difference(&reference_methods) {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!("compression method {method} is never used by the reference"),
+            Some(match reference_methods.first() {
+                Some(supported) => format!("repack with `--compression {supported}`"),
+                None => "repack without `--compression`".to_owned(),
+            }),
+        ));
+    }
+
+    if let (Some(seed), Some(reference_seed)) = (pak.path_hash_seed(), reference.path_hash_seed()) {
+        if seed != reference_seed {
+            findings.push(Finding::new(
+                Severity::Note,
+                format!(
+                    "path hash seed {seed:08X} differs from the reference path hash seed {reference_seed:08X}"
+                ),
+                Some(format!("repack with `--path-hash-seed {reference_seed}`")),
+            ));
+        }
+    }
+
+    let reference_paths = PathSet::new(reference);
+    let mut missing = vec![];
+    for file in pak.file_names() {
+        let mounted = format!("{}{file}", pak.mount_point());
+        match reference_paths.get(&mounted) {
+            Some(original) if original == mounted => {}
+            Some(original) => findings.push(Finding::new(
+                Severity::Warning,
+                format!("{mounted} only matches {original} of the reference when ignoring case"),
+                Some(format!("rename it to {original}")),
+            )),
+            None => missing.push(mounted),
+        }
+    }
+    if let Some(example) = missing.first() {
+        findings.push(Finding::new(
+            Severity::Note,
+            format!(
+                "{} of {} entries do not exist in the reference and add files instead of replacing them, e.g. {example}",
+                missing.len(),
+                pak.len()
+            ),
+            (missing.len() == pak.len()).then(|| {
+                "check the mount point and entry paths if the pak should replace game files"
+                    .to_owned()
+            }),
+        ));
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    if !stem.to_lowercase().ends_with("_p") {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "file name does not end with _P so the pak may load before the originals".to_owned(),
+            Some(format!("rename it to {stem}_P.pak")),
+        ));
+    }
+
+    findings.sort_by_key(|finding| finding.severity);
+    findings
+}
+
+pub fn print_findings(findings: &[Finding]) {
+    for finding in findings {
+        println!("{}: {}", finding.severity, finding.message);
+        if let Some(help) = &finding.help {
+            println!("  = help: {help}");
+        }
+    }
+    let warnings = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Warning)
+        .count();
+    match findings.len() {
+        0 => println!("no problems found"),
+        total => println!("{warnings} warnings, {} notes", total - warnings),
+    }
+}
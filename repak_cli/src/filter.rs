@@ -0,0 +1,374 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::builder::TypedValueParser;
+use path_slash::PathExt;
+use strum::VariantNames;
+
+/// Entry filters evaluated against index metadata only, without reading entry data
+#[derive(clap::Args, Debug, Default, Clone)]
+pub struct EntryFilter {
+    /// Only match encrypted entries
+    #[arg(long, default_value = "false")]
+    pub only_encrypted: bool,
+
+    /// Only match compressed entries
+    #[arg(long, default_value = "false")]
+    pub only_compressed: bool,
+
+    /// Only match entries compressed with this method
+    #[arg(
+        long,
+        value_parser = clap::builder::PossibleValuesParser::new(repak::Compression::VARIANTS).map(|s| s.parse::<repak::Compression>().unwrap())
+    )]
+    pub compression: Option<repak::Compression>,
+
+    /// Only match entries with an uncompressed size of at least this many bytes
+    #[arg(long)]
+    pub min_size: Option<u64>,
+
+    /// Only match entries with an uncompressed size of at most this many bytes
+    #[arg(long)]
+    pub max_size: Option<u64>,
+}
+
+impl EntryFilter {
+    /// Whether any filter was specified
+    pub fn is_active(&self) -> bool {
+        self.only_encrypted
+            || self.only_compressed
+            || self.compression.is_some()
+            || self.min_size.is_some()
+            || self.max_size.is_some()
+    }
+
+    pub fn matches(&self, info: &repak::EntryInfo) -> bool {
+        (!self.only_encrypted || info.encrypted)
+            && (!self.only_compressed || info.compression.is_some())
+            && self
+                .compression
+                .is_none_or(|method| info.compression == Some(method))
+            && self.min_size.is_none_or(|min| info.uncompressed >= min)
+            && self.max_size.is_none_or(|max| info.uncompressed <= max)
+    }
+}
+
+/// Include and exclude patterns shared by pack and unpack. Both match them against the path of a
+/// file relative to the unpacked directory with forward slashes: for pack the path below the
+/// input directory, for unpack the entry path with `--strip-prefix` removed. A file is selected
+/// if it is matched by any include pattern, or there are none, and then by no exclude pattern.
+#[derive(clap::Args, Debug, Default, Clone)]
+pub struct PathFilter {
+    /// Files or directories to include, as glob patterns matched against forward-slash paths relative to the input directories (pack), the same for each of them, or output directory (unpack). Can be specified multiple times. If not specified, everything is included
+    #[arg(action = clap::ArgAction::Append, short, long)]
+    pub include: Vec<glob::Pattern>,
+
+    /// Files or directories to exclude, even if they are included. Can be specified multiple times
+    #[arg(action = clap::ArgAction::Append, short, long)]
+    pub exclude: Vec<glob::Pattern>,
+}
+
+impl PathFilter {
+    /// Whether any pattern was specified
+    pub fn is_active(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    /// Whether the file at `path`, relative to the unpacked directory, is selected
+    pub fn matches(&self, path: &Path) -> bool {
+        (self.include.is_empty() || is_included(&self.include, path))
+            && !is_included(&self.exclude, path)
+    }
+
+    /// Selected files below `input` as their path relative to it with forward slashes, which is
+    /// also their path in the pak, and their path on disk, sorted. The relative path doesn't
+    /// depend on how `input` is spelled, so patterns match the same whether it is given as
+    /// `mod`, `./mod` or an absolute path.
+    pub fn select_files(&self, input: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+        let mut paths = vec![];
+        crate::collect_files(&mut paths, input)?;
+        paths.sort();
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let rel = path
+                    .strip_prefix(input)
+                    .expect("file not in input directory")
+                    .to_slash()
+                    .expect("failed to convert to slash path")
+                    .into_owned();
+                (rel, path)
+            })
+            .filter(|(rel, _)| self.matches(Path::new(rel)))
+            .collect())
+    }
+}
+
+/// Whether `path` or any of its ancestor directories is matched by one of the `include`
+/// patterns
+pub fn is_included(include: &[glob::Pattern], path: &Path) -> bool {
+    let options = glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    include.iter().any(|i| {
+        // check full file path
+        i.matches_path_with(path, options)
+            // check ancestor directories
+            || path.ancestors().skip(1).any(|a| {
+                i.matches_path_with(a, options)
+                    // hack to check ancestor directories with trailing slash
+                    || i.matches_path_with(&a.join(""), options)
+            })
+    })
+}
+
+/// Extensions of the files a cooked asset is split into, which are only usable together
+pub const ASSET_EXTENSIONS: &[&str] = &["uasset", "uexp", "ubulk", "uptnl"];
+
+/// Entries of `entries` which share their stem with a `selected` cooked asset file but weren't
+/// selected themselves, e.g. `Foo.uexp` and `Foo.ubulk` for a selected `Foo.uasset`. Extensions
+/// are compared case-insensitively, stems exactly.
+pub fn asset_siblings<'a>(
+    entries: impl IntoIterator<Item = &'a str>,
+    selected: &HashSet<&str>,
+) -> HashSet<&'a str> {
+    let stem = |path: &'a str| {
+        let (stem, extension) = path.rsplit_once('.')?;
+        ASSET_EXTENSIONS
+            .iter()
+            .any(|e| e.eq_ignore_ascii_case(extension))
+            .then_some(stem)
+    };
+    let mut assets = HashMap::<&str, Vec<&str>>::new();
+    for path in entries {
+        if let Some(stem) = stem(path) {
+            assets.entry(stem).or_default().push(path);
+        }
+    }
+    assets
+        .into_values()
+        .filter(|group| group.iter().any(|path| selected.contains(path)))
+        .flatten()
+        .filter(|path| !selected.contains(path))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{asset_siblings, is_included, EntryFilter, PathFilter};
+    use repak::{Compression, EntryInfo};
+    use std::path::Path;
+
+    fn entry(compression: Option<Compression>, encrypted: bool, uncompressed: u64) -> EntryInfo {
+        EntryInfo {
+            offset: 0,
+            compressed: uncompressed / 2,
+            uncompressed,
+            compression,
+            encrypted,
+            encoded_offset: None,
+        }
+    }
+
+    #[test]
+    fn test_default_matches_everything() {
+        let filter = EntryFilter::default();
+        assert!(!filter.is_active());
+        assert!(filter.matches(&entry(None, false, 0)));
+        assert!(filter.matches(&entry(Some(Compression::Zlib), true, 100)));
+    }
+
+    #[test]
+    fn test_flags() {
+        let plain = entry(None, false, 100);
+        let encrypted = entry(None, true, 100);
+        let zlib = entry(Some(Compression::Zlib), false, 100);
+        let oodle = entry(Some(Compression::Oodle), true, 100);
+
+        let filter = EntryFilter {
+            only_encrypted: true,
+            ..Default::default()
+        };
+        assert!(filter.is_active());
+        assert!(!filter.matches(&plain));
+        assert!(filter.matches(&encrypted));
+        assert!(!filter.matches(&zlib));
+        assert!(filter.matches(&oodle));
+
+        let filter = EntryFilter {
+            only_compressed: true,
+            ..Default::default()
+        };
+        assert!(!filter.matches(&plain));
+        assert!(!filter.matches(&encrypted));
+        assert!(filter.matches(&zlib));
+        assert!(filter.matches(&oodle));
+
+        let filter = EntryFilter {
+            compression: Some(Compression::Zlib),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&plain));
+        assert!(filter.matches(&zlib));
+        assert!(!filter.matches(&oodle));
+
+        let filter = EntryFilter {
+            only_encrypted: true,
+            only_compressed: true,
+            ..Default::default()
+        };
+        assert!(!filter.matches(&encrypted));
+        assert!(!filter.matches(&zlib));
+        assert!(filter.matches(&oodle));
+    }
+
+    #[test]
+    fn test_size_bounds_are_inclusive() {
+        let filter = EntryFilter {
+            min_size: Some(10),
+            max_size: Some(20),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&entry(None, false, 9)));
+        assert!(filter.matches(&entry(None, false, 10)));
+        assert!(filter.matches(&entry(None, false, 20)));
+        assert!(!filter.matches(&entry(None, false, 21)));
+    }
+
+    #[test]
+    fn test_is_included() {
+        let included = |patterns: &[&str], path: &str| {
+            let patterns = patterns
+                .iter()
+                .map(|p| glob::Pattern::new(p).unwrap())
+                .collect::<Vec<_>>();
+            is_included(&patterns, Path::new(path))
+        };
+
+        assert!(!included(&[], "root/test.txt"));
+
+        // full paths
+        assert!(included(&["root/test.txt"], "root/test.txt"));
+        assert!(included(&["root/*.txt"], "root/test.txt"));
+        assert!(!included(&["root/*.txt"], "root/directory/nested.txt"));
+        assert!(included(&["root/**/*.txt"], "root/directory/nested.txt"));
+        assert!(!included(&["Root/test.txt"], "root/test.txt"));
+
+        // ancestor directories with and without a trailing slash
+        assert!(included(&["root"], "root/directory/nested.txt"));
+        assert!(included(&["root/directory"], "root/directory/nested.txt"));
+        assert!(included(&["root/directory/"], "root/directory/nested.txt"));
+        assert!(included(&["root/dir*"], "root/directory/nested.txt"));
+        assert!(!included(&["root/directory"], "root/test.txt"));
+        assert!(!included(&["directory"], "root/directory/nested.txt"));
+
+        // any pattern may match
+        assert!(included(&["root/*.png", "root/*.txt"], "root/test.txt"));
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let patterns = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .map(|p| glob::Pattern::new(p).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let filter = |include: &[&str], exclude: &[&str]| PathFilter {
+            include: patterns(include),
+            exclude: patterns(exclude),
+        };
+
+        let all = filter(&[], &[]);
+        assert!(!all.is_active());
+        assert!(all.matches(Path::new("root/test.txt")));
+
+        // excludes alone remove from everything
+        let no_txt = filter(&[], &["**/*.txt"]);
+        assert!(no_txt.is_active());
+        assert!(!no_txt.matches(Path::new("root/test.txt")));
+        assert!(!no_txt.matches(Path::new("root/directory/nested.txt")));
+        assert!(no_txt.matches(Path::new("root/test.png")));
+
+        // excludes win over includes, including whole directories
+        let filter = filter(&["root"], &["root/directory/"]);
+        assert!(filter.matches(Path::new("root/test.txt")));
+        assert!(!filter.matches(Path::new("root/directory/nested.txt")));
+        assert!(!filter.matches(Path::new("other/test.txt")));
+    }
+
+    #[test]
+    fn test_asset_siblings() {
+        let entries = [
+            "Game/Foo.uasset",
+            "Game/Foo.uexp",
+            "Game/Foo.ubulk",
+            "Game/Foo.txt",
+            "Game/Sub/Foo.uexp",
+            "Game/Bar.uasset",
+            "Game/Bar.UEXP",
+            "Game/Baz.uptnl",
+            "Game/Baz.uasset",
+        ];
+        let siblings = |selected: &[&str]| {
+            let mut siblings = asset_siblings(entries, &selected.iter().copied().collect())
+                .into_iter()
+                .collect::<Vec<_>>();
+            siblings.sort();
+            siblings
+        };
+
+        assert!(siblings(&[]).is_empty());
+        assert_eq!(
+            siblings(&["Game/Foo.uasset"]),
+            ["Game/Foo.ubulk", "Game/Foo.uexp"]
+        );
+        // any part of an asset pulls in the rest, already selected parts aren't repeated
+        assert_eq!(
+            siblings(&["Game/Foo.uexp", "Game/Foo.ubulk"]),
+            ["Game/Foo.uasset"]
+        );
+        assert_eq!(siblings(&["Game/Bar.uasset"]), ["Game/Bar.UEXP"]);
+        assert_eq!(siblings(&["Game/Baz.uasset"]), ["Game/Baz.uptnl"]);
+        // other extensions and directories don't count
+        assert!(siblings(&["Game/Foo.txt"]).is_empty());
+        assert!(siblings(&["Game/Sub/Foo.uexp"]).is_empty());
+    }
+
+    #[test]
+    fn test_select_files_input_spelling() {
+        let filter = PathFilter {
+            include: vec![],
+            exclude: vec![
+                glob::Pattern::new("root/directory").unwrap(),
+                glob::Pattern::new("root/*.png").unwrap(),
+            ],
+        };
+        let absolute = std::env::current_dir().unwrap().join("../repak/tests/pack");
+        let selected = |input: &Path| {
+            filter
+                .select_files(input)
+                .unwrap()
+                .into_iter()
+                .map(|(rel, _)| rel)
+                .collect::<Vec<_>>()
+        };
+        for input in [
+            Path::new("../repak/tests/pack"),
+            Path::new("./../repak/tests/pack"),
+            Path::new("../repak/tests/pack/"),
+            Path::new("../repak/tests/./pack"),
+            &absolute,
+        ] {
+            assert_eq!(
+                selected(input),
+                ["root/test.txt", "root/zeros.bin"],
+                "{}",
+                input.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Hashes of entry contents printed by hash-list
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha1,
+    /// 64 bit XXH3, much faster but not cryptographic
+    #[cfg(feature = "xxh3")]
+    Xxh3,
+}
+
+/// Streaming hasher for one of the [`HashAlgorithm`]s
+pub enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+    #[cfg(feature = "xxh3")]
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        use sha2::Digest;
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            #[cfg(feature = "xxh3")]
+            HashAlgorithm::Xxh3 => Self::Xxh3(Default::default()),
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        use sha2::Digest;
+        match self {
+            Self::Sha256(hasher) => hasher.finalize().to_vec(),
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            #[cfg(feature = "xxh3")]
+            Self::Xxh3(hasher) => hasher.digest().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        use sha2::Digest;
+        match self {
+            Self::Sha256(hasher) => hasher.update(buf),
+            Self::Sha1(hasher) => hasher.update(buf),
+            #[cfg(feature = "xxh3")]
+            Self::Xxh3(hasher) => hasher.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parses the SHA256 hashes by path listed by hash-list without `--merged`, see
+/// [`crate::text::lines`]
+pub fn parse_hash_list(text: &str) -> Result<BTreeMap<String, [u8; 32]>, repak::Error> {
+    crate::text::lines(text)
+        .map(|(i, line)| {
+            let invalid = || {
+                repak::Error::Other(format!(
+                    "line {i} of hash list is not a SHA256 hash followed by a path"
+                ))
+            };
+            let (hash, path) = line.split_once(' ').ok_or_else(invalid)?;
+            let mut bytes = [0; 32];
+            hex::decode_to_slice(hash, &mut bytes).map_err(|_| invalid())?;
+            Ok((path.to_owned(), bytes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_hash_list, HashAlgorithm, Hasher};
+    use std::io::Write;
+
+    #[test]
+    fn test_hasher() {
+        for (algorithm, expected) in [
+            (
+                HashAlgorithm::Sha256,
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                HashAlgorithm::Sha1,
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            ),
+            #[cfg(feature = "xxh3")]
+            (HashAlgorithm::Xxh3, "2d06800538d394c2"),
+        ] {
+            assert_eq!(hex::encode(Hasher::new(algorithm).finish()), expected);
+
+            // split writes hash the same as a single one
+            let mut hasher = Hasher::new(algorithm);
+            hasher.write_all(b"repak").unwrap();
+            hasher.write_all(b"").unwrap();
+            hasher.write_all(b" hash").unwrap();
+            let mut single = Hasher::new(algorithm);
+            single.write_all(b"repak hash").unwrap();
+            assert_eq!(hasher.finish(), single.finish());
+        }
+    }
+
+    #[test]
+    fn test_parse_hash_list() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let hashes =
+            parse_hash_list(&format!("{hash} a.txt\n\n{hash} dir/with space.txt\n")).unwrap();
+        assert_eq!(
+            hashes.keys().collect::<Vec<_>>(),
+            ["a.txt", "dir/with space.txt"]
+        );
+        assert_eq!(hex::encode(hashes["a.txt"]), hash);
+
+        // other algorithms and merged listings don't parse
+        assert!(parse_hash_list("da39a3ee5e6b4b0d3255bfef95601890afd80709 a.txt").is_err());
+        assert!(parse_hash_list("a.txt").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! AES keys listed by GUID in a crypto.json as used by FModel and other tools
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{AesKey, CliError};
+
+/// Environment variable read for the AES key when neither `--aes-key` nor `--aes-key-file` is
+/// given
+pub const AES_KEY_VAR: &str = "REPAK_AES_KEY";
+
+#[derive(Deserialize)]
+struct KeyEntry {
+    #[serde(alias = "Guid")]
+    guid: String,
+    #[serde(alias = "Key")]
+    key: String,
+}
+
+/// Parses a GUID written as the four hex components of an FGuid, optionally separated by dashes
+/// and prefixed with 0x, into the value stored in pak footers
+fn parse_guid(s: &str) -> Option<u128> {
+    let hex = s
+        .strip_prefix("0x")
+        .unwrap_or(s)
+        .chars()
+        .filter(|&c| c != '-')
+        .collect::<String>();
+    if hex.len() != 32 {
+        return None;
+    }
+    (0..4).try_fold(0, |guid, i| {
+        let component = u32::from_str_radix(hex.get(i * 8..i * 8 + 8)?, 16).ok()?;
+        Some(guid | (component as u128) << (32 * i))
+    })
+}
+
+/// Reads either a JSON list of `{"Guid": ..., "Key": ...}` objects or the Crypto.json of an
+/// Unreal project with its `EncryptionKey` and `SecondaryEncryptionKeys`
+pub fn read_key_file(path: &Path) -> Result<Vec<(u128, aes::Aes256)>, CliError> {
+    use serde_json::Value;
+
+    let invalid = |message: String| CliError::KeyFile {
+        path: path.to_string_lossy().into_owned(),
+        message,
+    };
+    // saved by Notepad with a byte order mark or as UTF-16 as often as not
+    let json: Value = serde_json::from_str(crate::text::TextFile::read(path)?.text())
+        .map_err(|err| invalid(format!("invalid JSON: {err}")))?;
+    let entries = match json {
+        Value::Array(entries) => entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| (format!("entry {i}"), entry))
+            .collect(),
+        Value::Object(mut object)
+            if object.contains_key("EncryptionKey")
+                || object.contains_key("SecondaryEncryptionKeys") =>
+        {
+            let mut entries = vec![];
+            if let Some(key) = object.remove("EncryptionKey").filter(|key| !key.is_null()) {
+                entries.push(("EncryptionKey".to_owned(), key));
+            }
+            match object.remove("SecondaryEncryptionKeys") {
+                None | Some(Value::Null) => {}
+                Some(Value::Array(keys)) => entries.extend(
+                    keys.into_iter()
+                        .enumerate()
+                        .map(|(i, key)| (format!("SecondaryEncryptionKeys entry {i}"), key)),
+                ),
+                Some(_) => return Err(invalid("SecondaryEncryptionKeys is not a list".to_owned())),
+            }
+            entries
+        }
+        _ => {
+            return Err(invalid(
+                "expected a list of {Guid, Key} objects or an Unreal Crypto.json".to_owned(),
+            ))
+        }
+    };
+    entries
+        .into_iter()
+        .map(|(name, entry)| {
+            let entry =
+                KeyEntry::deserialize(entry).map_err(|err| invalid(format!("{name}: {err}")))?;
+            let guid = parse_guid(&entry.guid)
+                .ok_or_else(|| invalid(format!("{name}: invalid GUID {:?}", entry.guid)))?;
+            let key = entry
+                .key
+                .parse::<AesKey>()
+                .map_err(|err| invalid(format!("{name}: {err}")))?;
+            Ok((guid, key.0))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_guid;
+
+    #[test]
+    fn test_parse_guid() {
+        assert_eq!(parse_guid("00000000000000000000000000000000"), Some(0));
+        assert_eq!(
+            parse_guid("0x00000001000000020000000300000004"),
+            Some(1 | 2 << 32 | 3 << 64 | 4 << 96)
+        );
+        assert_eq!(
+            parse_guid("00000001-00000002-00000003-0000000A"),
+            Some(1 | 2 << 32 | 3 << 64 | 10 << 96)
+        );
+        assert_eq!(parse_guid("0000000100000002000000030000000"), None);
+        assert_eq!(parse_guid("0000000100000002000000030000000G"), None);
+        assert_eq!(parse_guid("000000010000000200000003000000é"), None);
+    }
+}
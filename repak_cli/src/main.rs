@@ -1,41 +1,134 @@
-use std::collections::BTreeMap;
+mod analyze;
+mod audit;
+mod doctor;
+mod filter;
+mod hash;
+mod keys;
+mod mount;
+mod order;
+mod outdirs;
+mod overlay;
+mod patch;
+mod sharing;
+mod siblings;
+mod space;
+mod sparse;
+mod stack;
+mod symlink;
+mod text;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{self, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 
 use clap::builder::TypedValueParser;
 use clap::{Parser, Subcommand};
-use path_clean::PathClean;
+use filter::{EntryFilter, PathFilter};
+use mount::MountedPaths;
 use path_slash::PathExt;
 use rayon::prelude::*;
 use strum::VariantNames;
 
 #[derive(Parser, Debug)]
 struct ActionInfo {
-    /// Input .pak path
-    #[arg(index = 1)]
-    input: String,
+    /// Input .pak paths. Directories are expanded to the .pak files they contain
+    #[arg(index = 1, required = true)]
+    input: Vec<String>,
+
+    /// Also report dead space left behind by replaced entries, records of the path hash index which disagree with the full directory index, whether Zlib entries are stored as raw deflate streams, and compression blocks which don't decompress to the compression block size
+    #[arg(long)]
+    check: bool,
+
+    /// Number of compressed entries spread over the pak whose blocks --check decompresses. Defaults to all of them
+    #[arg(long, value_name = "N", requires = "check")]
+    block_sample: Option<usize>,
+
+    /// Also print the mod metadata stored in repak_mod.json, which reads the full index like --check
+    #[arg(long)]
+    mod_metadata: bool,
 }
 
 #[derive(Parser, Debug)]
 struct ActionList {
+    /// Input .pak paths. Directories are expanded to the .pak files they contain
+    #[arg(index = 1, required = true)]
+    input: Vec<String>,
+
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
+    strip_prefix: String,
+
+    /// Print size, compression and encryption of each entry
+    #[arg(short, long, default_value = "false")]
+    long: bool,
+
+    #[command(flatten)]
+    filter: EntryFilter,
+}
+
+#[derive(Parser, Debug)]
+struct ActionLsTree {
     /// Input .pak path
     #[arg(index = 1)]
     input: String,
 
-    /// Prefix to strip from entry path
-    #[arg(short, long, default_value = "../../../")]
-    strip_prefix: String,
+    /// Maximum directory depth to expand. Directories below are summarized only
+    #[arg(short, long)]
+    depth: Option<usize>,
+
+    /// Order of entries within a directory
+    #[arg(long, value_enum, default_value_t = TreeSort::Name)]
+    sort: TreeSort,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum TreeSort {
+    /// Alphabetical by name
+    Name,
+    /// Largest uncompressed size first
+    Size,
 }
 
 #[derive(Parser, Debug)]
 struct ActionHashList {
+    /// Input .pak paths. Directories are expanded to the .pak files they contain
+    #[arg(index = 1, required = true)]
+    input: Vec<String>,
+
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
+    strip_prefix: String,
+
+    /// Print a single listing of all inputs sorted by path, with the name of the pak as a column
+    #[arg(long, default_value = "false")]
+    merged: bool,
+
+    /// Hash algorithm
+    #[arg(long, value_enum, default_value_t = hash::HashAlgorithm::Sha256)]
+    algorithm: hash::HashAlgorithm,
+
+    /// Hash the data as stored, still compressed and encrypted, instead of the contents. Much cheaper, but the hash also changes if only the compression or encryption does
+    #[arg(long, default_value = "false")]
+    compressed_hash: bool,
+
+    /// Read the entries on a single thread in the order of their data in the pak, which avoids seeking and is often faster on spinning disks
+    #[arg(long, default_value = "false")]
+    sequential: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ActionChanged {
     /// Input .pak path
     #[arg(index = 1)]
     input: String,
 
-    /// Prefix to strip from entry path
-    #[arg(short, long, default_value = "../../../")]
+    /// Output of hash-list (without --merged) for the pak to compare with
+    #[arg(long)]
+    baseline: PathBuf,
+
+    /// Prefix to strip from entry path, which must match the one the baseline was listed with. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
     strip_prefix: String,
 }
 
@@ -49,8 +142,8 @@ struct ActionUnpack {
     #[arg(short, long)]
     output: Option<String>,
 
-    /// Prefix to strip from entry path
-    #[arg(short, long, default_value = "../../../")]
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
     strip_prefix: String,
 
     /// Verbose
@@ -65,20 +158,57 @@ struct ActionUnpack {
     #[arg(short, long, default_value = "false")]
     force: bool,
 
-    /// Files or directories to include. Can be specified multiple times. If not specified, everything is extracted.
-    #[arg(action = clap::ArgAction::Append, short, long)]
-    include: Vec<glob::Pattern>,
+    #[command(flatten)]
+    paths: PathFilter,
+
+    /// Also unpack the other parts of selected cooked assets (.uasset, .uexp, .ubulk, .uptnl)
+    /// with the same name, even if they weren't matched by --include
+    #[arg(long, default_value = "false")]
+    with_siblings: bool,
+
+    /// Look for files matched by an --include pattern that matches nothing in the input pak in the other .pak files of the same directory, such as the other chunks of a pakchunk set, and unpack them from the first one containing them by mount priority
+    #[arg(long, default_value = "false", requires = "include")]
+    search_siblings: bool,
+
+    #[command(flatten)]
+    filter: EntryFilter,
+
+    /// Print the entries that would be unpacked and where to without writing anything
+    #[arg(long, default_value = "false")]
+    dry_run: bool,
+
+    /// Skip checking the hash and size of each entry as it is unpacked
+    #[arg(long, default_value = "false")]
+    no_verify: bool,
+
+    /// Leave holes in place of zero-filled blocks of unpacked files on filesystems supporting
+    /// sparse files instead of writing the zeros
+    #[arg(long, default_value = "false")]
+    sparse: bool,
+
+    /// Write through symbolic links to directories or files found inside the output directory
+    /// instead of failing, which may write outside of it
+    #[arg(long, default_value = "false")]
+    follow_symlinks: bool,
+
+    /// Unpack even if the output filesystem has less free space than the unpacked entries need
+    #[arg(long, default_value = "false")]
+    no_space_check: bool,
+
+    /// Write a JSON line to this file for each pak, with its footer details and SHA256, and for each unpacked entry, with its sizes, output path and hash. Lines are written as entries finish so an interrupted run keeps what was unpacked
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    audit_log: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
 struct ActionPack {
-    /// Input directory
-    #[arg(index = 1)]
-    input: String,
+    /// Input directories, optionally followed by the output .pak path, which defaults to next to the first input directory, or - to write the pak to stdout after staging it in memory and a temporary file. Files of later directories take the place of files with the same path in earlier ones, see --conflict. Symbolic links to directories are followed
+    #[arg(index = 1, required = true, value_name = "INPUT")]
+    inputs: Vec<String>,
 
-    /// Output directory. Defaults to next to input dir
-    #[arg(index = 2)]
-    output: Option<String>,
+    /// What to do with files whose path is in more than one input directory
+    #[arg(long, value_enum, default_value_t = overlay::Conflict::Last)]
+    conflict: overlay::Conflict,
 
     /// Mount point
     #[arg(short, long, default_value = "../../../")]
@@ -103,6 +233,36 @@ struct ActionPack {
     #[arg(short, long, default_value = "0")]
     path_hash_seed: u64,
 
+    /// Use the mount point and path hash seed of this pak, such as a pak of the game, for loaders which only accept paks mounted exactly where the game's own are
+    #[arg(long, value_name = "REFERENCE", conflicts_with_all = ["mount_point", "path_hash_seed"])]
+    match_mount_of: Option<PathBuf>,
+
+    /// File listing paths relative to the input directories, one per line, to write first in that order for faster loading. Lines may be glob patterns, lines starting with # are comments. Remaining files follow sorted by path
+    #[arg(long)]
+    order: Option<PathBuf>,
+
+    /// Fail instead of writing an index (including the footer) larger than this many bytes, for platforms which can't allocate large indexes at mount
+    #[arg(long, value_name = "BYTES")]
+    max_index_size: Option<u64>,
+
+    /// Store files with identical contents once, with an entry for each path sharing the data
+    #[arg(long, default_value = "false")]
+    dedup: bool,
+
+    /// Read the pak back after writing it and fail if its index or entry records differ from what was written or the data of an entry doesn't match its hash
+    #[arg(long, default_value = "false")]
+    verify: bool,
+
+    /// Number of entries spread over the pak whose data --verify reads back. Defaults to all of them
+    #[arg(long, value_name = "N", requires = "verify")]
+    verify_sample: Option<usize>,
+
+    #[command(flatten)]
+    paths: PathFilter,
+
+    #[command(flatten)]
+    mod_metadata: ModMetadataArgs,
+
     /// Verbose
     #[arg(short, long, default_value = "false")]
     verbose: bool,
@@ -112,6 +272,44 @@ struct ActionPack {
     quiet: bool,
 }
 
+/// Mod metadata written by pack to repak_mod.json when any of it is given
+#[derive(clap::Args, Debug)]
+struct ModMetadataArgs {
+    /// Name of the mod, stored in repak_mod.json in the pak for mod managers
+    #[arg(long)]
+    mod_name: Option<String>,
+
+    /// Version of the mod, stored in repak_mod.json
+    #[arg(long)]
+    mod_version: Option<String>,
+
+    /// Author of the mod, stored in repak_mod.json
+    #[arg(long)]
+    mod_author: Option<String>,
+
+    /// Description of the mod, stored in repak_mod.json
+    #[arg(long)]
+    mod_description: Option<String>,
+
+    /// Mod this one depends on, stored in repak_mod.json. May be given more than once
+    #[arg(long, value_name = "MOD")]
+    mod_dependency: Vec<String>,
+}
+
+impl ModMetadataArgs {
+    fn metadata(&self) -> Option<repak::ModMetadata> {
+        let metadata = repak::ModMetadata {
+            name: self.mod_name.clone(),
+            version: self.mod_version.clone(),
+            author: self.mod_author.clone(),
+            description: self.mod_description.clone(),
+            dependencies: self.mod_dependency.clone(),
+            ..Default::default()
+        };
+        (metadata != repak::ModMetadata::default()).then_some(metadata)
+    }
+}
+
 #[derive(Parser, Debug)]
 struct ActionGet {
     /// Input .pak path
@@ -122,38 +320,393 @@ struct ActionGet {
     #[arg(index = 2)]
     file: String,
 
-    /// Prefix to strip from entry path
-    #[arg(short, long, default_value = "../../../")]
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
+    strip_prefix: String,
+
+    /// Byte offset in the file to start reading at
+    #[arg(long)]
+    offset: Option<u64>,
+
+    /// Number of bytes to read. Defaults to the rest of the file
+    #[arg(long)]
+    length: Option<u64>,
+
+    /// Read only the last N bytes of the file
+    #[arg(long, value_name = "N", conflicts_with_all = ["offset", "length"])]
+    tail: Option<u64>,
+
+    /// If the file isn't in the pak, look for it in the other .pak files of the same directory, such as the other chunks of a pakchunk set, highest priority first. The pak it is read from is printed to stderr
+    #[arg(long, default_value = "false")]
+    search_siblings: bool,
+
+    /// Print the stripped prefix and how many blocks of the file were read to stderr
+    #[arg(short, long, default_value = "false")]
+    verbose: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ActionEntryBlocks {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Path of the file whose blocks to list
+    #[arg(index = 2)]
+    file: String,
+
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
+    strip_prefix: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionCherryPick {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Output .pak path
+    #[arg(index = 2)]
+    output: String,
+
+    /// Files or directories to copy. Globs are matched the same as unpack --include
+    #[arg(index = 3, required = true)]
+    paths: Vec<glob::Pattern>,
+
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
+    strip_prefix: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionBlank {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Output .pak path
+    #[arg(index = 2)]
+    output: String,
+
+    /// Files or directories to blank. Globs are matched the same as unpack --include. Can be specified multiple times
+    #[arg(action = clap::ArgAction::Append, short, long, required = true)]
+    include: Vec<glob::Pattern>,
+
+    /// File whose contents replace the data of blanked entries instead of leaving them empty
+    #[arg(long)]
+    stub_file: Option<PathBuf>,
+
+    /// Prefix to strip from entry path. "auto" strips the "../" segments the mount point starts with
+    #[arg(short, long, default_value = mount::AUTO_PREFIX)]
     strip_prefix: String,
 }
 
+#[derive(Parser, Debug)]
+struct ActionDecrypt {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Output .pak path
+    #[arg(index = 2)]
+    output: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionCompact {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Output .pak path
+    #[arg(index = 2)]
+    output: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionFixIndex {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Output .pak path
+    #[arg(index = 2)]
+    output: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionMakePatch {
+    /// Old .pak path
+    #[arg(index = 1)]
+    old: String,
+
+    /// New .pak path
+    #[arg(index = 2)]
+    new: String,
+
+    /// Output patch path
+    #[arg(index = 3)]
+    output: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionApplyPatch {
+    /// Old .pak path the patch was made from
+    #[arg(index = 1)]
+    input: String,
+
+    /// Patch path
+    #[arg(index = 2)]
+    patch: String,
+
+    /// Output .pak path
+    #[arg(index = 3)]
+    output: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionAnalyze {
+    /// Input directory or .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Compression methods to try on a directory
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "Zlib,Zstd",
+        value_parser = clap::builder::PossibleValuesParser::new(repak::Compression::VARIANTS).map(|s| s.parse::<repak::Compression>().unwrap())
+    )]
+    compression: Vec<repak::Compression>,
+
+    /// Maximum number of bytes of each file to trial compress. The compressed size of larger files is projected from this sample
+    #[arg(long, default_value = "1048576", value_parser = clap::value_parser!(u64).range(1..))]
+    sample_size: u64,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+#[derive(Parser, Debug)]
+struct ActionLayout {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionDoctor {
+    /// Input .pak path
+    #[arg(index = 1)]
+    input: String,
+
+    /// Original .pak of the game to compare against
+    #[arg(long)]
+    reference: String,
+}
+
+#[derive(Parser, Debug)]
+struct ActionMergedList {
+    /// Directory containing the .pak files of a game such as Content/Paks, including its subdirectories
+    #[arg(index = 1)]
+    input: String,
+
+    /// Only list the paks containing this file, highest priority first so the first provides it
+    #[arg(long, value_name = "PATH")]
+    who_has: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
 #[derive(Subcommand, Debug)]
 enum Action {
     /// Print .pak info
     Info(ActionInfo),
     /// List .pak files
     List(ActionList),
-    /// List .pak files and the SHA256 of their contents. Useful for finding differences between paks
+    /// List .pak files as a tree with cumulative directory sizes
+    LsTree(ActionLsTree),
+    /// List .pak files and a hash of their contents, SHA256 by default. Useful for finding differences between paks
     HashList(ActionHashList),
+    /// List .pak files whose contents differ from a hash-list of another .pak, prefixed with M (modified), A (added) or D (removed)
+    Changed(ActionChanged),
     /// Unpack .pak file
     Unpack(ActionUnpack),
-    /// Pack directory into .pak file
+    /// Pack directories into a .pak file
     Pack(ActionPack),
     /// Reads a single file to stdout
     Get(ActionGet),
+    /// List the compression blocks of a file with the size each decompresses to, flagging blocks other than the last which don't decompress to the compression block size
+    EntryBlocks(ActionEntryBlocks),
+    /// Copy entries into a new .pak file without recompressing them
+    CherryPick(ActionCherryPick),
+    /// Copy a .pak with the data of some entries replaced by empty or stub data, keeping their paths
+    Blank(ActionBlank),
+    /// Copy a .pak with its index and entries decrypted, keeping their compression
+    Decrypt(ActionDecrypt),
+    /// Copy a .pak without the dead space left behind by replaced entries
+    Compact(ActionCompact),
+    /// Copy a .pak with its path hash and full directory indexes regenerated from its entries
+    FixIndex(ActionFixIndex),
+    /// Create a patch which turns the old .pak into the new one
+    MakePatch(ActionMakePatch),
+    /// Reconstruct the new .pak from the old one and a patch created with make-patch
+    ApplyPatch(ActionApplyPatch),
+    /// Report how well a directory would compress or how well the entries of a .pak did
+    Analyze(ActionAnalyze),
+    /// List .pak entries in the order of their data with their offsets, sizes and the unused bytes after them
+    Layout(ActionLayout),
+    /// Check a .pak against an original .pak of the game for common reasons it won't load
+    Doctor(ActionDoctor),
+    /// List the files a game sees once the .pak files of a directory are mounted in the order of their priority, with the pak providing each
+    MergedList(ActionMergedList),
 }
 
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  Unclassified error
+  2  Invalid arguments or input
+  3  Not a pak, corrupt, or unsupported version
+  4  Pak is encrypted and the AES key is missing or wrong
+  5  I/O error
+  6  Some inputs of a batch operation failed
+  7  Output directory is not empty, an entry would be written outside of it, or the index
+     exceeds --max-index-size
+  8  Required feature is unavailable";
+
 #[derive(Parser, Debug)]
-#[command(author, version)]
+#[command(author, version, after_help = EXIT_CODES)]
 struct Args {
-    /// 256 bit AES encryption key as base64 or hex string if the pak is encrypted
+    /// 256 bit AES encryption key as base64 or hex string if the pak is encrypted. Defaults to the REPAK_AES_KEY environment variable unless --aes-key-file is given
     #[arg(short, long)]
     aes_key: Option<AesKey>,
 
+    /// crypto.json listing keys by GUID, either a JSON list of {"Guid": ..., "Key": ...} objects as used by FModel or the Crypto.json of an Unreal project. The key matching the GUID of the pak is used unless --aes-key is given
+    #[arg(long)]
+    aes_key_file: Option<PathBuf>,
+
+    /// 256 bit AES key for entry data if it differs from the key of the index
+    #[arg(long, global = true)]
+    data_key: Option<AesKey>,
+
+    /// Footer magic as hex (0x prefixed) or decimal for games using a non-standard value
+    #[arg(long, global = true, value_parser = parse_magic)]
+    magic: Option<u32>,
+
+    /// Number of unknown bytes between the standard footer fields and the compression names for games which extend the footer
+    #[arg(long, global = true, default_value = "0")]
+    footer_extra_bytes: usize,
+
+    /// Read entry names which are not valid UTF-8 or UTF-16 lossily instead of failing
+    #[arg(long, global = true, default_value = "false")]
+    lossy_paths: bool,
+
+    /// Read paks whose index doesn't match its stored hash instead of failing, to salvage a corrupt index
+    #[arg(long, global = true, default_value = "false")]
+    ignore_index_hashes: bool,
+
+    /// Skip entries whose compression block count can't be valid for their size with a warning instead of failing, to salvage the other entries of a corrupt index
+    #[arg(long, global = true, default_value = "false")]
+    skip_implausible_entries: bool,
+
+    /// Decrypt only the first N bytes of encrypted entries for games which encrypt just the start of each file
+    #[arg(long, global = true, value_name = "N")]
+    decrypt_limit: Option<usize>,
+
+    /// Fail on paks with problems that are otherwise worked around with a warning
+    #[arg(long, global = true, default_value = "false")]
+    warnings_as_errors: bool,
+
     #[command(subcommand)]
     action: Action,
 }
 
+fn parse_magic(s: &str) -> Result<u32, std::num::ParseIntError> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+/// Global options used to configure every PakBuilder
+#[derive(Debug)]
+struct Config {
+    /// Set up once from the global options and cloned for each pak
+    builder: repak::PakBuilder,
+    magic: Option<u32>,
+}
+
+impl Config {
+    /// Builder with the global options, for adding options of a single command
+    fn builder(&self) -> repak::PakBuilder {
+        self.builder.clone()
+    }
+}
+
+/// Builder with the keys and the other global options of `args`
+fn global_builder(
+    args: &Args,
+    aes_key: Option<aes::Aes256>,
+    guid_keys: Vec<(u128, aes::Aes256)>,
+) -> repak::PakBuilder {
+    let mut builder = repak::PakBuilder::new();
+    if let Some(aes_key) = aes_key {
+        builder = builder.key(aes_key);
+    } else if !guid_keys.is_empty() {
+        builder = builder.keys_by_guid(guid_keys);
+    }
+    if let Some(data_key) = &args.data_key {
+        builder = builder.data_key(data_key.0.clone());
+    }
+    if let Some(magic) = args.magic {
+        builder = builder.magic(magic);
+    }
+    if let Some(limit) = args.decrypt_limit {
+        builder = builder.decrypt_limit(repak::DecryptLimit::FirstBytes(limit));
+    }
+    builder
+        .footer_extra_bytes(args.footer_extra_bytes)
+        .lossy_paths(args.lossy_paths)
+        .ignore_index_hashes(args.ignore_index_hashes)
+        .skip_implausible_entries(args.skip_implausible_entries)
+        .strict(args.warnings_as_errors)
+}
+
+fn print_mod_metadata(metadata: &repak::ModMetadata) {
+    let fields = [
+        ("name", &metadata.name),
+        ("version", &metadata.version),
+        ("author", &metadata.author),
+        ("description", &metadata.description),
+    ];
+    for (field, value) in fields {
+        if let Some(value) = value {
+            println!("mod {field}: {value}");
+        }
+    }
+    if !metadata.dependencies.is_empty() {
+        println!("mod dependencies: {}", metadata.dependencies.join(", "));
+    }
+}
+
+/// Prints the problems repak worked around while reading `pak` to stderr
+fn print_warnings(input: impl AsRef<Path>, pak: &repak::PakReader) {
+    for warning in pak.warnings() {
+        eprintln!("warning: {}: {warning}", input.as_ref().display());
+    }
+}
+
 #[derive(Debug, Clone)]
 struct AesKey(aes::Aes256);
 impl std::str::FromStr for AesKey {
@@ -175,329 +728,1328 @@ impl std::str::FromStr for AesKey {
     }
 }
 
-fn main() -> Result<(), repak::Error> {
-    let args = Args::parse();
-    let aes_key = args.aes_key.map(|k| k.0);
+/// Errors which end the process, reported as `error[E{code}]: {message}`
+#[derive(Debug)]
+enum CliError {
+    Pak(repak::Error),
+    Patch(patch::Error),
+    /// A key given by file or environment variable rather than an argument is malformed
+    KeyFile {
+        path: String,
+        message: String,
+    },
+    /// A byte range requested from an entry extends past its end
+    Range {
+        path: String,
+        offset: u64,
+        length: u64,
+        size: u64,
+    },
+    /// The index of a pak being written exceeds `--max-index-size`
+    IndexSize {
+        size: u64,
+        max: u64,
+    },
+    /// Some inputs of a batch operation failed and have already been reported
+    Partial {
+        failed: usize,
+        total: usize,
+    },
+}
 
-    match args.action {
-        Action::Info(action) => info(aes_key, action),
-        Action::List(action) => list(aes_key, action),
-        Action::HashList(action) => hash_list(aes_key, action),
-        Action::Unpack(action) => unpack(aes_key, action),
-        Action::Pack(action) => pack(action),
-        Action::Get(action) => get(aes_key, action),
+impl From<repak::Error> for CliError {
+    fn from(value: repak::Error) -> Self {
+        Self::Pak(value)
     }
 }
 
-fn info(aes_key: Option<aes::Aes256>, action: ActionInfo) -> Result<(), repak::Error> {
-    let mut builder = repak::PakBuilder::new();
-    if let Some(aes_key) = aes_key {
-        builder = builder.key(aes_key);
+impl From<std::io::Error> for CliError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Pak(value.into())
     }
-    let pak = builder.reader(&mut BufReader::new(File::open(action.input)?))?;
-    println!("mount point: {}", pak.mount_point());
-    println!("version: {}", pak.version());
-    println!("version major: {}", pak.version().version_major());
-    println!("encrypted index: {}", pak.encrypted_index());
-    println!("encrytion guid: {:032X?}", pak.encryption_guid());
-    println!("path hash seed: {:08X?}", pak.path_hash_seed());
-    println!("{} file entries", pak.files().len());
-    Ok(())
 }
 
-fn list(aes_key: Option<aes::Aes256>, action: ActionList) -> Result<(), repak::Error> {
-    let mut builder = repak::PakBuilder::new();
-    if let Some(aes_key) = aes_key {
-        builder = builder.key(aes_key);
+impl From<patch::Error> for CliError {
+    fn from(value: patch::Error) -> Self {
+        Self::Patch(value)
     }
-    let pak = builder.reader(&mut BufReader::new(File::open(action.input)?))?;
-
-    let mount_point = PathBuf::from(pak.mount_point());
-    let prefix = Path::new(&action.strip_prefix);
+}
 
-    let full_paths = pak
-        .files()
-        .into_iter()
-        .map(|f| mount_point.join(f))
-        .collect::<Vec<_>>();
-    let stripped = full_paths
-        .iter()
-        .map(|f| {
-            f.strip_prefix(prefix)
-                .map_err(|_| repak::Error::PrefixMismatch {
-                    path: f.to_string_lossy().to_string(),
-                    prefix: prefix.to_string_lossy().to_string(),
-                })
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+impl CliError {
+    fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Pak(err) => exit_code(err.kind()),
+            CliError::Patch(err) => exit_code(err.kind()),
+            CliError::KeyFile { .. } | CliError::Range { .. } => {
+                exit_code(repak::ErrorKind::InvalidInput)
+            }
+            CliError::IndexSize { .. } => exit_code(repak::ErrorKind::Output),
+            CliError::Partial { .. } => 6,
+        }
+    }
+}
 
-    for f in stripped {
-        println!("{}", f.to_slash_lossy());
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Pak(err) => err.fmt(f),
+            CliError::Patch(err) => err.fmt(f),
+            CliError::KeyFile { path, message } => write!(f, "invalid key in {path}: {message}"),
+            CliError::Range {
+                path,
+                offset,
+                length,
+                size,
+            } => write!(
+                f,
+                "{length} bytes at offset {offset} exceed {path}, which is {size} bytes"
+            ),
+            CliError::IndexSize { size, max } => write!(
+                f,
+                "the index would be {size} bytes, more than the maximum of {max} bytes"
+            ),
+            CliError::Partial { failed, total } => write!(f, "{failed} of {total} inputs failed"),
+        }
     }
+}
 
-    Ok(())
+/// Must be kept in sync with [`EXIT_CODES`]
+fn exit_code(kind: repak::ErrorKind) -> u8 {
+    match kind {
+        repak::ErrorKind::Other => 1,
+        repak::ErrorKind::InvalidInput => 2,
+        repak::ErrorKind::Format => 3,
+        repak::ErrorKind::Key => 4,
+        repak::ErrorKind::Io => 5,
+        repak::ErrorKind::Output => 7,
+        repak::ErrorKind::Unsupported => 8,
+    }
 }
 
-fn hash_list(aes_key: Option<aes::Aes256>, action: ActionHashList) -> Result<(), repak::Error> {
-    let mut builder = repak::PakBuilder::new();
-    if let Some(aes_key) = aes_key {
-        builder = builder.key(aes_key);
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error[E{}]: {err}", err.exit_code());
+            err.exit_code().into()
+        }
+    }
+}
+
+fn run() -> Result<(), CliError> {
+    let args = Args::parse();
+    // an explicit key takes precedence over the key file, which takes precedence over the
+    // environment
+    let guid_keys = match (&args.aes_key, &args.aes_key_file) {
+        (None, Some(path)) => keys::read_key_file(path)?,
+        _ => vec![],
+    };
+    let aes_key = match (args.aes_key.clone(), &args.aes_key_file) {
+        (Some(key), _) => Some(key),
+        (None, Some(_)) => None,
+        (None, None) => match std::env::var(keys::AES_KEY_VAR) {
+            Ok(key) if !key.is_empty() => {
+                Some(key.parse::<AesKey>().map_err(|err| CliError::KeyFile {
+                    path: keys::AES_KEY_VAR.to_owned(),
+                    message: err.to_string(),
+                })?)
+            }
+            _ => None,
+        },
+    };
+    let config = Config {
+        builder: global_builder(&args, aes_key.map(|k| k.0), guid_keys),
+        magic: args.magic,
+    };
+
+    match args.action {
+        Action::Info(action) => info(&config, action)?,
+        Action::List(action) => list(&config, action)?,
+        Action::LsTree(action) => ls_tree(&config, action)?,
+        Action::HashList(action) => hash_list(&config, action)?,
+        Action::Changed(action) => changed(&config, action)?,
+        Action::Unpack(action) => unpack(&config, action)?,
+        Action::Pack(action) => pack(&config, action)?,
+        Action::Get(action) => get(&config, action)?,
+        Action::EntryBlocks(action) => entry_blocks(&config, action)?,
+        Action::CherryPick(action) => cherry_pick(&config, action)?,
+        Action::Blank(action) => blank(&config, action)?,
+        Action::Decrypt(action) => decrypt(&config, action)?,
+        Action::Compact(action) => compact(&config, action)?,
+        Action::FixIndex(action) => fix_index(&config, action)?,
+        Action::MakePatch(action) => make_patch(&config, action)?,
+        Action::ApplyPatch(action) => apply_patch(&config, action)?,
+        Action::Analyze(action) => analyze(&config, action)?,
+        Action::Layout(action) => layout(&config, action)?,
+        Action::Doctor(action) => doctor(&config, action)?,
+        Action::MergedList(action) => merged_list(&config, action)?,
+    }
+    Ok(())
+}
+
+/// Expands directories among `inputs` to the .pak files they contain
+fn expand_inputs(inputs: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for input in inputs {
+        let input = PathBuf::from(input);
+        if input.is_dir() {
+            let mut paks = fs::read_dir(&input)?
+                .map(|entry| Ok(entry?.path()))
+                .filter(|path| {
+                    path.as_ref().map_or(true, |path: &PathBuf| {
+                        path.is_file()
+                            && path
+                                .extension()
+                                .is_some_and(|e| e.eq_ignore_ascii_case("pak"))
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            paks.sort();
+            paths.extend(paks);
+        } else {
+            paths.push(input);
+        }
+    }
+    Ok(paths)
+}
+
+/// Runs `f` for each of `inputs`. A single pak is handled as is, while multiple paks are each
+/// printed in a section headed by their path and failures are reported without stopping the
+/// remaining paks.
+fn for_each_input(
+    inputs: &[String],
+    mut f: impl FnMut(&Path) -> Result<(), repak::Error>,
+) -> Result<(), CliError> {
+    let paths = expand_inputs(inputs).map_err(repak::Error::from)?;
+    if let ([path], [input]) = (&paths[..], inputs) {
+        if Path::new(input) == path {
+            return Ok(f(path)?);
+        }
+    }
+    let mut failed = 0;
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("==> {} <==", path.display());
+        if let Err(err) = f(path) {
+            eprintln!(
+                "error[E{}]: {}: {err}",
+                exit_code(err.kind()),
+                path.display()
+            );
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(CliError::Partial {
+            failed,
+            total: paths.len(),
+        }),
+    }
+}
+
+fn info(config: &Config, action: ActionInfo) -> Result<(), CliError> {
+    for_each_input(&action.input, |input| info_pak(config, &action, input))
+}
+
+fn info_pak(config: &Config, action: &ActionInfo, input: &Path) -> Result<(), repak::Error> {
+    let mut reader = BufReader::new(sharing::open_pak(input)?);
+    // the checks and the mod metadata need the entries, everything else is in the index header
+    let full_index = action.check || action.mod_metadata;
+    let pak = config
+        .builder
+        .clone()
+        .metadata_only(!full_index)
+        .reader(&mut reader)?;
+    print_warnings(input, &pak);
+    println!("mount point: {}", pak.mount_point());
+    println!("version: {}", pak.version());
+    println!("version major: {}", pak.version().version_major());
+    println!("encrypted index: {}", pak.encrypted_index());
+    println!("encrytion guid: {:032X?}", pak.encryption_guid());
+    println!("path hash seed: {:08X?}", pak.path_hash_seed());
+    if !pak.footer_extra_bytes().is_empty() {
+        println!(
+            "footer extra bytes: {}",
+            hex::encode_upper(pak.footer_extra_bytes())
+        );
+    }
+    print_compression_slots(config, &pak, &mut reader)?;
+    println!("{} file entries", pak.entry_count());
+    if full_index {
+        if let Some(metadata) = pak.mod_metadata(&mut reader)? {
+            print_mod_metadata(&metadata);
+        }
+    }
+    if action.check {
+        if let Some(repak::VersionDetection {
+            score,
+            max_score,
+            runner_up: Some((runner_up, runner_up_score)),
+        }) = pak.version_detection()
+        {
+            println!(
+                "version detection: {} passed {score} of {max_score} checks, also parses as {runner_up} which passed {runner_up_score}",
+                pak.version()
+            );
+        }
+        let report = pak.fragmentation_report(input.metadata()?.len());
+        println!(
+            "dead space: {} bytes ({:.1}%) in {} gaps, largest {} bytes",
+            report.dead_bytes,
+            report.percentage(),
+            report.gaps,
+            report.largest_gap
+        );
+        if let Some(report) = pak.check_path_hash_index() {
+            print_path_hash_index_report(&report);
+        }
+        // the format is detected by reading the first block of a Zlib entry
+        let zlib_entry = pak.file_names().find(|path| {
+            pak.entry_info(path).is_some_and(|info| {
+                info.compression == Some(repak::Compression::Zlib) && info.uncompressed > 0
+            })
+        });
+        if let Some(path) = zlib_entry {
+            use io::Read;
+            // a failed read leaves the format undetected, reported as unknown
+            let _ = pak
+                .open_entry(path, &mut reader)
+                .map(|mut entry| entry.read_exact(&mut [0]));
+            println!(
+                "zlib format: {}",
+                pak.zlib_format()
+                    .map_or("unknown".to_owned(), |format| format.to_string())
+            );
+        }
+        check_blocks(&pak, &mut reader, action.block_sample)?;
+    }
+    Ok(())
+}
+
+fn print_path_hash_index_report(report: &repak::PathHashIndexReport) {
+    if report.is_consistent() {
+        println!("path hash index: consistent");
+        return;
+    }
+    println!(
+        "path hash index: {} missing, {} mismatched, {} extra records",
+        report.missing.len(),
+        report.mismatched.len(),
+        report.extra.len()
+    );
+    for path in &report.missing {
+        println!("  missing: {path}");
+    }
+    for path in &report.mismatched {
+        println!("  mismatched: {path}");
+    }
+    for (hash, offset) in &report.extra {
+        println!("  extra: {hash:016X} -> encoded entry at {offset}");
+    }
+}
+
+/// Prints the non-empty compression name slots of the footer, with the stored bytes of names
+/// that don't match a supported method
+fn print_compression_slots<R: io::Read + io::Seek>(
+    config: &Config,
+    pak: &repak::PakReader,
+    reader: &mut R,
+) -> Result<(), repak::Error> {
+    let range = repak::Footer::range(reader.seek(io::SeekFrom::End(0))?);
+    let extra_bytes = pak.footer_extra_bytes().len();
+    reader.seek(io::SeekFrom::Start(
+        range.start.saturating_sub(extra_bytes as u64),
+    ))?;
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let footer = repak::Footer::parse_with_extra_bytes(
+        &bytes,
+        pak.version(),
+        config.magic.unwrap_or(repak::MAGIC),
+        extra_bytes,
+    )?;
+    for (i, (slot, raw)) in footer
+        .compression_slots()
+        .iter()
+        .zip(footer.raw_compression_slots())
+        .enumerate()
+    {
+        match slot {
+            repak::CompressionSlot::Empty => {}
+            repak::CompressionSlot::Known(compression) => {
+                println!("compression slot {i}: {compression}")
+            }
+            repak::CompressionSlot::Unknown(name) => {
+                let len = raw.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+                println!(
+                    "compression slot {i}: unknown {name:?} (stored as {})",
+                    raw[..len]
+                        .iter()
+                        .map(|b| format!("{b:02X}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list(config: &Config, action: ActionList) -> Result<(), CliError> {
+    for_each_input(&action.input, |input| list_pak(config, &action, input))
+}
+
+fn list_pak(config: &Config, action: &ActionList, input: &Path) -> Result<(), repak::Error> {
+    let pak = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(input)?))?;
+    print_warnings(input, &pak);
+
+    let paths = MountedPaths::new(pak.mount_point(), &action.strip_prefix);
+
+    let total = pak.len();
+    let entries = pak
+        .file_names()
+        .filter_map(|f| {
+            let info = pak.entry_info(f).expect("listed entry exists");
+            action
+                .filter
+                .matches(&info)
+                .then(|| (paths.full_path(f), info, pak.is_lossy_path(f)))
+        })
+        .collect::<Vec<_>>();
+    let stripped = entries
+        .iter()
+        .map(|(f, info, lossy)| paths.stripped(f).map(|f| (f, info, lossy)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (f, info, lossy) in stripped {
+        let marker = match lossy {
+            true => " [lossy]",
+            false => "",
+        };
+        if action.long {
+            let mut details = format!(
+                "{} bytes, {} compressed",
+                info.uncompressed, info.compressed
+            );
+            if let Some(compression) = info.compression {
+                details += &format!(", {compression}");
+            }
+            if info.encrypted {
+                details += ", encrypted";
+            }
+            println!("{}{marker} ({details})", f.to_slash_lossy());
+        } else {
+            println!("{}{marker}", f.to_slash_lossy());
+        }
+    }
+    if action.filter.is_active() {
+        eprintln!("{} of {} entries matched", entries.len(), total);
+    }
+
+    Ok(())
+}
+
+fn ls_tree(config: &Config, action: ActionLsTree) -> Result<(), repak::Error> {
+    let pak = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(&action.input)?))?;
+    print_warnings(&action.input, &pak);
+    let summary = pak.directory_summary();
+
+    enum Node<'a> {
+        Directory(&'a repak::DirectorySummary),
+        File(&'a repak::EntryInfo),
+    }
+    impl Node<'_> {
+        fn uncompressed(&self) -> u64 {
+            match self {
+                Node::Directory(dir) => dir.uncompressed,
+                Node::File(info) => info.uncompressed,
+            }
+        }
+    }
+
+    fn print_directory(dir: &repak::DirectorySummary, level: usize, action: &ActionLsTree) {
+        if action.depth.is_some_and(|depth| level > depth) {
+            return;
+        }
+        let mut children = dir
+            .directories
+            .iter()
+            .map(|(name, dir)| (name, Node::Directory(dir)))
+            .chain(
+                dir.files
+                    .iter()
+                    .map(|(name, info)| (name, Node::File(info))),
+            )
+            .collect::<Vec<_>>();
+        match action.sort {
+            TreeSort::Name => children.sort_by_key(|(name, _)| *name),
+            TreeSort::Size => {
+                children.sort_by_key(|(_, node)| std::cmp::Reverse(node.uncompressed()))
+            }
+        }
+        let indent = "  ".repeat(level);
+        for (name, node) in children {
+            match node {
+                Node::Directory(dir) => {
+                    println!(
+                        "{indent}{name}/ ({} files, {} bytes, {} compressed)",
+                        dir.file_count, dir.uncompressed, dir.compressed
+                    );
+                    print_directory(dir, level + 1, action);
+                }
+                Node::File(info) => println!(
+                    "{indent}{name} ({} bytes, {} compressed)",
+                    info.uncompressed, info.compressed
+                ),
+            }
+        }
+    }
+
+    println!(
+        "{} ({} files, {} bytes, {} compressed)",
+        pak.mount_point(),
+        summary.file_count,
+        summary.uncompressed,
+        summary.compressed
+    );
+    print_directory(&summary, 1, &action);
+
+    Ok(())
+}
+
+fn hash_list(config: &Config, action: ActionHashList) -> Result<(), CliError> {
+    if !action.merged {
+        return for_each_input(&action.input, |input| {
+            for (file, hash) in hash_pak(config, &action, input)? {
+                println!("{} {}", hex::encode(hash), file);
+            }
+            Ok(())
+        });
+    }
+
+    let paths = expand_inputs(&action.input).map_err(repak::Error::from)?;
+    let mut merged = vec![];
+    let mut failed = 0;
+    for path in &paths {
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        match hash_pak(config, &action, path) {
+            Ok(hashes) => merged.extend(
+                hashes
+                    .into_iter()
+                    .map(|(file, hash)| (file, name.clone(), hash)),
+            ),
+            Err(err) => {
+                eprintln!(
+                    "error[E{}]: {}: {err}",
+                    exit_code(err.kind()),
+                    path.display()
+                );
+                failed += 1;
+            }
+        }
+    }
+    merged.sort();
+    for (file, pak, hash) in merged {
+        println!("{} {} {}", hex::encode(hash), pak, file);
     }
-    let pak = builder.reader(&mut BufReader::new(File::open(&action.input)?))?;
+    match failed {
+        0 => Ok(()),
+        failed => Err(CliError::Partial {
+            failed,
+            total: paths.len(),
+        }),
+    }
+}
 
-    let mount_point = PathBuf::from(pak.mount_point());
-    let prefix = Path::new(&action.strip_prefix);
+/// Hash of the contents (or the stored data with `--compressed-hash`) of each entry in `input`
+/// by path with the prefix stripped
+fn hash_pak(
+    config: &Config,
+    action: &ActionHashList,
+    input: &Path,
+) -> Result<BTreeMap<String, Vec<u8>>, repak::Error> {
+    let pak = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(input)?))?;
+    print_warnings(input, &pak);
+
+    let paths = MountedPaths::new(pak.mount_point(), &action.strip_prefix);
 
     let full_paths = pak
-        .files()
-        .into_iter()
-        .map(|f| (mount_point.join(&f), f))
+        .file_names()
+        .map(|f| (paths.full_path(f), f))
         .collect::<Vec<_>>();
     let stripped = full_paths
         .iter()
-        .map(|(full_path, _path)| {
-            full_path
-                .strip_prefix(prefix)
-                .map_err(|_| repak::Error::PrefixMismatch {
-                    path: full_path.to_string_lossy().to_string(),
-                    prefix: prefix.to_string_lossy().to_string(),
-                })
-        })
+        .map(|(full_path, _path)| paths.stripped(full_path))
         .collect::<Result<Vec<_>, _>>()?;
 
-    let hashes: std::sync::Arc<std::sync::Mutex<BTreeMap<std::borrow::Cow<'_, str>, Vec<u8>>>> =
-        Default::default();
+    if action.sequential {
+        let stripped = full_paths
+            .iter()
+            .map(|(_full_path, path)| *path)
+            .zip(stripped)
+            .collect::<HashMap<_, _>>();
+        let mut hashes = BTreeMap::new();
+        let mut insert = |path: &str, hasher: hash::Hasher| {
+            hashes.insert(
+                stripped[path].to_slash_lossy().into_owned(),
+                hasher.finish(),
+            );
+        };
+        let mut reader = BufReader::new(sharing::open_input(input)?);
+        if action.compressed_hash {
+            for entry in pak.entries_by_offset() {
+                let mut hasher = hash::Hasher::new(action.algorithm);
+                pak.read_stored(&entry.path, &mut reader, &mut hasher)?;
+                insert(&entry.path, hasher);
+            }
+        } else {
+            pak.read_all_sequential(&mut reader, |path, data| {
+                let mut hasher = hash::Hasher::new(action.algorithm);
+                io::Write::write_all(&mut hasher, data)?;
+                insert(path, hasher);
+                Ok(())
+            })?;
+        }
+        return Ok(hashes);
+    }
+
+    let hashes: std::sync::Arc<std::sync::Mutex<BTreeMap<String, Vec<u8>>>> = Default::default();
     full_paths.par_iter().zip(stripped).try_for_each_init(
-        || (hashes.clone(), File::open(&action.input)),
+        || (hashes.clone(), sharing::open_input(input)),
         |(hashes, file), ((_full_path, path), stripped)| -> Result<(), repak::Error> {
-            use sha2::Digest;
-
-            let mut hasher = sha2::Sha256::new();
-            pak.read_file(
-                path,
-                &mut BufReader::new(file.as_ref().unwrap()),
-                &mut hasher,
-            )?;
-            let hash = hasher.finalize();
+            let mut hasher = hash::Hasher::new(action.algorithm);
+            let mut reader = BufReader::new(file.as_ref().unwrap());
+            if action.compressed_hash {
+                pak.read_stored(path, &mut reader, &mut hasher)?;
+            } else {
+                // streamed a block at a time so memory use doesn't grow with the entry size
+                io::copy(&mut pak.open_entry(path, &mut reader)?, &mut hasher)?;
+            }
             hashes
                 .lock()
                 .unwrap()
-                .insert(stripped.to_slash_lossy(), hash.to_vec());
+                .insert(stripped.to_slash_lossy().into_owned(), hasher.finish());
             Ok(())
         },
     )?;
 
-    for (file, hash) in hashes.lock().unwrap().iter() {
-        println!("{} {}", hex::encode(hash), file);
+    let hashes = std::mem::take(&mut *hashes.lock().unwrap());
+    Ok(hashes)
+}
+
+fn changed(config: &Config, action: ActionChanged) -> Result<(), CliError> {
+    let pak = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(&action.input)?))?;
+    print_warnings(&action.input, &pak);
+    let paths = MountedPaths::new(pak.mount_point(), &action.strip_prefix);
+
+    // listed paths outside of the mount point can't match an entry
+    let mut baseline = BTreeMap::new();
+    let mut outside = vec![];
+    for (path, hash) in hash::parse_hash_list(text::TextFile::read(&action.baseline)?.text())? {
+        match paths.entry_path(&path) {
+            Ok(entry) => {
+                baseline.insert(entry.to_slash_lossy().into_owned(), hash);
+            }
+            Err(_) => outside.push(path),
+        }
     }
 
+    let changes = pak.changed_entries(&baseline, || {
+        sharing::open_input(&action.input).map(BufReader::new)
+    })?;
+    let stripped = |path: &str| -> Result<String, repak::Error> {
+        let full_path = paths.full_path(path);
+        Ok(paths.stripped(&full_path)?.to_slash_lossy().into_owned())
+    };
+    let mut lines = outside
+        .into_iter()
+        .map(|path| Ok(('D', path)))
+        .chain(
+            changes
+                .modified
+                .iter()
+                .map(|path| Ok(('M', stripped(path)?))),
+        )
+        .chain(changes.added.iter().map(|path| Ok(('A', stripped(path)?))))
+        .chain(
+            changes
+                .removed
+                .iter()
+                .map(|path| Ok(('D', stripped(path)?))),
+        )
+        .collect::<Result<Vec<_>, repak::Error>>()?;
+    lines.sort_by(|a, b| a.1.cmp(&b.1));
+    for (status, path) in lines {
+        println!("{status} {path}");
+    }
     Ok(())
 }
 
 const STYLE: &str = "[{elapsed_precise}] [{wide_bar}] {pos}/{len} ({eta})";
+/// Progress of [`pack`], in bytes read with the running ratio of stored to read bytes
+const PACK_STYLE: &str = "[{elapsed_precise}] [{wide_bar}] {binary_bytes}/{binary_total_bytes} {binary_bytes_per_sec} ({eta}) {msg}";
 
 #[derive(Clone)]
 enum Output {
     Progress(indicatif::ProgressBar),
     Stdout,
+    Stderr,
 }
 impl Output {
     pub fn println<I: AsRef<str>>(&self, msg: I) {
         match self {
             Output::Progress(progress) => progress.println(msg),
             Output::Stdout => println!("{}", msg.as_ref()),
+            Output::Stderr => eprintln!("{}", msg.as_ref()),
         }
     }
 }
 
-fn unpack(aes_key: Option<aes::Aes256>, action: ActionUnpack) -> Result<(), repak::Error> {
+fn unpack(config: &Config, action: ActionUnpack) -> Result<(), CliError> {
+    let audit = match &action.audit_log {
+        Some(path) => Some(audit::JsonLines::create(path)?),
+        None => None,
+    };
+    let audit = audit.as_ref().map(|log| log as &dyn audit::AuditLog);
+    let builder = config.builder().verify_reads(!action.no_verify);
+    if let [input] = &action.input[..] {
+        return Ok(unpack_pak(&builder, &action, input, audit)?);
+    }
+    let mut failed = 0;
     for input in &action.input {
-        let mut builder = repak::PakBuilder::new();
-        if let Some(aes_key) = aes_key.clone() {
-            builder = builder.key(aes_key);
-        }
-        let pak = builder.reader(&mut BufReader::new(File::open(input)?))?;
-        let output = action
-            .output
-            .as_ref()
-            .map(PathBuf::from)
-            .unwrap_or_else(|| Path::new(input).with_extension(""));
+        if let Err(err) = unpack_pak(&builder, &action, input, audit) {
+            eprintln!("error[E{}]: {input}: {err}", exit_code(err.kind()));
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(CliError::Partial {
+            failed,
+            total: action.input.len(),
+        }),
+    }
+}
+
+/// Entry selected for unpacking
+struct UnpackEntry<'a> {
+    entry_path: &'a str,
+    out_path: PathBuf,
+    out_dir: PathBuf,
+    /// Uncompressed size
+    size: u64,
+    /// Added by --with-siblings rather than matched by the path filter
+    sibling: bool,
+}
+
+/// Applies the include and exclude patterns of `selection` and the filters of `action` to the
+/// entries of `pak` and resolves where each is written below `output`. Shared by real and dry
+/// runs so they can't diverge.
+fn resolve_unpack_entries<'a>(
+    pak: &'a repak::PakReader,
+    action: &ActionUnpack,
+    selection: &PathFilter,
+    output: &Path,
+) -> Result<Vec<UnpackEntry<'a>>, repak::Error> {
+    let paths = MountedPaths::new(pak.mount_point(), &action.strip_prefix);
+    if action.verbose {
+        println!("strip prefix: {}", paths.prefix().to_slash_lossy());
+    }
+
+    let matched = pak
+        .file_names()
+        .filter(|entry_path| {
+            !selection.is_active()
+                || paths
+                    .strip(&paths.full_path(entry_path))
+                    .is_some_and(|stripped| selection.matches(stripped))
+        })
+        .collect::<HashSet<_>>();
+    let siblings = match action.with_siblings {
+        true => filter::asset_siblings(pak.file_names(), &matched),
+        false => HashSet::new(),
+    };
+
+    pak.file_names()
+        .map(|entry_path| {
+            let sibling = siblings.contains(entry_path);
+            if !sibling && !matched.contains(entry_path) {
+                return Ok(None);
+            }
+            let info = pak.entry_info(entry_path).expect("listed entry exists");
+            if !action.filter.matches(&info) {
+                return Ok(None);
+            }
+            let out_path = paths.out_path(output, entry_path)?;
+
+            let out_dir = out_path.parent().expect("will be a file").to_path_buf();
+
+            Ok(Some(UnpackEntry {
+                entry_path,
+                out_path,
+                out_dir,
+                size: info.uncompressed,
+                sibling,
+            }))
+        })
+        .filter_map(|e| e.transpose())
+        .collect()
+}
+
+/// Prints how many entries the filters matched and how many siblings were added, if used
+fn report_matches(action: &ActionUnpack, entries: &[UnpackEntry], total: usize) {
+    if action.filter.is_active() {
+        println!("{} of {} entries matched", entries.len(), total);
+    }
+    if action.with_siblings {
+        let siblings = entries.iter().filter(|e| e.sibling).count();
+        println!("{siblings} sibling entries added by --with-siblings");
+    }
+}
+
+/// Unpacks `input`, opened with `builder`, which has the options of `action`
+fn unpack_pak(
+    builder: &repak::PakBuilder,
+    action: &ActionUnpack,
+    input: &str,
+    audit: Option<&dyn audit::AuditLog>,
+) -> Result<(), repak::Error> {
+    let pak = builder.build_reader(&mut BufReader::new(sharing::open_pak(input)?))?;
+    print_warnings(input, &pak);
+    let output = action
+        .output
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(input).with_extension(""));
+    if !action.dry_run {
         match fs::create_dir(&output) {
             Ok(_) => Ok(()),
-            Err(ref e)
-                if action.output.is_some() && e.kind() == std::io::ErrorKind::AlreadyExists =>
-            {
-                Ok(())
-            }
+            // an existing default output directory is checked for contents below
+            Err(ref e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(()),
             Err(e) => Err(e),
         }?;
-        if action.output.is_none() && !action.force && output.read_dir()?.next().is_some() {
-            return Err(repak::Error::OutputNotEmpty(
-                output.to_string_lossy().to_string(),
-            ));
+    }
+    let non_empty = match output.read_dir() {
+        Ok(mut dir) => dir.next().is_some(),
+        // a dry run doesn't create the output directory
+        Err(e) if action.dry_run && e.kind() == io::ErrorKind::NotFound => false,
+        Err(e) => return Err(e.into()),
+    };
+    if action.output.is_none() && !action.force && non_empty {
+        return Err(repak::Error::OutputNotEmpty(
+            output.to_string_lossy().to_string(),
+        ));
+    }
+
+    let entries = resolve_unpack_entries(&pak, action, &action.paths, &output)?;
+    unpack_entries(action, &pak, input, &output, entries, audit)?;
+
+    if action.search_siblings {
+        let unmatched = unmatched_includes(&pak, action, &action.paths.include);
+        if !unmatched.is_empty() {
+            unpack_from_siblings(builder, action, input, &output, unmatched, audit)?;
         }
-        let mount_point = PathBuf::from(pak.mount_point());
-        let prefix = Path::new(&action.strip_prefix);
+    }
+    Ok(())
+}
 
-        struct UnpackEntry {
-            entry_path: String,
-            out_path: PathBuf,
-            out_dir: PathBuf,
+/// The patterns of `include` which match no entry of `pak`
+fn unmatched_includes(
+    pak: &repak::PakReader,
+    action: &ActionUnpack,
+    include: &[glob::Pattern],
+) -> Vec<glob::Pattern> {
+    let paths = MountedPaths::new(pak.mount_point(), &action.strip_prefix);
+    let mut unmatched = include.to_vec();
+    for entry_path in pak.file_names() {
+        let full_path = paths.full_path(entry_path);
+        if let Some(stripped) = paths.strip(&full_path) {
+            unmatched
+                .retain(|pattern| !filter::is_included(std::slice::from_ref(pattern), stripped));
         }
+    }
+    unmatched
+}
 
-        let entries = pak
-            .files()
+/// Unpacks the files matching `unmatched`, the --include patterns matching nothing in `input`,
+/// from the paks next to it for --search-siblings. Each pattern is served by the pak of highest
+/// priority with files matching it.
+fn unpack_from_siblings(
+    builder: &repak::PakBuilder,
+    action: &ActionUnpack,
+    input: &str,
+    output: &Path,
+    mut unmatched: Vec<glob::Pattern>,
+    audit: Option<&dyn audit::AuditLog>,
+) -> Result<(), repak::Error> {
+    let siblings = siblings::Siblings::new(Path::new(input), || builder.clone(), action.verbose)?;
+    for (path, pak) in siblings.candidates(&action.strip_prefix, siblings::overlaps_prefix) {
+        let remaining = unmatched_includes(pak, action, &unmatched);
+        if remaining.len() == unmatched.len() {
+            continue;
+        }
+        let include = std::mem::replace(&mut unmatched, remaining)
             .into_iter()
-            .map(|entry_path| {
-                let full_path = mount_point.join(&entry_path);
-                if !action.include.is_empty() {
-                    if let Ok(stripped) = full_path.strip_prefix(prefix) {
-                        let options = glob::MatchOptions {
-                            case_sensitive: true,
-                            require_literal_separator: true,
-                            require_literal_leading_dot: false,
-                        };
-                        if !action.include.iter().any(|i| {
-                            // check full file path
-                            i.matches_path_with(stripped, options)
-                                // check ancestor directories
-                                || stripped.ancestors().skip(1).any(|a| {
-                                    i.matches_path_with(a, options)
-                                        // hack to check ancestor directories with trailing slash
-                                        || i.matches_path_with(&a.join(""), options)
-                                })
-                        }) {
-                            return Ok(None);
-                        }
-                    } else {
-                        return Ok(None);
-                    }
-                }
-                let out_path = output
-                    .join(full_path.strip_prefix(prefix).map_err(|_| {
-                        repak::Error::PrefixMismatch {
-                            path: full_path.to_string_lossy().to_string(),
-                            prefix: prefix.to_string_lossy().to_string(),
-                        }
-                    })?)
-                    .clean();
+            .filter(|pattern| !unmatched.contains(pattern))
+            .collect();
+        let selection = PathFilter {
+            include,
+            exclude: action.paths.exclude.clone(),
+        };
+        let entries = resolve_unpack_entries(pak, action, &selection, output)?;
+        if !action.quiet {
+            println!(
+                "Found files missing from {input} in sibling pak {}",
+                path.display()
+            );
+        }
+        unpack_entries(action, pak, &path.to_string_lossy(), output, entries, audit)?;
+        if unmatched.is_empty() {
+            break;
+        }
+    }
+    if !action.quiet {
+        for pattern in &unmatched {
+            println!("{pattern} matches nothing in {input} or its sibling paks");
+        }
+    }
+    Ok(())
+}
 
-                if !out_path.starts_with(&output) {
-                    return Err(repak::Error::WriteOutsideOutput(
-                        out_path.to_string_lossy().to_string(),
-                    ));
-                }
+/// Writes `entries` of `pak`, read from the file at `input`, below `output`, or lists them for
+/// a dry run
+fn unpack_entries(
+    action: &ActionUnpack,
+    pak: &repak::PakReader,
+    input: &str,
+    output: &Path,
+    entries: Vec<UnpackEntry>,
+    audit: Option<&dyn audit::AuditLog>,
+) -> Result<(), repak::Error> {
+    let total = pak.len();
+
+    if action.dry_run {
+        for entry in &entries {
+            println!(
+                "{} -> {} ({} bytes)",
+                entry.entry_path,
+                entry.out_path.display(),
+                entry.size
+            );
+        }
+        report_matches(action, &entries, total);
+        println!(
+            "Would unpack {} files ({} bytes) to {} from {}",
+            entries.len(),
+            entries.iter().map(|e| e.size).sum::<u64>(),
+            output.display(),
+            input
+        );
+        return Ok(());
+    }
 
-                let out_dir = out_path.parent().expect("will be a file").to_path_buf();
+    let oodle = entries.iter().any(|entry| {
+        pak.entry_info(entry.entry_path)
+            .is_some_and(|info| info.compression == Some(repak::Compression::Oodle))
+    });
+    if oodle {
+        preload_oodle(action.quiet)?;
+    }
 
-                Ok(Some(UnpackEntry {
-                    entry_path,
-                    out_path,
-                    out_dir,
-                }))
-            })
-            .filter_map(|e| e.transpose())
-            .collect::<Result<Vec<_>, repak::Error>>()?;
+    let required = space::required_space(entries.iter().map(|e| e.size));
+    if !action.no_space_check {
+        space::check_space(&space::Filesystem, output, required)?;
+    }
+    if !action.quiet {
+        println!(
+            "Unpacking {} in {} files to {}",
+            indicatif::HumanBytes(required),
+            indicatif::HumanCount(entries.len() as u64),
+            output.display()
+        );
+    }
 
-        let progress = (!action.quiet).then(|| {
-            indicatif::ProgressBar::new(entries.len() as u64)
-                .with_style(indicatif::ProgressStyle::with_template(STYLE).unwrap())
-        });
-        let log = match &progress {
-            Some(progress) => Output::Progress(progress.clone()),
-            None => Output::Stdout,
-        };
+    if let Some(log) = audit {
+        audit::record_pak(log, input, pak)?;
+    }
 
-        entries.par_iter().try_for_each_init(
-            || (progress.clone(), File::open(input)),
-            |(progress, file), entry| -> Result<(), repak::Error> {
-                if action.verbose {
-                    log.println(format!("unpacking {}", entry.entry_path));
-                }
-                fs::create_dir_all(&entry.out_dir)?;
-                pak.read_file(
-                    &entry.entry_path,
-                    &mut BufReader::new(
-                        file.as_ref()
-                            .map_err(|e| repak::Error::Other(format!("error reading pak: {e}")))?,
-                    ),
-                    &mut fs::File::create(&entry.out_path)?,
-                )?;
-                if let Some(progress) = progress {
-                    progress.inc(1);
-                }
-                Ok(())
-            },
-        )?;
-        if let Some(progress) = progress {
-            progress.finish();
-        }
+    let progress = (!action.quiet).then(|| {
+        indicatif::ProgressBar::new(entries.len() as u64)
+            .with_style(indicatif::ProgressStyle::with_template(STYLE).unwrap())
+    });
+    let log = match &progress {
+        Some(progress) => Output::Progress(progress.clone()),
+        None => Output::Stdout,
+    };
 
-        if !action.quiet {
-            println!(
-                "Unpacked {} files to {} from {}",
-                entries.len(),
-                output.display(),
-                input
+    let dirs = outdirs::unique_dirs(entries.iter().map(|e| e.out_dir.as_path()));
+    outdirs::create_dirs(&dirs, |dir| match action.follow_symlinks {
+        true => Ok(()),
+        false => symlink::check_symlinks(output, dir),
+    })?;
+
+    entries.par_iter().try_for_each_init(
+        || (progress.clone(), sharing::open_input(input)),
+        |(progress, file), entry| -> Result<(), repak::Error> {
+            if action.verbose {
+                log.println(format!("unpacking {}", entry.entry_path));
+            }
+            if !action.follow_symlinks {
+                symlink::check_symlinks(output, &entry.out_path)?;
+            }
+            let reader = &mut BufReader::new(
+                file.as_ref()
+                    .map_err(|e| repak::Error::Other(format!("error reading pak: {e}")))?,
             );
+            // verified reads check the stored hash, which saves hashing the output again
+            let stored_hash = match (audit, action.no_verify) {
+                (Some(_), false) => pak.stored_hash(entry.entry_path, reader)?,
+                _ => None,
+            };
+            let hash_output = audit.is_some() && stored_hash.is_none();
+            let out = outdirs::create_file(&entry.out_path)?;
+            let output_hash = if action.sparse {
+                let mut writer =
+                    audit::HashingWriter::new(sparse::SparseFile::new(out), hash_output);
+                pak.read_file(entry.entry_path, reader, &mut writer)?;
+                let (sparse, hash) = writer.finish();
+                sparse.finish()?;
+                hash
+            } else {
+                let mut writer = audit::HashingWriter::new(out, hash_output);
+                pak.read_file(entry.entry_path, reader, &mut writer)?;
+                writer.finish().1
+            };
+            if let Some(log) = audit {
+                let info = pak
+                    .entry_info(entry.entry_path)
+                    .expect("listed entry exists");
+                let (hash_algorithm, hash_of, hash) = match stored_hash {
+                    Some(hash) => ("sha1", "stored", hex::encode(hash)),
+                    None => ("sha256", "output", output_hash.unwrap_or_default()),
+                };
+                log.record(&audit::Record::Entry(audit::EntryRecord {
+                    pak: input.to_owned(),
+                    path: entry.entry_path.to_owned(),
+                    output: entry.out_path.display().to_string(),
+                    compressed: info.compressed,
+                    uncompressed: info.uncompressed,
+                    hash_algorithm,
+                    hash_of,
+                    hash,
+                    timestamp: audit::timestamp(),
+                }))?;
+            }
+            if let Some(progress) = progress {
+                progress.inc(1);
+            }
+            Ok(())
+        },
+    )?;
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+
+    if !action.quiet {
+        report_matches(action, &entries, total);
+        println!(
+            "Unpacked {} files to {} from {}",
+            entries.len(),
+            output.display(),
+            input
+        );
+    }
+
+    Ok(())
+}
+
+fn collect_files(paths: &mut Vec<PathBuf>, dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(paths, &path)?;
+        } else {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Splits the positional arguments of pack into the input directories and the output path. The
+/// last argument is the output unless it is the only one or an existing directory.
+fn pack_inputs(args: &[String]) -> (&[String], Option<&String>) {
+    match args {
+        [inputs @ .., output] if !inputs.is_empty() && !Path::new(output).is_dir() => {
+            (inputs, Some(output))
         }
+        inputs => (inputs, None),
     }
+}
 
+/// Loads Oodle before the first entry needs it, as it is downloaded on first use which would
+/// otherwise stall the progress bar without explanation
+#[cfg_attr(not(feature = "oodle"), allow(unused_variables))]
+fn preload_oodle(quiet: bool) -> Result<(), repak::Error> {
+    // without the feature reading fails with a clearer error than loading would
+    #[cfg(feature = "oodle")]
+    {
+        if !quiet {
+            eprintln!("Loading Oodle, downloading it next to repak if it isn't there yet");
+        }
+        repak::preload_oodle()?;
+    }
     Ok(())
 }
 
-fn pack(args: ActionPack) -> Result<(), repak::Error> {
-    let output = args.output.map(PathBuf::from).unwrap_or_else(|| {
+/// Warns about files whose path starts with directories the mount point ends with, which the
+/// game finds at the doubled path rather than where they were meant to be
+fn warn_double_rooted<'a>(mount_point: &str, paths: impl Iterator<Item = &'a str>) {
+    // the files starting with each repeated prefix and the first of them
+    let mut repeated = BTreeMap::<String, (usize, &str)>::new();
+    for path in paths {
+        if let Some(prefix) = mount::double_rooted(mount_point, path) {
+            repeated.entry(prefix).or_insert((0, path)).0 += 1;
+        }
+    }
+    for (prefix, (count, example)) in repeated {
+        eprintln!(
+            "warning: {count} files such as {example} start with {prefix}, which the mount point {mount_point} already ends with, so the game finds them below {mount_point}{prefix}"
+        );
+    }
+}
+
+fn pack(config: &Config, args: ActionPack) -> Result<(), CliError> {
+    let (inputs, output) = pack_inputs(&args.inputs);
+    let output = output.map(PathBuf::from).unwrap_or_else(|| {
         // NOTE: don't use `with_extension` here because it will replace e.g. the `.1` in
         // `test_v1.1`.
-        PathBuf::from(format!("{}.pak", args.input))
+        PathBuf::from(format!("{}.pak", inputs[0]))
     });
 
-    fn collect_files(paths: &mut Vec<PathBuf>, dir: &Path) -> io::Result<()> {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                collect_files(paths, &path)?;
-            } else {
-                paths.push(entry.path());
+    let mut selected = vec![];
+    for input in inputs {
+        let input_path = Path::new(input);
+        if !input_path.is_dir() {
+            return Err(
+                repak::Error::InputNotADirectory(input_path.to_string_lossy().to_string()).into(),
+            );
+        }
+        selected.push(args.paths.select_files(input_path)?);
+    }
+    let overlay = overlay::merge(selected, args.conflict)?;
+    let mut paths = overlay.files;
+    if let Some(order) = &args.order {
+        let file = text::TextFile::read(order)?;
+        if args.verbose {
+            eprintln!("order file {}: {}", order.display(), file.describe());
+        }
+        let order = order::parse_order(&file)?;
+        for line in order::apply_order(&mut paths, &order) {
+            eprintln!(
+                "warning: line {} of order file does not match any file: {}",
+                line.line, line.text
+            );
+        }
+    }
+
+    let (mount_point, path_hash_seed) = match &args.match_mount_of {
+        Some(reference) => {
+            let reference = config
+                .builder()
+                .metadata_only(true)
+                .reader(&mut BufReader::new(sharing::open_pak(reference)?))?;
+            let seed = reference.path_hash_seed().unwrap_or(args.path_hash_seed);
+            if args.verbose {
+                eprintln!(
+                    "mount point {} and path hash seed {seed:08X} from reference",
+                    reference.mount_point()
+                );
             }
+            (reference.mount_point().to_owned(), seed)
+        }
+        None => (args.mount_point.clone(), args.path_hash_seed),
+    };
+    warn_double_rooted(&mount_point, paths.iter().map(|(path, _)| path.as_str()));
+    let mod_metadata = args.mod_metadata.metadata();
+    if mod_metadata.is_some() {
+        if let Some(i) = paths
+            .iter()
+            .position(|(path, _)| path == repak::MOD_METADATA_PATH)
+        {
+            let (path, file) = paths.remove(i);
+            eprintln!(
+                "warning: {} is replaced by the metadata given with --mod-*, so {path} is not packed",
+                file.display()
+            );
         }
-        Ok(())
     }
-    let input_path = Path::new(&args.input);
-    if !input_path.is_dir() {
-        return Err(repak::Error::InputNotADirectory(
-            input_path.to_string_lossy().to_string(),
-        ));
+    if args.compression == Some(repak::Compression::Oodle) {
+        preload_oodle(args.quiet)?;
     }
-    let mut paths = vec![];
-    collect_files(&mut paths, input_path)?;
-    paths.sort();
 
-    let mut pak = repak::PakBuilder::new()
-        .compression(args.compression.iter().cloned())
-        .writer(
-            BufWriter::new(File::create(&output)?),
+    let builder = config
+        .builder()
+        .compression(args.compression.iter().cloned());
+    // the pak itself goes to stdout when streaming, so the summary goes to stderr
+    let stream = output == Path::new("-");
+    let report = |line: String| match stream {
+        true => eprintln!("{line}"),
+        false => println!("{line}"),
+    };
+
+    let start = std::time::Instant::now();
+    let total = paths
+        .iter()
+        .map(|(_, p)| Ok(fs::metadata(p)?.len()))
+        .sum::<io::Result<u64>>()?;
+    let sample = args.verify_sample.unwrap_or(usize::MAX).min(paths.len());
+    let (written, deduplicated) = if stream {
+        let mut pak = builder.writer_streaming(
+            CountingWriter::new(BufWriter::new(io::stdout().lock())),
             args.version,
-            args.mount_point,
-            Some(args.path_hash_seed),
+            mount_point,
+            Some(path_hash_seed),
         );
+        pack_entries(
+            &mut pak,
+            &args,
+            &paths,
+            total,
+            mod_metadata.as_ref(),
+            stream,
+        )?;
+        // nothing has reached stdout yet
+        check_index_size(&pak, args.max_index_size)?;
+        let deduplicated = pak.deduplicated_bytes();
+        let mut out = match args.verify {
+            true => pak.write_index_verified(sample)?,
+            false => pak.write_index()?,
+        };
+        io::Write::flush(&mut out)?;
+        (out.count, deduplicated)
+    } else {
+        let mut pak = builder.writer(
+            BufWriter::new(
+                // readable for --verify
+                fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&output)?,
+            ),
+            args.version,
+            mount_point,
+            Some(path_hash_seed),
+        );
+        pack_entries(
+            &mut pak,
+            &args,
+            &paths,
+            total,
+            mod_metadata.as_ref(),
+            stream,
+        )?;
+        if let Err(err) = check_index_size(&pak, args.max_index_size) {
+            // don't leave a pak without an index behind
+            drop(pak);
+            fs::remove_file(&output)?;
+            return Err(err);
+        }
+        let deduplicated = pak.deduplicated_bytes();
+        match args.verify {
+            true => {
+                pak.write_index_verified(sample, |writer| {
+                    writer.into_inner().map_err(io::IntoInnerError::into_error)
+                })?;
+            }
+            false => {
+                pak.write_index()?;
+            }
+        }
+        (fs::metadata(&output)?.len(), deduplicated)
+    };
+
+    if !args.quiet {
+        use indicatif::HumanBytes;
+        let elapsed = start.elapsed();
+        report(format!(
+            "Packed {} files to {}",
+            paths.len(),
+            match stream {
+                true => "stdout".to_owned(),
+                false => output.display().to_string(),
+            }
+        ));
+        report(format!(
+            "{} in, {} out ({:.0}%)",
+            HumanBytes(total),
+            HumanBytes(written),
+            written as f64 * 100.0 / total.max(1) as f64
+        ));
+        report(format!(
+            "Took {elapsed:.2?} ({}/s)",
+            HumanBytes((total as f64 / elapsed.as_secs_f64().max(1e-6)) as u64)
+        ));
+        match args.conflict {
+            _ if overlay.conflicts == 0 => {}
+            overlay::Conflict::First => report(format!(
+                "{} files of later input directories ignored",
+                overlay.conflicts
+            )),
+            _ => report(format!(
+                "{} files overridden by later input directories",
+                overlay.conflicts
+            )),
+        }
+        if args.dedup {
+            report(format!("Deduplication saved {deduplicated} bytes"));
+        }
+        if args.verify {
+            report(format!(
+                "Verified the written pak, reading back the data of {sample} files"
+            ));
+        }
+    }
 
-    use indicatif::ProgressIterator;
+    Ok(())
+}
 
-    let iter = paths.iter();
-    let (log, iter) = if !args.quiet {
-        let iter =
-            iter.progress_with_style(indicatif::ProgressStyle::with_template(STYLE).unwrap());
-        (
-            Output::Progress(iter.progress.clone()),
-            itertools::Either::Left(iter),
-        )
+/// Writes the files of pack and its metadata, in the order of `paths`, leaving the index to
+/// the caller
+fn pack_entries<W: io::Write + io::Seek>(
+    pak: &mut repak::PakWriter<W>,
+    args: &ActionPack,
+    paths: &[(String, PathBuf)],
+    total: u64,
+    mod_metadata: Option<&repak::ModMetadata>,
+    stream: bool,
+) -> Result<(), CliError> {
+    let (progress, log) = if !args.quiet {
+        let progress = indicatif::ProgressBar::new(total)
+            .with_style(indicatif::ProgressStyle::with_template(PACK_STYLE).unwrap());
+        (progress.clone(), Output::Progress(progress))
+    } else if stream {
+        (indicatif::ProgressBar::hidden(), Output::Stderr)
     } else {
-        (Output::Stdout, itertools::Either::Right(iter))
+        (indicatif::ProgressBar::hidden(), Output::Stdout)
     };
-    let log = log.clone();
+    let iter = paths.iter().enumerate();
 
     let mut result = None;
     let result_ref = &mut result;
@@ -506,60 +2058,613 @@ fn pack(args: ActionPack) -> Result<(), repak::Error> {
         let entry_builder = pak.entry_builder();
 
         scope.spawn(move |_| {
-            *result_ref = Some(
-                iter.par_bridge()
-                    .try_for_each(|p| -> Result<(), repak::Error> {
-                        let rel = &p
-                            .strip_prefix(input_path)
-                            .expect("file not in input directory")
-                            .to_slash()
-                            .expect("failed to convert to slash path");
-                        if args.verbose {
-                            log.println(format!("packing {}", &rel));
-                        }
-                        let entry = entry_builder.build_entry(true, std::fs::read(p)?)?;
+            *result_ref = Some(iter.par_bridge().try_for_each(
+                |(i, (rel, p))| -> Result<(), repak::Error> {
+                    let data = std::fs::read(p)?;
+                    let hash = args.dedup.then(|| {
+                        use sha2::Digest;
+                        <[u8; 32]>::from(sha2::Sha256::digest(&data))
+                    });
+                    let entry = entry_builder.build_entry(true, data)?;
+                    if args.verbose {
+                        log.println(format!("packing {rel}: {}", describe_stored(&entry)));
+                    }
 
-                        tx.send((rel.to_string(), entry)).unwrap();
-                        Ok(())
-                    }),
-            );
+                    tx.send((i, rel.clone(), entry, hash)).unwrap();
+                    Ok(())
+                },
+            ));
         });
 
-        for (path, entry) in rx {
-            pak.write_entry(path, entry)?;
+        // entries are built out of order but written in the order of `paths`
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        // first path written with each content hash
+        let mut written: HashMap<[u8; 32], String> = HashMap::new();
+        // running sizes of the entries written so far, for the ratio shown by the progress bar
+        let (mut read, mut stored) = (0, 0);
+        for (i, path, entry, hash) in rx {
+            pending.insert(i, (path, entry, hash));
+            while let Some((path, entry, hash)) = pending.remove(&next) {
+                read += entry.uncompressed_size();
+                stored += entry.compressed_size();
+                progress.inc(entry.uncompressed_size());
+                progress.set_message(format!(
+                    "{:.0}%",
+                    stored as f64 * 100.0 / read.max(1) as f64
+                ));
+                match hash.and_then(|hash| written.get(&hash)) {
+                    Some(first) => pak.write_duplicate(&path, first)?,
+                    None => {
+                        if let Some(hash) = hash {
+                            written.insert(hash, path.clone());
+                        }
+                        pak.write_entry(path, entry)?;
+                    }
+                }
+                next += 1;
+            }
         }
         Ok(())
     })?;
     result.unwrap()?;
+    progress.finish();
+    if let Some(metadata) = mod_metadata {
+        pak.write_mod_metadata(metadata)?;
+    }
+    Ok(())
+}
+
+/// Fails with [`CliError::IndexSize`] if the index of `pak` would be larger than `max`
+fn check_index_size<W: io::Write + io::Seek>(
+    pak: &repak::PakWriter<W>,
+    max: Option<u64>,
+) -> Result<(), CliError> {
+    if let Some(max) = max {
+        let size = pak.predicted_index_size()?;
+        if size > max {
+            return Err(CliError::IndexSize { size, max });
+        }
+    }
+    Ok(())
+}
 
-    pak.write_index()?;
+/// Writer counting the bytes written through it, for the size of a pak streamed to stdout
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
 
-    if !args.quiet {
-        println!("Packed {} files to {}", paths.len(), output.display());
+impl<W> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: io::Write> io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
     }
 
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// How an entry is stored, e.g. "Zstd 1.20 MiB -> 300.00 KiB (24%)" or "stored 446 B"
+fn describe_stored<D: AsRef<[u8]>>(entry: &repak::PartialEntry<D>) -> String {
+    use indicatif::HumanBytes;
+    let (compressed, uncompressed) = (entry.compressed_size(), entry.uncompressed_size());
+    match entry.compression() {
+        Some(compression) => format!(
+            "{compression} {} -> {} ({:.0}%)",
+            HumanBytes(uncompressed),
+            HumanBytes(compressed),
+            compressed as f64 * 100.0 / uncompressed.max(1) as f64
+        ),
+        None => format!("stored {}", HumanBytes(uncompressed)),
+    }
+}
+
+fn get(config: &Config, args: ActionGet) -> Result<(), CliError> {
+    let mut reader = BufReader::new(sharing::open_pak(&args.input)?);
+    let pak = config.builder.build_reader(&mut reader)?;
+    print_warnings(&args.input, &pak);
+    let paths = MountedPaths::new(pak.mount_point(), &args.strip_prefix);
+    if args.verbose {
+        eprintln!("strip prefix: {}", paths.prefix().to_slash_lossy());
+    }
+    let file = paths
+        .entry_path(&args.file)
+        .map(|file| file.to_slash_lossy().into_owned());
+
+    let siblings;
+    let (pak, mut reader, file) = match file {
+        Ok(file) if !args.search_siblings || pak.resolve_path(&file).is_some() => {
+            (&pak, reader, file)
+        }
+        file if !args.search_siblings => (&pak, reader, file?),
+        file => {
+            siblings =
+                siblings::Siblings::new(Path::new(&args.input), || config.builder(), args.verbose)?;
+            match siblings.find(&args.file, &args.strip_prefix) {
+                Some((path, sibling, entry)) => {
+                    eprintln!("{} read from sibling pak {}", args.file, path.display());
+                    let reader = BufReader::new(sharing::open_input(path)?);
+                    (sibling, reader, entry)
+                }
+                // reported as missing from the pak given
+                None => (&pak, reader, file?),
+            }
+        }
+    };
+
+    use std::io::{Read, Seek, Write};
+    let mut stdout = std::io::stdout().lock();
+    if args.offset.is_none() && args.length.is_none() && args.tail.is_none() {
+        stdout.write_all(&pak.get(&file, &mut reader)?)?;
+        return Ok(());
+    }
+
+    let size = pak
+        .entry_info(&file)
+        .ok_or_else(|| repak::Error::MissingEntry(file.to_string()))?
+        .uncompressed;
+    let (offset, length) = match args.tail {
+        Some(tail) => (size.saturating_sub(tail), tail),
+        None => {
+            let offset = args.offset.unwrap_or_default();
+            (offset, args.length.unwrap_or(size.saturating_sub(offset)))
+        }
+    };
+    if offset.checked_add(length).is_none_or(|end| end > size) {
+        return Err(CliError::Range {
+            path: file,
+            offset,
+            length,
+            size,
+        });
+    }
+
+    let mut entry = pak.open_entry(&file, &mut reader)?;
+    entry.seek(io::SeekFrom::Start(offset))?;
+    io::copy(&mut (&mut entry).take(length), &mut stdout)?;
+    if args.verbose {
+        eprintln!(
+            "read {} of {} blocks",
+            entry.blocks_read(),
+            entry.block_count()
+        );
+    }
     Ok(())
 }
 
-fn get(aes_key: Option<aes::Aes256>, args: ActionGet) -> Result<(), repak::Error> {
-    let mut reader = BufReader::new(File::open(&args.input)?);
-    let mut builder = repak::PakBuilder::new();
-    if let Some(aes_key) = aes_key {
-        builder = builder.key(aes_key);
+fn entry_blocks(config: &Config, args: ActionEntryBlocks) -> Result<(), repak::Error> {
+    let mut reader = BufReader::new(sharing::open_pak(&args.input)?);
+    let pak = config.builder.build_reader(&mut reader)?;
+    print_warnings(&args.input, &pak);
+    let paths = MountedPaths::new(pak.mount_point(), &args.strip_prefix);
+    let file = paths.entry_path(&args.file)?.to_slash_lossy().into_owned();
+    let info = pak
+        .entry_info(&file)
+        .ok_or_else(|| repak::Error::MissingEntry(file.clone()))?;
+    match info.compression {
+        Some(compression) => println!(
+            "{file}: {} bytes compressed with {compression}, {} bytes uncompressed",
+            info.compressed, info.uncompressed
+        ),
+        None => println!("{file}: {} bytes stored uncompressed", info.uncompressed),
+    }
+
+    let blocks = pak.probe_blocks(&file, &mut reader)?;
+    for (i, block) in blocks.iter().enumerate() {
+        let uncompressed = match &block.uncompressed {
+            Ok(size) => format!("{size} bytes uncompressed"),
+            Err(err) => format!("failed to decompress: {err}"),
+        };
+        let flag = match block.is_ok() {
+            true => String::new(),
+            false => format!(", expected {} bytes", block.expected),
+        };
+        println!(
+            "block {i}: {:#x}..{:#x}, {} bytes compressed, {uncompressed}{flag}",
+            block.range.start, block.range.end, block.compressed
+        );
+    }
+    let mismatched = blocks.iter().filter(|block| !block.is_ok()).count();
+    if mismatched > 0 {
+        println!(
+            "{mismatched} of {} blocks don't decompress to the expected size",
+            blocks.len()
+        );
+    }
+    Ok(())
+}
+
+/// Decompresses the blocks of up to `sample` compressed entries spread over `pak` and prints
+/// how many don't decompress to the expected size, for `info --check`
+fn check_blocks<R: io::Read + io::Seek>(
+    pak: &repak::PakReader,
+    reader: &mut R,
+    sample: Option<usize>,
+) -> Result<(), repak::Error> {
+    let compressed = pak
+        .file_names()
+        .filter(|path| {
+            pak.entry_info(path)
+                .is_some_and(|i| i.compression.is_some())
+        })
+        .collect::<Vec<_>>();
+    if compressed.is_empty() {
+        return Ok(());
+    }
+    let sample = sample.unwrap_or(usize::MAX).min(compressed.len());
+    let (mut blocks, mut mismatched) = (0, vec![]);
+    for i in 0..sample {
+        let path = compressed[i * compressed.len() / sample];
+        for (index, block) in pak.probe_blocks(path, reader)?.into_iter().enumerate() {
+            blocks += 1;
+            if !block.is_ok() {
+                mismatched.push((path, index, block));
+            }
+        }
+    }
+    println!(
+        "compression blocks: {} of {blocks} blocks in {sample} of {} compressed entries don't decompress to the expected size",
+        mismatched.len(),
+        compressed.len()
+    );
+    for (path, index, block) in mismatched {
+        match block.uncompressed {
+            Ok(size) => println!(
+                "  {path} block {index}: {size} bytes instead of {}",
+                block.expected
+            ),
+            Err(err) => println!("  {path} block {index}: {err}"),
+        }
+    }
+    Ok(())
+}
+
+fn cherry_pick(config: &Config, args: ActionCherryPick) -> Result<(), repak::Error> {
+    let mut reader = BufReader::new(sharing::open_pak(&args.input)?);
+    let pak = config.builder.build_reader(&mut reader)?;
+    print_warnings(&args.input, &pak);
+    let paths = MountedPaths::new(pak.mount_point(), &args.strip_prefix);
+
+    let mut matched = vec![false; args.paths.len()];
+    let mut files = vec![];
+    for file in pak.files() {
+        let full_path = paths.full_path(&file);
+        let Some(stripped) = paths.strip(&full_path) else {
+            continue;
+        };
+        let mut selected = false;
+        for (pattern, matched) in args.paths.iter().zip(&mut matched) {
+            if filter::is_included(std::slice::from_ref(pattern), stripped) {
+                *matched = true;
+                selected = true;
+            }
+        }
+        if selected {
+            files.push(file);
+        }
+    }
+    if let Some(i) = matched.iter().position(|m| !m) {
+        return Err(repak::Error::MissingEntry(args.paths[i].to_string()));
+    }
+
+    let mut writer = config.builder.build_writer(
+        BufWriter::new(File::create(&args.output)?),
+        pak.version(),
+        pak.mount_point().to_owned(),
+        pak.path_hash_seed(),
+    );
+    for file in &files {
+        writer.copy_entry_from(&pak, &mut reader, file)?;
+    }
+    writer.write_index()?;
+
+    println!("Copied {} files to {}", files.len(), args.output);
+    Ok(())
+}
+
+fn blank(config: &Config, args: ActionBlank) -> Result<(), repak::Error> {
+    let stub = match &args.stub_file {
+        Some(path) => fs::read(path)?,
+        None => vec![],
+    };
+    let mut reader = BufReader::new(sharing::open_pak(&args.input)?);
+    let pak = config.builder.build_reader(&mut reader)?;
+    print_warnings(&args.input, &pak);
+    let paths = MountedPaths::new(pak.mount_point(), &args.strip_prefix);
+
+    let mut matched = vec![false; args.include.len()];
+    let mut selected = HashSet::new();
+    for file in pak.file_names() {
+        let full_path = paths.full_path(file);
+        let Some(stripped) = paths.strip(&full_path) else {
+            continue;
+        };
+        for (pattern, matched) in args.include.iter().zip(&mut matched) {
+            if filter::is_included(std::slice::from_ref(pattern), stripped) {
+                *matched = true;
+                selected.insert(file);
+            }
+        }
+    }
+    if let Some(i) = matched.iter().position(|m| !m) {
+        return Err(repak::Error::MissingEntry(args.include[i].to_string()));
+    }
+
+    let mut writer = config.builder.build_writer(
+        BufWriter::new(File::create(&args.output)?),
+        pak.version(),
+        pak.mount_point().to_owned(),
+        pak.path_hash_seed(),
+    );
+    for file in pak.file_names() {
+        match selected.contains(file) {
+            true => {
+                writer.write_file(file, false, &stub)?;
+            }
+            false => writer.copy_entry_from(&pak, &mut reader, file)?,
+        }
     }
-    let pak = builder.reader(&mut reader)?;
-    let mount_point = PathBuf::from(pak.mount_point());
-    let prefix = Path::new(&args.strip_prefix);
+    writer.write_index()?;
+
+    println!(
+        "Blanked {} of {} files to {}",
+        selected.len(),
+        pak.len(),
+        args.output
+    );
+    Ok(())
+}
+
+fn decrypt(config: &Config, args: ActionDecrypt) -> Result<(), repak::Error> {
+    let mut reader = BufReader::new(sharing::open_pak(&args.input)?);
+    let pak = config.builder.build_reader(&mut reader)?;
+    print_warnings(&args.input, &pak);
+    pak.write_decrypted(&mut reader, BufWriter::new(File::create(&args.output)?))?;
+
+    println!("Decrypted {} files to {}", pak.len(), args.output);
+    Ok(())
+}
+
+fn compact(config: &Config, args: ActionCompact) -> Result<(), repak::Error> {
+    let mut reader = BufReader::new(sharing::open_pak(&args.input)?);
+    let pak = config.builder.build_reader(&mut reader)?;
+    print_warnings(&args.input, &pak);
+    pak.write_compacted(&mut reader, BufWriter::new(File::create(&args.output)?))?;
+
+    println!(
+        "Compacted {} files to {} ({} -> {} bytes)",
+        pak.len(),
+        args.output,
+        std::fs::metadata(&args.input)?.len(),
+        std::fs::metadata(&args.output)?.len()
+    );
+    Ok(())
+}
+
+fn fix_index(config: &Config, args: ActionFixIndex) -> Result<(), repak::Error> {
+    std::fs::copy(&args.input, &args.output)?;
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&args.output)?;
+    // the secondary indexes are regenerated, so they may well not match their hashes
+    let pak = config
+        .builder()
+        .ignore_index_hashes(true)
+        .reader(&mut BufReader::new(&file))?;
+    let files = pak.len();
+    if let Some(report) = pak.check_path_hash_index() {
+        print_path_hash_index_report(&report);
+    }
+
+    // entries stay in place and only the indexes following them are rewritten
+    // the new index may be shorter than the old one
+    let writer = pak
+        .into_pakwriter(BufWriter::new(&mut file))?
+        .write_index()?;
+    let file = writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?;
+    let len = io::Seek::stream_position(file)?;
+    file.set_len(len)?;
+
+    println!("Rewrote the index of {files} files to {}", args.output);
+    Ok(())
+}
 
-    let full_path = prefix.join(args.file);
-    let file = full_path
-        .strip_prefix(&mount_point)
-        .map_err(|_| repak::Error::PrefixMismatch {
-            path: full_path.to_string_lossy().to_string(),
-            prefix: mount_point.to_string_lossy().to_string(),
-        })?;
+fn make_patch(config: &Config, args: ActionMakePatch) -> Result<(), patch::Error> {
+    let mut old = BufReader::new(sharing::open_pak(&args.old)?);
+    let old_pak = config.builder.build_reader(&mut old)?;
+    print_warnings(&args.old, &old_pak);
+    let mut new = BufReader::new(sharing::open_pak(&args.new)?);
+    let new_pak = config.builder.build_reader(&mut new)?;
+    print_warnings(&args.new, &new_pak);
+
+    let patch = patch::Patch::make(&old_pak, &mut old, &new_pak, &mut new)?;
+    patch.write(BufWriter::new(File::create(&args.output)?))?;
+
+    let header = &patch.header;
+    for (change, files) in [
+        ("removed", &header.removed),
+        ("added", &header.added),
+        ("changed", &header.changed),
+    ] {
+        for file in files {
+            println!("{change} {file}");
+        }
+    }
+    println!(
+        "Wrote patch to {} ({} removed, {} added, {} changed)",
+        args.output,
+        header.removed.len(),
+        header.added.len(),
+        header.changed.len()
+    );
+    Ok(())
+}
+
+fn apply_patch(_config: &Config, args: ActionApplyPatch) -> Result<(), patch::Error> {
+    let patch = patch::Patch::read(BufReader::new(sharing::open_input(&args.patch)?))?;
+    let mut input = BufReader::new(sharing::open_pak(&args.input)?);
+    let result = patch.apply(&mut input, BufWriter::new(File::create(&args.output)?));
+    if result.is_err() {
+        // don't leave a partial or corrupt pak behind
+        let _ = fs::remove_file(&args.output);
+    }
+    result?;
+    println!("Wrote {}", args.output);
+    Ok(())
+}
 
-    use std::io::Write;
-    std::io::stdout().write_all(&pak.get(&file.to_slash_lossy(), &mut reader)?)?;
+fn analyze(config: &Config, args: ActionAnalyze) -> Result<(), repak::Error> {
+    let input = Path::new(&args.input);
+    let json = if input.is_dir() {
+        let analysis = analyze::analyze_directory(input, &args.compression, args.sample_size)?;
+        match args.format {
+            OutputFormat::Table => {
+                analysis.print_table(&args.compression);
+                return Ok(());
+            }
+            OutputFormat::Json => serde_json::to_string_pretty(&analysis),
+        }
+    } else {
+        let pak = config
+            .builder
+            .build_reader(&mut BufReader::new(sharing::open_pak(input)?))?;
+        print_warnings(input, &pak);
+        let analysis = analyze::analyze_pak(&pak);
+        match args.format {
+            OutputFormat::Table => {
+                analysis.print_table();
+                return Ok(());
+            }
+            OutputFormat::Json => serde_json::to_string_pretty(&analysis),
+        }
+    };
+    println!(
+        "{}",
+        json.map_err(|err| repak::Error::Other(err.to_string()))?
+    );
+    Ok(())
+}
+
+fn layout(config: &Config, args: ActionLayout) -> Result<(), repak::Error> {
+    let pak = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(&args.input)?))?;
+    print_warnings(&args.input, &pak);
+    println!(
+        "{:>12} {:>12} {:<11} {:>10} path",
+        "offset", "size", "compression", "gap"
+    );
+    for entry in pak.entries_by_offset() {
+        let compression = entry.info.compression.map(|c| c.to_string());
+        println!(
+            "{:>#12x} {:>12} {:<11} {:>10} {}",
+            entry.info.offset,
+            entry.size,
+            compression.as_deref().unwrap_or("none"),
+            entry.gap,
+            entry.path
+        );
+    }
+    Ok(())
+}
+
+fn doctor(config: &Config, args: ActionDoctor) -> Result<(), repak::Error> {
+    let input = Path::new(&args.input);
+    let pak = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(input)?))?;
+    let reference = config
+        .builder
+        .build_reader(&mut BufReader::new(sharing::open_pak(&args.reference)?))?;
+    doctor::print_findings(&doctor::diagnose(input, &pak, &reference));
+    Ok(())
+}
+
+fn merged_list(config: &Config, args: ActionMergedList) -> Result<(), repak::Error> {
+    let dir = Path::new(&args.input);
+    let mut paks = vec![];
+    collect_files(&mut paks, dir)?;
+    let mut paks = paks
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("pak"))
+        })
+        .map(|path| {
+            let name = path.strip_prefix(dir).unwrap_or(&path).to_slash_lossy();
+            (name.into_owned(), path)
+        })
+        .collect::<Vec<_>>();
+    paks.sort_by(|(a, _), (b, _)| {
+        let file_name = |name: &str| name.rsplit('/').next().unwrap_or_default().to_owned();
+        stack::compare_priority(&file_name(a), &file_name(b))
+    });
+
+    let mut stack = vec![];
+    for (name, path) in paks {
+        let pak = config
+            .builder
+            .build_reader(&mut BufReader::new(sharing::open_pak(&path)?))?;
+        print_warnings(&path, &pak);
+        let paths = MountedPaths::new(pak.mount_point(), mount::AUTO_PREFIX);
+        let files = pak
+            .file_names()
+            .map(|f| {
+                let full_path = paths.full_path(f);
+                paths
+                    .strip(&full_path)
+                    .unwrap_or(&full_path)
+                    .to_slash_lossy()
+                    .into_owned()
+            })
+            .collect();
+        stack.push((name, files));
+    }
+    let merged = stack::merge(&stack);
+
+    let json = match args.who_has {
+        Some(who_has) => {
+            let provided = merged
+                .into_iter()
+                .find(|provided| provided.path.to_lowercase() == who_has.to_lowercase())
+                .ok_or(repak::Error::MissingEntry(who_has))?;
+            match args.format {
+                OutputFormat::Table => {
+                    println!("{}", provided.pak);
+                    for pak in &provided.overridden {
+                        println!("{pak} (overridden)");
+                    }
+                    return Ok(());
+                }
+                OutputFormat::Json => serde_json::to_string_pretty(&provided),
+            }
+        }
+        None => match args.format {
+            OutputFormat::Table => {
+                let width = merged.iter().map(|p| p.pak.len()).max().unwrap_or(0);
+                for provided in &merged {
+                    println!("{:<width$} {}", provided.pak, provided.path);
+                }
+                return Ok(());
+            }
+            OutputFormat::Json => serde_json::to_string_pretty(&merged),
+        },
+    };
+    println!(
+        "{}",
+        json.map_err(|err| repak::Error::Other(err.to_string()))?
+    );
     Ok(())
 }
@@ -0,0 +1,326 @@
+//! Paths of entries below the mount point and the prefix stripped from them on output
+
+use std::path::{Path, PathBuf};
+
+use path_clean::PathClean;
+use path_slash::PathExt;
+
+/// Value of `--strip-prefix` which derives the prefix from the mount point
+pub const AUTO_PREFIX: &str = "auto";
+
+/// Resolves `.` and `..` segments lexically, keeping the leading `..` segments which can't be
+/// resolved. Unlike [`PathClean::clean`] an empty result stays empty rather than becoming `.`,
+/// which would never match as a prefix.
+fn normalize(path: &str) -> PathBuf {
+    let path = Path::new(path).clean();
+    match path == Path::new(".") {
+        true => PathBuf::new(),
+        false => path,
+    }
+}
+
+/// Mount point and strip prefix in normalized form, so a mount point such as
+/// `../../../Game/Content/Paks/../` matches the prefix `../../../Game/Content/`
+pub struct MountedPaths {
+    mount_point: PathBuf,
+    prefix: PathBuf,
+}
+
+impl MountedPaths {
+    /// A `strip_prefix` of [`AUTO_PREFIX`] strips the `../` segments the mount point starts
+    /// with, or the root of rooted mount points, so entries land below the output directory.
+    /// The placeholder of mount points which can't be read is treated as an empty mount point.
+    pub fn new(mount_point: &str, strip_prefix: &str) -> Self {
+        let mount_point = match mount_point {
+            repak::UNREADABLE_MOUNT_POINT => PathBuf::new(),
+            mount_point => normalize(mount_point),
+        };
+        let prefix = match strip_prefix {
+            AUTO_PREFIX => {
+                let slashed = mount_point.to_slash_lossy();
+                match repak::MountPoint(&slashed).leading_parent_segments() {
+                    "" if mount_point.has_root() => PathBuf::from("/"),
+                    parents => normalize(parents),
+                }
+            }
+            prefix => normalize(prefix),
+        };
+        Self {
+            mount_point,
+            prefix,
+        }
+    }
+
+    /// Prefix stripped from the paths of entries including the mount point
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Path of an entry including the mount point. `..` segments of the entry path itself are
+    /// left in place so paths escaping the output directory can still be detected.
+    pub fn full_path(&self, entry_path: &str) -> PathBuf {
+        self.mount_point.join(entry_path)
+    }
+
+    /// Path of an entry with the prefix stripped, or `None` if it is not below the prefix
+    pub fn strip<'a>(&self, full_path: &'a Path) -> Option<&'a Path> {
+        full_path.strip_prefix(&self.prefix).ok()
+    }
+
+    /// Like [`Self::strip`], failing with [`repak::Error::PrefixMismatch`]
+    pub fn stripped<'a>(&self, full_path: &'a Path) -> Result<&'a Path, repak::Error> {
+        self.strip(full_path)
+            .ok_or_else(|| repak::Error::PrefixMismatch {
+                path: full_path.to_string_lossy().to_string(),
+                prefix: self.prefix.to_string_lossy().to_string(),
+            })
+    }
+
+    /// Where an entry is written below `output`, failing with
+    /// [`repak::Error::WriteOutsideOutput`] for paths escaping it
+    pub fn out_path(&self, output: &Path, entry_path: &str) -> Result<PathBuf, repak::Error> {
+        let out_path = output
+            .join(self.stripped(&self.full_path(entry_path))?)
+            .clean();
+        match out_path.starts_with(output.clean()) {
+            true => Ok(out_path),
+            false => Err(repak::Error::WriteOutsideOutput(
+                out_path.to_string_lossy().to_string(),
+            )),
+        }
+    }
+
+    /// Path of an entry relative to the mount point from a path relative to the prefix, the
+    /// reverse of [`Self::stripped`]
+    pub fn entry_path(&self, path: &str) -> Result<PathBuf, repak::Error> {
+        let full_path = self.prefix.join(normalize(path));
+        full_path
+            .strip_prefix(&self.mount_point)
+            .map(Path::to_path_buf)
+            .map_err(|_| repak::Error::PrefixMismatch {
+                path: full_path.to_string_lossy().to_string(),
+                prefix: self.mount_point.to_string_lossy().to_string(),
+            })
+    }
+}
+
+/// Leading directories of `path`, an entry path relative to `mount_point`, which repeat the
+/// last directories of the mount point, such as `Game/Content/` for entries starting with it
+/// in a pak mounted at `../../../Game/Content/`. Such entries mount at
+/// `../../../Game/Content/Game/Content/`, a sign of packing the full paths of files below a
+/// mount point which already contains them. Compared ignoring case like the engine does.
+pub fn double_rooted(mount_point: &str, path: &str) -> Option<String> {
+    fn segments(path: &str) -> Vec<&str> {
+        path.split(['/', '\\'])
+            .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+            .collect()
+    }
+    let mount_point = segments(mount_point);
+    let path = segments(path);
+    // the file name itself can't repeat a directory of the mount point
+    let directories = &path[..path.len().saturating_sub(1)];
+    let repeated = (1..=mount_point.len().min(directories.len()))
+        .rev()
+        .find(|&n| {
+            directories[..n]
+                .iter()
+                .zip(&mount_point[mount_point.len() - n..])
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+        })?;
+    Some(format!("{}/", directories[..repeated].join("/")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{double_rooted, MountedPaths};
+    use std::path::Path;
+
+    #[test]
+    fn test_out_path() {
+        let output = Path::new("out");
+        for (mount_point, prefix, entry, expected) in [
+            (
+                "../../../",
+                "../../../",
+                "Game/a.txt",
+                Some("out/Game/a.txt"),
+            ),
+            ("../../../", "", "Game/a.txt", None),
+            (
+                "../../../Game/",
+                "../../../",
+                "a.txt",
+                Some("out/Game/a.txt"),
+            ),
+            (
+                "../../../Game",
+                "../../../",
+                "a.txt",
+                Some("out/Game/a.txt"),
+            ),
+            // ".." inside the mount point is resolved before stripping the prefix
+            (
+                "../../../Game/Content/Paks/../",
+                "../../../",
+                "a.txt",
+                Some("out/Game/Content/a.txt"),
+            ),
+            (
+                "../../../Game/Content/Paks/../",
+                "../../../Game/Content/",
+                "a.txt",
+                Some("out/a.txt"),
+            ),
+            (
+                "../../../Game/./Content/",
+                "../../../Game/Content",
+                "a.txt",
+                Some("out/a.txt"),
+            ),
+            (
+                "../../../Game/Content/Paks/../../",
+                "../../../Game/",
+                "dir/a.txt",
+                Some("out/dir/a.txt"),
+            ),
+            (
+                "../../..//Game//Content//",
+                "../../../Game/Content/",
+                "a.txt",
+                Some("out/a.txt"),
+            ),
+            (
+                "../../../Game/Content/",
+                "../../../Game/Content/Paks/../",
+                "a.txt",
+                Some("out/a.txt"),
+            ),
+            (
+                "../../../Game/Content/Paks/../",
+                "../../../Game/Content/Paks/",
+                "a.txt",
+                None,
+            ),
+            ("../../../Other/", "../../../Game/", "a.txt", None),
+            // entries escaping the output directory are still rejected
+            ("../../../", "../../../", "../a.txt", None),
+            ("../../../Game/", "../../../Game/", "dir/../../a.txt", None),
+            ("../../../Game/", "../../../", "../../a.txt", None),
+            ("../../../Game/", "../../../", "../a.txt", Some("out/a.txt")),
+        ] {
+            let paths = MountedPaths::new(mount_point, prefix);
+            let out_path = paths.out_path(output, entry).ok();
+            assert_eq!(
+                out_path.as_deref(),
+                expected.map(Path::new),
+                "{mount_point} {prefix} {entry}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_out_path_errors() {
+        let paths = MountedPaths::new("../../../Game/", "../../../");
+        assert!(matches!(
+            paths.out_path(Path::new("out"), "../../a.txt"),
+            Err(repak::Error::WriteOutsideOutput(_))
+        ));
+        let paths = MountedPaths::new("../../../Other/", "../../../Game/");
+        assert!(matches!(
+            paths.out_path(Path::new("out"), "a.txt"),
+            Err(repak::Error::PrefixMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_auto_prefix() {
+        let output = Path::new("out");
+        for (mount_point, prefix, out_path) in [
+            ("../../../", "../../..", "out/Game/a.txt"),
+            ("../../", "../..", "out/Game/a.txt"),
+            ("/", "/", "out/Game/a.txt"),
+            ("Game/", "", "out/Game/Game/a.txt"),
+            ("../../../Engine/", "../../..", "out/Engine/Game/a.txt"),
+            ("../../../Game/Paks/../", "../../..", "out/Game/Game/a.txt"),
+            (repak::UNREADABLE_MOUNT_POINT, "", "out/Game/a.txt"),
+        ] {
+            let paths = MountedPaths::new(mount_point, super::AUTO_PREFIX);
+            assert_eq!(paths.prefix(), Path::new(prefix), "{mount_point}");
+            assert_eq!(
+                paths.out_path(output, "Game/a.txt").unwrap(),
+                Path::new(out_path),
+                "{mount_point}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_entry_path() {
+        for (mount_point, prefix, path, expected) in [
+            ("../../../", "../../../", "Game/a.txt", Some("Game/a.txt")),
+            ("../../../Game/", "../../../", "Game/a.txt", Some("a.txt")),
+            (
+                "../../../Game/Content/Paks/../",
+                "../../../",
+                "Game/Content/a.txt",
+                Some("a.txt"),
+            ),
+            ("../../../Game/", "../../../", "Other/a.txt", None),
+        ] {
+            let paths = MountedPaths::new(mount_point, prefix);
+            assert_eq!(
+                paths.entry_path(path).ok().as_deref(),
+                expected.map(Path::new),
+                "{mount_point} {prefix} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_double_rooted() {
+        for (mount_point, path, expected) in [
+            (
+                "../../../Game/Content/",
+                "Game/Content/A.uasset",
+                Some("Game/Content/"),
+            ),
+            (
+                "../../../Game/Content/",
+                "Content/Maps/A.umap",
+                Some("Content/"),
+            ),
+            (
+                "../../../Game/Content/",
+                "game/content/A.uasset",
+                Some("game/content/"),
+            ),
+            ("../../../Game/Content/", "Maps/A.umap", None),
+            // the mount point ends with the file name, not a directory
+            ("../../../Game/Content/", "Content", None),
+            ("../../../", "Game/Content/A.uasset", None),
+            ("../../../Game/", "Game/Content/A.uasset", Some("Game/")),
+            (
+                "../../../GameName/Plugins/SomePlugin/Content/",
+                "SomePlugin/Content/Textures/T.uasset",
+                Some("SomePlugin/Content/"),
+            ),
+            (
+                "../../../GameName/Plugins/SomePlugin/Content/",
+                "Plugins/SomePlugin/A.uasset",
+                None,
+            ),
+            (
+                "/Game/Content/",
+                "Game/Content/A.uasset",
+                Some("Game/Content/"),
+            ),
+            ("", "Game/A.uasset", None),
+        ] {
+            assert_eq!(
+                double_rooted(mount_point, path).as_deref(),
+                expected,
+                "{mount_point} {path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Order files listing entries to write to the start of the data region, similar to the
+//! `-order` files of UnrealPak
+
+use crate::text::TextFile;
+
+/// A line of an order file matching entry paths relative to the packed directory
+#[derive(Debug)]
+pub struct OrderLine {
+    /// 1-based line number in the order file
+    pub line: usize,
+    pub text: String,
+    pattern: Option<glob::Pattern>,
+}
+
+impl OrderLine {
+    fn matches(&self, path: &str) -> bool {
+        match &self.pattern {
+            Some(pattern) => pattern.matches_with(
+                path,
+                glob::MatchOptions {
+                    case_sensitive: true,
+                    require_literal_separator: true,
+                    require_literal_leading_dot: false,
+                },
+            ),
+            None => self.text == path,
+        }
+    }
+}
+
+/// Reads the lines of an order file, see [`crate::text::lines`]. Lines containing glob
+/// metacharacters are matched as patterns, everything else as a literal path.
+pub fn parse_order(file: &TextFile) -> Result<Vec<OrderLine>, repak::Error> {
+    file.lines()
+        .map(|(line, text)| {
+            let text = text.trim_start().to_owned();
+            let pattern = text
+                .contains(['*', '?', '['])
+                .then(|| glob::Pattern::new(&text))
+                .transpose()
+                .map_err(|err| {
+                    repak::Error::Other(format!(
+                        "invalid pattern on line {line} of order file: {err}"
+                    ))
+                })?;
+            Ok(OrderLine {
+                line,
+                text,
+                pattern,
+            })
+        })
+        .collect()
+}
+
+/// Sorts `paths` so entries matched by an order line come first, in the order of the first
+/// line matching each of them, followed by the rest in their existing order. Returns the
+/// lines which didn't match any path.
+pub fn apply_order<'a, T>(paths: &mut [(String, T)], order: &'a [OrderLine]) -> Vec<&'a OrderLine> {
+    let mut matched = vec![false; order.len()];
+    paths.sort_by_cached_key(|(path, _)| {
+        let mut position = order.len();
+        for (i, line) in order.iter().enumerate().rev() {
+            if line.matches(path) {
+                matched[i] = true;
+                position = i;
+            }
+        }
+        position
+    });
+    order
+        .iter()
+        .zip(matched)
+        .filter_map(|(line, matched)| (!matched).then_some(line))
+        .collect()
+}
@@ -0,0 +1,110 @@
+//! Creating the output directories of unpack once up front rather than once per entry, which
+//! on large paks re-checks the same directories hundreds of thousands of times
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+/// Directories to create so that all of `dirs` exist, shallowest first. Parents are left out
+/// as creating a directory creates them too.
+pub fn unique_dirs<'a>(dirs: impl IntoIterator<Item = &'a Path>) -> Vec<&'a Path> {
+    let mut dirs = dirs.into_iter().collect::<BTreeSet<_>>();
+    let parents = dirs
+        .iter()
+        .flat_map(|dir| dir.ancestors().skip(1))
+        .collect::<Vec<_>>();
+    for parent in parents {
+        dirs.remove(parent);
+    }
+    let mut dirs = dirs.into_iter().collect::<Vec<_>>();
+    dirs.sort_by_key(|dir| dir.components().count());
+    dirs
+}
+
+/// Creates each of `dirs` once, after `check` passes for it
+pub fn create_dirs(
+    dirs: &[&Path],
+    check: impl Fn(&Path) -> Result<(), repak::Error>,
+) -> Result<(), repak::Error> {
+    for dir in dirs {
+        check(dir)?;
+        std::fs::create_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Creates `path` with its parent created first if it has gone missing since
+/// [`create_dirs`], such as when another process removed it
+pub fn create_file(path: &Path) -> io::Result<std::fs::File> {
+    match std::fs::File::create(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::File::create(path)
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_create_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = (0..1000)
+            .map(|i| {
+                dir.path()
+                    .join(format!("out/a/b{}/c{}/file{i}", i % 3, i % 5))
+            })
+            .collect::<Vec<_>>();
+        let dirs = unique_dirs(files.iter().map(|file| file.parent().unwrap()));
+        assert_eq!(dirs.len(), 15);
+        create_dirs(&dirs, |_| Ok(())).unwrap();
+        assert!(files.iter().all(|file| file.parent().unwrap().is_dir()));
+
+        // shallower directories come first and only those not created along with another
+        let dirs = unique_dirs(
+            ["out/a/b", "out", "out/c", "out/a", "out/a/b"]
+                .into_iter()
+                .map(Path::new),
+        );
+        assert_eq!(dirs, [Path::new("out/c"), Path::new("out/a/b")]);
+
+        // nothing is created once the check fails
+        let other = dir.path().join("other");
+        let dirs = [other.join("c"), other.join("a/b")];
+        let dirs = dirs.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        let err = create_dirs(&dirs, |dir| {
+            Err(repak::Error::SymlinkInOutput(dir.display().to_string()))
+        });
+        assert!(
+            matches!(err, Err(repak::Error::SymlinkInOutput(ref failed)) if Path::new(failed) == other.join("c"))
+        );
+        assert!(!other.exists());
+    }
+
+    #[test]
+    fn test_create_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = (0..200)
+            .map(|i| {
+                dir.path()
+                    .join(format!("d{}/e{}/f{}/file{i}", i % 2, i % 4, i % 8))
+            })
+            .collect::<Vec<_>>();
+        let dirs = unique_dirs(files.iter().map(|file| file.parent().unwrap()));
+        create_dirs(&dirs, |_| Ok(())).unwrap();
+        for file in &files {
+            create_file(file).unwrap();
+        }
+        assert!(files.iter().all(|file| file.is_file()));
+
+        std::fs::remove_dir_all(dir.path().join("d0")).unwrap();
+        create_file(&files[0]).unwrap();
+        assert!(files[0].is_file());
+    }
+}
@@ -0,0 +1,160 @@
+//! Combining the files of several input directories into one pak, as if they had been copied
+//! into a single directory in order
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// What to do with a file whose path is also in an earlier input directory
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Fail without writing the pak
+    Error,
+    /// Keep the file of the earlier directory
+    First,
+    /// Keep the file of the later directory
+    Last,
+}
+
+/// Files selected from all input directories
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Overlay {
+    /// Path relative to the input directory and path on disk of each file, sorted
+    pub files: Vec<(String, PathBuf)>,
+    /// Number of files whose path was already taken by a file of an earlier directory
+    pub conflicts: usize,
+}
+
+/// Key identifying a relative path. Paths differing only in case name the same file on
+/// Windows, where copying the directories into one would merge them.
+fn key(rel: &str) -> String {
+    match cfg!(windows) {
+        true => rel.to_lowercase(),
+        false => rel.to_owned(),
+    }
+}
+
+/// Merges the files of each input directory, in order, as returned by
+/// [`crate::filter::PathFilter::select_files`]
+pub fn merge(
+    inputs: impl IntoIterator<Item = Vec<(String, PathBuf)>>,
+    conflict: Conflict,
+) -> Result<Overlay, repak::Error> {
+    let mut merged = BTreeMap::new();
+    let mut conflicts = 0;
+    for files in inputs {
+        for (rel, path) in files {
+            match merged.entry(key(&rel)) {
+                Entry::Vacant(entry) => {
+                    entry.insert((rel, path));
+                }
+                Entry::Occupied(mut entry) => {
+                    conflicts += 1;
+                    match conflict {
+                        Conflict::Error => {
+                            return Err(repak::Error::Other(format!(
+                                "{rel} is in more than one input directory, as {} and {} (see --conflict)",
+                                entry.get().1.display(),
+                                path.display()
+                            )))
+                        }
+                        Conflict::First => {}
+                        Conflict::Last => {
+                            entry.insert((rel, path));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let mut files = merged.into_values().collect::<Vec<_>>();
+    files.sort();
+    Ok(Overlay { files, conflicts })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn files(dir: &str, rels: &[&str]) -> Vec<(String, PathBuf)> {
+        rels.iter()
+            .map(|rel| (rel.to_string(), PathBuf::from(dir).join(rel)))
+            .collect()
+    }
+
+    fn inputs() -> Vec<Vec<(String, PathBuf)>> {
+        vec![
+            files("base", &["a.txt", "dir/b.txt", "dir/c.txt"]),
+            files("variant", &["dir/b.txt", "d.txt"]),
+            files("fix", &["dir/b.txt", "a.txt"]),
+        ]
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let overlay = merge(inputs(), Conflict::Last).unwrap();
+        assert_eq!(
+            overlay,
+            Overlay {
+                files: vec![
+                    ("a.txt".into(), "fix/a.txt".into()),
+                    ("d.txt".into(), "variant/d.txt".into()),
+                    ("dir/b.txt".into(), "fix/dir/b.txt".into()),
+                    ("dir/c.txt".into(), "base/dir/c.txt".into()),
+                ],
+                conflicts: 3,
+            }
+        );
+
+        let overlay = merge(inputs(), Conflict::First).unwrap();
+        assert_eq!(
+            overlay,
+            Overlay {
+                files: vec![
+                    ("a.txt".into(), "base/a.txt".into()),
+                    ("d.txt".into(), "variant/d.txt".into()),
+                    ("dir/b.txt".into(), "base/dir/b.txt".into()),
+                    ("dir/c.txt".into(), "base/dir/c.txt".into()),
+                ],
+                conflicts: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_merge_conflict_error() {
+        let err = merge(inputs(), Conflict::Error).unwrap_err().to_string();
+        assert!(
+            err.starts_with("dir/b.txt is in more than one input directory"),
+            "{err}"
+        );
+
+        // disjoint directories don't conflict
+        let overlay = merge(
+            [files("base", &["a.txt"]), files("variant", &["b.txt"])],
+            Conflict::Error,
+        )
+        .unwrap();
+        assert_eq!(overlay.files.len(), 2);
+        assert_eq!(overlay.conflicts, 0);
+    }
+
+    #[test]
+    fn test_merge_case() {
+        let overlay = merge(
+            [
+                files("base", &["Dir/A.txt"]),
+                files("variant", &["dir/a.txt"]),
+            ],
+            Conflict::Last,
+        )
+        .unwrap();
+        match cfg!(windows) {
+            true => assert_eq!(
+                overlay.files,
+                [("dir/a.txt".into(), "variant/dir/a.txt".into())]
+            ),
+            false => assert_eq!(overlay.files.len(), 2),
+        }
+    }
+}
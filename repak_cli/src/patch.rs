@@ -0,0 +1,334 @@
+//! Patches reconstructing a pak from an older version of it.
+//!
+//! A patch file starts with [`MAGIC`] followed by sections, each framed by a four byte tag and
+//! the length of its contents as a little endian u64:
+//! - `HEAD`: the [`Header`] as JSON
+//! - `DATA`: bytes referenced by [`Op::Insert`]
+//!
+//! Entries whose record and stored data are byte-identical in both paks are copied from the
+//! source pak. Everything else, including added and changed entries as well as the index and
+//! footer, is stored in full in the data section. Unknown sections are skipped when reading.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const MAGIC: &[u8; 8] = b"RPAKPTCH";
+const HEAD: &[u8; 4] = b"HEAD";
+const DATA: &[u8; 4] = b"DATA";
+
+#[derive(Debug)]
+pub enum Error {
+    Pak(repak::Error),
+    /// The patch is malformed
+    Format(String),
+    /// The source pak is not the one the patch was made from
+    SourceMismatch,
+    /// The reconstructed pak does not match the target the patch was made for
+    TargetMismatch,
+}
+
+impl Error {
+    pub fn kind(&self) -> repak::ErrorKind {
+        match self {
+            Error::Pak(err) => err.kind(),
+            Error::Format(_) | Error::TargetMismatch => repak::ErrorKind::Format,
+            Error::SourceMismatch => repak::ErrorKind::InvalidInput,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Pak(err) => err.fmt(f),
+            Error::Format(msg) => write!(f, "invalid patch: {msg}"),
+            Error::SourceMismatch => write!(f, "input pak is not the one the patch was made from"),
+            Error::TargetMismatch => write!(f, "patched pak does not match the expected SHA256"),
+        }
+    }
+}
+
+impl From<repak::Error> for Error {
+    fn from(value: repak::Error) -> Self {
+        Self::Pak(value)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Pak(value.into())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Header {
+    source_sha256: String,
+    target_sha256: String,
+    target_size: u64,
+    /// Entries only present in the source pak
+    pub removed: Vec<String>,
+    /// Entries only present in the target pak
+    pub added: Vec<String>,
+    /// Entries present in both paks whose record or data differ
+    pub changed: Vec<String>,
+    ops: Vec<Op>,
+}
+
+/// Steps writing the target pak from start to end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Op {
+    /// Copy `len` bytes at `offset` of the source pak
+    Copy { offset: u64, len: u64 },
+    /// Insert `len` bytes at `offset` of the data section
+    Insert { offset: u64, len: u64 },
+}
+
+#[derive(Debug)]
+pub struct Patch {
+    pub header: Header,
+    data: Vec<u8>,
+}
+
+impl Patch {
+    /// Creates a patch reconstructing `target` from `source`
+    pub fn make<S: Read + Seek, T: Read + Seek>(
+        source_pak: &repak::PakReader,
+        source: &mut S,
+        target_pak: &repak::PakReader,
+        target: &mut T,
+    ) -> Result<Self, Error> {
+        let mut source_ranges = BTreeMap::new();
+        for file in source_pak.files() {
+            let range = source_pak.entry_range(&file, source)?;
+            source_ranges.insert(file, range);
+        }
+        let mut target_ranges = target_pak
+            .files()
+            .into_iter()
+            .map(|file| Ok((target_pak.entry_range(&file, target)?, file)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        target_ranges.sort_by_key(|(range, _)| range.start);
+
+        let mut patch = Patch {
+            header: Header {
+                source_sha256: sha256(source)?,
+                target_sha256: sha256(target)?,
+                target_size: target.seek(SeekFrom::End(0))?,
+                removed: vec![],
+                added: vec![],
+                changed: vec![],
+                ops: vec![],
+            },
+            data: vec![],
+        };
+
+        let mut pos = 0;
+        for (range, file) in target_ranges {
+            let copy = match source_ranges.get(&file) {
+                Some(source_range) => {
+                    let unchanged = source_range.end - source_range.start
+                        == range.end - range.start
+                        && read_range(source, source_range.clone())?
+                            == read_range(target, range.clone())?;
+                    if !unchanged {
+                        patch.header.changed.push(file);
+                    }
+                    unchanged.then_some(source_range.start)
+                }
+                None => {
+                    patch.header.added.push(file);
+                    None
+                }
+            };
+            if range.end <= pos {
+                // shares its data with an entry already written
+                continue;
+            }
+            match copy {
+                Some(offset) if range.start >= pos => {
+                    patch.insert(target, pos..range.start)?;
+                    patch.push(Op::Copy {
+                        offset,
+                        len: range.end - range.start,
+                    });
+                }
+                _ => patch.insert(target, pos..range.end)?,
+            }
+            pos = range.end;
+        }
+        patch.insert(target, pos..patch.header.target_size)?;
+
+        patch.header.removed = source_ranges
+            .into_keys()
+            .filter(|file| target_pak.entry_info(file).is_none())
+            .collect();
+        Ok(patch)
+    }
+
+    /// Stores `range` of `target` in the data section
+    fn insert<R: Read + Seek>(
+        &mut self,
+        target: &mut R,
+        range: std::ops::Range<u64>,
+    ) -> Result<(), Error> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let offset = self.data.len() as u64;
+        self.data.extend(read_range(target, range.clone())?);
+        self.push(Op::Insert {
+            offset,
+            len: range.end - range.start,
+        });
+        Ok(())
+    }
+
+    /// Appends `op`, merging it with the previous op if they cover adjacent bytes
+    fn push(&mut self, op: Op) {
+        match (self.header.ops.last_mut(), op) {
+            (
+                Some(Op::Copy { offset, len }),
+                Op::Copy {
+                    offset: next,
+                    len: next_len,
+                },
+            )
+            | (
+                Some(Op::Insert { offset, len }),
+                Op::Insert {
+                    offset: next,
+                    len: next_len,
+                },
+            ) if *offset + *len == next => *len += next_len,
+            _ => self.header.ops.push(op),
+        }
+    }
+
+    /// Writes the target pak to `writer`, verifying `source` and the result against the hashes
+    /// stored in the patch
+    pub fn apply<R: Read + Seek, W: Write>(&self, source: &mut R, writer: W) -> Result<(), Error> {
+        if sha256(source)? != self.header.source_sha256 {
+            return Err(Error::SourceMismatch);
+        }
+        let mut writer = HashWriter {
+            inner: writer,
+            hasher: Sha256::new(),
+            written: 0,
+        };
+        for op in &self.header.ops {
+            match *op {
+                Op::Copy { offset, len } => {
+                    source.seek(SeekFrom::Start(offset))?;
+                    if io::copy(&mut (&mut *source).take(len), &mut writer)? != len {
+                        return Err(Error::Format("copy exceeds the input pak".to_owned()));
+                    }
+                }
+                Op::Insert { offset, len } => {
+                    let data = usize::try_from(offset)
+                        .ok()
+                        .zip(usize::try_from(offset + len).ok())
+                        .and_then(|(start, end)| self.data.get(start..end))
+                        .ok_or_else(|| Error::Format("insert exceeds the data".to_owned()))?;
+                    writer.write_all(data)?;
+                }
+            }
+        }
+        writer.flush()?;
+        if writer.written != self.header.target_size
+            || hex::encode(writer.hasher.finalize()) != self.header.target_sha256
+        {
+            return Err(Error::TargetMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let header = serde_json::to_vec(&self.header)
+            .map_err(|err| Error::Format(format!("failed to serialize header: {err}")))?;
+        writer.write_all(MAGIC)?;
+        for (tag, data) in [(HEAD, &header), (DATA, &self.data)] {
+            writer.write_all(tag)?;
+            writer.write_all(&(data.len() as u64).to_le_bytes())?;
+            writer.write_all(data)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::Format("not a patch file".to_owned()));
+        }
+
+        let (mut header, mut data) = (None, None);
+        loop {
+            let mut tag = [0; 4];
+            match reader.read_exact(&mut tag) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                res => res?,
+            }
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            let len = u64::from_le_bytes(len);
+            let mut section = vec![];
+            if (&mut reader).take(len).read_to_end(&mut section)? as u64 != len {
+                return Err(Error::Format("truncated section".to_owned()));
+            }
+            match &tag {
+                HEAD => header = Some(section),
+                DATA => data = Some(section),
+                _ => {}
+            }
+        }
+
+        let header = header.ok_or_else(|| Error::Format("missing HEAD section".to_owned()))?;
+        Ok(Self {
+            header: serde_json::from_slice(&header)
+                .map_err(|err| Error::Format(err.to_string()))?,
+            data: data.ok_or_else(|| Error::Format("missing DATA section".to_owned()))?,
+        })
+    }
+}
+
+fn read_range<R: Read + Seek>(
+    reader: &mut R,
+    range: std::ops::Range<u64>,
+) -> Result<Vec<u8>, Error> {
+    reader.seek(SeekFrom::Start(range.start))?;
+    let mut data = vec![0; (range.end - range.start) as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn sha256<R: Read + Seek>(reader: &mut R) -> Result<String, Error> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hashes everything written to the inner writer
+struct HashWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,188 @@
+//! Opening input files which other processes, such as the game or its launcher, may hold open
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// `FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE`, letting other processes keep
+/// reading, writing and deleting the file while it is open. Windows denies opening files
+/// opened by others without these flags even when only reading.
+#[cfg_attr(not(windows), allow(dead_code))]
+const SHARE_MODE: u32 = 0x1 | 0x2 | 0x4;
+
+/// `ERROR_SHARING_VIOLATION` and `ERROR_LOCK_VIOLATION`
+const SHARING_VIOLATIONS: [i32; 2] = [32, 33];
+
+/// Time to wait before the only retry, as the game often just briefly holds the file
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+fn options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        options.share_mode(SHARE_MODE);
+    }
+    options
+}
+
+fn is_sharing_violation(err: &io::Error) -> bool {
+    cfg!(windows)
+        && err
+            .raw_os_error()
+            .is_some_and(|code| SHARING_VIOLATIONS.contains(&code))
+}
+
+/// Opens `path` for reading, allowing others to keep it open. Sharing violations are retried
+/// once and otherwise reported as the file being locked.
+pub fn open_input(path: impl AsRef<Path>) -> io::Result<File> {
+    let path = path.as_ref();
+    let result = match options().open(path) {
+        Err(err) if is_sharing_violation(&err) => {
+            std::thread::sleep(RETRY_DELAY);
+            options().open(path)
+        }
+        result => result,
+    };
+    result.map_err(|err| match is_sharing_violation(&err) {
+        true => io::Error::new(
+            err.kind(),
+            format!(
+                "{} is locked by another process, close the game or launcher and try again",
+                path.display()
+            ),
+        ),
+        false => err,
+    })
+}
+
+/// Fails with a targeted message if `path` can't be a pak, rather than with an I/O error or
+/// after probing every version: if it doesn't exist, isn't a regular file or is shorter than
+/// the footer of any version
+pub fn check_pak_input(path: &Path) -> Result<(), repak::Error> {
+    let not_a_pak = |reason: String| repak::Error::NotAPak {
+        path: path.display().to_string(),
+        reason,
+    };
+    let metadata = match fs::metadata(path) {
+        // still an I/O error, as scripts may tell missing files apart by the exit code
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(repak::Error::Io(io::Error::new(
+                err.kind(),
+                format!("{} does not exist", path.display()),
+            )))
+        }
+        result => result?,
+    };
+    if metadata.is_dir() {
+        return Err(not_a_pak(
+            "path is a directory, did you mean pack?".to_owned(),
+        ));
+    }
+    if !metadata.is_file() {
+        return Err(not_a_pak("path is not a regular file".to_owned()));
+    }
+    let min_size = repak::Version::iter()
+        .map(|version| version.size() as u64)
+        .min()
+        .unwrap_or_default();
+    match metadata.len() {
+        0 => Err(not_a_pak("file is empty".to_owned())),
+        len if len < min_size => Err(not_a_pak(format!(
+            "file too small to be a pak ({len} bytes)"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Opens a pak like [`open_input`] once [`check_pak_input`] passes
+pub fn open_pak(path: impl AsRef<Path>) -> Result<File, repak::Error> {
+    check_pak_input(path.as_ref())?;
+    Ok(open_input(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_share_mode() {
+        assert_eq!(SHARE_MODE, 7);
+        // the same codes mean something else elsewhere, e.g. EPIPE on Linux
+        let err = io::Error::from_raw_os_error(32);
+        assert_eq!(is_sharing_violation(&err), cfg!(windows));
+        assert!(!is_sharing_violation(&io::Error::from_raw_os_error(2)));
+    }
+
+    #[test]
+    fn test_open_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.pak");
+        std::fs::write(&path, b"data").unwrap();
+        let _writer = OpenOptions::new().append(true).open(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+        assert!(open_input(&path).is_ok());
+        assert_eq!(
+            open_input(dir.path().join("missing.pak"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_open_input_locked() {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("input.pak");
+        std::fs::write(&path, b"data").unwrap();
+        let locked = OpenOptions::new()
+            .read(true)
+            .share_mode(0)
+            .open(&path)
+            .unwrap();
+        let err = open_input(&path).unwrap_err();
+        assert!(
+            err.to_string().contains("locked by another process"),
+            "{err}"
+        );
+        drop(locked);
+        assert!(open_input(&path).is_ok());
+    }
+
+    #[test]
+    fn test_check_pak_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let reason = |path: &Path| match check_pak_input(path).unwrap_err() {
+            repak::Error::NotAPak { reason, .. } => reason,
+            err => panic!("{err}"),
+        };
+        let err = check_pak_input(&dir.path().join("missing.pak")).unwrap_err();
+        assert_eq!(err.kind(), repak::ErrorKind::Io);
+        assert!(
+            err.to_string().ends_with("missing.pak does not exist"),
+            "{err}"
+        );
+        assert_eq!(
+            reason(dir.path()),
+            "path is a directory, did you mean pack?"
+        );
+
+        let path = dir.path().join("input.pak");
+        std::fs::write(&path, b"").unwrap();
+        assert_eq!(reason(&path), "file is empty");
+        std::fs::write(&path, b"not a pak\n").unwrap();
+        assert_eq!(reason(&path), "file too small to be a pak (10 bytes)");
+        let err = check_pak_input(&path).unwrap_err();
+        assert_eq!(err.kind(), repak::ErrorKind::InvalidInput);
+
+        // large enough files are left to the reader
+        std::fs::write(&path, [0; 1024]).unwrap();
+        check_pak_input(&path).unwrap();
+        assert!(open_pak(&path).is_ok());
+    }
+}
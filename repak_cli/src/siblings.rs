@@ -0,0 +1,138 @@
+//! The other paks of a split pakchunk set, searched with `--search-siblings` for files missing
+//! from the pak given. Games spread their content over `pakchunk0` to `pakchunkN`, so a file is
+//! often in a chunk other than the one at hand.
+
+use std::cell::OnceCell;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use path_slash::PathExt;
+
+use crate::mount::MountedPaths;
+
+/// A pak in the directory of the input, read on first use
+struct Sibling {
+    path: PathBuf,
+    /// Mount point from only the header of the index, `None` if the pak can't be read
+    mount_point: OnceCell<Option<String>>,
+    pak: OnceCell<Option<repak::PakReader>>,
+}
+
+/// Paks next to an input pak. Each is opened once per invocation however many lookups use it,
+/// and paks whose mount point rules them out are never fully read.
+pub struct Siblings<'a> {
+    paks: Vec<Sibling>,
+    builder: Box<dyn Fn() -> repak::PakBuilder + 'a>,
+    verbose: bool,
+}
+
+impl<'a> Siblings<'a> {
+    /// The other `.pak` files in the directory of `input`, highest priority first as the game
+    /// would pick among paks containing the same file. Paks are opened with `builder`.
+    pub fn new(
+        input: &Path,
+        builder: impl Fn() -> repak::PakBuilder + 'a,
+        verbose: bool,
+    ) -> io::Result<Self> {
+        let dir = match input.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut paths = vec![];
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            let is_pak = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pak"));
+            if is_pak && path.file_name() != input.file_name() && path.is_file() {
+                paths.push(path);
+            }
+        }
+        let file_name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+        paths.sort_by(|a, b| crate::stack::compare_priority(&file_name(b), &file_name(a)));
+        Ok(Self {
+            paks: paths
+                .into_iter()
+                .map(|path| Sibling {
+                    path,
+                    mount_point: OnceCell::new(),
+                    pak: OnceCell::new(),
+                })
+                .collect(),
+            builder: Box::new(builder),
+            verbose,
+        })
+    }
+
+    fn open(&self, path: &Path, metadata_only: bool) -> Option<repak::PakReader> {
+        let result = crate::sharing::open_pak(path).and_then(|file| {
+            (self.builder)()
+                .metadata_only(metadata_only)
+                .reader(&mut BufReader::new(file))
+        });
+        match result {
+            Ok(pak) => Some(pak),
+            Err(err) => {
+                if self.verbose {
+                    eprintln!("skipping sibling {}: {err}", path.display());
+                }
+                None
+            }
+        }
+    }
+
+    /// Siblings whose mount point passes `may_contain`, given the mounted paths of the sibling
+    /// with `strip_prefix`, with their index read
+    pub fn candidates<'s: 'p, 'p>(
+        &'s self,
+        strip_prefix: &'p str,
+        may_contain: impl Fn(&MountedPaths) -> bool + 'p,
+    ) -> impl Iterator<Item = (&'s Path, &'s repak::PakReader)> + 'p {
+        self.paks.iter().filter_map(move |sibling| {
+            let mount_point = sibling
+                .mount_point
+                .get_or_init(|| {
+                    // a fully read pak from an earlier lookup already knows its mount point
+                    match sibling.pak.get() {
+                        Some(pak) => pak.as_ref().map(|pak| pak.mount_point().to_owned()),
+                        None => self
+                            .open(&sibling.path, true)
+                            .map(|pak| pak.mount_point().to_owned()),
+                    }
+                })
+                .as_deref()?;
+            if !may_contain(&MountedPaths::new(mount_point, strip_prefix)) {
+                return None;
+            }
+            let pak = sibling
+                .pak
+                .get_or_init(|| self.open(&sibling.path, false))
+                .as_ref()?;
+            Some((sibling.path.as_path(), pak))
+        })
+    }
+
+    /// First sibling containing `file`, a path relative to `strip_prefix` as given to get, and
+    /// the path of the entry in it
+    pub fn find(
+        &self,
+        file: &str,
+        strip_prefix: &str,
+    ) -> Option<(&Path, &repak::PakReader, String)> {
+        let entry_path = |paths: &MountedPaths| paths.entry_path(file).ok();
+        self.candidates(strip_prefix, move |paths| entry_path(paths).is_some())
+            .find_map(|(path, pak)| {
+                let paths = MountedPaths::new(pak.mount_point(), strip_prefix);
+                let entry = entry_path(&paths)?;
+                let (entry, _) = pak.resolve_path(&entry.to_slash_lossy())?;
+                Some((path, pak, entry.to_owned()))
+            })
+    }
+}
+
+/// Whether any entry of a pak mounted at `paths` can be below the strip prefix: the mount
+/// point is below the prefix or the prefix below the mount point
+pub fn overlaps_prefix(paths: &MountedPaths) -> bool {
+    paths.strip(&paths.full_path("")).is_some() || paths.entry_path("").is_ok()
+}
@@ -0,0 +1,89 @@
+//! Checking that the output filesystem has room for the unpacked entries before writing any,
+//! rather than failing partway through with a half-written tree
+
+use std::io;
+use std::path::Path;
+
+use indicatif::HumanBytes;
+
+/// Free space of the filesystem containing a path
+pub trait SpaceProvider {
+    fn available_space(&self, path: &Path) -> io::Result<u64>;
+}
+
+/// Queries the filesystem itself
+pub struct Filesystem;
+
+impl SpaceProvider for Filesystem {
+    fn available_space(&self, path: &Path) -> io::Result<u64> {
+        fs2::available_space(path)
+    }
+}
+
+/// Bytes needed to unpack entries of the given uncompressed sizes, not counting filesystem
+/// overhead
+pub fn required_space(sizes: impl IntoIterator<Item = u64>) -> u64 {
+    sizes.into_iter().fold(0, u64::saturating_add)
+}
+
+/// Fails if the filesystem containing `output` has less than `required` bytes available
+pub fn check_space(
+    provider: &impl SpaceProvider,
+    output: &Path,
+    required: u64,
+) -> Result<(), repak::Error> {
+    let available = provider.available_space(output)?;
+    match required <= available {
+        true => Ok(()),
+        false => Err(repak::Error::Io(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!(
+                "not enough space to unpack to {}: {} are required but only {} are available (pass --no-space-check to unpack anyway)",
+                output.display(),
+                HumanBytes(required),
+                HumanBytes(available)
+            ),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FakeSpace(u64);
+
+    impl SpaceProvider for FakeSpace {
+        fn available_space(&self, _path: &Path) -> io::Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn test_required_space() {
+        assert_eq!(required_space([]), 0);
+        assert_eq!(required_space([1, 2, 3]), 6);
+        assert_eq!(required_space([u64::MAX, 1]), u64::MAX);
+    }
+
+    #[test]
+    fn test_check_space() {
+        let output = Path::new("out");
+        check_space(&FakeSpace(100), output, 100).unwrap();
+        check_space(&FakeSpace(100), output, 0).unwrap();
+        let err = check_space(&FakeSpace(1 << 20), output, 3 << 30).unwrap_err();
+        assert_eq!(err.kind(), repak::ErrorKind::Io);
+        let message = err.to_string();
+        assert!(message.contains("3.00 GiB are required"), "{message}");
+        assert!(message.contains("only 1.00 MiB are available"), "{message}");
+    }
+
+    #[test]
+    fn test_filesystem_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let available = Filesystem.available_space(dir.path()).unwrap();
+        assert!(available > 0);
+        check_space(&Filesystem, dir.path(), 0).unwrap();
+        assert!(check_space(&Filesystem, dir.path(), u64::MAX).is_err());
+    }
+}
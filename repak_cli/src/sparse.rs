@@ -0,0 +1,82 @@
+//! Output files with holes in place of zero-filled blocks
+
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Size of the runs checked for zeros, matching the block size of common filesystems
+const BLOCK_SIZE: usize = 4096;
+
+/// Writes to a file, seeking past zero-filled blocks instead of writing them so filesystems
+/// supporting sparse files leave holes there. [`SparseFile::finish`] must be called once all
+/// data is written to extend the file over a trailing hole.
+pub struct SparseFile {
+    file: File,
+    /// Zeros skipped since the last write
+    hole: u64,
+}
+
+impl SparseFile {
+    pub fn new(file: File) -> Self {
+        Self { file, hole: 0 }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.hole > 0 {
+            let len = self.file.stream_position()? + self.hole;
+            self.file.set_len(len)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for SparseFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for run in buf.chunks(BLOCK_SIZE) {
+            if run.iter().fold(0, |acc, &b| acc | b) == 0 {
+                self.hole += run.len() as u64;
+                continue;
+            }
+            if self.hole > 0 {
+                self.file.seek(SeekFrom::Current(self.hole as i64))?;
+                self.hole = 0;
+            }
+            self.file.write_all(run)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SparseFile;
+    use std::io::Write;
+
+    #[test]
+    fn test_sparse_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut data = vec![0; 5 * 4096 + 100];
+        data[4096 + 3] = 1;
+        data[3 * 4096 + 4000..4 * 4096 + 10].fill(2);
+
+        for (name, data) in [
+            ("mixed", data.clone()),
+            ("trailing", data[..4 * 4096 + 10].to_vec()),
+            ("zeros", vec![0; 2048]),
+            ("empty", vec![]),
+        ] {
+            let path = dir.path().join(name);
+            let mut file = SparseFile::new(std::fs::File::create(&path).unwrap());
+            // split writes across block boundaries
+            for chunk in data.chunks(3000) {
+                file.write_all(chunk).unwrap();
+            }
+            file.flush().unwrap();
+            file.finish().unwrap();
+            assert!(std::fs::read(&path).unwrap() == data, "{name}");
+        }
+    }
+}
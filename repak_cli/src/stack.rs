@@ -0,0 +1,178 @@
+//! Mount order of the paks of a game and the files the game sees once all of them are mounted,
+//! each provided by the pak of highest priority containing it
+
+use std::cmp::Ordering;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// Mount priority of a pak derived from its file name
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Priority {
+    /// 0 for paks which aren't patches. Like in the engine, paks ending in `_P` are 1 and
+    /// numbered patches ending in `_N_P` are N + 1 for N of at least 1.
+    patch: u32,
+    /// Number of `pakchunkN` paks, paks which aren't chunks come first
+    chunk: Option<u32>,
+    /// Whether the pak is an optional chunk such as `pakchunk0optional`, mounted after the
+    /// chunk itself
+    optional: bool,
+    /// Lowercase file name ordering paks which are otherwise equal
+    name: String,
+}
+
+fn priority(file_name: &str) -> Priority {
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let name = file_name.to_lowercase();
+    let stem = name.strip_suffix(".pak").unwrap_or(&name);
+    let patch = match stem.strip_suffix("_p") {
+        None => 0,
+        Some(base) => base
+            .rsplit_once('_')
+            .map(|(_, version)| version)
+            .filter(|version| is_number(version))
+            .and_then(|version| version.parse::<u32>().ok())
+            .filter(|&version| version >= 1)
+            .map_or(1, |version| version.saturating_add(1)),
+    };
+    let (chunk, optional) = match stem.strip_prefix("pakchunk") {
+        Some(rest) => {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            match rest[..digits].parse::<u32>() {
+                Ok(chunk) => (Some(chunk), rest[digits..].starts_with("optional")),
+                Err(_) => (None, false),
+            }
+        }
+        None => (None, false),
+    };
+    Priority {
+        patch,
+        chunk,
+        optional,
+        name,
+    }
+}
+
+/// Orders pak file names by mount priority, lowest first, so the files of later paks take the
+/// place of those of earlier ones. Patches come after all other paks, and otherwise paks are
+/// ordered by chunk number, ignoring case.
+pub fn compare_priority(a: &str, b: &str) -> Ordering {
+    priority(a).cmp(&priority(b))
+}
+
+/// A file of the merged view and the paks containing it
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Provided {
+    /// Path of the file as listed by the pak providing it
+    pub path: String,
+    /// Pak providing the file
+    pub pak: String,
+    /// Paks of lower priority which also contain the file, highest priority first
+    pub overridden: Vec<String>,
+}
+
+/// Merges the files of paks given as their name and paths, in mount order with the lowest
+/// priority first. Paths are matched ignoring case, like the engine does. Returns the files
+/// sorted by path.
+pub fn merge(paks: &[(String, Vec<String>)]) -> Vec<Provided> {
+    let mut merged = BTreeMap::<String, Provided>::new();
+    for (pak, paths) in paks {
+        for path in paths {
+            match merged.entry(path.to_lowercase()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(Provided {
+                        path: path.clone(),
+                        pak: pak.clone(),
+                        overridden: vec![],
+                    });
+                }
+                Entry::Occupied(mut entry) => {
+                    let provided = entry.get_mut();
+                    let previous = std::mem::replace(&mut provided.pak, pak.clone());
+                    provided.overridden.insert(0, previous);
+                    provided.path = path.clone();
+                }
+            }
+        }
+    }
+    merged.into_values().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare_priority() {
+        let mut names = vec![
+            "pakchunk0-Windows_1_P.pak",
+            "pakchunk10-Windows.pak",
+            "pakchunk0optional-Windows.pak",
+            "MyMod_P.pak",
+            "pakchunk2-Windows.pak",
+            "pakchunk0-Windows_P.pak",
+            "Game-Windows.pak",
+            "PAKCHUNK0-Windows.pak",
+            "pakchunk0-Windows_0_P.pak",
+            "pakchunk1-Windows_P.pak",
+        ];
+        names.sort_by(|a, b| compare_priority(a, b));
+        assert_eq!(
+            names,
+            [
+                "Game-Windows.pak",
+                "PAKCHUNK0-Windows.pak",
+                "pakchunk0optional-Windows.pak",
+                "pakchunk2-Windows.pak",
+                "pakchunk10-Windows.pak",
+                // `_0_P` counts as an unnumbered patch
+                "MyMod_P.pak",
+                "pakchunk0-Windows_0_P.pak",
+                "pakchunk0-Windows_P.pak",
+                "pakchunk1-Windows_P.pak",
+                "pakchunk0-Windows_1_P.pak",
+            ]
+        );
+        assert_eq!(
+            compare_priority("pakchunk0-Windows_2_P.pak", "pakchunk0-Windows_10_P.pak"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_priority("pakchunk0_p.PAK", "pakchunk0_P.pak"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let paks = [
+            ("base.pak", &["Game/A.uasset", "Game/B.uasset"][..]),
+            ("patch_P.pak", &["Game/b.uasset", "Game/C.uasset"]),
+            ("patch_2_P.pak", &["Game/B.uasset"]),
+        ]
+        .map(|(pak, paths)| {
+            (
+                pak.to_owned(),
+                paths.iter().map(|path| path.to_string()).collect(),
+            )
+        });
+        let provided = |path: &str, pak: &str, overridden: &[&str]| Provided {
+            path: path.to_owned(),
+            pak: pak.to_owned(),
+            overridden: overridden.iter().map(|pak| pak.to_string()).collect(),
+        };
+        assert_eq!(
+            merge(&paks),
+            [
+                provided("Game/A.uasset", "base.pak", &[]),
+                provided(
+                    "Game/B.uasset",
+                    "patch_2_P.pak",
+                    &["patch_P.pak", "base.pak"]
+                ),
+                provided("Game/C.uasset", "patch_P.pak", &[]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Keeps unpacked files from being written through symbolic links below the output directory,
+//! which the lexical check of the output paths can't see
+
+use std::io;
+use std::path::Path;
+
+use path_clean::PathClean;
+
+/// Fails with [`repak::Error::SymlinkInOutput`] if any existing component of `path` below
+/// `output`, including `path` itself, is a symbolic link. `output` itself may be one.
+///
+/// Junctions on Windows are caught as far as the standard library reports them as symbolic
+/// links, which is best-effort.
+pub fn check_symlinks(output: &Path, path: &Path) -> Result<(), repak::Error> {
+    let output = output.clean();
+    let relative = path
+        .strip_prefix(&output)
+        .map_err(|_| repak::Error::WriteOutsideOutput(path.to_string_lossy().to_string()))?;
+    let mut current = output;
+    for component in relative.components() {
+        current.push(component);
+        match current.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(repak::Error::SymlinkInOutput(
+                    current.to_string_lossy().to_string(),
+                ))
+            }
+            Ok(_) => {}
+            // nothing below a missing component exists either
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::check_symlinks;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_check_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        let elsewhere = dir.path().join("elsewhere");
+        std::fs::create_dir_all(output.join("dir")).unwrap();
+        std::fs::create_dir(&elsewhere).unwrap();
+        std::fs::write(elsewhere.join("file.txt"), "").unwrap();
+        symlink(&elsewhere, output.join("linked")).unwrap();
+        symlink(elsewhere.join("file.txt"), output.join("dir/file.txt")).unwrap();
+
+        assert!(check_symlinks(&output, &output.join("dir/new/a.txt")).is_ok());
+        assert!(check_symlinks(&output, &output.join("missing/a.txt")).is_ok());
+        assert!(check_symlinks(&output.join("."), &output.join("dir/a.txt")).is_ok());
+        assert!(check_symlinks(&output, &elsewhere.join("a.txt")).is_err());
+        assert!(matches!(
+            check_symlinks(&output, &output.join("linked/a.txt")),
+            Err(repak::Error::SymlinkInOutput(path)) if path.ends_with("linked")
+        ));
+        // a link in place of the file itself
+        assert!(matches!(
+            check_symlinks(&output, &output.join("dir/file.txt")),
+            Err(repak::Error::SymlinkInOutput(path)) if path.ends_with("file.txt")
+        ));
+
+        // the output directory itself may be a link
+        let linked_output = dir.path().join("linked_output");
+        symlink(&output, &linked_output).unwrap();
+        assert!(check_symlinks(&linked_output, &linked_output.join("dir/a.txt")).is_ok());
+    }
+}
@@ -0,0 +1,186 @@
+//! Text files listing paths or hashes, such as order files and hash lists. Files written on
+//! Windows often start with a byte order mark, end lines with CRLF or are UTF-16 as saved by
+//! Notepad, which must not end up in the paths read from them.
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+/// Encoding a text file was detected to be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        })
+    }
+}
+
+/// Contents of a text file as UTF-8 without byte order mark and with `\n` line endings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextFile {
+    pub encoding: Encoding,
+    /// Whether any line ended with CRLF or a lone CR
+    pub crlf: bool,
+    text: String,
+}
+
+impl TextFile {
+    pub fn read(path: &Path) -> Result<Self, repak::Error> {
+        let mut bytes = vec![];
+        crate::sharing::open_input(path)?.read_to_end(&mut bytes)?;
+        Self::decode(&bytes).map_err(|reason| {
+            repak::Error::Other(format!("{} is not a text file: {reason}", path.display()))
+        })
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let (encoding, text) = match bytes {
+            [0xEF, 0xBB, 0xBF, rest @ ..] => (Encoding::Utf8Bom, utf8(rest)?),
+            [0xFF, 0xFE, rest @ ..] => (Encoding::Utf16Le, utf16(rest, u16::from_le_bytes)?),
+            [0xFE, 0xFF, rest @ ..] => (Encoding::Utf16Be, utf16(rest, u16::from_be_bytes)?),
+            // UTF-16 without byte order mark, recognized by the zero byte of an ASCII first
+            // character
+            [first, 0, ..] if *first != 0 => (Encoding::Utf16Le, utf16(bytes, u16::from_le_bytes)?),
+            [0, first, ..] if *first != 0 => (Encoding::Utf16Be, utf16(bytes, u16::from_be_bytes)?),
+            _ => (Encoding::Utf8, utf8(bytes)?),
+        };
+        let crlf = text.contains('\r');
+        let text = match crlf {
+            true => text.replace("\r\n", "\n").replace('\r', "\n"),
+            false => text,
+        };
+        Ok(Self {
+            encoding,
+            crlf,
+            text,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// See [`lines`]
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &str)> {
+        lines(&self.text)
+    }
+
+    /// Encoding and line endings, for verbose output
+    pub fn describe(&self) -> String {
+        match self.crlf {
+            true => format!("{}, CRLF line endings", self.encoding),
+            false => self.encoding.to_string(),
+        }
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|err| format!("invalid UTF-8: {err}"))
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err("UTF-16 with an odd number of bytes".to_owned());
+    }
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&units).map_err(|err| format!("invalid UTF-16: {err}"))
+}
+
+/// Lines of `text` with their 1-based line number, without trailing whitespace. Blank lines
+/// and comments, lines starting with `#`, are skipped. A trailing `\r` counts as whitespace
+/// for text which wasn't read through [`TextFile`].
+pub fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .map(str::trim_end)
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.is_empty() && !line.trim_start().starts_with('#'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXPECTED: [(usize, &str); 3] = [
+        (1, "Game/A.uasset"),
+        (3, "Game/dir with space/B.uasset"),
+        (5, "Game/*"),
+    ];
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn test_lines() {
+        let lf = "Game/A.uasset\n\nGame/dir with space/B.uasset  \n# comment\nGame/*\n";
+        let crlf = lf.replace('\n', "\r\n");
+        let mixed = "Game/A.uasset\r\n \r\nGame/dir with space/B.uasset\t\r  # comment\nGame/*";
+        let files = [
+            (lf.as_bytes().to_vec(), Encoding::Utf8, false),
+            (crlf.as_bytes().to_vec(), Encoding::Utf8, true),
+            (mixed.as_bytes().to_vec(), Encoding::Utf8, true),
+            (
+                [&[0xEF, 0xBB, 0xBF], crlf.as_bytes()].concat(),
+                Encoding::Utf8Bom,
+                true,
+            ),
+            (
+                [&[0xFF, 0xFE][..], &utf16le(&crlf)].concat(),
+                Encoding::Utf16Le,
+                true,
+            ),
+            (
+                [&[0xFE, 0xFF][..], &utf16be(lf)].concat(),
+                Encoding::Utf16Be,
+                false,
+            ),
+            (utf16le(lf), Encoding::Utf16Le, false),
+            (utf16be(&crlf), Encoding::Utf16Be, true),
+        ];
+        for (i, (bytes, encoding, crlf)) in files.into_iter().enumerate() {
+            let file = TextFile::decode(&bytes).unwrap();
+            assert_eq!(file.encoding, encoding, "file {i}");
+            assert_eq!(file.crlf, crlf, "file {i}");
+            assert_eq!(file.lines().collect::<Vec<_>>(), EXPECTED, "file {i}");
+        }
+        // text read some other way
+        assert_eq!(lines(&crlf).collect::<Vec<_>>(), EXPECTED);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(TextFile::decode(b"\xFF\xFEa").is_err());
+        assert!(TextFile::decode(&[0xFF, 0xFE, 0x00, 0xD8]).is_err());
+        assert!(TextFile::decode(b"Game/\xFF.uasset").is_err());
+        let empty = TextFile::decode(b"").unwrap();
+        assert_eq!(empty.encoding, Encoding::Utf8);
+        assert_eq!(empty.lines().count(), 0);
+        assert_eq!(TextFile::decode(b"\xEF\xBB\xBF").unwrap().text(), "");
+    }
+
+    #[test]
+    fn test_describe() {
+        let file = TextFile::decode(&[&[0xFF, 0xFE][..], &utf16le("a\r\n")].concat()).unwrap();
+        assert_eq!(file.describe(), "UTF-16LE, CRLF line endings");
+        assert_eq!(TextFile::decode(b"a\n").unwrap().describe(), "UTF-8");
+    }
+}
@@ -3,6 +3,7 @@ use indoc::{formatdoc, indoc};
 use std::process::Command;
 
 const PAK: &str = "../repak/tests/packs/pack_v11.pak";
+const AES_KEY: &str = "lNJbw660IOC+kU7cnVQ1oeqrXyhk4J6UAZrCBbcnp94=";
 
 #[test]
 fn test_cli_info() {
@@ -20,6 +21,135 @@ fn test_cli_info() {
         path hash seed: Some(205C5A7D)
         4 file entries
     "});
+
+    let dir = tempfile::tempdir().unwrap();
+    let unknown = dir.path().join("unknown.pak");
+    let mut bytes = std::fs::read("../repak/tests/packs/pack_v11_compress.pak").unwrap();
+    let slots_start = bytes.len() - 5 * 32;
+    bytes[slots_start + 32..slots_start + 40].copy_from_slice(b"LZMA\x01 \xff\0");
+    std::fs::write(&unknown, bytes).unwrap();
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg(&unknown)
+        .assert();
+    assert
+        .success()
+        .stdout(indoc! {"
+            mount point: ../mount/point/root/
+            version: V11
+            version major: Fnv64BugFix
+            encrypted index: false
+            encrytion guid: Some(00000000000000000000000000000000)
+            path hash seed: Some(D8EC32CB)
+            compression slot 0: Zlib
+            compression slot 1: unknown \"LZMA\\u{1} \u{fffd}\" (stored as 4C 5A 4D 41 01 20 FF)
+            4 file entries
+        "})
+        .stderr(format!(
+            "warning: {}: compression slot 1 names \"LZMA\\u{{1}} \u{fffd}\", which is not supported, so entries compressed with it can't be read\n",
+            unknown.display()
+        ));
+}
+
+#[test]
+fn test_cli_info_check_version_detection() {
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg("--check")
+        .arg("--ignore-index-hashes")
+        .arg("../repak/tests/ambiguous/v8a_also_v8b.pak")
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("version: V8A\n"), "{stdout}");
+    assert!(
+        stdout.contains(
+            "version detection: V8A passed 3 of 3 checks, also parses as V8B which passed 2\n"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_cli_info_check_zlib_format() {
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg("--check")
+        .arg("../repak/tests/packs/pack_v11_compress.pak")
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(
+        stdout.ends_with(indoc! {"
+            zlib format: zlib
+            compression blocks: 0 of 2 blocks in 2 of 2 compressed entries don't decompress to the expected size
+        "}),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_cli_info_footer_extra_bytes() {
+    let dir = tempfile::tempdir().unwrap();
+    let extended = dir.path().join("extended.pak");
+    let mut bytes = std::fs::read(PAK).unwrap();
+    let slots_start = bytes.len() - 5 * 32;
+    bytes.splice(slots_start..slots_start, *b"\x01\x02\x03\x04\xab\xcd\0\0");
+    std::fs::write(&extended, bytes).unwrap();
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg(&extended)
+        .assert()
+        .failure();
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg("--footer-extra-bytes")
+        .arg("8")
+        .arg(&extended)
+        .assert();
+    assert.success().stdout(indoc! {"
+        mount point: ../mount/point/root/
+        version: V11
+        version major: Fnv64BugFix
+        encrypted index: false
+        encrytion guid: Some(00000000000000000000000000000000)
+        path hash seed: Some(205C5A7D)
+        footer extra bytes: 01020304ABCD0000
+        4 file entries
+    "});
+}
+
+#[test]
+fn test_cli_info_metadata_only() {
+    // the full directory index ends right before the footer, break its last encoded offset
+    let dir = tempfile::tempdir().unwrap();
+    let damaged = dir.path().join("damaged.pak");
+    let mut bytes = std::fs::read(PAK).unwrap();
+    let footer_start = bytes.len() - repak::Version::V11.size() as usize;
+    bytes[footer_start - 1] ^= 0xff;
+    std::fs::write(&damaged, bytes).unwrap();
+
+    // only the index header is read without --check or --mod-metadata
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg(&damaged)
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.ends_with("4 file entries\n"), "{stdout}");
+    for flag in ["--check", "--mod-metadata"] {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("info")
+            .arg(flag)
+            .arg(&damaged)
+            .assert()
+            .failure();
+    }
 }
 
 #[test]
@@ -60,7 +190,135 @@ fn test_cli_list() {
         .arg(PAK)
         .assert();
     assert.failure().stderr(indoc! {r#"
-        Error: Prefix "../mount/root/asdf" does not match path "../mount/point/root/directory/nested.txt"
+        error[E2]: Prefix "../mount/root/asdf" does not match path "../mount/point/root/directory/nested.txt"
+    "#});
+}
+
+#[test]
+fn test_cli_strip_prefix_auto() {
+    // the mount point ../mount/point/root/ has a single leading "../"
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg(PAK)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        mount/point/root/directory/nested.txt
+        mount/point/root/test.png
+        mount/point/root/test.txt
+        mount/point/root/zeros.bin
+    "#});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("get")
+        .arg("-v")
+        .arg(PAK)
+        .arg("mount/point/root/test.txt")
+        .assert();
+    let assert = assert.success().stderr("strip prefix: ..\n");
+    assert_eq!(assert.get_output().stdout.len(), 446);
+
+    let dir = tempfile::tempdir().unwrap();
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg("-v")
+        .arg("--quiet")
+        .arg("-o")
+        .arg(dir.path())
+        .arg(PAK)
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(stdout.starts_with("strip prefix: ..\n"), "{stdout}");
+    assert!(dir.path().join("mount/point/root/test.txt").is_file());
+}
+
+#[test]
+fn test_cli_list_filter() {
+    let encrypted = "../repak/tests/packs/pack_v11_compress_encrypt.pak";
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--aes-key")
+        .arg(AES_KEY)
+        .arg("list")
+        .arg("-s")
+        .arg("../mount")
+        .arg("--long")
+        .arg(encrypted)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        point/root/directory/nested.txt (596 bytes, 596 compressed, encrypted)
+        point/root/test.png (10257 bytes, 7760 compressed, Zlib, encrypted)
+        point/root/test.txt (446 bytes, 446 compressed, encrypted)
+        point/root/zeros.bin (2048 bytes, 32 compressed, Zlib, encrypted)
+    "#});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--aes-key")
+        .arg(AES_KEY)
+        .arg("list")
+        .arg("-s")
+        .arg("../mount")
+        .arg("--only-encrypted")
+        .arg("--compression")
+        .arg("Zlib")
+        .arg("--max-size")
+        .arg("4096")
+        .arg(encrypted)
+        .assert();
+    assert
+        .success()
+        .stdout(indoc! {r#"
+            point/root/zeros.bin
+        "#})
+        .stderr("1 of 4 entries matched\n");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg("--only-encrypted")
+        .arg(PAK)
+        .assert();
+    assert
+        .success()
+        .stdout("")
+        .stderr("0 of 4 entries matched\n");
+}
+
+#[test]
+fn test_cli_ls_tree() {
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("ls-tree")
+        .arg(PAK)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        ../mount/point/root/ (4 files, 13347 bytes, 13347 compressed)
+          directory/ (1 files, 596 bytes, 596 compressed)
+            nested.txt (596 bytes, 596 compressed)
+          test.png (10257 bytes, 10257 compressed)
+          test.txt (446 bytes, 446 compressed)
+          zeros.bin (2048 bytes, 2048 compressed)
+    "#});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("ls-tree")
+        .arg("--depth")
+        .arg("1")
+        .arg("--sort")
+        .arg("size")
+        .arg(PAK)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        ../mount/point/root/ (4 files, 13347 bytes, 13347 compressed)
+          test.png (10257 bytes, 10257 compressed)
+          zeros.bin (2048 bytes, 2048 compressed)
+          directory/ (1 files, 596 bytes, 596 compressed)
+          test.txt (446 bytes, 446 compressed)
     "#});
 }
 
@@ -87,10 +345,562 @@ fn test_cli_get() {
         .arg("root/doesnotexist.txt")
         .assert();
     assert.failure().stderr(indoc! {r#"
-        Error: No entry found at doesnotexist.txt
+        error[E2]: No entry found at doesnotexist.txt
     "#});
 }
 
+#[test]
+fn test_cli_search_siblings() {
+    let dir = tempfile::tempdir().unwrap();
+    let write_pak = |name: &str, files: &[(&str, &str)]| {
+        let mut writer = repak::PakBuilder::new().writer(
+            std::io::Cursor::new(vec![]),
+            repak::Version::V11,
+            "../../../".into(),
+            None,
+        );
+        for (path, data) in files {
+            writer.write_file(path, false, data.as_bytes()).unwrap();
+        }
+        let bytes = writer.write_index().unwrap().into_inner();
+        std::fs::write(dir.path().join(name), bytes).unwrap();
+    };
+    write_pak("pakchunk0-Windows.pak", &[("a.txt", "chunk0")]);
+    write_pak(
+        "pakchunk1-Windows.pak",
+        &[("b.txt", "chunk1"), ("shared.txt", "chunk1")],
+    );
+    write_pak("pakchunk1-Windows_P.pak", &[("shared.txt", "patch")]);
+    std::fs::write(dir.path().join("broken.pak"), [0; 1024]).unwrap();
+    let input = dir.path().join("pakchunk0-Windows.pak");
+    let sibling = |name: &str| dir.path().join(name).display().to_string();
+
+    let get = |file: &str, search: bool| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("get")
+            .arg(&input)
+            .arg(file)
+            .args(search.then_some("--search-siblings"))
+            .assert()
+    };
+    get("a.txt", true).success().stdout("chunk0").stderr("");
+    get("b.txt", false)
+        .failure()
+        .stderr("error[E2]: No entry found at b.txt\n");
+    get("b.txt", true)
+        .success()
+        .stdout("chunk1")
+        .stderr(format!(
+            "b.txt read from sibling pak {}\n",
+            sibling("pakchunk1-Windows.pak")
+        ));
+    // the patch takes priority like in game
+    get("shared.txt", true)
+        .success()
+        .stdout("patch")
+        .stderr(format!(
+            "shared.txt read from sibling pak {}\n",
+            sibling("pakchunk1-Windows_P.pak")
+        ));
+    get("missing.txt", true)
+        .failure()
+        .stderr("error[E2]: No entry found at missing.txt\n");
+
+    let output = dir.path().join("out");
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .args(["-i", "a.txt", "-i", "b.txt", "-i", "missing.txt"])
+        .arg("--search-siblings")
+        .arg("--quiet")
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(output.join("a.txt")).unwrap(),
+        "chunk0"
+    );
+    assert_eq!(
+        std::fs::read_to_string(output.join("b.txt")).unwrap(),
+        "chunk1"
+    );
+    assert!(!output.join("shared.txt").exists());
+}
+
+#[test]
+fn test_cli_pack_match_mount_of() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("mod");
+    std::fs::create_dir_all(input.join("root/directory")).unwrap();
+    std::fs::write(input.join("a.txt"), "a").unwrap();
+    std::fs::write(input.join("root/directory/b.txt"), "b").unwrap();
+    let output = dir.path().join("mod.pak");
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg(&output)
+        .args(["--version", "V11", "--match-mount-of", PAK, "--quiet"])
+        .assert()
+        .success()
+        .stderr(indoc! {"
+            warning: 1 files such as root/directory/b.txt start with root/, which the mount point ../mount/point/root/ already ends with, so the game finds them below ../mount/point/root/root/
+        "});
+
+    let open = |path: &std::path::Path| {
+        repak::PakBuilder::new()
+            .reader(&mut std::io::BufReader::new(
+                std::fs::File::open(path).unwrap(),
+            ))
+            .unwrap()
+    };
+    let reference = open(std::path::Path::new(PAK));
+    let packed = open(&output);
+    assert_eq!(packed.mount_point(), reference.mount_point());
+    assert_eq!(packed.path_hash_seed(), reference.path_hash_seed());
+    assert_eq!(packed.path_hash_seed(), Some(0x205C5A7D));
+    assert_eq!(packed.files(), ["a.txt", "root/directory/b.txt"]);
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg(&output)
+        .args(["--match-mount-of", PAK, "--mount-point", "../../../"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_warnings_as_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let unknown = dir.path().join("unknown.pak");
+    let mut bytes = std::fs::read("../repak/tests/packs/pack_v11_compress.pak").unwrap();
+    let slots_start = bytes.len() - 5 * 32;
+    bytes[slots_start..slots_start + 5].copy_from_slice(b"Zlib2");
+    std::fs::write(&unknown, bytes).unwrap();
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg(&unknown)
+        .assert()
+        .success()
+        .stderr(format!(
+            "warning: {}: compression slot 0 names \"Zlib2\", which is not supported, so entries compressed with it can't be read\n",
+            unknown.display()
+        ));
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg(&unknown)
+        .arg("--warnings-as-errors")
+        .assert()
+        .failure()
+        .stderr(indoc! {r#"
+            error[E3]: compression slot 0 names "Zlib2", which is not supported, so entries compressed with it can't be read (refused by strict parsing)
+        "#});
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .args(["list", PAK, "--warnings-as-errors"])
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[test]
+fn test_cli_pack_mod_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("mod");
+    std::fs::create_dir_all(&input).unwrap();
+    std::fs::write(input.join("a.txt"), "a").unwrap();
+    let output = dir.path().join("mod.pak");
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg(&output)
+        .args(["--version", "V11", "--quiet"])
+        .args(["--mod-name", "Better Lighting", "--mod-version", "1.2.0"])
+        .args(["--mod-dependency", "Core", "--mod-dependency", "UI Lib"])
+        .assert()
+        .success();
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg("--mod-metadata")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(indoc! {"
+            mount point: ../../../
+            version: V11
+            version major: Fnv64BugFix
+            encrypted index: false
+            encrytion guid: Some(00000000000000000000000000000000)
+            path hash seed: Some(00000000)
+            2 file entries
+            mod name: Better Lighting
+            mod version: 1.2.0
+            mod dependencies: Core, UI Lib
+        "});
+
+    // a repak_mod.json of the input is replaced by the one given
+    std::fs::write(input.join("repak_mod.json"), "{}").unwrap();
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg(&output)
+        .args(["--quiet", "--mod-name", "other"])
+        .assert()
+        .success()
+        .stderr(format!(
+            "warning: {} is replaced by the metadata given with --mod-*, so repak_mod.json is not packed\n",
+            input.join("repak_mod.json").display()
+        ));
+    Command::cargo_bin("repak")
+        .unwrap()
+        .args(["get", output.to_str().unwrap(), "repak_mod.json"])
+        .assert()
+        .success()
+        .stdout("{\n  \"format\": 1,\n  \"name\": \"other\"\n}");
+}
+
+#[test]
+fn test_cli_entry_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("mod");
+    std::fs::create_dir_all(&input).unwrap();
+    let data = (0..400_000u32)
+        .map(|i| (i.wrapping_mul(i) >> 7) as u8)
+        .collect::<Vec<_>>();
+    std::fs::write(input.join("large.bin"), data).unwrap();
+    let output = dir.path().join("mod.pak");
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg(&output)
+        .args(["--version", "V8B", "--compression", "Zlib", "--quiet"])
+        .assert()
+        .success();
+
+    let entry_blocks = |pak: &std::path::Path| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("--ignore-index-hashes")
+            .arg("entry-blocks")
+            .arg(pak)
+            .arg("large.bin")
+            .assert()
+            .success()
+    };
+    entry_blocks(&output).stdout(indoc! {"
+        large.bin: 106169 bytes compressed with Zlib, 400000 bytes uncompressed
+        block 0: 0x79..0x7cd0, 31831 bytes compressed, 126976 bytes uncompressed
+        block 1: 0x7cd0..0xf5e3, 30995 bytes compressed, 126976 bytes uncompressed
+        block 2: 0xf5e3..0x16080, 27293 bytes compressed, 126976 bytes uncompressed
+        block 3: 0x16080..0x19f32, 16050 bytes compressed, 19072 bytes uncompressed
+    "});
+
+    // the compression block size of the index and the record before the data no longer
+    // matches the blocks, and the index its hash
+    let mut bytes = std::fs::read(&output).unwrap();
+    let stored = 126976u32.to_le_bytes();
+    let positions = (0..bytes.len() - 4)
+        .filter(|&i| bytes[i..i + 4] == stored)
+        .collect::<Vec<_>>();
+    assert_eq!(positions.len(), 2);
+    for i in positions {
+        bytes[i..i + 4].copy_from_slice(&131072u32.to_le_bytes());
+    }
+    let mismatched = dir.path().join("mismatched.pak");
+    std::fs::write(&mismatched, bytes).unwrap();
+    entry_blocks(&mismatched).stdout(indoc! {"
+        large.bin: 106169 bytes compressed with Zlib, 400000 bytes uncompressed
+        block 0: 0x79..0x7cd0, 31831 bytes compressed, 126976 bytes uncompressed, expected 131072 bytes
+        block 1: 0x7cd0..0xf5e3, 30995 bytes compressed, 126976 bytes uncompressed, expected 131072 bytes
+        block 2: 0xf5e3..0x16080, 27293 bytes compressed, 126976 bytes uncompressed, expected 131072 bytes
+        block 3: 0x16080..0x19f32, 16050 bytes compressed, 19072 bytes uncompressed, expected 6784 bytes
+        4 of 4 blocks don't decompress to the expected size
+    "});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .args([
+            "info",
+            "--check",
+            "--block-sample",
+            "1",
+            "--ignore-index-hashes",
+        ])
+        .arg(&mismatched)
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(
+        stdout.ends_with(indoc! {"
+            compression blocks: 4 of 4 blocks in 1 of 1 compressed entries don't decompress to the expected size
+              large.bin block 0: 126976 bytes instead of 131072
+              large.bin block 1: 126976 bytes instead of 131072
+              large.bin block 2: 126976 bytes instead of 131072
+              large.bin block 3: 19072 bytes instead of 6784
+        "}),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_cli_hashlist_sequential() {
+    let hash_list = |pak: &str, args: &[&str]| {
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("--aes-key")
+            .arg(AES_KEY)
+            .arg("hash-list")
+            .arg(pak)
+            .args(args)
+            .assert()
+            .success();
+        String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+    };
+    for pak in [
+        PAK,
+        "../repak/tests/packs/pack_v11_compress.pak",
+        "../repak/tests/packs/pack_v11_compress_encrypt.pak",
+        "../repak/tests/packs/pack_v5.pak",
+    ] {
+        for args in [&[][..], &["--compressed-hash"]] {
+            let parallel = hash_list(pak, args);
+            assert_eq!(parallel.lines().count(), 4, "{pak}");
+            let sequential = hash_list(pak, &[args, &["--sequential"]].concat());
+            assert_eq!(sequential, parallel, "{pak} {args:?}");
+        }
+    }
+}
+
+#[test]
+fn test_cli_pack_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("mod");
+    std::fs::create_dir_all(input.join("directory")).unwrap();
+    std::fs::write(input.join("a.txt"), "a".repeat(0x1000)).unwrap();
+    std::fs::write(input.join("directory/b.txt"), "b").unwrap();
+    let output = dir.path().join("mod.pak");
+    let args = ["--version", "V11", "--compression", "Zlib"];
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg(&output)
+        .args(args)
+        .arg("--quiet")
+        .assert()
+        .success();
+    let expected = std::fs::read(&output).unwrap();
+
+    // the summary goes to stderr, leaving stdout to the pak
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg(&input)
+        .arg("-")
+        .args(args)
+        .arg("--verify")
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Packed 2 files to stdout"), "{stderr}");
+    assert!(assert.get_output().stdout == expected);
+    assert!(!dir.path().join("-").exists());
+}
+
+#[test]
+fn test_cli_decrypt_limit() {
+    use aes::cipher::{BlockEncrypt, KeyInit};
+    use base64::{engine::general_purpose, Engine as _};
+    let key =
+        aes::Aes256::new_from_slice(&general_purpose::STANDARD.decode(AES_KEY).unwrap()).unwrap();
+
+    let data = "partially encrypted\n".repeat(10);
+    let mut writer = repak::PakBuilder::new().writer(
+        std::io::Cursor::new(vec![]),
+        repak::Version::V8B,
+        "../../../".into(),
+        None,
+    );
+    writer
+        .write_file("test.txt", false, data.as_bytes())
+        .unwrap();
+    let mut bytes = writer.write_index().unwrap().into_inner();
+    // encrypt the first 32 bytes of the data and set the encrypted flag of the only record,
+    // which no longer matches the hash of the index
+    let index = repak::PakBuilder::new()
+        .parse_footer(&bytes)
+        .unwrap()
+        .index_range();
+    let data_offset = index.start as usize - data.len();
+    for block in bytes[data_offset..data_offset + 32].chunks_exact_mut(16) {
+        key.encrypt_block(aes::Block::from_mut_slice(block));
+    }
+    bytes[index.end as usize - 5] = 1;
+    let dir = tempfile::tempdir().unwrap();
+    let pak = dir.path().join("partial.pak");
+    std::fs::write(&pak, bytes).unwrap();
+
+    let get = |args: &[&str]| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("--aes-key")
+            .arg(AES_KEY)
+            .arg("--ignore-index-hashes")
+            .arg("get")
+            .args(args)
+            .arg(&pak)
+            .arg("test.txt")
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+    assert_eq!(get(&["--decrypt-limit", "32"]), data.as_bytes());
+    assert_ne!(get(&[]), data.as_bytes());
+}
+
+#[test]
+fn test_cli_not_a_pak() {
+    let dir = tempfile::tempdir().unwrap();
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("get")
+        .arg(dir.path())
+        .arg("test.txt")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(formatdoc! {r#"
+            error[E2]: path is a directory, did you mean pack?: "{}"
+        "#, dir.path().display()});
+
+    let empty = dir.path().join("empty.pak");
+    std::fs::write(&empty, b"").unwrap();
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("get")
+        .arg(&empty)
+        .arg("test.txt")
+        .assert()
+        .failure()
+        .stderr(formatdoc! {r#"
+            error[E2]: file is empty: "{}"
+        "#, empty.display()});
+}
+
+#[test]
+fn test_cli_get_range() {
+    let get = |pak: &str, args: &[&str]| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("get")
+            .arg("-s")
+            .arg("../mount/point")
+            .arg(pak)
+            .arg("root/test.png")
+            .args(args)
+            .assert()
+    };
+    for pak in [PAK, "../repak/tests/packs/pack_v11_compress.pak"] {
+        let full = get(pak, &[]).success().get_output().stdout.clone();
+        assert_eq!(full.len(), 10257);
+
+        let ranged = |args: &[&str]| get(pak, args).success().get_output().stdout.clone();
+        assert_eq!(ranged(&["--offset", "0", "--length", "16"]), &full[..16]);
+        assert_eq!(
+            ranged(&["--offset", "5000", "--length", "100"]),
+            &full[5000..5100]
+        );
+        assert_eq!(ranged(&["--offset", "10000"]), &full[10000..]);
+        assert_eq!(ranged(&["--length", "10257"]), full);
+        assert_eq!(ranged(&["--tail", "57"]), &full[10200..]);
+        assert!(ranged(&["--offset", "10257"]).is_empty());
+
+        get(pak, &["--offset", "10000", "--length", "300"])
+            .failure()
+            .code(2)
+            .stderr(indoc! {"
+                error[E2]: 300 bytes at offset 10000 exceed test.png, which is 10257 bytes
+            "});
+        get(pak, &["--tail", "10258"]).failure().code(2);
+        get(pak, &["--tail", "1", "--offset", "0"])
+            .failure()
+            .code(2);
+    }
+
+    // only the last of the blocks of a large compressed entry is read
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    std::fs::create_dir(&input).unwrap();
+    let data = (0..300_000u32)
+        .map(|i| (i.wrapping_mul(i) >> 7) as u8)
+        .collect::<Vec<_>>();
+    std::fs::write(input.join("large.bin"), &data).unwrap();
+    let pak = dir.path().join("large.pak");
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg("--quiet")
+        .arg("--compression")
+        .arg("Zlib")
+        .arg(&input)
+        .arg(&pak)
+        .assert()
+        .success();
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("get")
+        .arg(&pak)
+        .arg("large.bin")
+        .arg("--tail")
+        .arg("10")
+        .arg("-v")
+        .assert();
+    let assert = assert
+        .success()
+        .stderr("strip prefix: ../../..\nread 1 of 3 blocks\n");
+    assert_eq!(assert.get_output().stdout, &data[299_990..]);
+}
+
+/// Stdout of a successful `repak pack` without the line with the time taken, which varies
+fn pack_stdout(assert: assert_cmd::assert::Assert) -> String {
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    let timing = stdout.lines().find(|line| line.starts_with("Took "));
+    assert!(timing.is_some_and(|line| line.ends_with("/s)")), "{stdout}");
+    stdout
+        .lines()
+        .filter(|line| !line.starts_with("Took "))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Summary line of `repak pack` for `read` bytes of input packed to `output`
+fn pack_sizes(read: u64, output: &std::path::Path) -> String {
+    let written = std::fs::metadata(output).unwrap().len();
+    format!(
+        "{} in, {} out ({:.0}%)",
+        indicatif::HumanBytes(read),
+        indicatif::HumanBytes(written),
+        written as f64 * 100.0 / read as f64
+    )
+}
+
 #[test]
 fn test_cli_pack() {
     let dir = tempfile::tempdir().unwrap();
@@ -108,9 +918,14 @@ fn test_cli_pack() {
         .arg("V11")
         .arg(&out_pak)
         .assert();
-    assert.success().stdout(formatdoc! {r#"
-        Packed 4 files to {}
-    "#, out_pak.to_string_lossy()});
+    let stdout = pack_stdout(assert);
+    assert_eq!(
+        stdout,
+        formatdoc! {r#"
+            Packed 4 files to {}
+            {}
+        "#, out_pak.to_string_lossy(), pack_sizes(13_347, &out_pak)}
+    );
 
     // TODO test packing to non-empty file
 
@@ -122,11 +937,44 @@ fn test_cli_pack() {
         .arg("../mount/point/root")
         .assert();
     assert.success().stdout(formatdoc! {r#"
-        Unpacked 4 files to {} from {}
+        Unpacking 13.03 KiB in 4 files to {0}
+        Unpacked 4 files to {0} from {1}
     "#, out_dir.to_string_lossy(), out_pak.to_string_lossy()});
     assert!(!dir_diff::is_different("../repak/tests/pack/", out_dir).unwrap());
 }
 
+#[test]
+fn test_cli_magic() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_pak = dir.path().join("output.pak");
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--magic")
+        .arg("0xFEEDFACE")
+        .arg("pack")
+        .arg("../repak/tests/pack/")
+        .arg(&out_pak)
+        .assert()
+        .success();
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg(&out_pak)
+        .assert()
+        .failure();
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg(&out_pak)
+        .arg("--magic")
+        .arg("4277009102")
+        .assert()
+        .success();
+}
+
 #[test]
 fn test_cli_unpack() {
     let dir = tempfile::tempdir().unwrap();
@@ -141,7 +989,8 @@ fn test_cli_unpack() {
         .arg(dir.path())
         .assert();
     assert.success().stdout(formatdoc! {r#"
-        Unpacked 4 files to {} from ../repak/tests/packs/pack_v11.pak
+        Unpacking 13.03 KiB in 4 files to {0}
+        Unpacked 4 files to {0} from ../repak/tests/packs/pack_v11.pak
     "#, &dir.path().to_string_lossy()});
     assert!(!dir_diff::is_different("../repak/tests/pack/", dir.path().join("point")).unwrap());
 
@@ -149,26 +998,744 @@ fn test_cli_unpack() {
 }
 
 #[test]
-fn test_cli_unpack_include() {
+fn test_cli_unpack_audit_log() {
+    use sha2::Digest;
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("audit.jsonl");
+    let unpack = |output: &str, args: &[&str]| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("unpack")
+            .arg(PAK)
+            .arg("-q")
+            .arg("-o")
+            .arg(dir.path().join(output))
+            .arg("--audit-log")
+            .arg(&log)
+            .args(args)
+            .assert()
+            .success();
+        std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>()
+    };
+    let keys = |record: &serde_json::Value| {
+        let mut keys = record
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    };
+
+    let records = unpack("verified", &[]);
+    assert_eq!(records.len(), 5);
+    let pak = &records[0];
+    assert_eq!(
+        keys(pak),
+        [
+            "encrypted_index",
+            "encryption_guid",
+            "entry_count",
+            "mount_point",
+            "pak",
+            "path_hash_seed",
+            "sha256",
+            "size",
+            "timestamp",
+            "tool",
+            "tool_version",
+            "type",
+            "version",
+        ]
+    );
+    let bytes = std::fs::read(PAK).unwrap();
+    assert_eq!(pak["type"], "pak");
+    assert_eq!(pak["sha256"], hex::encode(sha2::Sha256::digest(&bytes)));
+    assert_eq!(pak["size"], bytes.len());
+    assert_eq!(pak["version"], "V11");
+    assert_eq!(pak["entry_count"], 4);
+    let mut paths = vec![];
+    for entry in &records[1..] {
+        assert_eq!(
+            keys(entry),
+            [
+                "compressed",
+                "hash",
+                "hash_algorithm",
+                "hash_of",
+                "output",
+                "pak",
+                "path",
+                "timestamp",
+                "type",
+                "uncompressed",
+            ]
+        );
+        assert_eq!(entry["type"], "entry");
+        // the stored hash checked while reading
+        assert_eq!(entry["hash_algorithm"], "sha1");
+        assert_eq!(entry["hash_of"], "stored");
+        let output = std::fs::read(entry["output"].as_str().unwrap()).unwrap();
+        assert_eq!(entry["uncompressed"], output.len());
+        paths.push(entry["path"].as_str().unwrap().to_owned());
+    }
+    paths.sort();
+    assert_eq!(
+        paths,
+        ["directory/nested.txt", "test.png", "test.txt", "zeros.bin"]
+    );
+
+    // the output is hashed instead when not verifying
+    let records = unpack("unverified", &["--no-verify"]);
+    assert_eq!(records.len(), 5);
+    for entry in &records[1..] {
+        assert_eq!(entry["hash_algorithm"], "sha256");
+        assert_eq!(entry["hash_of"], "output");
+        let output = std::fs::read(entry["output"].as_str().unwrap()).unwrap();
+        assert_eq!(entry["hash"], hex::encode(sha2::Sha256::digest(&output)));
+    }
+}
+
+#[test]
+fn test_cli_unpack_verify() {
     let dir = tempfile::tempdir().unwrap();
+    let corrupt = dir.path().join("corrupt.pak");
+    let mut bytes = std::fs::read(PAK).unwrap();
+    let text = std::fs::read("../repak/tests/pack/root/test.txt").unwrap();
+    let start = bytes
+        .windows(text.len())
+        .position(|window| window == text)
+        .unwrap();
+    bytes[start] ^= 0xff;
+    std::fs::write(&corrupt, bytes).unwrap();
 
+    let output = dir.path().join("verified");
     let assert = Command::cargo_bin("repak")
         .unwrap()
         .arg("unpack")
-        .arg(PAK)
+        .arg(&corrupt)
+        .arg("-q")
         .arg("-s")
-        .arg("../mount")
-        .arg("-i")
-        .arg("point/**/*.txt")
+        .arg("../mount/point/root/")
         .arg("-o")
-        .arg(dir.path())
+        .arg(&output)
         .assert();
-    assert.success().stdout(formatdoc! {r#"
-        Unpacked 2 files to {} from ../repak/tests/packs/pack_v11.pak
-    "#, &dir.path().to_string_lossy()});
-}
+    let stderr = String::from_utf8(assert.failure().code(3).get_output().stderr.clone()).unwrap();
+    assert!(
+        stderr.starts_with(r#"error[E3]: verification of "test.txt" failed: expected sha1 "#),
+        "{stderr}"
+    );
 
-#[test]
+    let output = dir.path().join("unverified");
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(&corrupt)
+        .arg("-q")
+        .arg("-s")
+        .arg("../mount/point/root/")
+        .arg("-o")
+        .arg(&output)
+        .arg("--no-verify")
+        .assert();
+    assert.success();
+    let unpacked = std::fs::read(output.join("test.txt")).unwrap();
+    assert_ne!(unpacked, text);
+    assert_eq!(unpacked.len(), text.len());
+}
+
+#[test]
+fn test_cli_unpack_sparse() {
+    for pak in [PAK, "../repak/tests/packs/pack_v11_compress.pak"] {
+        let dir = tempfile::tempdir().unwrap();
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("unpack")
+            .arg(pak)
+            .arg("-s")
+            .arg("../mount")
+            .arg("-o")
+            .arg(dir.path())
+            .arg("--sparse")
+            .assert();
+        assert.success();
+        assert!(
+            !dir_diff::is_different("../repak/tests/pack/", dir.path().join("point")).unwrap(),
+            "{pak}"
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_unpack_symlink() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("output");
+    let elsewhere = dir.path().join("elsewhere");
+    std::fs::create_dir(&output).unwrap();
+    std::fs::create_dir(&elsewhere).unwrap();
+    std::os::unix::fs::symlink(&elsewhere, output.join("point")).unwrap();
+
+    let unpack = |follow: bool| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("unpack")
+            .arg(PAK)
+            .arg("-s")
+            .arg("../mount")
+            .arg("-o")
+            .arg(&output)
+            .arg("--quiet")
+            .args(follow.then_some("--follow-symlinks"))
+            .assert()
+    };
+    let assert = unpack(false).failure().code(7);
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("symbolic link"), "{stderr}");
+    assert!(
+        stderr.contains(&*output.join("point").to_string_lossy()),
+        "{stderr}"
+    );
+    assert_eq!(std::fs::read_dir(&elsewhere).unwrap().count(), 0);
+
+    unpack(true).success();
+    assert!(!dir_diff::is_different("../repak/tests/pack/", &elsewhere).unwrap());
+}
+
+#[test]
+fn test_cli_unpack_include() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(PAK)
+        .arg("-s")
+        .arg("../mount")
+        .arg("-i")
+        .arg("point/**/*.txt")
+        .arg("-o")
+        .arg(dir.path())
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        Unpacking 1.02 KiB in 2 files to {0}
+        Unpacked 2 files to {0} from ../repak/tests/packs/pack_v11.pak
+    "#, &dir.path().to_string_lossy()});
+}
+
+#[test]
+fn test_cli_include_exclude() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(PAK)
+        .arg("-s")
+        .arg("../mount")
+        .arg("-e")
+        .arg("point/root/directory")
+        .arg("--exclude")
+        .arg("**/*.png")
+        .arg("-o")
+        .arg(dir.path())
+        .arg("--dry-run")
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        test.txt -> {out}/point/root/test.txt (446 bytes)
+        zeros.bin -> {out}/point/root/zeros.bin (2048 bytes)
+        Would unpack 2 files (2494 bytes) to {out} from ../repak/tests/packs/pack_v11.pak
+    "#, out = dir.path().to_string_lossy()});
+
+    // patterns match the same relative paths whichever way the input directory is written
+    for input in ["../repak/tests/pack", "../repak/tests/pack/"] {
+        let output = dir.path().join("output.pak");
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .arg("--quiet")
+            .arg("-i")
+            .arg("root")
+            .arg("-e")
+            .arg("root/*.png")
+            .arg("-e")
+            .arg("root/directory/")
+            .arg(input)
+            .arg(&output)
+            .assert();
+        assert.success();
+
+        let mut reader = std::io::BufReader::new(std::fs::File::open(&output).unwrap());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        let mut files = pak.files();
+        files.sort();
+        assert_eq!(files, ["root/test.txt", "root/zeros.bin"]);
+    }
+}
+
+#[test]
+fn test_cli_unpack_with_siblings() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    std::fs::create_dir_all(input.join("Game/Maps")).unwrap();
+    for file in [
+        "Game/Foo.uasset",
+        "Game/Foo.uexp",
+        "Game/Foo.ubulk",
+        "Game/Foo.txt",
+        "Game/Bar.uasset",
+        "Game/Bar.uexp",
+        "Game/Maps/Foo.uexp",
+    ] {
+        std::fs::write(input.join(file), file).unwrap();
+    }
+    let pak = dir.path().join("assets.pak");
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg("--quiet")
+        .arg(&input)
+        .arg(&pak)
+        .assert()
+        .success();
+
+    let output = dir.path().join("output");
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(&pak)
+        .arg("-i")
+        .arg("Game/Foo.uasset")
+        .arg("--with-siblings")
+        .arg("-o")
+        .arg(&output)
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        Unpacking 42 B in 3 files to {0}
+        2 sibling entries added by --with-siblings
+        Unpacked 3 files to {0} from {1}
+    "#, output.to_string_lossy(), pak.to_string_lossy()});
+    let mut unpacked = std::fs::read_dir(output.join("Game"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    unpacked.sort();
+    assert_eq!(unpacked, ["Foo.uasset", "Foo.ubulk", "Foo.uexp"]);
+    assert_eq!(
+        std::fs::read_to_string(output.join("Game/Foo.uexp")).unwrap(),
+        "Game/Foo.uexp"
+    );
+
+    // without the flag only the matched file is unpacked
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(&pak)
+        .arg("-i")
+        .arg("Game/Foo.uasset")
+        .arg("-o")
+        .arg(dir.path().join("only"))
+        .arg("--dry-run")
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        Game/Foo.uasset -> {out}/Game/Foo.uasset (15 bytes)
+        Would unpack 1 files (15 bytes) to {out} from {pak}
+    "#, out = dir.path().join("only").to_string_lossy(), pak = pak.to_string_lossy()});
+}
+
+#[test]
+fn test_cli_unpack_dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("output");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(PAK)
+        .arg("-s")
+        .arg("../mount")
+        .arg("-i")
+        .arg("point/root/directory/")
+        .arg("-i")
+        .arg("point/root/*.png")
+        .arg("-o")
+        .arg(&output)
+        .arg("--dry-run")
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        directory/nested.txt -> {out}/point/root/directory/nested.txt (596 bytes)
+        test.png -> {out}/point/root/test.png (10257 bytes)
+        Would unpack 2 files (10853 bytes) to {out} from ../repak/tests/packs/pack_v11.pak
+    "#, out = output.to_string_lossy()});
+    assert!(!output.exists(), "dry run must not create the output");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(PAK)
+        .arg("-s")
+        .arg("../mount/root/asdf")
+        .arg("-o")
+        .arg(&output)
+        .arg("--dry-run")
+        .assert();
+    assert.failure().stderr(indoc! {r#"
+        error[E2]: Prefix "../mount/root/asdf" does not match path "../mount/point/root/directory/nested.txt"
+    "#});
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_cli_unpack_filter() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg("../repak/tests/packs/pack_v11_compress.pak")
+        .arg("-s")
+        .arg("../mount")
+        .arg("-i")
+        .arg("point/root/*")
+        .arg("--only-compressed")
+        .arg("-o")
+        .arg(dir.path())
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        Unpacking 12.02 KiB in 2 files to {0}
+        2 of 4 entries matched
+        Unpacked 2 files to {0} from ../repak/tests/packs/pack_v11_compress.pak
+    "#, &dir.path().to_string_lossy()});
+    assert!(dir.path().join("point/root/test.png").exists());
+    assert!(dir.path().join("point/root/zeros.bin").exists());
+    assert!(!dir.path().join("point/root/test.txt").exists());
+}
+
+#[test]
+fn test_cli_key_sources() {
+    use base64::Engine as _;
+
+    let encrypted = "../repak/tests/packs/pack_v11_encryptindex.pak";
+    let dir = tempfile::tempdir().unwrap();
+    let hex_key = format!(
+        "0x{}",
+        hex::encode_upper(
+            base64::engine::general_purpose::STANDARD
+                .decode(AES_KEY)
+                .unwrap()
+        )
+    );
+    let wrong_key = format!("0x{}", "00".repeat(32));
+    let key_file = |name: &str, contents: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    };
+    let crypto = key_file(
+        "crypto.json",
+        &formatdoc! {r#"
+            [
+              {{ "Guid": "0x7D5A5C20000000000000000000000001", "Key": "{wrong_key}" }},
+              {{ "Name": "main", "Guid": "00000000-00000000-00000000-00000000", "Key": "{hex_key}" }}
+            ]
+        "#},
+    );
+    let wrong_crypto = key_file(
+        "wrong.json",
+        &formatdoc! {r#"
+            [{{ "guid": "00000000000000000000000000000000", "key": "{wrong_key}" }}]
+        "#},
+    );
+    let run = |args: &[&str], env: Option<&str>| {
+        let mut command = Command::cargo_bin("repak").unwrap();
+        command.args(args).arg("info").arg(encrypted);
+        match env {
+            Some(key) => command.env("REPAK_AES_KEY", key),
+            None => command.env_remove("REPAK_AES_KEY"),
+        };
+        command.assert().get_output().status.code()
+    };
+
+    // the key matching the GUID of the pak is picked from the file
+    assert_eq!(run(&["--aes-key-file", &crypto], None), Some(0));
+    assert_eq!(
+        run(&["--aes-key-file", "../repak/tests/crypto.json"], None),
+        Some(0),
+        "Crypto.json of an Unreal project"
+    );
+    assert_eq!(run(&["--aes-key-file", &wrong_crypto], None), Some(4));
+    // the environment is used without either option
+    assert_eq!(run(&[], Some(AES_KEY)), Some(0));
+    assert_eq!(run(&[], Some(&wrong_key)), Some(4));
+    // the flag wins over the file, which wins over the environment
+    assert_eq!(
+        run(&["-a", AES_KEY, "--aes-key-file", &wrong_crypto], None),
+        Some(0)
+    );
+    assert_eq!(run(&["-a", &wrong_key], Some(AES_KEY)), Some(4));
+    assert_eq!(run(&["--aes-key-file", &crypto], Some(&wrong_key)), Some(0));
+    assert_eq!(
+        run(&["--aes-key-file", &wrong_crypto], Some(AES_KEY)),
+        Some(4)
+    );
+
+    // malformed files name the offending entry
+    let malformed = key_file(
+        "malformed.json",
+        &formatdoc! {r#"
+            [
+              {{ "Guid": "00000000000000000000000000000000", "Key": "{hex_key}" }},
+              {{ "Guid": "00000000000000000000000000000000", "Key": "0x1234" }}
+            ]
+        "#},
+    );
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--aes-key-file")
+        .arg(&malformed)
+        .arg("info")
+        .arg(encrypted)
+        .assert();
+    assert.code(2).stderr(formatdoc! {"
+        error[E2]: invalid key in {malformed}: entry 1: expect 256 bit AES key as base64 or hex string
+    "});
+    let missing_key = key_file(
+        "missing.json",
+        r#"[{"Guid": "00000000000000000000000000000000"}]"#,
+    );
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--aes-key-file")
+        .arg(&missing_key)
+        .arg("info")
+        .arg(encrypted)
+        .assert();
+    assert.code(2).stderr(formatdoc! {"
+        error[E2]: invalid key in {missing_key}: entry 0: missing field `key`
+    "});
+    let unreal = key_file(
+        "unreal.json",
+        r#"{"EncryptionKey": null, "SecondaryEncryptionKeys": [{"Guid": "1", "Key": "0x00"}]}"#,
+    );
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--aes-key-file")
+        .arg(&unreal)
+        .arg("info")
+        .arg(encrypted)
+        .assert();
+    assert.code(2).stderr(formatdoc! {r#"
+        error[E2]: invalid key in {unreal}: SecondaryEncryptionKeys entry 0: invalid GUID "1"
+    "#});
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .env("REPAK_AES_KEY", "not a key")
+        .arg("info")
+        .arg(encrypted)
+        .assert();
+    assert.code(2).stderr(indoc! {"
+        error[E2]: invalid key in REPAK_AES_KEY: expect 256 bit AES key as base64 or hex string
+    "});
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    let encrypted = "../repak/tests/packs/pack_v11_encryptindex.pak";
+    let code = |args: &[&str]| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .args(args)
+            .assert()
+            .get_output()
+            .status
+            .code()
+    };
+
+    assert_eq!(code(&["info", PAK]), Some(0));
+    assert_eq!(code(&["info"]), Some(2), "missing argument");
+    assert_eq!(code(&["get", PAK, "missing.txt"]), Some(2));
+    assert_eq!(code(&["info", "Cargo.toml"]), Some(3), "not a pak");
+    assert_eq!(code(&["info", encrypted]), Some(4), "missing key");
+    assert_eq!(
+        code(&["-a", &"00".repeat(32), "info", encrypted]),
+        Some(4),
+        "wrong key"
+    );
+    assert_eq!(code(&["-a", AES_KEY, "info", encrypted]), Some(0));
+    assert_eq!(code(&["info", "does/not/exist.pak"]), Some(5));
+
+    let dir = tempfile::tempdir().unwrap();
+    let pak = dir.path().join("pack_v11.pak");
+    std::fs::copy(PAK, &pak).unwrap();
+    std::fs::create_dir(dir.path().join("pack_v11")).unwrap();
+    std::fs::write(dir.path().join("pack_v11/existing"), "").unwrap();
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg(&pak)
+        .assert();
+    assert.code(7).stderr(formatdoc! {r#"
+        error[E7]: Output directory is not empty: "{}"
+    "#, dir.path().join("pack_v11").to_string_lossy()});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg("-q")
+        .arg("-s")
+        .arg("../mount")
+        .arg("-o")
+        .arg(dir.path().join("batch"))
+        .arg(PAK)
+        .arg("Cargo.toml")
+        .assert();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert.code(6);
+    assert!(stderr.starts_with("error[E3]: Cargo.toml: "), "{stderr}");
+    assert!(
+        stderr.ends_with("error[E6]: 1 of 2 inputs failed\n"),
+        "{stderr}"
+    );
+    assert!(dir.path().join("batch/point/root/test.txt").exists());
+}
+
+#[test]
+fn test_cli_cherry_pick() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_pak = dir.path().join("picked.pak");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("cherry-pick")
+        .arg("-s")
+        .arg("../mount")
+        .arg("../repak/tests/packs/pack_v11_compress.pak")
+        .arg(&out_pak)
+        .arg("point/root/*.png")
+        .arg("point/root/directory")
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        Copied 2 files to {}
+    "#, out_pak.to_string_lossy()});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg("--long")
+        .arg("-s")
+        .arg("../mount")
+        .arg(&out_pak)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        point/root/directory/nested.txt (596 bytes, 596 compressed)
+        point/root/test.png (10257 bytes, 7746 compressed, Zlib)
+    "#});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("cherry-pick")
+        .arg("-s")
+        .arg("../mount")
+        .arg(PAK)
+        .arg(&out_pak)
+        .arg("point/root/missing.txt")
+        .assert();
+    assert.code(2).stderr(indoc! {r#"
+        error[E2]: No entry found at point/root/missing.txt
+    "#});
+}
+
+#[test]
+fn test_cli_blank() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_pak = dir.path().join("blanked.pak");
+    let stub = dir.path().join("stub.bin");
+    std::fs::write(&stub, b"stub").unwrap();
+
+    for (pak, stub_file, expected) in [
+        (PAK, None, &b""[..]),
+        ("../repak/tests/packs/pack_v11_compress.pak", None, b""),
+        (
+            "../repak/tests/packs/pack_v8b_compress.pak",
+            Some(&stub),
+            b"stub",
+        ),
+    ] {
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("blank")
+            .arg(pak)
+            .arg(&out_pak)
+            .arg("-s")
+            .arg("../mount")
+            .arg("--include")
+            .arg("point/root/test.png")
+            .args(
+                stub_file
+                    .map(|stub| ["--stub-file".as_ref(), stub.as_os_str()])
+                    .into_iter()
+                    .flatten(),
+            )
+            .assert();
+        assert.success().stdout(formatdoc! {r#"
+            Blanked 1 of 4 files to {}
+        "#, out_pak.to_string_lossy()});
+
+        let bytes = std::fs::read(pak).unwrap();
+        let original = repak::PakBuilder::new()
+            .reader(&mut std::io::Cursor::new(&bytes))
+            .unwrap();
+        let blanked_bytes = std::fs::read(&out_pak).unwrap();
+        let mut reader = std::io::Cursor::new(&blanked_bytes);
+        let blanked = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        assert_eq!(blanked.files(), original.files());
+        assert_eq!(blanked.version(), original.version());
+        assert_eq!(blanked.get("test.png", &mut reader).unwrap(), expected);
+        let info = blanked.entry_info("test.png").unwrap();
+        assert_eq!(
+            (info.compression, info.uncompressed),
+            (None, expected.len() as u64)
+        );
+
+        // the other entries are copied as stored
+        let stored = |pak: &repak::PakReader, bytes: &[u8], file: &str| {
+            let mut data = vec![];
+            pak.read_stored(file, &mut std::io::Cursor::new(bytes), &mut data)
+                .unwrap();
+            data
+        };
+        for file in ["directory/nested.txt", "test.txt", "zeros.bin"] {
+            assert_eq!(
+                stored(&blanked, &blanked_bytes, file),
+                stored(&original, &bytes, file),
+                "{pak} {file}"
+            );
+            assert_eq!(
+                blanked.entry_info(file).unwrap().compression,
+                original.entry_info(file).unwrap().compression
+            );
+        }
+    }
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("blank")
+        .arg(PAK)
+        .arg(&out_pak)
+        .arg("--include")
+        .arg("missing.txt")
+        .assert();
+    assert.code(2).stderr(indoc! {r#"
+        error[E2]: No entry found at missing.txt
+    "#});
+}
+
+#[test]
 fn test_cli_hashlist() {
     let assert = Command::cargo_bin("repak")
         .unwrap()
@@ -184,3 +1751,1095 @@ fn test_cli_hashlist() {
         e5a00aa9991ac8a5ee3109844d84a55583bd20572ad3ffcd42792f3c36b183ad point/root/zeros.bin
     "#});
 }
+
+#[test]
+fn test_cli_hashlist_algorithm() {
+    let hash_list = |pak: &str, args: &[&str]| {
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("hash-list")
+            .arg(pak)
+            .arg("-s")
+            .arg("../mount")
+            .args(args)
+            .assert()
+            .success();
+        String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+    };
+    let hashes = |output: &str| {
+        output
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().0.to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    for (algorithm, len) in [("sha256", 64), ("sha1", 40), ("xxh3", 16)] {
+        let output = hash_list(PAK, &["--algorithm", algorithm]);
+        assert_eq!(output.lines().count(), 4);
+        assert!(
+            hashes(&output).iter().all(|hash| hash.len() == len),
+            "{output}"
+        );
+    }
+    assert_eq!(
+        hash_list(PAK, &["--algorithm", "sha1"])
+            .lines()
+            .nth(2)
+            .unwrap(),
+        "4518012e1b365e504001dbc94120624f15b8bbd5 point/root/test.txt"
+    );
+
+    // stored data of uncompressed entries is their contents
+    assert_eq!(hash_list(PAK, &[]), hash_list(PAK, &["--compressed-hash"]));
+
+    let compressed = "../repak/tests/packs/pack_v11_compress.pak";
+    let content = hash_list(compressed, &[]);
+    let stored = hash_list(compressed, &["--compressed-hash"]);
+    assert_eq!(content, hash_list(PAK, &[]));
+    assert_eq!(stored, hash_list(compressed, &["--compressed-hash"]));
+    // only test.png and zeros.bin were worth compressing
+    let changed = hashes(&content)
+        .iter()
+        .zip(hashes(&stored))
+        .map(|(content, stored)| *content != stored)
+        .collect::<Vec<_>>();
+    assert_eq!(changed, [false, true, false, true]);
+}
+
+#[test]
+fn test_cli_changed() {
+    let dir = tempfile::tempdir().unwrap();
+    let pack = |name: &str, files: &[(&str, &str)]| {
+        let input = dir.path().join(name);
+        std::fs::create_dir_all(input.join("dir")).unwrap();
+        for (path, contents) in files {
+            std::fs::write(input.join(path), contents).unwrap();
+        }
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .arg("--quiet")
+            .arg(&input)
+            .assert()
+            .success();
+        dir.path().join(format!("{name}.pak"))
+    };
+    let old = pack(
+        "old",
+        &[
+            ("dir/same.txt", "same"),
+            ("modified.txt", "old"),
+            ("removed.txt", "removed"),
+        ],
+    );
+    let new = pack(
+        "new",
+        &[
+            ("dir/same.txt", "same"),
+            ("modified.txt", "new"),
+            ("dir/added.txt", "added"),
+        ],
+    );
+
+    let baseline = dir.path().join("hashes.txt");
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("hash-list")
+        .arg(&old)
+        .assert()
+        .success();
+    std::fs::write(&baseline, &assert.get_output().stdout).unwrap();
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("changed")
+        .arg(&new)
+        .arg("--baseline")
+        .arg(&baseline)
+        .assert();
+    assert.success().stdout(indoc! {"
+        A dir/added.txt
+        M modified.txt
+        D removed.txt
+    "});
+
+    std::fs::write(&baseline, "not a hash list").unwrap();
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("changed")
+        .arg(&new)
+        .arg("--baseline")
+        .arg(&baseline)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_patch() {
+    let dir = tempfile::tempdir().unwrap();
+    let write_dir = |name: &str, files: &[(&str, &[u8])]| {
+        let root = dir.path().join(name);
+        for (path, data) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+        root
+    };
+    let png = include_bytes!("../../repak/tests/pack/root/test.png");
+    let old = write_dir(
+        "old",
+        &[
+            ("same.png", png),
+            ("changed.txt", b"old contents"),
+            ("removed.txt", b"removed"),
+            ("directory/same.bin", &[0; 2048]),
+        ],
+    );
+    let new = write_dir(
+        "new",
+        &[
+            ("same.png", png),
+            ("changed.txt", b"new contents"),
+            ("added.txt", b"added"),
+            ("directory/same.bin", &[0; 2048]),
+        ],
+    );
+
+    let pack = |input: &std::path::Path| {
+        let output = input.with_extension("pak");
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .arg("--version")
+            .arg("V11")
+            .arg("--compression")
+            .arg("Zlib")
+            .arg("--quiet")
+            .arg(input)
+            .arg(&output)
+            .assert()
+            .success();
+        output
+    };
+    let (old_pak, new_pak) = (pack(&old), pack(&new));
+    let patch = dir.path().join("update.rpatch");
+    let patched = dir.path().join("patched.pak");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("make-patch")
+        .arg(&old_pak)
+        .arg(&new_pak)
+        .arg(&patch)
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        removed removed.txt
+        added added.txt
+        changed changed.txt
+        Wrote patch to {} (1 removed, 1 added, 1 changed)
+    "#, patch.to_string_lossy()});
+    // unchanged entries are copied from the old pak
+    assert!(std::fs::metadata(&patch).unwrap().len() < std::fs::metadata(&new_pak).unwrap().len());
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("apply-patch")
+        .arg(&old_pak)
+        .arg(&patch)
+        .arg(&patched)
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        Wrote {}
+    "#, patched.to_string_lossy()});
+    assert_eq!(
+        std::fs::read(&patched).unwrap(),
+        std::fs::read(&new_pak).unwrap()
+    );
+
+    // the patch only applies to the pak it was made from
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("apply-patch")
+        .arg(&new_pak)
+        .arg(&patch)
+        .arg(&patched)
+        .assert();
+    assert.code(2).stderr(indoc! {r#"
+        error[E2]: input pak is not the one the patch was made from
+    "#});
+    assert!(!patched.exists());
+}
+
+#[test]
+fn test_cli_lossy_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let pak = dir.path().join("lossy.pak");
+
+    let mut pak_writer = repak::PakBuilder::new().writer(
+        std::io::Cursor::new(vec![]),
+        repak::Version::V8B,
+        "../mount/point/root/".to_owned(),
+        None,
+    );
+    pak_writer
+        .write_file("caf_.txt", false, b"latin-1")
+        .unwrap();
+    pak_writer.write_file("test.txt", false, b"valid").unwrap();
+    let mut bytes = pak_writer.write_index().unwrap().into_inner();
+    // a Latin-1 'é' is not valid UTF-8
+    let i = bytes.windows(8).position(|w| w == b"caf_.txt").unwrap();
+    bytes[i + 3] = 0xE9;
+    std::fs::write(&pak, bytes).unwrap();
+
+    // the patched index no longer matches its hash
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg("--ignore-index-hashes")
+        .arg(&pak)
+        .assert();
+    assert.code(3).stderr(indoc! {"
+        error[E3]: \"caf\u{FFFD}.txt\" in the index is not valid UTF-8 or UTF-16 (it can be read lossily with --lossy-paths)
+    "});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg("--ignore-index-hashes")
+        .arg("--lossy-paths")
+        .arg("-s")
+        .arg("../mount")
+        .arg(&pak)
+        .assert();
+    assert.success().stdout(indoc! {"
+        point/root/caf\u{FFFD}.txt [lossy]
+        point/root/test.txt
+    "});
+}
+
+#[test]
+fn test_cli_analyze_directory() {
+    let output = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("analyze")
+        .arg("--format")
+        .arg("json")
+        .arg("../repak/tests/pack/root")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let analysis: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let ratio = |path: &str, method: &str| {
+        let file = analysis["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["path"] == path)
+            .unwrap();
+        file["projected"][method].as_f64().unwrap() / file["size"].as_f64().unwrap()
+    };
+    for method in ["Zlib", "Zstd"] {
+        assert!(ratio("zeros.bin", method) < 0.1);
+        assert!(ratio("test.png", method) > 0.5);
+    }
+    assert_eq!(analysis["total"]["files"], 4);
+    assert_eq!(analysis["total"]["size"], 446 + 10257 + 2048 + 596);
+    assert_eq!(analysis["extensions"]["bin"]["size"], 2048);
+
+    // a sample smaller than the file is scaled up to the full size
+    let output = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("analyze")
+        .arg("--format")
+        .arg("json")
+        .arg("--compression")
+        .arg("Zlib")
+        .arg("--sample-size")
+        .arg("1024")
+        .arg("../repak/tests/pack/root")
+        .output()
+        .unwrap();
+    let analysis: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let zeros = &analysis["files"][3];
+    assert_eq!(zeros["path"], "zeros.bin");
+    assert_eq!(zeros["sampled"], 1024);
+    assert!(zeros["projected"]["Zlib"].as_u64().unwrap() < 100);
+    assert!(zeros["projected"].get("Zstd").is_none());
+}
+
+#[test]
+fn test_cli_analyze_pak() {
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("analyze")
+        .arg("../repak/tests/packs/pack_v11_compress.pak")
+        .assert();
+    assert.success().stdout(concat!(
+        " ratio uncompressed   compressed compression path\n",
+        "  100%          596          596 none        directory/nested.txt\n",
+        "   76%        10257         7746 Zlib        test.png\n",
+        "  100%          446          446 none        test.txt\n",
+        "    1%         2048           23 Zlib        zeros.bin\n",
+        "   66%        13347         8811             total of 4 entries\n",
+    ));
+}
+
+#[test]
+fn test_cli_batch() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy(PAK, dir.path().join("pack_v11.pak")).unwrap();
+    std::fs::write(dir.path().join("garbage.pak"), [0xAB; 256]).unwrap();
+    // only .pak files are picked up from directories
+    std::fs::write(dir.path().join("readme.txt"), "not a pak").unwrap();
+    let garbage = dir.path().join("garbage.pak");
+    let pak = dir.path().join("pack_v11.pak");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg("-s")
+        .arg("../mount")
+        .arg(dir.path())
+        .assert();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.starts_with(&format!("error[E3]: {}: ", garbage.display())));
+    assert!(stderr.ends_with("error[E6]: 1 of 2 inputs failed\n"));
+    assert.code(6).stdout(formatdoc! {r#"
+        ==> {} <==
+
+        ==> {} <==
+        point/root/directory/nested.txt
+        point/root/test.png
+        point/root/test.txt
+        point/root/zeros.bin
+    "#, garbage.display(), pak.display()});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg(&pak)
+        .arg(&pak)
+        .assert();
+    assert.success().stdout(formatdoc! {r#"
+        ==> {pak} <==
+        mount point: ../mount/point/root/
+        version: V11
+        version major: Fnv64BugFix
+        encrypted index: false
+        encrytion guid: Some(00000000000000000000000000000000)
+        path hash seed: Some(205C5A7D)
+        4 file entries
+
+        ==> {pak} <==
+        mount point: ../mount/point/root/
+        version: V11
+        version major: Fnv64BugFix
+        encrypted index: false
+        encrytion guid: Some(00000000000000000000000000000000)
+        path hash seed: Some(205C5A7D)
+        4 file entries
+    "#, pak = pak.display()});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("hash-list")
+        .arg("--merged")
+        .arg("-s")
+        .arg("../mount")
+        .arg(dir.path())
+        .arg("../repak/tests/packs/pack_v11_compress.pak")
+        .assert();
+    assert.code(6).stdout(indoc! {r#"
+        246c88de650fb20d63abaeb7c1bd8556d0ea260bf4579beafe0b2597e00270a5 pack_v11.pak point/root/directory/nested.txt
+        246c88de650fb20d63abaeb7c1bd8556d0ea260bf4579beafe0b2597e00270a5 pack_v11_compress.pak point/root/directory/nested.txt
+        d7d3e1c21a5b98621add61a4244a413abf5ad6413b0d25ba09bfd5536c75e3b1 pack_v11.pak point/root/test.png
+        d7d3e1c21a5b98621add61a4244a413abf5ad6413b0d25ba09bfd5536c75e3b1 pack_v11_compress.pak point/root/test.png
+        56293a80e0394d252e995f2debccea8223e4b5b2b150bee212729b3b39ac4d46 pack_v11.pak point/root/test.txt
+        56293a80e0394d252e995f2debccea8223e4b5b2b150bee212729b3b39ac4d46 pack_v11_compress.pak point/root/test.txt
+        e5a00aa9991ac8a5ee3109844d84a55583bd20572ad3ffcd42792f3c36b183ad pack_v11.pak point/root/zeros.bin
+        e5a00aa9991ac8a5ee3109844d84a55583bd20572ad3ffcd42792f3c36b183ad pack_v11_compress.pak point/root/zeros.bin
+    "#});
+}
+
+#[test]
+fn test_cli_doctor() {
+    let dir = tempfile::tempdir().unwrap();
+    let pack = |name: &str, files: &[&str], args: &[&str]| {
+        let input = dir.path().join(name);
+        std::fs::create_dir(&input).unwrap();
+        for file in files {
+            // long enough to be worth compressing
+            std::fs::write(input.join(file), "modded".repeat(100)).unwrap();
+        }
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .arg("--quiet")
+            .args(args)
+            .arg(&input)
+            .assert()
+            .success();
+        dir.path().join(format!("{name}.pak"))
+    };
+
+    // wrong version, mount point and name
+    let pak = pack("mod", &["test.txt"], &["--version", "V8B"]);
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("doctor")
+        .arg(&pak)
+        .arg("--reference")
+        .arg(PAK)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        warning: version V8B does not match the reference version V11
+          = help: repack with `--version V11`
+        warning: mount point "../../../" does not match the reference mount point "../mount/point/root/"
+          = help: repack with `--mount-point ../mount/point/root/` unless the entry paths account for the difference
+        warning: file name does not end with _P so the pak may load before the originals
+          = help: rename it to mod_P.pak
+        note: 1 of 1 entries do not exist in the reference and add files instead of replacing them, e.g. ../../../test.txt
+          = help: check the mount point and entry paths if the pak should replace game files
+        3 warnings, 1 notes
+    "#});
+
+    // matching layout with a miscased and a new entry
+    let pak = pack(
+        "mod_P",
+        &["Test.txt", "new.txt"],
+        &[
+            "--version",
+            "V11",
+            "--mount-point",
+            "../mount/point/root/",
+            "--path-hash-seed",
+            "542923389",
+        ],
+    );
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("doctor")
+        .arg(&pak)
+        .arg("--reference")
+        .arg(PAK)
+        .assert();
+    assert.success().stdout(indoc! {r#"
+        warning: ../mount/point/root/Test.txt only matches ../mount/point/root/test.txt of the reference when ignoring case
+          = help: rename it to ../mount/point/root/test.txt
+        note: 1 of 2 entries do not exist in the reference and add files instead of replacing them, e.g. ../mount/point/root/new.txt
+        1 warnings, 1 notes
+    "#});
+
+    // compression the reference never uses
+    let pak = pack(
+        "compressed_P",
+        &["test.txt"],
+        &["--version", "V11", "--compression", "Zlib"],
+    );
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("doctor")
+        .arg(&pak)
+        .arg("--reference")
+        .arg(PAK)
+        .assert();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains(indoc! {"
+        warning: compression method Zlib is never used by the reference
+          = help: repack without `--compression`
+    "}));
+}
+
+#[test]
+fn test_cli_pack_max_index_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("output.pak");
+    let pack = |max: u64| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .arg("--quiet")
+            .arg("--version")
+            .arg("V11")
+            .arg("--max-index-size")
+            .arg(max.to_string())
+            .arg("../repak/tests/pack/root")
+            .arg(&output)
+            .assert()
+    };
+
+    pack(u64::MAX).success();
+    let written = std::fs::read(&output).unwrap();
+    let footer = repak::PakBuilder::new().parse_footer(&written).unwrap();
+    let size = written.len() as u64 - footer.index_range().start;
+
+    pack(size).success();
+    let assert = pack(size - 1).code(7);
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert_eq!(
+        stderr,
+        format!(
+            "error[E7]: the index would be {size} bytes, more than the maximum of {} bytes\n",
+            size - 1
+        )
+    );
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_cli_fix_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let corrupt = dir.path().join("corrupt.pak");
+    let fixed = dir.path().join("fixed.pak");
+
+    // stale hash in the last record of the path hash index
+    let mut bytes = std::fs::read(PAK).unwrap();
+    let builder = repak::PakBuilder::new();
+    let footer = builder.parse_footer(&bytes).unwrap();
+    let index = &bytes[footer.index_range().start as usize..footer.index_range().end as usize];
+    let phi = builder
+        .secondary_index_ranges(&footer, index)
+        .unwrap()
+        .path_hash_index
+        .unwrap();
+    let record = phi.start as usize + 4 + 3 * 12;
+    bytes[record..record + 8].copy_from_slice(&0x0123456789abcdefu64.to_le_bytes());
+    let offset = u32::from_le_bytes(bytes[record + 8..record + 12].try_into().unwrap());
+    std::fs::write(&corrupt, &bytes).unwrap();
+
+    let info = |pak: &std::path::Path| {
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("info")
+            .arg("--check")
+            .arg("--ignore-index-hashes")
+            .arg(pak)
+            .assert()
+            .success();
+        String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+    };
+    let report = info(&corrupt);
+    let report = report.split_once("path hash index: ").unwrap().1;
+    let missing = report.lines().nth(1).unwrap();
+    assert_eq!(
+        report.lines().next().unwrap(),
+        "1 missing, 0 mismatched, 1 extra records"
+    );
+    assert!(missing.starts_with("  missing: "), "{report}");
+    assert_eq!(
+        report.lines().nth(2).unwrap(),
+        format!("  extra: 0123456789ABCDEF -> encoded entry at {offset}")
+    );
+
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("fix-index")
+        .arg(&corrupt)
+        .arg(&fixed)
+        .assert()
+        .success()
+        .stdout(format!(
+            "path hash index: 1 missing, 0 mismatched, 1 extra records\n{missing}\n  extra: 0123456789ABCDEF -> encoded entry at {offset}\nRewrote the index of 4 files to {}\n",
+            fixed.display()
+        ));
+    assert!(info(&fixed).ends_with("path hash index: consistent\n"));
+    assert!(std::fs::read(&fixed).unwrap() == std::fs::read(PAK).unwrap());
+}
+
+#[test]
+fn test_cli_pack_dedup() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    let png = include_bytes!("../../repak/tests/pack/root/test.png");
+    for (file, data) in [
+        ("skins/a/texture.png", &png[..]),
+        ("skins/b/texture.png", png),
+        ("skins/c/texture.png", png),
+        ("test.txt", b"text"),
+    ] {
+        let path = input.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    let pack = |name: &str, args: &[&str]| {
+        let output = dir.path().join(name);
+        let assert = Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .args(args)
+            .arg(&input)
+            .arg(&output)
+            .assert()
+            .success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+        (output, stdout)
+    };
+    let (plain, _) = pack("plain.pak", &["--quiet"]);
+    let (deduped, stdout) = pack("deduped.pak", &["--dedup"]);
+    let plain_len = std::fs::metadata(plain).unwrap().len();
+    let deduped_len = std::fs::metadata(&deduped).unwrap().len();
+    assert!(deduped_len < plain_len);
+    assert!(stdout.ends_with(&format!(
+        "Deduplication saved {} bytes\n",
+        plain_len - deduped_len
+    )));
+
+    let output = dir.path().join("output");
+    Command::cargo_bin("repak")
+        .unwrap()
+        .arg("unpack")
+        .arg("--quiet")
+        .arg(&deduped)
+        .arg("--output")
+        .arg(&output)
+        .assert()
+        .success();
+    assert!(!dir_diff::is_different(&input, &output).unwrap());
+}
+
+#[test]
+fn test_cli_pack_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    for file in ["a.txt", "dir/b.txt", "dir/c.txt"] {
+        let path = input.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, file).unwrap();
+    }
+    let output = dir.path().join("output.pak");
+    let pack = |args: &[&str]| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("pack")
+            .args(args)
+            .arg(&input)
+            .arg(&output)
+            .assert()
+    };
+
+    assert_eq!(
+        pack_stdout(pack(&["--verify", "--compression", "Zlib"])),
+        formatdoc! {"
+            Packed 3 files to {}
+            {}
+            Verified the written pak, reading back the data of 3 files
+        ", output.display(), pack_sizes(23, &output)}
+    );
+    assert_eq!(
+        pack_stdout(pack(&["--verify", "--verify-sample", "1"])),
+        formatdoc! {"
+            Packed 3 files to {}
+            {}
+            Verified the written pak, reading back the data of 1 files
+        ", output.display(), pack_sizes(23, &output)}
+    );
+    pack(&["--verify-sample", "1"]).failure();
+}
+
+#[test]
+fn test_cli_pack_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    for file in [
+        "a.txt",
+        "b.txt",
+        "Config/DefaultGame.ini",
+        "Content/Localization/en.locres",
+        "Content/Localization/fr.locres",
+        "Content/ShaderArchive.ushaderbytecode",
+        "z.txt",
+    ] {
+        let path = input.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, file).unwrap();
+    }
+    let order = dir.path().join("order.txt");
+    std::fs::write(
+        &order,
+        indoc! {"
+            Content/ShaderArchive.ushaderbytecode
+            Content/Localization/*.locres
+
+            missing.bin
+            z.txt
+        "},
+    )
+    .unwrap();
+    let output = dir.path().join("output.pak");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg("--quiet")
+        .arg("--order")
+        .arg(&order)
+        .arg(&input)
+        .arg(&output)
+        .assert();
+    assert
+        .success()
+        .stderr("warning: line 4 of order file does not match any file: missing.bin\n");
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(&output).unwrap());
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let mut files = pak.files();
+    files.sort_by_key(|file| pak.entry_info(file).unwrap().offset);
+    assert_eq!(
+        files,
+        [
+            "Content/ShaderArchive.ushaderbytecode",
+            "Content/Localization/en.locres",
+            "Content/Localization/fr.locres",
+            "z.txt",
+            "Config/DefaultGame.ini",
+            "a.txt",
+            "b.txt",
+        ]
+    );
+}
+
+#[test]
+fn test_cli_pack_order_utf16() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input");
+    for file in ["a.txt", "b.txt", "Content/z.txt"] {
+        let path = input.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, file).unwrap();
+    }
+    // as saved by Notepad on Windows, which must not leave '\r' at the end of the paths
+    let order = dir.path().join("order.txt");
+    let text = "# last files first\r\nContent/z.txt  \r\nmissing.bin\r\nb.txt\r\n";
+    let bytes = [0xFF, 0xFE]
+        .into_iter()
+        .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+        .collect::<Vec<_>>();
+    std::fs::write(&order, bytes).unwrap();
+    let output = dir.path().join("output.pak");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("pack")
+        .arg("--quiet")
+        .arg("--verbose")
+        .arg("--order")
+        .arg(&order)
+        .arg(&input)
+        .arg(&output)
+        .assert();
+    assert.success().stderr(format!(
+        "order file {}: UTF-16LE, CRLF line endings\n\
+         warning: line 3 of order file does not match any file: missing.bin\n",
+        order.display()
+    ));
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(&output).unwrap());
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let mut files = pak.files();
+    files.sort_by_key(|file| pak.entry_info(file).unwrap().offset);
+    assert_eq!(files, ["Content/z.txt", "b.txt", "a.txt"]);
+}
+
+#[test]
+fn test_cli_decrypt() {
+    let dir = tempfile::tempdir().unwrap();
+    let encrypted = "../repak/tests/packs/pack_v11_compress_encrypt_encryptindex.pak";
+    let output = dir.path().join("decrypted.pak");
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("decrypt")
+        .arg(encrypted)
+        .arg(&output)
+        .assert();
+    assert.code(4);
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("--aes-key")
+        .arg(AES_KEY)
+        .arg("decrypt")
+        .arg(encrypted)
+        .arg(&output)
+        .assert();
+    assert.success().stdout(formatdoc! {"
+        Decrypted 4 files to {}
+    ", output.display()});
+
+    // readable without a key, with the same contents and compression
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("list")
+        .arg("--long")
+        .arg("-s")
+        .arg("../mount")
+        .arg(&output)
+        .assert();
+    assert.success().stdout(indoc! {"
+        point/root/directory/nested.txt (596 bytes, 596 compressed)
+        point/root/test.png (10257 bytes, 7746 compressed, Zlib)
+        point/root/test.txt (446 bytes, 446 compressed)
+        point/root/zeros.bin (2048 bytes, 23 compressed, Zlib)
+    "});
+    let hashes = |args: &[&str], pak: &std::path::Path| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .args(args)
+            .arg("hash-list")
+            .arg("-s")
+            .arg("")
+            .arg(pak)
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone()
+    };
+    assert_eq!(
+        hashes(&[], &output),
+        hashes(&["--aes-key", AES_KEY], std::path::Path::new(encrypted))
+    );
+}
+
+#[test]
+fn test_cli_info_check_and_compact() {
+    let dir = tempfile::tempdir().unwrap();
+    let fragmented = dir.path().join("fragmented.pak");
+    let output = dir.path().join("compacted.pak");
+
+    let mut writer = repak::PakBuilder::new().writer(
+        std::io::BufWriter::new(std::fs::File::create(&fragmented).unwrap()),
+        repak::Version::V11,
+        "../mount/point/root/".to_owned(),
+        None,
+    );
+    writer.write_file("a.txt", false, [1; 100]).unwrap();
+    writer.write_file("b.txt", false, [2; 1000]).unwrap();
+    writer.write_index().unwrap();
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(&fragmented).unwrap());
+    let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&fragmented)
+        .unwrap();
+    let mut writer = pak.into_pakwriter(file).unwrap();
+    writer.write_file("b.txt", false, [3; 10]).unwrap();
+    writer.write_index().unwrap();
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg("--check")
+        .arg(&fragmented)
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(
+        stdout.contains("dead space: 1053 bytes (61.5%) in 1 gaps, largest 1053 bytes"),
+        "{stdout}"
+    );
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("compact")
+        .arg(&fragmented)
+        .arg(&output)
+        .assert();
+    assert.success().stdout(formatdoc! {"
+        Compacted 2 files to {} (1713 -> 660 bytes)
+    ", output.display()});
+
+    let assert = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("info")
+        .arg("--check")
+        .arg(&output)
+        .assert();
+    let stdout = String::from_utf8(assert.success().get_output().stdout.clone()).unwrap();
+    assert!(
+        stdout.contains("dead space: 0 bytes (0.0%) in 0 gaps, largest 0 bytes"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_cli_layout() {
+    let output = Command::cargo_bin("repak")
+        .unwrap()
+        .arg("layout")
+        .arg(PAK)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+    let mut lines = output.lines();
+    assert!(lines.next().unwrap().trim_start().starts_with("offset"));
+    let rows = lines
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut paths = rows.iter().map(|row| row[4]).collect::<Vec<_>>();
+    let offsets = rows
+        .iter()
+        .map(|row| u64::from_str_radix(row[0].trim_start_matches("0x"), 16).unwrap())
+        .collect::<Vec<_>>();
+    assert!(offsets.is_sorted());
+    // entries of paks written by UnrealPak are packed without gaps
+    assert!(rows.iter().all(|row| row[3] == "0"));
+    paths.sort();
+    assert_eq!(
+        paths,
+        ["directory/nested.txt", "test.png", "test.txt", "zeros.bin"]
+    );
+}
+
+#[test]
+fn test_cli_pack_overlay() {
+    let dir = tempfile::tempdir().unwrap();
+    let layers = [
+        ("base", &["a.txt", "dir/b.txt", "dir/c.txt"][..]),
+        ("variant", &["dir/b.txt", "d.txt"]),
+        ("fix", &["a.txt", "dir/b.txt"]),
+    ];
+    for (layer, files) in layers {
+        for file in files {
+            let path = dir.path().join(layer).join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("{layer} {file}")).unwrap();
+        }
+    }
+    let output = dir.path().join("output.pak");
+    let pack = |conflict: &str| {
+        let mut command = Command::cargo_bin("repak").unwrap();
+        command.arg("pack").arg("--conflict").arg(conflict);
+        for (layer, _) in layers {
+            command.arg(dir.path().join(layer));
+        }
+        command.arg(&output).assert()
+    };
+    let contents = || {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(&output).unwrap());
+        let pak = repak::PakBuilder::new().reader(&mut reader).unwrap();
+        pak.files()
+            .into_iter()
+            .map(|file| {
+                let data = pak.get(&file, &mut reader).unwrap();
+                (file, String::from_utf8(data).unwrap())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        pack_stdout(pack("last")),
+        formatdoc! {"
+            Packed 4 files to {}
+            {}
+            3 files overridden by later input directories
+        ", output.display(), pack_sizes(49, &output)}
+    );
+    assert_eq!(
+        contents(),
+        [
+            ("a.txt".to_owned(), "fix a.txt".to_owned()),
+            ("d.txt".to_owned(), "variant d.txt".to_owned()),
+            ("dir/b.txt".to_owned(), "fix dir/b.txt".to_owned()),
+            ("dir/c.txt".to_owned(), "base dir/c.txt".to_owned()),
+        ]
+    );
+
+    assert_eq!(
+        pack_stdout(pack("first")),
+        formatdoc! {"
+            Packed 4 files to {}
+            {}
+            3 files of later input directories ignored
+        ", output.display(), pack_sizes(51, &output)}
+    );
+    assert_eq!(
+        contents(),
+        [
+            ("a.txt".to_owned(), "base a.txt".to_owned()),
+            ("d.txt".to_owned(), "variant d.txt".to_owned()),
+            ("dir/b.txt".to_owned(), "base dir/b.txt".to_owned()),
+            ("dir/c.txt".to_owned(), "base dir/c.txt".to_owned()),
+        ]
+    );
+
+    std::fs::remove_file(&output).unwrap();
+    pack("error").failure().stderr(formatdoc! {"
+        error[E1]: dir/b.txt is in more than one input directory, as {} and {} (see --conflict)
+    ", dir.path().join("base/dir/b.txt").display(), dir.path().join("variant/dir/b.txt").display()});
+    assert!(!output.exists());
+}
+
+#[test]
+fn test_cli_merged_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let paks = [
+        (
+            "pakchunk0-Windows.pak",
+            "../../../",
+            &["Game/A.txt", "Game/B.txt"][..],
+        ),
+        ("pakchunk10-Windows.pak", "../../../", &["Game/C.txt"]),
+        (
+            "pakchunk2-Windows.pak",
+            "../../../Game/",
+            &["B.txt", "C.txt"],
+        ),
+        ("~mods/MyMod_P.pak", "../../../", &["Game/b.txt"]),
+        ("pakchunk0-Windows_1_P.pak", "../../../", &["Game/A.txt"]),
+    ];
+    for (name, mount_point, files) in paks {
+        let path = dir.path().join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut writer = repak::PakBuilder::new().writer(
+            std::io::BufWriter::new(std::fs::File::create(path).unwrap()),
+            repak::Version::V11,
+            mount_point.to_owned(),
+            None,
+        );
+        for file in files {
+            writer.write_file(file, false, name.as_bytes()).unwrap();
+        }
+        writer.write_index().unwrap();
+    }
+    let merged_list = |args: &[&str]| {
+        Command::cargo_bin("repak")
+            .unwrap()
+            .arg("merged-list")
+            .arg(dir.path())
+            .args(args)
+            .assert()
+    };
+
+    merged_list(&[]).success().stdout(indoc! {"
+        pakchunk0-Windows_1_P.pak Game/A.txt
+        ~mods/MyMod_P.pak         Game/b.txt
+        pakchunk10-Windows.pak    Game/C.txt
+    "});
+    merged_list(&["--who-has", "game/B.txt"])
+        .success()
+        .stdout(indoc! {"
+            ~mods/MyMod_P.pak
+            pakchunk2-Windows.pak (overridden)
+            pakchunk0-Windows.pak (overridden)
+        "});
+    merged_list(&["--who-has", "Game/C.txt", "--format", "json"])
+        .success()
+        .stdout(indoc! {r#"
+            {
+              "path": "Game/C.txt",
+              "pak": "pakchunk10-Windows.pak",
+              "overridden": [
+                "pakchunk2-Windows.pak"
+              ]
+            }
+        "#});
+    merged_list(&["--who-has", "Game/D.txt"]).failure();
+}